@@ -0,0 +1,8 @@
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // protoc をインストールしていない環境でもビルドできるよう、同梱の protoc を使う
+    // sqlx::migrate! に埋め込むマイグレーションが増えたら作り直す
+    println!("cargo:rerun-if-changed=db/migrations");
+    std::env::set_var("PROTOC", protoc_bin_vendored::protoc_bin_path()?);
+    tonic_build::configure().compile(&["proto/todo.proto", "proto/label.proto"], &["proto"])?;
+    Ok(())
+}
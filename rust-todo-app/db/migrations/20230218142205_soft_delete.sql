@@ -0,0 +1,2 @@
+ALTER TABLE todos ADD COLUMN deleted_at TIMESTAMPTZ;
+ALTER TABLE labels ADD COLUMN deleted_at TIMESTAMPTZ;
@@ -0,0 +1,13 @@
+CREATE TABLE todo_history (
+    id SERIAL PRIMARY KEY,
+    user_id TEXT NOT NULL,
+    todo_id INTEGER NOT NULL,
+    action TEXT NOT NULL,
+    before JSONB,
+    after JSONB,
+    undone BOOLEAN NOT NULL DEFAULT FALSE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX todo_history_user_id_idx ON todo_history (user_id, id);
+CREATE INDEX todo_history_todo_id_idx ON todo_history (todo_id, id);
@@ -0,0 +1,12 @@
+CREATE TABLE reminders (
+    id SERIAL PRIMARY KEY,
+    todo_id INTEGER NOT NULL,
+    fire_at TIMESTAMPTZ NOT NULL,
+    channel TEXT NOT NULL,
+    fired_at TIMESTAMPTZ,
+    dismissed_at TIMESTAMPTZ
+);
+
+CREATE INDEX reminders_todo_id_idx ON reminders (todo_id);
+CREATE INDEX reminders_due_idx ON reminders (fire_at)
+    WHERE fired_at IS NULL AND dismissed_at IS NULL;
@@ -0,0 +1,2 @@
+ALTER TABLE labels ADD COLUMN color TEXT NOT NULL DEFAULT '#808080';
+ALTER TABLE labels ADD COLUMN description TEXT;
@@ -0,0 +1,3 @@
+ALTER TABLE labels ADD COLUMN parent_id INTEGER REFERENCES labels (id);
+
+CREATE INDEX labels_parent_id_idx ON labels (parent_id);
@@ -0,0 +1,71 @@
+CREATE TABLE changes (
+    seq BIGSERIAL PRIMARY KEY,
+    entity TEXT NOT NULL,
+    entity_id INTEGER NOT NULL,
+    workspace_id INTEGER,
+    changed_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX changes_entity_seq_idx ON changes (entity, seq);
+
+-- todo / label の行が変わるたびに変更を記録する
+CREATE FUNCTION record_change() RETURNS trigger AS $$
+BEGIN
+    IF TG_OP = 'DELETE' THEN
+        INSERT INTO changes (entity, entity_id, workspace_id)
+        VALUES (TG_ARGV[0], OLD.id, OLD.workspace_id);
+    ELSE
+        INSERT INTO changes (entity, entity_id, workspace_id)
+        VALUES (TG_ARGV[0], NEW.id, NEW.workspace_id);
+    END IF;
+    RETURN NULL;
+END
+$$ LANGUAGE plpgsql;
+
+-- blocker の完了や削除で blocked が変わる todo も変更として記録する
+CREATE FUNCTION record_blocked_change() RETURNS trigger AS $$
+BEGIN
+    IF OLD.completed IS DISTINCT FROM NEW.completed
+        OR OLD.deleted_at IS DISTINCT FROM NEW.deleted_at THEN
+        INSERT INTO changes (entity, entity_id, workspace_id)
+        SELECT 'todo', todos.id, todos.workspace_id
+        FROM todo_dependencies d
+        INNER JOIN todos ON todos.id = d.todo_id
+        WHERE d.blocker_id = NEW.id;
+    END IF;
+    RETURN NULL;
+END
+$$ LANGUAGE plpgsql;
+
+-- ラベルや blocker の付け外しは todo の変更として記録する
+CREATE FUNCTION record_todo_relation_change() RETURNS trigger AS $$
+DECLARE
+    changed_todo_id INTEGER;
+BEGIN
+    IF TG_OP = 'DELETE' THEN
+        changed_todo_id := OLD.todo_id;
+    ELSE
+        changed_todo_id := NEW.todo_id;
+    END IF;
+    INSERT INTO changes (entity, entity_id, workspace_id)
+    SELECT 'todo', id, workspace_id FROM todos WHERE id = changed_todo_id;
+    RETURN NULL;
+END
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER todos_changes AFTER INSERT OR UPDATE OR DELETE ON todos
+    FOR EACH ROW EXECUTE FUNCTION record_change('todo');
+CREATE TRIGGER todos_blocked_changes AFTER UPDATE ON todos
+    FOR EACH ROW EXECUTE FUNCTION record_blocked_change();
+CREATE TRIGGER labels_changes AFTER INSERT OR UPDATE OR DELETE ON labels
+    FOR EACH ROW EXECUTE FUNCTION record_change('label');
+CREATE TRIGGER todo_labels_changes AFTER INSERT OR DELETE ON todo_labels
+    FOR EACH ROW EXECUTE FUNCTION record_todo_relation_change();
+CREATE TRIGGER todo_dependencies_changes AFTER INSERT OR DELETE ON todo_dependencies
+    FOR EACH ROW EXECUTE FUNCTION record_todo_relation_change();
+
+-- 既存のデータは最初の同期ですべて返す
+INSERT INTO changes (entity, entity_id, workspace_id)
+SELECT 'label', id, workspace_id FROM labels ORDER BY id;
+INSERT INTO changes (entity, entity_id, workspace_id)
+SELECT 'todo', id, workspace_id FROM todos ORDER BY id;
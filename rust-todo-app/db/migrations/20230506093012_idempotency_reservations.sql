@@ -0,0 +1,4 @@
+-- 処理中のリクエストを予約として先に記録し、完了したらレスポンスを埋める
+ALTER TABLE idempotency_keys ALTER COLUMN status_code DROP NOT NULL;
+ALTER TABLE idempotency_keys ALTER COLUMN response_body DROP NOT NULL;
+ALTER TABLE idempotency_keys ADD COLUMN response_headers JSONB;
@@ -0,0 +1,2 @@
+-- 通知中のリマインダーを他のワーカーが取らないよう、期限付きで確保する
+ALTER TABLE reminders ADD COLUMN claimed_until TIMESTAMPTZ;
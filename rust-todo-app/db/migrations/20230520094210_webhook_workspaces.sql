@@ -0,0 +1,5 @@
+-- webhook はワークスペースごとに登録し、そのワークスペースのイベントだけを配信する
+ALTER TABLE webhook_subscriptions
+    ADD COLUMN workspace_id INTEGER REFERENCES workspaces (id) ON DELETE CASCADE;
+
+CREATE INDEX webhook_subscriptions_workspace_id_idx ON webhook_subscriptions (workspace_id);
@@ -0,0 +1,5 @@
+-- 変更を書き込んだトランザクションを残す。同期では、まだ終わっていないトランザクションのうち
+-- 最も古いもの (スナップショットの xmin) をカーソルにして、後からコミットされる変更を飛ばさない
+ALTER TABLE changes ADD COLUMN xid xid8 NOT NULL DEFAULT pg_current_xact_id();
+
+CREATE INDEX changes_entity_xid_idx ON changes (entity, xid);
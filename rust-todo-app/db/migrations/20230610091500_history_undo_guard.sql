@@ -0,0 +1,4 @@
+-- undo で戻した後の todo の version。redo はこの version から変わっていない場合だけ適用する
+ALTER TABLE todo_history ADD COLUMN undone_version INTEGER;
+-- todo が消えていたり他の変更と食い違ったりして取り消せなかった変更。undo / redo の対象から外す
+ALTER TABLE todo_history ADD COLUMN skipped BOOLEAN NOT NULL DEFAULT FALSE;
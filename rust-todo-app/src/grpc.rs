@@ -0,0 +1,535 @@
+// tonic のサービスは Status をそのまま返すため、大きなエラー型を許容する
+#![allow(clippy::result_large_err)]
+
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use axum::extract::FromRequestParts;
+use hyper::{http::request::Parts, Body, StatusCode};
+use serde_json::json;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{body::BoxBody, Request, Response, Status};
+use tower::{Layer, Service};
+use validator::Validate;
+
+use crate::{
+    handlers::{
+        api_token::{ApiTokenAuth, ApiTokenState, AuthenticatedUser},
+        history::HistoryState,
+        oidc::{JwtAuth, OidcState},
+        todo::TodoChanges,
+        webhook::WebhookState,
+        workspace::{WorkspaceState, WORKSPACE_ID},
+        UserId,
+    },
+    repositories::{
+        api_token::ApiTokenRepository,
+        history::HistoryRepository,
+        label::{self, CreateLabel, LabelRepository, DEFAULT_COLOR},
+        todo::{self, CreateTodo, TodoEntity, TodoRepository},
+        user::UserRepository,
+        webhook::{WebhookEvent, WebhookRepository},
+        workspace::WorkspaceRepository,
+        RepositoryError, Scope,
+    },
+};
+
+// ListTodos で 1 回に読み込む件数
+const LIST_PAGE_SIZE: i64 = 100;
+
+pub mod proto {
+    tonic::include_proto!("todo_app.v1");
+}
+
+use proto::{
+    label_service_server::LabelService, todo_service_server::TodoService, CreateLabelRequest,
+    CreateTodoRequest, DeleteLabelRequest, DeleteLabelResponse, DeleteTodoRequest,
+    DeleteTodoResponse, FindLabelRequest, FindTodoRequest, ListLabelsRequest, ListLabelsResponse,
+    ListTodosRequest, UpdateTodoRequest,
+};
+
+impl From<label::Label> for proto::Label {
+    fn from(label: label::Label) -> Self {
+        Self {
+            id: label.id,
+            name: label.name,
+            color: label.color,
+            description: label.description,
+            parent_id: label.parent_id,
+            workspace_id: label.workspace_id,
+        }
+    }
+}
+
+impl From<TodoEntity> for proto::TodoEntity {
+    fn from(todo: TodoEntity) -> Self {
+        Self {
+            id: todo.id,
+            title: todo.title,
+            description: todo.description,
+            completed: todo.completed,
+            version: todo.version,
+            labels: todo.labels.into_iter().map(proto::Label::from).collect(),
+            workspace_id: todo.workspace_id,
+            blocked: todo.blocked,
+            blocked_by: todo.blocked_by,
+        }
+    }
+}
+
+impl From<proto::UpdateTodo> for todo::UpdateTodo {
+    fn from(todo: proto::UpdateTodo) -> Self {
+        Self {
+            title: todo.title,
+            description: None,
+            completed: todo.completed,
+            labels: todo.labels.map(|labels| labels.ids),
+        }
+    }
+}
+
+fn repository_error(e: anyhow::Error) -> Status {
+    match e.downcast_ref::<RepositoryError>() {
+        Some(RepositoryError::NotFound(_)) => Status::not_found(e.to_string()),
+        Some(RepositoryError::Conflict(_)) => Status::failed_precondition(e.to_string()),
+        Some(RepositoryError::Duplicate(_)) => Status::already_exists(e.to_string()),
+        Some(RepositoryError::DependencyCycle(_)) => Status::failed_precondition(e.to_string()),
+        Some(RepositoryError::LabelCycle(_)) => Status::invalid_argument(e.to_string()),
+        _ => {
+            tracing::error!("failed to handle grpc request: {}", e);
+            Status::internal("Internal error")
+        }
+    }
+}
+
+fn validate(payload: &impl Validate) -> Result<(), Status> {
+    payload
+        .validate()
+        .map_err(|err| Status::invalid_argument(format!("Validation error: [{}]", err)))
+}
+
+// `/todo_app.v1.TodoService/CreateTodo` のようなパスをサービス名とメソッド名に分ける
+pub fn grpc_method(path: &str) -> Option<(&str, &str)> {
+    path.strip_prefix("/todo_app.v1.")?.split_once('/')
+}
+
+// gRPC のメソッドは全て POST なので、参照系かどうかはメソッド名で判断する
+pub fn is_read_only(rpc: &str) -> bool {
+    rpc.starts_with("Find") || rpc.starts_with("List")
+}
+
+fn auth_error((status, message): (StatusCode, String)) -> Status {
+    match status {
+        StatusCode::UNAUTHORIZED => Status::unauthenticated(message),
+        StatusCode::FORBIDDEN => Status::permission_denied(message),
+        StatusCode::BAD_REQUEST => Status::invalid_argument(message),
+        _ => {
+            tracing::error!("failed to authenticate grpc request: {}", message);
+            Status::internal("Internal error")
+        }
+    }
+}
+
+// 認証レイヤーが確かめた呼び出し元。サービスはこの値でリポジトリの範囲と履歴のユーザーを決める
+#[derive(Debug, Clone)]
+pub struct GrpcCaller {
+    pub scope: Scope,
+    pub user_id: String,
+}
+
+fn caller<M>(request: &Request<M>) -> Result<GrpcCaller, Status> {
+    request
+        .extensions()
+        .get::<GrpcCaller>()
+        .cloned()
+        .ok_or_else(|| Status::unauthenticated("Token error: [authentication is required]"))
+}
+
+// REST と同じく API トークンか JWT で呼び出し元を認証し、
+// `x-workspace-id` で選んだワークスペースのメンバーか確認する。
+// tonic の interceptor は同期的にしか動かないため、tower のレイヤーとして全てのサービスの前に置く
+pub struct GrpcAuthLayer<A: ApiTokenRepository, U: UserRepository, K: WorkspaceRepository> {
+    api_token_state: ApiTokenState<A>,
+    oidc_state: OidcState<U>,
+    workspace_state: WorkspaceState<K>,
+}
+
+impl<A: ApiTokenRepository, U: UserRepository, K: WorkspaceRepository> Clone
+    for GrpcAuthLayer<A, U, K>
+{
+    fn clone(&self) -> Self {
+        Self {
+            api_token_state: self.api_token_state.clone(),
+            oidc_state: self.oidc_state.clone(),
+            workspace_state: self.workspace_state.clone(),
+        }
+    }
+}
+
+impl<A: ApiTokenRepository, U: UserRepository, K: WorkspaceRepository> GrpcAuthLayer<A, U, K> {
+    pub fn new(
+        api_token_state: ApiTokenState<A>,
+        oidc_state: OidcState<U>,
+        workspace_state: WorkspaceState<K>,
+    ) -> Self {
+        Self {
+            api_token_state,
+            oidc_state,
+            workspace_state,
+        }
+    }
+
+    async fn authenticate(&self, parts: &mut Parts) -> Result<GrpcCaller, Status> {
+        // トークンの検証とスコープの確認は REST の抽出器に任せる
+        JwtAuth::<U>::from_request_parts(parts, &self.oidc_state)
+            .await
+            .map_err(auth_error)?;
+        ApiTokenAuth::<A>::from_request_parts(parts, &self.api_token_state)
+            .await
+            .map_err(auth_error)?;
+        let UserId(user_id) = UserId::from_request_parts(parts, &())
+            .await
+            .map_err(auth_error)?;
+
+        let workspace_id = match parts.headers.get(WORKSPACE_ID) {
+            Some(value) => Some(
+                value
+                    .to_str()
+                    .ok()
+                    .and_then(|value| value.trim().parse::<i32>().ok())
+                    .ok_or_else(|| {
+                        Status::invalid_argument("Workspace error: [invalid workspace id]")
+                    })?,
+            ),
+            None => None,
+        };
+        if let Some(workspace_id) = workspace_id {
+            // `x-user-id` は誰でも名乗れるため、メンバーかどうかは認証されたユーザーで判断する
+            let Some(AuthenticatedUser(user_id)) = parts.extensions.get::<AuthenticatedUser>()
+            else {
+                return Err(Status::unauthenticated(
+                    "Workspace error: [authentication is required]",
+                ));
+            };
+            let role = self
+                .workspace_state
+                .require_role(workspace_id, user_id)
+                .await
+                .map_err(auth_error)?;
+            let read_only = grpc_method(parts.uri.path()).is_some_and(|(_, rpc)| is_read_only(rpc));
+            if !read_only && !role.can_edit() {
+                let message = format!(
+                    "Workspace error: [{} can not modify workspace data]",
+                    user_id
+                );
+                return Err(Status::permission_denied(message));
+            }
+        }
+
+        Ok(GrpcCaller {
+            scope: Scope::new(workspace_id),
+            user_id,
+        })
+    }
+}
+
+impl<S, A: ApiTokenRepository, U: UserRepository, K: WorkspaceRepository> Layer<S>
+    for GrpcAuthLayer<A, U, K>
+{
+    type Service = GrpcAuth<S, A, U, K>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        GrpcAuth {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+pub struct GrpcAuth<S, A: ApiTokenRepository, U: UserRepository, K: WorkspaceRepository> {
+    inner: S,
+    layer: GrpcAuthLayer<A, U, K>,
+}
+
+impl<S: Clone, A: ApiTokenRepository, U: UserRepository, K: WorkspaceRepository> Clone
+    for GrpcAuth<S, A, U, K>
+{
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            layer: self.layer.clone(),
+        }
+    }
+}
+
+impl<S, A, U, K> Service<hyper::Request<Body>> for GrpcAuth<S, A, U, K>
+where
+    S: Service<hyper::Request<Body>, Response = hyper::Response<BoxBody>> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+    A: ApiTokenRepository,
+    U: UserRepository,
+    K: WorkspaceRepository,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: hyper::Request<Body>) -> Self::Future {
+        // poll_ready を済ませたサービスで呼び出すため、クローンと入れ替える
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let layer = self.layer.clone();
+        Box::pin(async move {
+            let (mut parts, body) = req.into_parts();
+            match layer.authenticate(&mut parts).await {
+                Ok(caller) => {
+                    parts.extensions.insert(caller);
+                    inner.call(hyper::Request::from_parts(parts, body)).await
+                }
+                Err(status) => Ok(status.to_http()),
+            }
+        })
+    }
+}
+
+pub struct TodoGrpcService<T: TodoRepository, H: HistoryRepository, W: WebhookRepository> {
+    repository: Arc<T>,
+    history_state: HistoryState<H>,
+    webhook_state: WebhookState<W>,
+}
+
+impl<T: TodoRepository, H: HistoryRepository, W: WebhookRepository> TodoGrpcService<T, H, W> {
+    pub fn new(
+        repository: Arc<T>,
+        history_state: HistoryState<H>,
+        webhook_state: WebhookState<W>,
+    ) -> Self {
+        Self {
+            repository,
+            history_state,
+            webhook_state,
+        }
+    }
+
+    fn changes<'a>(
+        &'a self,
+        repository: &'a T,
+        webhook_state: &'a WebhookState<W>,
+        user_id: &'a str,
+    ) -> TodoChanges<'a, T, H, W> {
+        TodoChanges {
+            repository,
+            history_state: &self.history_state,
+            webhook_state,
+            user_id,
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl<T: TodoRepository, H: HistoryRepository, W: WebhookRepository> TodoService
+    for TodoGrpcService<T, H, W>
+{
+    type ListTodosStream = ReceiverStream<Result<proto::TodoEntity, Status>>;
+
+    async fn create_todo(
+        &self,
+        request: Request<CreateTodoRequest>,
+    ) -> Result<Response<proto::TodoEntity>, Status> {
+        let GrpcCaller { scope, user_id } = caller(&request)?;
+        let repository = self.repository.scoped(scope);
+        let webhook_state = self.webhook_state.scoped(scope);
+        let request = request.into_inner();
+        let payload = CreateTodo::new(request.title, request.labels);
+        validate(&payload)?;
+        let todo = self
+            .changes(&repository, &webhook_state, &user_id)
+            .create(payload)
+            .await
+            .map_err(repository_error)?;
+        Ok(Response::new(todo.into()))
+    }
+
+    async fn find_todo(
+        &self,
+        request: Request<FindTodoRequest>,
+    ) -> Result<Response<proto::TodoEntity>, Status> {
+        let repository = self.repository.scoped(caller(&request)?.scope);
+        let todo = repository
+            .find(request.into_inner().id)
+            .await
+            .map_err(repository_error)?;
+        Ok(Response::new(todo.into()))
+    }
+
+    async fn list_todos(
+        &self,
+        request: Request<ListTodosRequest>,
+    ) -> Result<Response<Self::ListTodosStream>, Status> {
+        let repository = self.repository.scoped(caller(&request)?.scope);
+        let label_id = request.into_inner().label_id;
+        // 一覧をまとめて読み込まず、ページごとに読み込んで送る。
+        // 受け取る側が遅い場合は、チャネルが空くまで次のページを読み込まない
+        let (tx, rx) = mpsc::channel(LIST_PAGE_SIZE as usize);
+        tokio::spawn(async move {
+            let mut offset = 0;
+            loop {
+                let page = match label_id {
+                    Some(label_id) => {
+                        repository
+                            .page_by_label(label_id, LIST_PAGE_SIZE, offset)
+                            .await
+                    }
+                    None => repository.page(LIST_PAGE_SIZE, offset).await,
+                };
+                let page = match page {
+                    Ok(page) => page,
+                    Err(e) => {
+                        let _ = tx.send(Err(repository_error(e))).await;
+                        return;
+                    }
+                };
+                let count = page.todos.len() as i64;
+                for todo in page.todos {
+                    // 受け取る側が切断した場合は読み込みをやめる
+                    if tx.send(Ok(todo.into())).await.is_err() {
+                        return;
+                    }
+                }
+                offset += count;
+                if count < LIST_PAGE_SIZE || offset >= page.total {
+                    return;
+                }
+            }
+        });
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
+
+    async fn update_todo(
+        &self,
+        request: Request<UpdateTodoRequest>,
+    ) -> Result<Response<proto::TodoEntity>, Status> {
+        let GrpcCaller { scope, user_id } = caller(&request)?;
+        let repository = self.repository.scoped(scope);
+        let webhook_state = self.webhook_state.scoped(scope);
+        let request = request.into_inner();
+        let payload = todo::UpdateTodo::from(request.todo.unwrap_or_default());
+        validate(&payload)?;
+        let before = repository
+            .find(request.id)
+            .await
+            .map_err(repository_error)?;
+        // 未完了の blocker が残っている todo は force=true の場合のみ完了にできる
+        if payload.completed == Some(true) && before.blocked && !request.force {
+            let message = format!("Todo {} is blocked", request.id);
+            return Err(Status::failed_precondition(message));
+        }
+        let todo = self
+            .changes(&repository, &webhook_state, &user_id)
+            .update(before, payload, request.expected_version)
+            .await
+            .map_err(repository_error)?;
+        Ok(Response::new(todo.into()))
+    }
+
+    async fn delete_todo(
+        &self,
+        request: Request<DeleteTodoRequest>,
+    ) -> Result<Response<DeleteTodoResponse>, Status> {
+        let GrpcCaller { scope, user_id } = caller(&request)?;
+        let repository = self.repository.scoped(scope);
+        let webhook_state = self.webhook_state.scoped(scope);
+        let request = request.into_inner();
+        self.changes(&repository, &webhook_state, &user_id)
+            .delete(request.id, request.expected_version)
+            .await
+            .map_err(repository_error)?;
+        Ok(Response::new(DeleteTodoResponse {}))
+    }
+}
+
+pub struct LabelGrpcService<L: LabelRepository, W: WebhookRepository> {
+    repository: Arc<L>,
+    webhook_state: WebhookState<W>,
+}
+
+impl<L: LabelRepository, W: WebhookRepository> LabelGrpcService<L, W> {
+    pub fn new(repository: Arc<L>, webhook_state: WebhookState<W>) -> Self {
+        Self {
+            repository,
+            webhook_state,
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl<L: LabelRepository, W: WebhookRepository> LabelService for LabelGrpcService<L, W> {
+    async fn create_label(
+        &self,
+        request: Request<CreateLabelRequest>,
+    ) -> Result<Response<proto::Label>, Status> {
+        let scope = caller(&request)?.scope;
+        let repository = self.repository.scoped(scope);
+        let request = request.into_inner();
+        let payload = CreateLabel {
+            name: request.name,
+            color: request.color.unwrap_or_else(|| DEFAULT_COLOR.to_string()),
+            description: request.description,
+            parent_id: request.parent_id,
+        };
+        validate(&payload)?;
+        let label = repository.create(payload).await.map_err(repository_error)?;
+        self.webhook_state
+            .scoped(scope)
+            .publish(WebhookEvent::LabelCreated, &label)
+            .await;
+        Ok(Response::new(label.into()))
+    }
+
+    async fn find_label(
+        &self,
+        request: Request<FindLabelRequest>,
+    ) -> Result<Response<proto::Label>, Status> {
+        let repository = self.repository.scoped(caller(&request)?.scope);
+        let label = repository
+            .find(request.into_inner().id)
+            .await
+            .map_err(repository_error)?;
+        Ok(Response::new(label.into()))
+    }
+
+    async fn list_labels(
+        &self,
+        request: Request<ListLabelsRequest>,
+    ) -> Result<Response<ListLabelsResponse>, Status> {
+        let repository = self.repository.scoped(caller(&request)?.scope);
+        let labels = repository.all().await.map_err(repository_error)?;
+        Ok(Response::new(ListLabelsResponse {
+            labels: labels.into_iter().map(|label| label.label.into()).collect(),
+        }))
+    }
+
+    async fn delete_label(
+        &self,
+        request: Request<DeleteLabelRequest>,
+    ) -> Result<Response<DeleteLabelResponse>, Status> {
+        let scope = caller(&request)?.scope;
+        let repository = self.repository.scoped(scope);
+        let id = request.into_inner().id;
+        repository.delete(id).await.map_err(repository_error)?;
+        self.webhook_state
+            .scoped(scope)
+            .publish(WebhookEvent::LabelDeleted, &json!({ "id": id }))
+            .await;
+        Ok(Response::new(DeleteLabelResponse {}))
+    }
+}
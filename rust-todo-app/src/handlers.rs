@@ -1,11 +1,38 @@
+pub mod api_token;
+pub mod graphql;
+pub mod history;
+pub mod idempotency;
 pub mod label;
+pub mod oidc;
+pub mod rate_limit;
+pub mod reminder;
+pub mod sync;
 pub mod todo;
+pub mod trash;
+pub mod webhook;
+pub mod workspace;
 
-use axum::{async_trait, body::HttpBody, extract::FromRequest, BoxError, Json};
-use hyper::{Request, StatusCode};
-use serde::de::DeserializeOwned;
+use axum::{
+    async_trait,
+    body::HttpBody,
+    extract::{FromRequest, FromRequestParts, Query},
+    response::{IntoResponse, Response},
+    BoxError, Json,
+};
+use hyper::{
+    header::{self, HeaderName},
+    http::request::Parts,
+    Request, StatusCode,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{json, Value};
 use validator::Validate;
 
+// JSON ボディの上限。BODY_LIMIT_BYTES で変更できる
+pub const DEFAULT_BODY_LIMIT: usize = 1024 * 1024;
+
+use api_token::AuthenticatedUser;
+
 #[derive(Debug)]
 pub struct ValidatedJson<T>(T);
 
@@ -18,19 +45,309 @@ where
     B::Error: Into<BoxError>,
     S: Send + Sync,
 {
-    type Rejection = (StatusCode, String);
+    type Rejection = Response;
 
     async fn from_request(req: Request<B>, state: &S) -> Result<Self, Self::Rejection> {
         let Json(value) = Json::<T>::from_request(req, state)
             .await
             .map_err(|rejection| {
+                // DefaultBodyLimit を超えたボディは 413 にする。
+                // JSON を送ってきたクライアントが読めるよう、本文も JSON で返す
+                if rejection.status() == StatusCode::PAYLOAD_TOO_LARGE {
+                    let message = format!("Payload error: [{}]", rejection);
+                    let body = Json(json!({ "error": message }));
+                    return (StatusCode::PAYLOAD_TOO_LARGE, body).into_response();
+                }
                 let message = format!("Json parse error: [{}]", rejection);
-                (StatusCode::BAD_REQUEST, message)
+                (StatusCode::BAD_REQUEST, message).into_response()
             })?;
         value.validate().map_err(|err| {
             let message = format!("Validation error: [{}]", err);
-            (StatusCode::BAD_REQUEST, message)
+            (StatusCode::BAD_REQUEST, message).into_response()
         })?;
         Ok(ValidatedJson(value))
     }
 }
+
+pub const MERGE_PATCH_JSON: &str = "application/merge-patch+json";
+pub const JSON_PATCH_JSON: &str = "application/json-patch+json";
+
+// PATCH のボディ。Content-Type に応じて通常の JSON、
+// JSON Merge Patch (RFC 7396)、JSON Patch (RFC 6902) のいずれかとして受け取る
+#[derive(Debug)]
+pub enum Patch<T> {
+    Replace(T),
+    Merge(Value),
+    Json(json_patch::Patch),
+}
+
+#[async_trait]
+impl<T, S, B> FromRequest<S, B> for Patch<T>
+where
+    T: DeserializeOwned + Validate,
+    B: HttpBody + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<BoxError>,
+    S: Send + Sync,
+{
+    type Rejection = Response;
+
+    async fn from_request(req: Request<B>, state: &S) -> Result<Self, Self::Rejection> {
+        let content_type = req
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.split(';').next())
+            .map(|value| value.trim().to_ascii_lowercase())
+            .unwrap_or_default();
+        match content_type.as_str() {
+            "application/json" => {
+                let ValidatedJson(value) = ValidatedJson::from_request(req, state).await?;
+                Ok(Patch::Replace(value))
+            }
+            MERGE_PATCH_JSON => {
+                let ValidatedJson(PatchBody(value)) =
+                    ValidatedJson::from_request(req, state).await?;
+                Ok(Patch::Merge(value))
+            }
+            JSON_PATCH_JSON => {
+                let ValidatedJson(PatchBody(value)) =
+                    ValidatedJson::from_request(req, state).await?;
+                Ok(Patch::Json(value))
+            }
+            _ => {
+                let message = format!(
+                    "Content-Type error: [expected application/json, {} or {}]",
+                    MERGE_PATCH_JSON, JSON_PATCH_JSON
+                );
+                Err((StatusCode::UNSUPPORTED_MEDIA_TYPE, message).into_response())
+            }
+        }
+    }
+}
+
+// パッチ文書そのものは検証せず、適用後の値を検証する
+#[derive(Debug, Deserialize)]
+#[serde(transparent)]
+struct PatchBody<T>(T);
+
+impl<T> Validate for PatchBody<T> {
+    fn validate(&self) -> Result<(), validator::ValidationErrors> {
+        Ok(())
+    }
+}
+
+// PATCH で更新する型。NULLABLE に挙げたフィールドは null を指定して値を消せる
+pub trait Patchable {
+    const NULLABLE: &'static [&'static str] = &[];
+}
+
+impl<T> Patch<T>
+where
+    T: Patchable + Serialize + DeserializeOwned + Validate,
+{
+    // 現在の値にパッチを当て、結果を検証する。
+    // NULLABLE 以外のフィールドは、null にしたり取り除いたりすることはできない
+    pub fn apply(self, current: T) -> Result<T, (StatusCode, String)> {
+        let (merge, patch) = match self {
+            Patch::Replace(value) => return Ok(value),
+            Patch::Merge(merge) => (Some(merge), None),
+            Patch::Json(patch) => (None, Some(patch)),
+        };
+        let before = serde_json::to_value(current).map_err(|e| {
+            let message = format!("Patch error: [{}]", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, message)
+        })?;
+        let mut after = before.clone();
+        if let Some(merge) = merge {
+            json_patch::merge(&mut after, &merge);
+        }
+        if let Some(patch) = patch {
+            json_patch::patch(&mut after, &patch).map_err(|e| {
+                let message = format!("Patch error: [{}]", e);
+                (StatusCode::CONFLICT, message)
+            })?;
+        }
+        if let (Value::Object(before), Value::Object(after)) = (&before, &mut after) {
+            let removed = before
+                .iter()
+                .filter(|(key, value)| !value.is_null() && !T::NULLABLE.contains(&key.as_str()))
+                .find(|(key, _)| after.get(*key).is_none_or(Value::is_null));
+            if let Some((key, _)) = removed {
+                let message = format!("Patch error: [{} can not be removed]", key);
+                return Err((StatusCode::UNPROCESSABLE_ENTITY, message));
+            }
+            // Merge Patch の null や JSON Patch の remove で消えたフィールドは、
+            // 省略ではなく値を消す指定として null を入れ直す
+            for key in T::NULLABLE {
+                after.entry(*key).or_insert(Value::Null);
+            }
+        }
+        let value: T = serde_json::from_value(after).map_err(|e| {
+            let message = format!("Patch error: [{}]", e);
+            (StatusCode::UNPROCESSABLE_ENTITY, message)
+        })?;
+        value.validate().map_err(|err| {
+            let message = format!("Validation error: [{}]", err);
+            (StatusCode::UNPROCESSABLE_ENTITY, message)
+        })?;
+        Ok(value)
+    }
+}
+
+#[derive(Debug)]
+pub struct ValidatedQuery<T>(T);
+
+#[async_trait]
+impl<T, S> FromRequestParts<S> for ValidatedQuery<T>
+where
+    T: DeserializeOwned + Validate,
+    S: Send + Sync,
+{
+    type Rejection = (StatusCode, String);
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Query(value) =
+            Query::<T>::from_request_parts(parts, state)
+                .await
+                .map_err(|rejection| {
+                    let message = format!("Query parse error: [{}]", rejection);
+                    (StatusCode::BAD_REQUEST, message)
+                })?;
+        value.validate().map_err(|err| {
+            let message = format!("Validation error: [{}]", err);
+            (StatusCode::BAD_REQUEST, message)
+        })?;
+        Ok(ValidatedQuery(value))
+    }
+}
+
+fn default_page() -> i64 {
+    1
+}
+
+fn default_per_page() -> i64 {
+    20
+}
+
+// `?page=2&per_page=20` 形式のページ指定。page は 1 始まり
+#[derive(Debug, Deserialize, Validate)]
+pub struct Pagination {
+    #[serde(default = "default_page")]
+    // offset の計算が溢れないよう上限を設ける
+    #[validate(range(min = 1, max = 10000, message = "Must be between 1 and 10000"))]
+    pub page: i64,
+    #[serde(default = "default_per_page")]
+    #[validate(range(min = 1, max = 100, message = "Must be between 1 and 100"))]
+    pub per_page: i64,
+}
+
+impl Pagination {
+    pub fn offset(&self) -> i64 {
+        (self.page - 1) * self.per_page
+    }
+}
+
+// `If-Match` ヘッダで指定された version の候補。ヘッダが無い場合や `*` の場合は空。
+// `W/"1"` の弱い ETag や `"1", "2"` のようなカンマ区切りのリストも受け付ける
+#[derive(Debug)]
+pub struct IfMatch(Vec<i32>);
+
+impl IfMatch {
+    // 現在の version が候補に含まれていればその version を、
+    // 含まれていなければリポジトリで競合になるよう先頭の候補を返す
+    pub fn version(&self, current: i32) -> Option<i32> {
+        if self.0.contains(&current) {
+            return Some(current);
+        }
+        self.0.first().copied()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for IfMatch
+where
+    S: Send + Sync,
+{
+    type Rejection = (StatusCode, String);
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let mut versions = vec![];
+        for value in parts.headers.get_all(header::IF_MATCH) {
+            for tag in value.to_str().unwrap_or_default().split(',') {
+                let tag = tag.trim();
+                if tag == "*" {
+                    return Ok(IfMatch(vec![]));
+                }
+                let version = tag
+                    .strip_prefix("W/")
+                    .unwrap_or(tag)
+                    .strip_prefix('"')
+                    .and_then(|v| v.strip_suffix('"'))
+                    .and_then(|v| v.parse::<i32>().ok())
+                    .ok_or_else(|| {
+                        let message = format!("If-Match error: [unknown entity tag {}]", tag);
+                        (StatusCode::PRECONDITION_FAILED, message)
+                    })?;
+                versions.push(version);
+            }
+        }
+        Ok(IfMatch(versions))
+    }
+}
+
+pub const USER_ID: HeaderName = HeaderName::from_static("x-user-id");
+
+// 操作したユーザーの識別子。API トークンか JWT で認証されていればそのユーザー、
+// そうでなければ `X-User-Id` ヘッダを使い、どちらも無い場合は anonymous として扱う。
+// 認証が有効な場合は ApiTokenAuth が未認証のリクエストを拒否するため、ヘッダは使われない
+#[derive(Debug)]
+pub struct UserId(pub String);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for UserId
+where
+    S: Send + Sync,
+{
+    type Rejection = (StatusCode, String);
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        if let Some(AuthenticatedUser(user_id)) = parts.extensions.get::<AuthenticatedUser>() {
+            return Ok(UserId(user_id.clone()));
+        }
+        let user_id = parts
+            .headers
+            .get(USER_ID)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.trim())
+            .filter(|value| !value.is_empty())
+            .unwrap_or("anonymous");
+        Ok(UserId(user_id.to_string()))
+    }
+}
+
+// `Authorization: Bearer <token>` のトークン。ヘッダが無い場合は None
+pub fn bearer_token(parts: &Parts) -> Result<Option<String>, (StatusCode, String)> {
+    let Some(value) = parts.headers.get(header::AUTHORIZATION) else {
+        return Ok(None);
+    };
+    value
+        .to_str()
+        .ok()
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(|token| token.trim().to_string())
+        .filter(|token| !token.is_empty())
+        .map(Some)
+        .ok_or_else(|| {
+            let message = "Token error: [Authorization must be a bearer token]";
+            (StatusCode::UNAUTHORIZED, message.to_string())
+        })
+}
+
+pub fn etag(version: i32) -> String {
+    format!("\"{}\"", version)
+}
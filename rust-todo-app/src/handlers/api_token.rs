@@ -0,0 +1,199 @@
+use super::{bearer_token, ValidatedJson};
+
+use axum::{
+    async_trait,
+    extract::{FromRef, FromRequestParts, MatchedPath, Path, State},
+    response::IntoResponse,
+    Json,
+};
+use hyper::{http::request::Parts, Method, StatusCode};
+use std::{marker::PhantomData, sync::Arc};
+
+use crate::{
+    grpc::{grpc_method, is_read_only},
+    repositories::{
+        api_token::{is_api_token, ApiScope, ApiTokenRepository, CreateApiToken},
+        RepositoryError,
+    },
+};
+
+#[derive(Clone)]
+pub struct ApiTokenState<T: ApiTokenRepository> {
+    pub repository: Arc<T>,
+    // JWT による認証が有効な場合は true。トークンの無いリクエストを `X-User-Id` で扱わずに拒否する
+    pub required: bool,
+}
+
+// API トークンか JWT で認証されたユーザー。`X-User-Id` ヘッダより優先する
+#[derive(Debug, Clone)]
+pub struct AuthenticatedUser(pub String);
+
+// 認証が必須なハンドラで使う。ヘッダで名乗っただけのユーザーは 401 にする
+#[async_trait]
+impl<S> FromRequestParts<S> for AuthenticatedUser
+where
+    S: Send + Sync,
+{
+    type Rejection = (StatusCode, String);
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts
+            .extensions
+            .get::<AuthenticatedUser>()
+            .cloned()
+            .ok_or_else(|| {
+                let message = "Token error: [authentication is required]";
+                (StatusCode::UNAUTHORIZED, message.to_string())
+            })
+    }
+}
+
+// API トークンが持つスコープ。GraphQL のように 1 つのルートで読み書きの両方を扱う場合に、
+// 操作ごとにスコープを確認するために使う
+#[derive(Debug, Clone)]
+pub struct ApiTokenScopes(pub Vec<ApiScope>);
+
+// `Authorization: Bearer <token>` が付いたリクエストのトークンを検証し、
+// ルートに必要なスコープを持っているか確認する
+pub struct ApiTokenAuth<T: ApiTokenRepository>(PhantomData<T>);
+
+#[async_trait]
+impl<S, T> FromRequestParts<S> for ApiTokenAuth<T>
+where
+    T: ApiTokenRepository,
+    ApiTokenState<T>: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = (StatusCode, String);
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        // API トークン以外のトークンは JWT として JwtAuth が検証する。
+        // トークンが無いリクエストは、認証が有効でない場合に限り `X-User-Id` ヘッダで扱う
+        let api_token_state = ApiTokenState::<T>::from_ref(state);
+        let token = match bearer_token(parts)? {
+            Some(token) if is_api_token(&token) => token,
+            Some(_) => return Ok(ApiTokenAuth(PhantomData)),
+            None if api_token_state.required => {
+                let message = "Token error: [authentication is required]";
+                return Err((StatusCode::UNAUTHORIZED, message.to_string()));
+            }
+            None => return Ok(ApiTokenAuth(PhantomData)),
+        };
+
+        let api_token = api_token_state
+            .repository
+            .authenticate(&token)
+            .await
+            .map_err(|e| match e.downcast_ref::<RepositoryError>() {
+                Some(RepositoryError::InvalidToken) => {
+                    let message = "Token error: [invalid or revoked token]";
+                    (StatusCode::UNAUTHORIZED, message.to_string())
+                }
+                _ => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+            })?;
+
+        let path = match parts.extensions.get::<MatchedPath>() {
+            Some(path) => path.as_str().to_string(),
+            None => parts.uri.path().to_string(),
+        };
+        let scopes = required_scopes(&parts.method, &path).ok_or_else(|| {
+            let message = format!("Token error: [{} can not be accessed with a token]", path);
+            (StatusCode::FORBIDDEN, message)
+        })?;
+        if let Some(scope) = scopes.iter().find(|scope| !api_token.has_scope(**scope)) {
+            let message = format!("Token error: [{} scope is required]", scope.as_str());
+            return Err((StatusCode::FORBIDDEN, message));
+        }
+
+        parts
+            .extensions
+            .insert(AuthenticatedUser(api_token.user_id));
+        parts.extensions.insert(ApiTokenScopes(api_token.scopes));
+        Ok(ApiTokenAuth(PhantomData))
+    }
+}
+
+// ルートごとに必要なスコープ。None のルートはトークンでは使えない
+fn required_scopes(method: &Method, path: &str) -> Option<Vec<ApiScope>> {
+    if let Some((service, rpc)) = grpc_method(path) {
+        let read_only = is_read_only(rpc);
+        return match service {
+            "TodoService" if read_only => Some(vec![ApiScope::TodosRead]),
+            "TodoService" => Some(vec![ApiScope::TodosWrite]),
+            "LabelService" if read_only => Some(vec![ApiScope::LabelsRead]),
+            "LabelService" => Some(vec![ApiScope::LabelsWrite]),
+            _ => None,
+        };
+    }
+    let read_only = matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS);
+    let (todos, labels, webhooks, workspaces) = if read_only {
+        (
+            ApiScope::TodosRead,
+            ApiScope::LabelsRead,
+            ApiScope::WebhooksRead,
+            ApiScope::WorkspacesRead,
+        )
+    } else {
+        (
+            ApiScope::TodosWrite,
+            ApiScope::LabelsWrite,
+            ApiScope::WebhooksWrite,
+            ApiScope::WorkspacesWrite,
+        )
+    };
+
+    let resource = path.trim_start_matches('/').split('/').next().unwrap_or("");
+    match resource {
+        "" => Some(vec![]),
+        "todos" | "reminders" | "history" => Some(vec![todos]),
+        "labels" if path.ends_with("/todos") => Some(vec![labels, todos]),
+        "labels" => Some(vec![labels]),
+        "trash" | "sync" => Some(vec![todos, labels]),
+        "webhooks" => Some(vec![webhooks]),
+        "workspaces" | "invitations" => Some(vec![workspaces]),
+        // 書き込みに必要なスコープは mutation ごとに確認する
+        "graphql" => Some(vec![ApiScope::TodosRead, ApiScope::LabelsRead]),
+        _ => None,
+    }
+}
+
+pub async fn create_api_token<T: ApiTokenRepository>(
+    State(api_token_state): State<ApiTokenState<T>>,
+    AuthenticatedUser(user_id): AuthenticatedUser,
+    ValidatedJson(payload): ValidatedJson<CreateApiToken>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let api_token = api_token_state
+        .repository
+        .create(&user_id, payload)
+        .await
+        .or(Err(StatusCode::INTERNAL_SERVER_ERROR))?;
+    Ok((StatusCode::CREATED, Json(api_token)))
+}
+
+pub async fn all_api_token<T: ApiTokenRepository>(
+    State(api_token_state): State<ApiTokenState<T>>,
+    AuthenticatedUser(user_id): AuthenticatedUser,
+) -> Result<impl IntoResponse, StatusCode> {
+    let api_tokens = api_token_state
+        .repository
+        .all(&user_id)
+        .await
+        .or(Err(StatusCode::INTERNAL_SERVER_ERROR))?;
+    Ok((StatusCode::OK, Json(api_tokens)))
+}
+
+pub async fn revoke_api_token<T: ApiTokenRepository>(
+    State(api_token_state): State<ApiTokenState<T>>,
+    AuthenticatedUser(user_id): AuthenticatedUser,
+    Path(id): Path<i32>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let api_token = api_token_state
+        .repository
+        .revoke(&user_id, id)
+        .await
+        .map_err(|e| match e.downcast_ref::<RepositoryError>() {
+            Some(RepositoryError::NotFound(_)) => StatusCode::NOT_FOUND,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        })?;
+    Ok((StatusCode::OK, Json(api_token)))
+}
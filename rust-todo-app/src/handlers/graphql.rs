@@ -0,0 +1,513 @@
+use super::{
+    api_token::ApiTokenScopes,
+    history::HistoryState,
+    label::LabelState,
+    todo::{TodoChanges, TodoState},
+    webhook::WebhookState,
+    workspace::WorkspaceRole,
+    UserId,
+};
+
+use async_graphql::{
+    dataloader::{DataLoader, Loader},
+    http::GraphiQLSource,
+    Context, EmptySubscription, ErrorExtensions, Guard, InputObject, MaybeUndefined, Object,
+    Schema,
+};
+use axum::{
+    extract::State,
+    response::{Html, IntoResponse},
+    Extension, Json,
+};
+use serde_json::json;
+use std::{collections::HashMap, marker::PhantomData, sync::Arc};
+use validator::Validate;
+
+use crate::repositories::{
+    api_token::ApiScope,
+    history::HistoryRepository,
+    label::{self, CreateLabel, LabelRepository, LabelWithCount, DEFAULT_COLOR},
+    todo::{CreateTodo, TodoEntity, TodoRepository, UpdateTodo},
+    webhook::{WebhookEvent, WebhookRepository},
+    RepositoryError,
+};
+
+// クエリのネストの上限。ラベルの親をたどるクエリが際限なく深くならないようにする
+const MAX_DEPTH: usize = 10;
+
+pub type GraphQLSchema<T, L, H, W> =
+    Schema<QueryRoot<T, L>, MutationRoot<T, L, H, W>, EmptySubscription>;
+
+#[derive(Clone)]
+pub struct GraphQLState<
+    T: TodoRepository,
+    L: LabelRepository,
+    H: HistoryRepository,
+    W: WebhookRepository,
+> {
+    pub schema: GraphQLSchema<T, L, H, W>,
+}
+
+impl<T: TodoRepository, L: LabelRepository, H: HistoryRepository, W: WebhookRepository>
+    GraphQLState<T, L, H, W>
+{
+    pub fn new() -> Self {
+        let schema = Schema::build(
+            QueryRoot(PhantomData),
+            MutationRoot(PhantomData),
+            EmptySubscription,
+        )
+        .limit_depth(MAX_DEPTH)
+        .finish();
+        Self { schema }
+    }
+}
+
+// REST と同じ区別ができるよう、HTTP のステータスに相当するコードを extensions.code に入れる
+fn error(code: &'static str, message: impl Into<String>) -> async_graphql::Error {
+    async_graphql::Error::new(message).extend_with(|_, e| e.set("code", code))
+}
+
+fn repository_error(e: anyhow::Error) -> async_graphql::Error {
+    match e.downcast_ref::<RepositoryError>() {
+        Some(RepositoryError::NotFound(_)) => error("NOT_FOUND", e.to_string()),
+        Some(RepositoryError::Conflict(_)) => error("PRECONDITION_FAILED", e.to_string()),
+        Some(RepositoryError::Duplicate(_)) => error("CONFLICT", e.to_string()),
+        _ => {
+            tracing::error!("failed to resolve graphql field: {}", e);
+            error("INTERNAL_SERVER_ERROR", "Internal error")
+        }
+    }
+}
+
+fn todo_changes<'a, T: TodoRepository, H: HistoryRepository, W: WebhookRepository>(
+    ctx: &'a Context<'_>,
+) -> TodoChanges<'a, T, H, W> {
+    let UserId(user_id) = ctx.data_unchecked::<UserId>();
+    TodoChanges {
+        repository: ctx.data_unchecked::<TodoState<T>>().repository.as_ref(),
+        history_state: ctx.data_unchecked::<HistoryState<H>>(),
+        webhook_state: ctx.data_unchecked::<WebhookState<W>>(),
+        user_id,
+    }
+}
+
+fn validate(payload: &impl Validate) -> async_graphql::Result<()> {
+    payload
+        .validate()
+        .map_err(|err| error("BAD_REQUEST", format!("Validation error: [{}]", err)))
+}
+
+// mutation ごとに書き込めるか確認する。API トークンで認証されたリクエストでは必要なスコープを、
+// ワークスペースを選んだリクエストではロールを確かめる
+struct WriteGuard(ApiScope);
+
+impl Guard for WriteGuard {
+    async fn check(&self, ctx: &Context<'_>) -> async_graphql::Result<()> {
+        if let Some(ApiTokenScopes(scopes)) = ctx.data_opt::<ApiTokenScopes>() {
+            if !scopes.contains(&self.0) {
+                return Err(error(
+                    "FORBIDDEN",
+                    format!("Token error: [{} scope is required]", self.0.as_str()),
+                ));
+            }
+        }
+        if let Some(WorkspaceRole(role)) = ctx.data_opt::<WorkspaceRole>() {
+            if !role.can_edit() {
+                let UserId(user_id) = ctx.data_unchecked::<UserId>();
+                return Err(error(
+                    "FORBIDDEN",
+                    format!(
+                        "Workspace error: [{} can not modify workspace data]",
+                        user_id
+                    ),
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+// todo ごとにラベルを取得すると N+1 になるため、1 回のクエリでまとめて取得する
+pub struct LabelLoader<L: LabelRepository> {
+    repository: Arc<L>,
+}
+
+impl<L: LabelRepository> Loader<i32> for LabelLoader<L> {
+    type Value = LabelWithCount;
+    type Error = async_graphql::Error;
+
+    async fn load(&self, keys: &[i32]) -> Result<HashMap<i32, Self::Value>, Self::Error> {
+        let labels = self
+            .repository
+            .find_many(keys)
+            .await
+            .map_err(repository_error)?;
+        Ok(labels
+            .into_iter()
+            .map(|label| (label.label.id, label))
+            .collect())
+    }
+}
+
+pub struct Todo<L: LabelRepository>(TodoEntity, PhantomData<L>);
+
+impl<L: LabelRepository> From<TodoEntity> for Todo<L> {
+    fn from(todo: TodoEntity) -> Self {
+        Todo(todo, PhantomData)
+    }
+}
+
+#[Object(name = "Todo")]
+impl<L: LabelRepository> Todo<L> {
+    async fn id(&self) -> i32 {
+        self.0.id
+    }
+
+    async fn title(&self) -> &str {
+        &self.0.title
+    }
+
+    async fn description(&self) -> Option<&str> {
+        self.0.description.as_deref()
+    }
+
+    async fn completed(&self) -> bool {
+        self.0.completed
+    }
+
+    async fn version(&self) -> i32 {
+        self.0.version
+    }
+
+    async fn workspace_id(&self) -> Option<i32> {
+        self.0.workspace_id
+    }
+
+    async fn blocked(&self) -> bool {
+        self.0.blocked
+    }
+
+    async fn blocked_by(&self) -> &[i32] {
+        &self.0.blocked_by
+    }
+
+    async fn labels(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<Label<L>>> {
+        let ids: Vec<i32> = self.0.labels.iter().map(|label| label.id).collect();
+        let mut labels = ctx
+            .data_unchecked::<DataLoader<LabelLoader<L>>>()
+            .load_many(ids.iter().copied())
+            .await?;
+        Ok(ids
+            .iter()
+            .filter_map(|id| labels.remove(id))
+            .map(Label::from)
+            .collect())
+    }
+}
+
+pub struct Label<L: LabelRepository>(LabelWithCount, PhantomData<L>);
+
+impl<L: LabelRepository> From<LabelWithCount> for Label<L> {
+    fn from(label: LabelWithCount) -> Self {
+        Label(label, PhantomData)
+    }
+}
+
+#[Object(name = "Label")]
+impl<L: LabelRepository> Label<L> {
+    async fn id(&self) -> i32 {
+        self.0.label.id
+    }
+
+    async fn name(&self) -> &str {
+        &self.0.label.name
+    }
+
+    async fn color(&self) -> &str {
+        &self.0.label.color
+    }
+
+    async fn description(&self) -> Option<&str> {
+        self.0.label.description.as_deref()
+    }
+
+    async fn workspace_id(&self) -> Option<i32> {
+        self.0.label.workspace_id
+    }
+
+    async fn todo_count(&self) -> TodoCount {
+        TodoCount(self.0.todo_count)
+    }
+
+    async fn parent(&self, ctx: &Context<'_>) -> async_graphql::Result<Option<Label<L>>> {
+        let Some(parent_id) = self.0.label.parent_id else {
+            return Ok(None);
+        };
+        let parent = ctx
+            .data_unchecked::<DataLoader<LabelLoader<L>>>()
+            .load_one(parent_id)
+            .await?;
+        Ok(parent.map(Label::from))
+    }
+}
+
+pub struct TodoCount(label::TodoCount);
+
+#[Object]
+impl TodoCount {
+    async fn total(&self) -> i64 {
+        self.0.total
+    }
+
+    async fn open(&self) -> i64 {
+        self.0.open
+    }
+
+    async fn completed(&self) -> i64 {
+        self.0.completed
+    }
+}
+
+#[derive(Debug, Default, InputObject)]
+pub struct TodoFilter {
+    // 指定したラベルか、その子孫のラベルが付いた todo に絞り込む
+    label_id: Option<i32>,
+    completed: Option<bool>,
+    blocked: Option<bool>,
+    // タイトルの部分一致。大文字と小文字は区別しない
+    title: Option<String>,
+}
+
+impl TodoFilter {
+    fn matches(&self, todo: &TodoEntity) -> bool {
+        self.completed
+            .is_none_or(|completed| todo.completed == completed)
+            && self.blocked.is_none_or(|blocked| todo.blocked == blocked)
+            && self
+                .title
+                .as_ref()
+                .is_none_or(|title| todo.title.to_lowercase().contains(&title.to_lowercase()))
+    }
+}
+
+#[derive(Debug, InputObject)]
+pub struct CreateTodoInput {
+    title: String,
+    #[graphql(default)]
+    labels: Vec<i32>,
+}
+
+#[derive(Debug, InputObject)]
+pub struct UpdateTodoInput {
+    title: Option<String>,
+    // null を指定すると消す
+    description: MaybeUndefined<String>,
+    completed: Option<bool>,
+    labels: Option<Vec<i32>>,
+}
+
+#[derive(Debug, InputObject)]
+pub struct CreateLabelInput {
+    name: String,
+    color: Option<String>,
+    description: Option<String>,
+    parent_id: Option<i32>,
+}
+
+pub struct QueryRoot<T, L>(PhantomData<(T, L)>);
+
+#[Object]
+impl<T: TodoRepository, L: LabelRepository> QueryRoot<T, L> {
+    async fn todos(
+        &self,
+        ctx: &Context<'_>,
+        filter: Option<TodoFilter>,
+    ) -> async_graphql::Result<Vec<Todo<L>>> {
+        let repository = &ctx.data_unchecked::<TodoState<T>>().repository;
+        let filter = filter.unwrap_or_default();
+        let todos = match filter.label_id {
+            Some(label_id) => repository.all_by_label(label_id).await,
+            None => repository.all().await,
+        }
+        .map_err(repository_error)?;
+        Ok(todos
+            .into_iter()
+            .filter(|todo| filter.matches(todo))
+            .map(Todo::from)
+            .collect())
+    }
+
+    async fn todo(&self, ctx: &Context<'_>, id: i32) -> async_graphql::Result<Option<Todo<L>>> {
+        let repository = &ctx.data_unchecked::<TodoState<T>>().repository;
+        match repository.find(id).await {
+            Ok(todo) => Ok(Some(Todo::from(todo))),
+            Err(e) => match e.downcast_ref::<RepositoryError>() {
+                Some(RepositoryError::NotFound(_)) => Ok(None),
+                _ => Err(repository_error(e)),
+            },
+        }
+    }
+
+    async fn labels(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<Label<L>>> {
+        let repository = &ctx.data_unchecked::<LabelState<L>>().repository;
+        let labels = repository.all().await.map_err(repository_error)?;
+        Ok(labels.into_iter().map(Label::from).collect())
+    }
+}
+
+pub struct MutationRoot<T, L, H, W>(PhantomData<(T, L, H, W)>);
+
+#[Object]
+impl<T: TodoRepository, L: LabelRepository, H: HistoryRepository, W: WebhookRepository>
+    MutationRoot<T, L, H, W>
+{
+    #[graphql(guard = "WriteGuard(ApiScope::TodosWrite)")]
+    async fn create_todo(
+        &self,
+        ctx: &Context<'_>,
+        input: CreateTodoInput,
+    ) -> async_graphql::Result<Todo<L>> {
+        let payload = CreateTodo::new(input.title, input.labels);
+        validate(&payload)?;
+        let todo = todo_changes::<T, H, W>(ctx)
+            .create(payload)
+            .await
+            .map_err(repository_error)?;
+        Ok(Todo::from(todo))
+    }
+
+    // version を指定すると REST の If-Match と同じく、他の更新と競合した場合に失敗する
+    #[graphql(guard = "WriteGuard(ApiScope::TodosWrite)")]
+    async fn update_todo(
+        &self,
+        ctx: &Context<'_>,
+        id: i32,
+        input: UpdateTodoInput,
+        version: Option<i32>,
+        #[graphql(default)] force: bool,
+    ) -> async_graphql::Result<Todo<L>> {
+        let payload = UpdateTodo {
+            title: input.title,
+            description: input.description.into(),
+            completed: input.completed,
+            labels: input.labels,
+        };
+        validate(&payload)?;
+        let repository = &ctx.data_unchecked::<TodoState<T>>().repository;
+        let before = repository.find(id).await.map_err(repository_error)?;
+        // 未完了の blocker が残っている todo は force=true の場合のみ完了にできる
+        if payload.completed == Some(true) && before.blocked && !force {
+            return Err(error("CONFLICT", format!("Todo {} is blocked", id)));
+        }
+        let todo = todo_changes::<T, H, W>(ctx)
+            .update(before, payload, version)
+            .await
+            .map_err(repository_error)?;
+        Ok(Todo::from(todo))
+    }
+
+    // 削除した todo の id を返す
+    #[graphql(guard = "WriteGuard(ApiScope::TodosWrite)")]
+    async fn delete_todo(
+        &self,
+        ctx: &Context<'_>,
+        id: i32,
+        version: Option<i32>,
+    ) -> async_graphql::Result<i32> {
+        todo_changes::<T, H, W>(ctx)
+            .delete(id, version)
+            .await
+            .map_err(repository_error)?;
+        Ok(id)
+    }
+
+    #[graphql(guard = "WriteGuard(ApiScope::LabelsWrite)")]
+    async fn create_label(
+        &self,
+        ctx: &Context<'_>,
+        input: CreateLabelInput,
+    ) -> async_graphql::Result<Label<L>> {
+        let payload = CreateLabel {
+            name: input.name,
+            color: input.color.unwrap_or_else(|| DEFAULT_COLOR.to_string()),
+            description: input.description,
+            parent_id: input.parent_id,
+        };
+        validate(&payload)?;
+        let label = ctx
+            .data_unchecked::<LabelState<L>>()
+            .repository
+            .create(payload)
+            .await
+            .map_err(repository_error)?;
+        ctx.data_unchecked::<WebhookState<W>>()
+            .publish(WebhookEvent::LabelCreated, &label)
+            .await;
+        // 件数も返せるよう、作成したラベルを読み直す
+        let label = ctx
+            .data_unchecked::<DataLoader<LabelLoader<L>>>()
+            .load_one(label.id)
+            .await?
+            .ok_or_else(|| repository_error(RepositoryError::NotFound(label.id).into()))?;
+        Ok(Label::from(label))
+    }
+
+    // 削除したラベルの id を返す
+    #[graphql(guard = "WriteGuard(ApiScope::LabelsWrite)")]
+    async fn delete_label(&self, ctx: &Context<'_>, id: i32) -> async_graphql::Result<i32> {
+        ctx.data_unchecked::<LabelState<L>>()
+            .repository
+            .delete(id)
+            .await
+            .map_err(repository_error)?;
+        ctx.data_unchecked::<WebhookState<W>>()
+            .publish(WebhookEvent::LabelDeleted, &json!({ "id": id }))
+            .await;
+        Ok(id)
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn graphql<
+    T: TodoRepository,
+    L: LabelRepository,
+    H: HistoryRepository,
+    W: WebhookRepository,
+>(
+    State(graphql_state): State<GraphQLState<T, L, H, W>>,
+    todo_state: TodoState<T>,
+    label_state: LabelState<L>,
+    State(history_state): State<HistoryState<H>>,
+    webhook_state: WebhookState<W>,
+    user_id: UserId,
+    scopes: Option<Extension<ApiTokenScopes>>,
+    role: Option<Extension<WorkspaceRole>>,
+    Json(request): Json<async_graphql::Request>,
+) -> impl IntoResponse {
+    // ワークスペースで絞り込んだリポジトリを使うため、DataLoader はリクエストごとに作る
+    let label_loader = DataLoader::new(
+        LabelLoader {
+            repository: label_state.repository.clone(),
+        },
+        tokio::spawn,
+    );
+    let mut request = request
+        .data(todo_state)
+        .data(label_state)
+        .data(history_state)
+        .data(webhook_state)
+        .data(user_id)
+        .data(label_loader);
+    if let Some(Extension(scopes)) = scopes {
+        request = request.data(scopes);
+    }
+    if let Some(Extension(role)) = role {
+        request = request.data(role);
+    }
+    Json(graphql_state.schema.execute(request).await)
+}
+
+pub async fn graphiql() -> impl IntoResponse {
+    Html(GraphiQLSource::build().endpoint("/graphql").finish())
+}
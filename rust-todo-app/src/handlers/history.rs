@@ -0,0 +1,198 @@
+use super::{todo::TodoState, webhook::WebhookState, UserId};
+
+use axum::{
+    extract::{Path, State},
+    response::IntoResponse,
+    Json,
+};
+use hyper::StatusCode;
+use std::sync::Arc;
+
+use crate::repositories::{
+    history::{ChangeAction, ChangeRecord, HistoryRepository, NewChange},
+    todo::{TodoRepository, UpdateTodo},
+    webhook::{WebhookEvent, WebhookRepository},
+    RepositoryError,
+};
+
+#[derive(Clone)]
+pub struct HistoryState<T: HistoryRepository> {
+    pub repository: Arc<T>,
+}
+
+impl<T: HistoryRepository> HistoryState<T> {
+    // 履歴の記録に失敗しても元の操作は成功しているため、ログに残すだけにする
+    pub async fn record(&self, user_id: &str, change: NewChange) {
+        if let Err(e) = self.repository.record(user_id, change).await {
+            tracing::error!("failed to record todo history: {}", e);
+        }
+    }
+}
+
+pub async fn find_todo_history<T: TodoRepository, H: HistoryRepository>(
+    todo_state: TodoState<T>,
+    State(history_state): State<HistoryState<H>>,
+    Path(id): Path<i32>,
+) -> Result<impl IntoResponse, StatusCode> {
+    // 履歴はワークスペースを持たないため、選ばれたワークスペースの todo か確かめる
+    todo_state
+        .repository
+        .find(id)
+        .await
+        .or(Err(StatusCode::NOT_FOUND))?;
+    let records = history_state
+        .repository
+        .find_by_todo(id)
+        .await
+        .or(Err(StatusCode::INTERNAL_SERVER_ERROR))?;
+    Ok((StatusCode::OK, Json(records)))
+}
+
+pub async fn undo<T: TodoRepository, H: HistoryRepository, W: WebhookRepository>(
+    todo_state: TodoState<T>,
+    State(history_state): State<HistoryState<H>>,
+    webhook_state: WebhookState<W>,
+    UserId(user_id): UserId,
+) -> Result<impl IntoResponse, StatusCode> {
+    let change = history_state
+        .repository
+        .next_undo(&user_id)
+        .await
+        .or(Err(StatusCode::INTERNAL_SERVER_ERROR))?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let version = match revert(todo_state.repository.as_ref(), &webhook_state, &change).await {
+        Ok(version) => version,
+        Err(e) => return Err(skip(&history_state, &change, e).await),
+    };
+    let change = history_state
+        .repository
+        .set_undone(change.id, true, version)
+        .await
+        .or(Err(StatusCode::INTERNAL_SERVER_ERROR))?;
+    Ok((StatusCode::OK, Json(change)))
+}
+
+pub async fn redo<T: TodoRepository, H: HistoryRepository, W: WebhookRepository>(
+    todo_state: TodoState<T>,
+    State(history_state): State<HistoryState<H>>,
+    webhook_state: WebhookState<W>,
+    UserId(user_id): UserId,
+) -> Result<impl IntoResponse, StatusCode> {
+    let change = history_state
+        .repository
+        .next_redo(&user_id)
+        .await
+        .or(Err(StatusCode::INTERNAL_SERVER_ERROR))?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    if let Err(e) = reapply(todo_state.repository.as_ref(), &webhook_state, &change).await {
+        return Err(skip(&history_state, &change, e).await);
+    }
+    let change = history_state
+        .repository
+        .set_undone(change.id, false, None)
+        .await
+        .or(Err(StatusCode::INTERNAL_SERVER_ERROR))?;
+    Ok((StatusCode::OK, Json(change)))
+}
+
+// todo が見つからなければ 404、他の変更と食い違えば 409 とし、
+// その変更は次回から飛ばして、残りの履歴を undo / redo できるようにする
+async fn skip<H: HistoryRepository>(
+    history_state: &HistoryState<H>,
+    change: &ChangeRecord,
+    e: anyhow::Error,
+) -> StatusCode {
+    let status = match e.downcast_ref::<RepositoryError>() {
+        Some(RepositoryError::NotFound(_)) => StatusCode::NOT_FOUND,
+        Some(RepositoryError::Conflict(_)) => StatusCode::CONFLICT,
+        _ => {
+            tracing::error!("failed to revert todo history: {}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR;
+        }
+    };
+    if let Err(e) = history_state.repository.skip(change.id).await {
+        tracing::error!("failed to skip todo history: {}", e);
+        return StatusCode::INTERNAL_SERVER_ERROR;
+    }
+    status
+}
+
+// 変更前の状態に戻し、戻した後の version を返す。削除された todo はゴミ箱から復元する。
+// 変更後に他の変更が入っていれば Conflict になる。
+// 取り消しは新しい変更として履歴に残さないため TodoChanges を通さず、webhook だけ同じように送る。
+// ゴミ箱からの復元は todo.updated として送る。復元の取り消しは作成の取り消しと同じくゴミ箱へ戻す
+async fn revert<T: TodoRepository, W: WebhookRepository>(
+    repository: &T,
+    webhook_state: &WebhookState<W>,
+    change: &ChangeRecord,
+) -> anyhow::Result<Option<i32>> {
+    let version = change.after.as_ref().map(|after| after.version);
+    match (change.action, change.before.clone(), change.after.as_ref()) {
+        (ChangeAction::Create | ChangeAction::Restore, _, Some(after)) => {
+            repository.delete(change.todo_id, version).await?;
+            webhook_state
+                .publish(WebhookEvent::TodoDeleted, after)
+                .await;
+            Ok(None)
+        }
+        (ChangeAction::Update, Some(before), _) => {
+            let todo = repository
+                .update(change.todo_id, UpdateTodo::from(before), version)
+                .await?;
+            webhook_state
+                .publish(WebhookEvent::TodoUpdated, &todo)
+                .await;
+            Ok(Some(todo.version))
+        }
+        (ChangeAction::Delete, _, _) => {
+            let todo = repository.restore(change.todo_id).await?;
+            webhook_state
+                .publish(WebhookEvent::TodoUpdated, &todo)
+                .await;
+            Ok(Some(todo.version))
+        }
+        // スナップショットの無い履歴は取り消せない
+        _ => Err(RepositoryError::Conflict(change.todo_id).into()),
+    }
+}
+
+// 取り消した変更をもう一度適用する。undo の後に他の変更が入っていれば Conflict になる
+async fn reapply<T: TodoRepository, W: WebhookRepository>(
+    repository: &T,
+    webhook_state: &WebhookState<W>,
+    change: &ChangeRecord,
+) -> anyhow::Result<()> {
+    match (change.action, change.after.clone()) {
+        (ChangeAction::Create | ChangeAction::Restore, _) => {
+            let todo = repository.restore(change.todo_id).await?;
+            webhook_state
+                .publish(WebhookEvent::TodoUpdated, &todo)
+                .await;
+        }
+        (ChangeAction::Update, Some(after)) => {
+            let todo = repository
+                .update(
+                    change.todo_id,
+                    UpdateTodo::from(after),
+                    change.undone_version,
+                )
+                .await?;
+            webhook_state
+                .publish(WebhookEvent::TodoUpdated, &todo)
+                .await;
+        }
+        (ChangeAction::Delete, _) => {
+            let before = repository.find(change.todo_id).await?;
+            repository
+                .delete(change.todo_id, change.undone_version)
+                .await?;
+            webhook_state
+                .publish(WebhookEvent::TodoDeleted, &before)
+                .await;
+        }
+        (ChangeAction::Update, None) => {
+            return Err(RepositoryError::Conflict(change.todo_id).into())
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,199 @@
+use super::{workspace::CurrentWorkspace, UserId};
+
+use axum::{
+    async_trait,
+    body::{boxed, Full},
+    extract::{FromRef, FromRequestParts},
+    response::{IntoResponse, Response},
+};
+use hyper::{
+    header::{self, HeaderName, HeaderValue},
+    http::request::Parts,
+    StatusCode,
+};
+use serde::Serialize;
+use std::{future::Future, sync::Arc};
+
+use crate::repositories::idempotency::{IdempotencyRepository, IdempotentResponse, Reservation};
+
+pub const IDEMPOTENCY_KEY: HeaderName = HeaderName::from_static("idempotency-key");
+
+#[derive(Clone)]
+pub struct IdempotencyState<T: IdempotencyRepository> {
+    pub repository: Arc<T>,
+}
+
+// `Idempotency-Key` ヘッダが指定されたリクエストの初回レスポンスを保存し、再送時に再生する
+pub struct Idempotency<T: IdempotencyRepository> {
+    repository: Arc<T>,
+    scope: String,
+    key: Option<String>,
+}
+
+#[async_trait]
+impl<S, T> FromRequestParts<S> for Idempotency<T>
+where
+    T: IdempotencyRepository,
+    IdempotencyState<T>: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = (StatusCode, String);
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let key = match parts.headers.get(IDEMPOTENCY_KEY) {
+            Some(value) => {
+                let key = value
+                    .to_str()
+                    .ok()
+                    .filter(|key| !key.is_empty() && key.len() <= 255)
+                    .ok_or_else(|| {
+                        let message =
+                            "Idempotency-Key error: [must be 1 to 255 visible ASCII characters]";
+                        (StatusCode::BAD_REQUEST, message.to_string())
+                    })?;
+                Some(key.to_string())
+            }
+            None => None,
+        };
+        // 同じパスでもユーザーやワークスペースが違えば別のリクエストとして扱い、
+        // 他のユーザーが同じキーを使っても保存したレスポンスを返さない
+        let UserId(user_id) = UserId::from_request_parts(parts, state).await?;
+        let scope = match parts.extensions.get::<CurrentWorkspace>() {
+            Some(CurrentWorkspace(Some(workspace_id))) => {
+                format!("{}:{}@{}", user_id, parts.uri.path(), workspace_id)
+            }
+            _ => format!("{}:{}", user_id, parts.uri.path()),
+        };
+        Ok(Idempotency {
+            repository: IdempotencyState::<T>::from_ref(state).repository,
+            scope,
+            key,
+        })
+    }
+}
+
+impl<T: IdempotencyRepository> Idempotency<T> {
+    pub async fn run<P, F, Fut, R>(self, payload: P, handler: F) -> Response
+    where
+        P: Serialize,
+        F: FnOnce(P) -> Fut,
+        Fut: Future<Output = R>,
+        R: IntoResponse,
+    {
+        let Some(key) = self.key else {
+            return handler(payload).await.into_response();
+        };
+
+        let request_body = match serde_json::to_vec(&payload) {
+            Ok(body) => body,
+            Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+        };
+        // 先にキーを予約し、同じキーのリクエストを同時に処理しないようにする
+        match self
+            .repository
+            .reserve(&self.scope, &key, &request_body)
+            .await
+        {
+            Ok(Reservation::Reserved) => {}
+            Ok(Reservation::Completed(saved)) if saved.request_body == request_body => {
+                return replay(saved)
+            }
+            Ok(Reservation::InProgress {
+                request_body: reserved,
+            }) if reserved == request_body => {
+                let message = "Idempotency-Key error: [a request with this key is in progress]";
+                return (StatusCode::CONFLICT, message).into_response();
+            }
+            Ok(_) => {
+                let message = "Idempotency-Key error: [already used with a different body]";
+                return (StatusCode::UNPROCESSABLE_ENTITY, message).into_response();
+            }
+            Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+        }
+
+        let mut reservation = ReservationGuard {
+            repository: self.repository.clone(),
+            scope: self.scope.clone(),
+            key: key.clone(),
+            completed: false,
+        };
+        let response = handler(payload).await.into_response();
+        // 失敗したレスポンスは保存せず、予約を解放して再送で再実行できるようにする
+        if !response.status().is_success() {
+            return response;
+        }
+        let (parts, body) = response.into_parts();
+        let response_body = match hyper::body::to_bytes(body).await {
+            Ok(bytes) => bytes,
+            Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+        };
+        let response_headers = parts
+            .headers
+            .iter()
+            .filter(|(name, _)| *name != header::CONTENT_LENGTH)
+            .filter_map(|(name, value)| {
+                let value = value.to_str().ok()?;
+                Some((name.to_string(), value.to_string()))
+            })
+            .collect();
+        let response = IdempotentResponse {
+            request_body,
+            status_code: parts.status.as_u16() as i16,
+            response_headers,
+            response_body: response_body.to_vec(),
+        };
+        match self
+            .repository
+            .complete(&self.scope, &key, response.clone())
+            .await
+        {
+            Ok(saved) => {
+                reservation.completed = true;
+                // 予約が期限切れになり別のリクエストが先に保存していれば、そちらを再生する
+                if saved != response {
+                    return replay(saved);
+                }
+            }
+            // 処理自体は終わっているので、保存に失敗してもレスポンスは返す
+            Err(e) => tracing::error!("failed to save idempotent response: {}", e),
+        }
+        Response::from_parts(parts, boxed(Full::from(response_body)))
+    }
+}
+
+// 処理が失敗したりクライアントが切断して途中で破棄されたりしたときに、予約を解放する
+struct ReservationGuard<T: IdempotencyRepository> {
+    repository: Arc<T>,
+    scope: String,
+    key: String,
+    completed: bool,
+}
+
+impl<T: IdempotencyRepository> Drop for ReservationGuard<T> {
+    fn drop(&mut self) {
+        if self.completed {
+            return;
+        }
+        let repository = self.repository.clone();
+        let scope = std::mem::take(&mut self.scope);
+        let key = std::mem::take(&mut self.key);
+        tokio::spawn(async move {
+            if let Err(e) = repository.release(&scope, &key).await {
+                tracing::error!("failed to release idempotency key: {}", e);
+            }
+        });
+    }
+}
+
+fn replay(saved: IdempotentResponse) -> Response {
+    let status = StatusCode::from_u16(saved.status_code as u16).unwrap_or(StatusCode::OK);
+    let mut response = Response::new(boxed(Full::from(saved.response_body)));
+    *response.status_mut() = status;
+    let headers = response.headers_mut();
+    for (name, value) in saved.response_headers {
+        if let (Ok(name), Ok(value)) = (HeaderName::try_from(name), HeaderValue::try_from(value)) {
+            headers.append(name, value);
+        }
+    }
+    response
+}
@@ -1,47 +1,230 @@
-use super::ValidatedJson;
+use super::{
+    idempotency::Idempotency, todo::TodoState, webhook::WebhookState, workspace::CurrentWorkspace,
+    Pagination, ValidatedJson, ValidatedQuery,
+};
 
 use axum::{
-    extract::{Path, State},
+    async_trait,
+    extract::{FromRef, FromRequestParts, Path},
     response::IntoResponse,
     Json,
 };
-use hyper::StatusCode;
-use std::sync::Arc;
+use hyper::{http::request::Parts, StatusCode};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::{convert::Infallible, sync::Arc};
 
-use crate::repositories::label::{CreateLabel, LabelRepository};
+use crate::repositories::{
+    idempotency::IdempotencyRepository,
+    label::{build_tree, CreateLabel, Label, LabelRepository, MergeLabel, MoveLabel},
+    todo::{TodoEntity, TodoRepository},
+    webhook::{WebhookEvent, WebhookRepository},
+    RepositoryError, Scope,
+};
 
 #[derive(Clone)]
 pub struct LabelState<T: LabelRepository> {
     pub repository: Arc<T>,
 }
 
-pub async fn create_label<T: LabelRepository>(
-    State(label_state): State<LabelState<T>>,
+// 認可レイヤーが選んだワークスペースに絞り込んだリポジトリを渡す
+#[async_trait]
+impl<S, T> FromRequestParts<S> for LabelState<T>
+where
+    T: LabelRepository,
+    LabelState<T>: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let CurrentWorkspace(workspace_id) = parts.extensions.get().copied().unwrap_or_default();
+        let repository = LabelState::<T>::from_ref(state)
+            .repository
+            .scoped(Scope::new(workspace_id));
+        Ok(LabelState {
+            repository: Arc::new(repository),
+        })
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct LabelTodos {
+    pub label: Label,
+    pub todos: Vec<TodoEntity>,
+    pub page: i64,
+    pub per_page: i64,
+    pub total: i64,
+}
+
+pub async fn create_label<T: LabelRepository, I: IdempotencyRepository, W: WebhookRepository>(
+    label_state: LabelState<T>,
+    webhook_state: WebhookState<W>,
+    idempotency: Idempotency<I>,
     ValidatedJson(payload): ValidatedJson<CreateLabel>,
-) -> Result<impl IntoResponse, StatusCode> {
-    let label = label_state
-        .repository
-        .create(payload.name)
+) -> impl IntoResponse {
+    idempotency
+        .run(payload, |payload| async move {
+            let label = label_state
+                .repository
+                .create(payload)
+                .await
+                .or(Err(StatusCode::NOT_FOUND))?;
+            webhook_state
+                .publish(WebhookEvent::LabelCreated, &label)
+                .await;
+            Ok::<_, StatusCode>((StatusCode::CREATED, Json(label)))
+        })
         .await
-        .or(Err(StatusCode::NOT_FOUND))?;
-    Ok((StatusCode::CREATED, Json(label)))
 }
 
 pub async fn all_label<T: LabelRepository>(
-    State(label_state): State<LabelState<T>>,
+    label_state: LabelState<T>,
 ) -> Result<impl IntoResponse, StatusCode> {
     let labels = label_state.repository.all().await.unwrap();
     Ok((StatusCode::OK, Json(labels)))
 }
 
-pub async fn delete_label<T: LabelRepository>(
-    State(label_state): State<LabelState<T>>,
+pub async fn find_label<T: LabelRepository>(
+    label_state: LabelState<T>,
     Path(id): Path<i32>,
-) -> StatusCode {
-    label_state
+) -> Result<impl IntoResponse, StatusCode> {
+    let label = label_state.repository.find(id).await.map_err(|e| {
+        match e.downcast_ref::<RepositoryError>() {
+            Some(RepositoryError::NotFound(_)) => StatusCode::NOT_FOUND,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    })?;
+    Ok((StatusCode::OK, Json(label)))
+}
+
+pub async fn find_label_todos<T: LabelRepository, U: TodoRepository>(
+    label_state: LabelState<T>,
+    todo_state: TodoState<U>,
+    Path(id): Path<i32>,
+    ValidatedQuery(pagination): ValidatedQuery<Pagination>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let label = label_state.repository.find(id).await.map_err(|e| {
+        match e.downcast_ref::<RepositoryError>() {
+            Some(RepositoryError::NotFound(_)) => StatusCode::NOT_FOUND,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    })?;
+    let page = todo_state
+        .repository
+        .page_by_label(id, pagination.per_page, pagination.offset())
+        .await
+        .or(Err(StatusCode::INTERNAL_SERVER_ERROR))?;
+    Ok((
+        StatusCode::OK,
+        Json(LabelTodos {
+            label,
+            todos: page.todos,
+            page: pagination.page,
+            per_page: pagination.per_page,
+            total: page.total,
+        }),
+    ))
+}
+
+pub async fn label_tree<T: LabelRepository>(
+    label_state: LabelState<T>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let labels = label_state
         .repository
-        .delete(id)
+        .all()
         .await
-        .map(|_| StatusCode::NO_CONTENT)
-        .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR)
+        .or(Err(StatusCode::INTERNAL_SERVER_ERROR))?;
+    Ok((StatusCode::OK, Json(build_tree(labels))))
+}
+
+pub async fn move_label<T: LabelRepository, W: WebhookRepository>(
+    label_state: LabelState<T>,
+    webhook_state: WebhookState<W>,
+    Path(id): Path<i32>,
+    ValidatedJson(payload): ValidatedJson<MoveLabel>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let label = label_state
+        .repository
+        .move_to(id, payload.parent_id)
+        .await
+        .map_err(|e| match e.downcast_ref::<RepositoryError>() {
+            Some(RepositoryError::LabelCycle(_)) => StatusCode::UNPROCESSABLE_ENTITY,
+            _ => StatusCode::NOT_FOUND,
+        })?;
+    webhook_state
+        .publish(WebhookEvent::LabelUpdated, &label)
+        .await;
+    Ok((StatusCode::OK, Json(label)))
+}
+
+pub async fn merge_label<T: LabelRepository, U: TodoRepository, W: WebhookRepository>(
+    label_state: LabelState<T>,
+    todo_state: TodoState<U>,
+    webhook_state: WebhookState<W>,
+    Path(id): Path<i32>,
+    ValidatedJson(payload): ValidatedJson<MergeLabel>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let merged = label_state
+        .repository
+        .merge(id, payload.target_id)
+        .await
+        .map_err(|e| match e.downcast_ref::<RepositoryError>() {
+            Some(RepositoryError::NotFound(_)) => StatusCode::NOT_FOUND,
+            Some(RepositoryError::LabelCycle(_)) => StatusCode::UNPROCESSABLE_ENTITY,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        })?;
+    webhook_state
+        .publish(
+            WebhookEvent::LabelDeleted,
+            &json!({ "id": id, "merged_into": merged.label.id }),
+        )
+        .await;
+    // 統合元を引き継いだ統合先と、親が変わった子ラベル
+    for label in std::iter::once(&merged.label).chain(&merged.children) {
+        webhook_state
+            .publish(WebhookEvent::LabelUpdated, label)
+            .await;
+    }
+    // ラベルが付け替わった todo。ゴミ箱の todo は通知しない
+    for todo_id in merged.todo_ids {
+        if let Ok(todo) = todo_state.repository.find(todo_id).await {
+            webhook_state
+                .publish(WebhookEvent::TodoUpdated, &todo)
+                .await;
+        }
+    }
+    Ok((StatusCode::OK, Json(merged.label)))
+}
+
+pub async fn delete_label<T: LabelRepository, W: WebhookRepository>(
+    label_state: LabelState<T>,
+    webhook_state: WebhookState<W>,
+    Path(id): Path<i32>,
+) -> StatusCode {
+    match label_state.repository.delete(id).await {
+        Ok(_) => {
+            webhook_state
+                .publish(WebhookEvent::LabelDeleted, &json!({ "id": id }))
+                .await;
+            StatusCode::NO_CONTENT
+        }
+        Err(e) => match e.downcast_ref::<RepositoryError>() {
+            Some(RepositoryError::NotFound(_)) => StatusCode::NOT_FOUND,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        },
+    }
+}
+
+pub async fn restore_label<T: LabelRepository>(
+    label_state: LabelState<T>,
+    Path(id): Path<i32>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let label = label_state.repository.restore(id).await.map_err(|e| {
+        match e.downcast_ref::<RepositoryError>() {
+            Some(RepositoryError::Duplicate(_)) => StatusCode::CONFLICT,
+            _ => StatusCode::NOT_FOUND,
+        }
+    })?;
+    Ok((StatusCode::OK, Json(label)))
 }
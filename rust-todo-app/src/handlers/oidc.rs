@@ -0,0 +1,67 @@
+use super::{api_token::AuthenticatedUser, bearer_token};
+
+use axum::{
+    async_trait,
+    extract::{FromRef, FromRequestParts},
+};
+use hyper::{http::request::Parts, StatusCode};
+use std::{marker::PhantomData, sync::Arc};
+
+use crate::{
+    oidc::JwtVerifier,
+    repositories::{api_token::is_api_token, user::UserRepository},
+};
+
+#[derive(Clone)]
+pub struct OidcState<T: UserRepository> {
+    pub repository: Arc<T>,
+    // None の場合は JWT による認証を無効にする
+    pub verifier: Option<JwtVerifier>,
+}
+
+// API トークン以外の bearer トークンを JWT として検証し、
+// 初めて見たユーザーはローカルのユーザーとして登録する。
+// JWT による認証が有効な場合は、JWT も API トークンも持たないリクエストを拒否する
+pub struct JwtAuth<T: UserRepository>(PhantomData<T>);
+
+#[async_trait]
+impl<S, T> FromRequestParts<S> for JwtAuth<T>
+where
+    T: UserRepository,
+    OidcState<T>: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = (StatusCode, String);
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let oidc_state = OidcState::<T>::from_ref(state);
+        let token = match bearer_token(parts)? {
+            Some(token) if !is_api_token(&token) => token,
+            // API トークンは ApiTokenAuth が検証し、無効なら 401 にする
+            Some(_) => return Ok(JwtAuth(PhantomData)),
+            None if oidc_state.verifier.is_some() => {
+                let message = "Token error: [authentication is required]";
+                return Err((StatusCode::UNAUTHORIZED, message.to_string()));
+            }
+            None => return Ok(JwtAuth(PhantomData)),
+        };
+
+        let verifier = oidc_state.verifier.as_ref().ok_or_else(|| {
+            let message = "Token error: [JWT authentication is not configured]";
+            (StatusCode::UNAUTHORIZED, message.to_string())
+        })?;
+        let identity = verifier.verify(&token).await.map_err(|e| {
+            tracing::debug!("rejected JWT: {}", e);
+            let message = "Token error: [invalid JWT]";
+            (StatusCode::UNAUTHORIZED, message.to_string())
+        })?;
+        let user = oidc_state
+            .repository
+            .upsert(identity)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+        parts.extensions.insert(AuthenticatedUser(user.user_id));
+        Ok(JwtAuth(PhantomData))
+    }
+}
@@ -0,0 +1,167 @@
+use super::api_token::AuthenticatedUser;
+
+use axum::{
+    extract::{ConnectInfo, State},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use hyper::{header::RETRY_AFTER, Request, StatusCode};
+use serde_json::json;
+use std::{
+    collections::HashMap,
+    env,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimitConfig {
+    // 連続して受け付けられるリクエスト数
+    pub burst: u32,
+    // 1 秒あたりに回復するリクエスト数
+    pub per_second: f64,
+}
+
+impl RateLimitConfig {
+    // RATE_LIMIT_PER_SEC に 0 を設定するとレート制限を無効にする
+    pub fn from_env() -> anyhow::Result<Option<Self>> {
+        let per_second: f64 = match env::var("RATE_LIMIT_PER_SEC") {
+            Ok(value) => value.parse()?,
+            Err(_) => 10.0,
+        };
+        let burst: u32 = match env::var("RATE_LIMIT_BURST") {
+            Ok(value) => value.parse()?,
+            Err(_) => 60,
+        };
+        if per_second <= 0.0 {
+            return Ok(None);
+        }
+        anyhow::ensure!(burst >= 1, "RATE_LIMIT_BURST must be at least 1");
+        Ok(Some(Self { burst, per_second }))
+    }
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+// クライアントごとのトークンバケット
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    buckets: Arc<Mutex<HashMap<String, Bucket>>>,
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig) -> Self {
+        Self {
+            config,
+            buckets: Arc::default(),
+        }
+    }
+
+    // 受け付けられない場合は、次のリクエストを受け付けられるまでの時間を返す
+    pub(crate) fn acquire(&self, client: &str) -> Result<(), Duration> {
+        self.take(client, 1.0)
+    }
+
+    // トークンを減らさずに、受け付けられるかだけを確かめる
+    fn check(&self, client: &str) -> Result<(), Duration> {
+        self.take(client, 0.0)
+    }
+
+    fn take(&self, client: &str, cost: f64) -> Result<(), Duration> {
+        let RateLimitConfig { burst, per_second } = self.config;
+        let burst = burst as f64;
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+        let bucket = buckets.entry(client.to_string()).or_insert(Bucket {
+            tokens: burst,
+            updated_at: now,
+        });
+        let elapsed = now.duration_since(bucket.updated_at).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * per_second).min(burst);
+        bucket.updated_at = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= cost;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / per_second))
+        }
+    }
+
+    // 満タンに戻ったクライアントのバケットを捨て、捨てた数を返す。
+    // 満タンのバケットは作り直しても同じなので、捨てても制限は変わらない
+    pub fn sweep(&self) -> usize {
+        let RateLimitConfig { burst, per_second } = self.config;
+        let burst = burst as f64;
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+        let before = buckets.len();
+        buckets.retain(|_, bucket| {
+            let elapsed = now.duration_since(bucket.updated_at).as_secs_f64();
+            bucket.tokens + elapsed * per_second < burst
+        });
+        before - buckets.len()
+    }
+}
+
+fn ip<B>(req: &Request<B>) -> String {
+    match req.extensions().get::<ConnectInfo<SocketAddr>>() {
+        Some(ConnectInfo(addr)) => addr.ip().to_string(),
+        None => "unknown".to_string(),
+    }
+}
+
+fn too_many_requests(retry_after: Duration) -> Response {
+    // Retry-After は秒単位なので切り上げる
+    let secs = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+    let message = format!("Rate limit error: [retry after {} seconds]", secs);
+    (
+        StatusCode::TOO_MANY_REQUESTS,
+        [(RETRY_AFTER, secs.to_string())],
+        Json(json!({ "error": message })),
+    )
+        .into_response()
+}
+
+// 認証の後に実行し、認証されたユーザーごと、認証されていなければ接続元の IP ごとに数える。
+// 検証前のトークンで数えると、トークンを変えるだけで制限を逃れられてしまう
+pub async fn rate_limit<B>(
+    State(rate_limiter): State<RateLimiter>,
+    req: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let client = match req.extensions().get::<AuthenticatedUser>() {
+        Some(AuthenticatedUser(user_id)) => format!("user:{}", user_id),
+        None => format!("ip:{}", ip(&req)),
+    };
+    match rate_limiter.acquire(&client) {
+        Ok(()) => next.run(req).await,
+        Err(retry_after) => too_many_requests(retry_after),
+    }
+}
+
+// 認証の前に実行し、認証に失敗したリクエストを接続元の IP ごとに数える。
+// 使い切った IP からのリクエストはトークンを検証せずに断り、総当たりを防ぐ
+pub async fn limit_failed_authentication<B>(
+    State(rate_limiter): State<RateLimiter>,
+    req: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let client = format!("auth:{}", ip(&req));
+    if let Err(retry_after) = rate_limiter.check(&client) {
+        return too_many_requests(retry_after);
+    }
+    let res = next.run(req).await;
+    if res.status() == StatusCode::UNAUTHORIZED {
+        // 既に使い切っていても次の check で断られるため、結果は見ない
+        let _ = rate_limiter.acquire(&client);
+    }
+    res
+}
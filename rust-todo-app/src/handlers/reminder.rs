@@ -0,0 +1,116 @@
+use super::{todo::TodoState, ValidatedJson};
+
+use axum::{
+    extract::{Path, State},
+    response::IntoResponse,
+    Json,
+};
+use chrono::{Duration, Utc};
+use hyper::StatusCode;
+use std::sync::Arc;
+
+use crate::repositories::{
+    reminder::{CreateReminder, Reminder, ReminderChannel, ReminderRepository, SnoozeReminder},
+    todo::TodoRepository,
+};
+
+#[derive(Clone)]
+pub struct ReminderState<T: ReminderRepository> {
+    pub repository: Arc<T>,
+    // 通知先が設定されているチャネル。それ以外のチャネルのリマインダーは作れない
+    pub channels: Vec<ReminderChannel>,
+}
+
+// リマインダーはワークスペースを持たないため、対象の todo が選ばれたワークスペースにあるかで確かめる
+async fn find_scoped_reminder<T: TodoRepository, R: ReminderRepository>(
+    todo_state: &TodoState<T>,
+    reminder_state: &ReminderState<R>,
+    id: i32,
+) -> Result<Reminder, StatusCode> {
+    let reminder = reminder_state
+        .repository
+        .find(id)
+        .await
+        .or(Err(StatusCode::NOT_FOUND))?;
+    todo_state
+        .repository
+        .find(reminder.todo_id)
+        .await
+        .or(Err(StatusCode::NOT_FOUND))?;
+    Ok(reminder)
+}
+
+pub async fn create_reminder<T: TodoRepository, R: ReminderRepository>(
+    todo_state: TodoState<T>,
+    State(reminder_state): State<ReminderState<R>>,
+    Path(todo_id): Path<i32>,
+    ValidatedJson(payload): ValidatedJson<CreateReminder>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    // 通知できないリマインダーは期限が来ても送られず、ずっと残ってしまう
+    if !reminder_state.channels.contains(&payload.channel) {
+        let message = format!(
+            "Reminder error: [channel {} is not configured]",
+            payload.channel.as_str()
+        );
+        return Err((StatusCode::UNPROCESSABLE_ENTITY, message));
+    }
+    todo_state
+        .repository
+        .find(todo_id)
+        .await
+        .map_err(|e| (StatusCode::NOT_FOUND, e.to_string()))?;
+    let reminder = reminder_state
+        .repository
+        .create(todo_id, payload)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok((StatusCode::CREATED, Json(reminder)))
+}
+
+pub async fn find_todo_reminders<T: TodoRepository, R: ReminderRepository>(
+    todo_state: TodoState<T>,
+    State(reminder_state): State<ReminderState<R>>,
+    Path(todo_id): Path<i32>,
+) -> Result<impl IntoResponse, StatusCode> {
+    todo_state
+        .repository
+        .find(todo_id)
+        .await
+        .or(Err(StatusCode::NOT_FOUND))?;
+    let reminders = reminder_state
+        .repository
+        .find_by_todo(todo_id)
+        .await
+        .or(Err(StatusCode::INTERNAL_SERVER_ERROR))?;
+    Ok((StatusCode::OK, Json(reminders)))
+}
+
+pub async fn snooze_reminder<T: TodoRepository, R: ReminderRepository>(
+    todo_state: TodoState<T>,
+    State(reminder_state): State<ReminderState<R>>,
+    Path(id): Path<i32>,
+    ValidatedJson(payload): ValidatedJson<SnoozeReminder>,
+) -> Result<impl IntoResponse, StatusCode> {
+    find_scoped_reminder(&todo_state, &reminder_state, id).await?;
+    let fire_at = Utc::now() + Duration::minutes(payload.minutes);
+    let reminder = reminder_state
+        .repository
+        .snooze(id, fire_at)
+        .await
+        .or(Err(StatusCode::NOT_FOUND))?;
+    Ok((StatusCode::OK, Json(reminder)))
+}
+
+pub async fn dismiss_reminder<T: TodoRepository, R: ReminderRepository>(
+    todo_state: TodoState<T>,
+    State(reminder_state): State<ReminderState<R>>,
+    Path(id): Path<i32>,
+) -> Result<impl IntoResponse, StatusCode> {
+    find_scoped_reminder(&todo_state, &reminder_state, id).await?;
+    let reminder = reminder_state
+        .repository
+        .dismiss(id)
+        .await
+        .or(Err(StatusCode::NOT_FOUND))?;
+    Ok((StatusCode::OK, Json(reminder)))
+}
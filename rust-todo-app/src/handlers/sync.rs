@@ -0,0 +1,325 @@
+use axum::{extract::State, response::IntoResponse, Json};
+use hyper::StatusCode;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use validator::Validate;
+
+use super::{
+    history::HistoryState,
+    label::LabelState,
+    todo::{TodoChanges, TodoState},
+    webhook::WebhookState,
+    UserId, ValidatedJson, ValidatedQuery,
+};
+use crate::repositories::{
+    history::HistoryRepository,
+    label::{CreateLabel, Label, LabelRepository},
+    sync::Changes,
+    todo::{CreateTodo, TodoEntity, TodoRepository, UpdateTodo},
+    webhook::{WebhookEvent, WebhookRepository},
+    RepositoryError,
+};
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct SyncQuery {
+    #[serde(default)]
+    #[validate(range(min = 0, message = "Must be 0 or greater"))]
+    since: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct SyncChanges {
+    pub cursor: i64,
+    pub todos: Changes<TodoEntity>,
+    pub labels: Changes<Label>,
+}
+
+// クライアントがオフラインの間に行った変更。
+// version を指定すればサーバーの version と一致する場合のみ反映し、
+// 指定しなければ後から届いた変更で上書きする (last-writer-wins)
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum ClientChange {
+    CreateTodo {
+        client_id: Option<String>,
+        todo: CreateTodo,
+    },
+    UpdateTodo {
+        id: i32,
+        version: Option<i32>,
+        todo: UpdateTodo,
+    },
+    DeleteTodo {
+        id: i32,
+        version: Option<i32>,
+    },
+    CreateLabel {
+        client_id: Option<String>,
+        label: CreateLabel,
+    },
+    DeleteLabel {
+        id: i32,
+    },
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct PushChanges {
+    #[validate(length(max = 100, message = "Can not push more than 100 changes at once"))]
+    pub changes: Vec<ClientChange>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SyncStatus {
+    Applied,
+    // サーバー側の最新の状態を todo / label に入れて返す。削除されていれば空になる
+    Conflict,
+    Rejected,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct SyncResult {
+    pub status: SyncStatus,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub todo: Option<TodoEntity>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<Label>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+impl SyncResult {
+    fn new(status: SyncStatus) -> Self {
+        Self {
+            status,
+            client_id: None,
+            todo: None,
+            label: None,
+            message: None,
+        }
+    }
+
+    fn todo(status: SyncStatus, todo: Option<TodoEntity>) -> Self {
+        Self {
+            todo,
+            ..Self::new(status)
+        }
+    }
+
+    fn label(status: SyncStatus, label: Option<Label>) -> Self {
+        Self {
+            label,
+            ..Self::new(status)
+        }
+    }
+
+    fn rejected(message: String) -> Self {
+        Self {
+            message: Some(message),
+            ..Self::new(SyncStatus::Rejected)
+        }
+    }
+
+    fn error(e: anyhow::Error) -> Self {
+        tracing::error!("failed to apply client change: {}", e);
+        Self::rejected("Internal error".to_string())
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct PushResults {
+    pub results: Vec<SyncResult>,
+}
+
+pub async fn pull_changes<T: TodoRepository, L: LabelRepository>(
+    todo_state: TodoState<T>,
+    label_state: LabelState<L>,
+    ValidatedQuery(query): ValidatedQuery<SyncQuery>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let todos = todo_state
+        .repository
+        .changes(query.since)
+        .await
+        .or(Err(StatusCode::INTERNAL_SERVER_ERROR))?;
+    let labels = label_state
+        .repository
+        .changes(query.since)
+        .await
+        .or(Err(StatusCode::INTERNAL_SERVER_ERROR))?;
+    // 先に読んだ方のカーソルより後の変更は取りこぼしている可能性があるため、小さい方を返す。
+    // 次回は重複して返ることがあるが、クライアントは upsert するだけでよい
+    let cursor = todos.cursor.min(labels.cursor);
+    Ok((
+        StatusCode::OK,
+        Json(SyncChanges {
+            cursor,
+            todos,
+            labels,
+        }),
+    ))
+}
+
+// 変更は送られた順に一つずつ反映し、反映できなかったものは結果で知らせる
+pub async fn push_changes<
+    T: TodoRepository,
+    L: LabelRepository,
+    H: HistoryRepository,
+    W: WebhookRepository,
+>(
+    todo_state: TodoState<T>,
+    label_state: LabelState<L>,
+    State(history_state): State<HistoryState<H>>,
+    webhook_state: WebhookState<W>,
+    UserId(user_id): UserId,
+    ValidatedJson(payload): ValidatedJson<PushChanges>,
+) -> impl IntoResponse {
+    let todos = TodoChanges {
+        repository: todo_state.repository.as_ref(),
+        history_state: &history_state,
+        webhook_state: &webhook_state,
+        user_id: &user_id,
+    };
+    let labels = label_state.repository.as_ref();
+    let mut results = vec![];
+    for change in payload.changes {
+        let result = match change {
+            ClientChange::CreateTodo { client_id, todo } => SyncResult {
+                client_id,
+                ..create_todo(&todos, todo).await
+            },
+            ClientChange::UpdateTodo { id, version, todo } => {
+                update_todo(&todos, id, version, todo).await
+            }
+            ClientChange::DeleteTodo { id, version } => delete_todo(&todos, id, version).await,
+            ClientChange::CreateLabel { client_id, label } => SyncResult {
+                client_id,
+                ..create_label(labels, &webhook_state, label).await
+            },
+            ClientChange::DeleteLabel { id } => delete_label(labels, &webhook_state, id).await,
+        };
+        results.push(result);
+    }
+    (StatusCode::OK, Json(PushResults { results }))
+}
+
+fn validation_error(payload: &impl Validate) -> Option<SyncResult> {
+    payload
+        .validate()
+        .err()
+        .map(|err| SyncResult::rejected(format!("Validation error: [{}]", err)))
+}
+
+async fn create_todo<T: TodoRepository, H: HistoryRepository, W: WebhookRepository>(
+    todos: &TodoChanges<'_, T, H, W>,
+    payload: CreateTodo,
+) -> SyncResult {
+    if let Some(rejected) = validation_error(&payload) {
+        return rejected;
+    }
+    let todo = match todos.create(payload).await {
+        Ok(todo) => todo,
+        Err(e) => return SyncResult::error(e),
+    };
+    SyncResult::todo(SyncStatus::Applied, Some(todo))
+}
+
+async fn update_todo<T: TodoRepository, H: HistoryRepository, W: WebhookRepository>(
+    todos: &TodoChanges<'_, T, H, W>,
+    id: i32,
+    version: Option<i32>,
+    payload: UpdateTodo,
+) -> SyncResult {
+    if let Some(rejected) = validation_error(&payload) {
+        return rejected;
+    }
+    // オフラインの間にサーバー側で削除されていれば、削除されたことを知らせる
+    let repository = todos.repository;
+    let before = match repository.find(id).await {
+        Ok(todo) => todo,
+        Err(_) => return SyncResult::todo(SyncStatus::Conflict, None),
+    };
+    // 未完了の blocker が残っている todo は完了にできない
+    if payload.completed == Some(true) && !before.completed && before.blocked {
+        return SyncResult::rejected(format!("Todo error: [todo {} is blocked]", id));
+    }
+    let todo = match todos.update(before, payload, version).await {
+        Ok(todo) => todo,
+        Err(e) => {
+            return match e.downcast_ref::<RepositoryError>() {
+                Some(RepositoryError::Conflict(_)) | Some(RepositoryError::NotFound(_)) => {
+                    SyncResult::todo(SyncStatus::Conflict, repository.find(id).await.ok())
+                }
+                _ => SyncResult::error(e),
+            }
+        }
+    };
+    SyncResult::todo(SyncStatus::Applied, Some(todo))
+}
+
+async fn delete_todo<T: TodoRepository, H: HistoryRepository, W: WebhookRepository>(
+    todos: &TodoChanges<'_, T, H, W>,
+    id: i32,
+    version: Option<i32>,
+) -> SyncResult {
+    if let Err(e) = todos.delete(id, version).await {
+        return match e.downcast_ref::<RepositoryError>() {
+            Some(RepositoryError::Conflict(_)) => {
+                SyncResult::todo(SyncStatus::Conflict, todos.repository.find(id).await.ok())
+            }
+            // 既に削除されていれば、クライアントの望む状態になっているので反映済みとする
+            Some(RepositoryError::NotFound(_)) => SyncResult::new(SyncStatus::Applied),
+            _ => SyncResult::error(e),
+        };
+    }
+    SyncResult::new(SyncStatus::Applied)
+}
+
+async fn create_label<L: LabelRepository, W: WebhookRepository>(
+    repository: &L,
+    webhook_state: &WebhookState<W>,
+    payload: CreateLabel,
+) -> SyncResult {
+    if let Some(rejected) = validation_error(&payload) {
+        return rejected;
+    }
+    let label = match repository.create(payload).await {
+        Ok(label) => label,
+        // 同じ名前のラベルが既にあれば、そのラベルを返してクライアントに統合してもらう
+        Err(e) => {
+            return match e.downcast_ref::<RepositoryError>() {
+                Some(RepositoryError::Duplicate(id)) => {
+                    SyncResult::label(SyncStatus::Conflict, repository.find(*id).await.ok())
+                }
+                Some(RepositoryError::NotFound(id)) => {
+                    SyncResult::rejected(format!("Label error: [label {} is not found]", id))
+                }
+                _ => SyncResult::error(e),
+            }
+        }
+    };
+    webhook_state
+        .publish(WebhookEvent::LabelCreated, &label)
+        .await;
+    SyncResult::label(SyncStatus::Applied, Some(label))
+}
+
+async fn delete_label<L: LabelRepository, W: WebhookRepository>(
+    repository: &L,
+    webhook_state: &WebhookState<W>,
+    id: i32,
+) -> SyncResult {
+    match repository.delete(id).await {
+        Ok(_) => {
+            webhook_state
+                .publish(WebhookEvent::LabelDeleted, &json!({ "id": id }))
+                .await;
+            SyncResult::new(SyncStatus::Applied)
+        }
+        Err(e) => match e.downcast_ref::<RepositoryError>() {
+            Some(RepositoryError::NotFound(_)) => SyncResult::new(SyncStatus::Applied),
+            _ => SyncResult::error(e),
+        },
+    }
+}
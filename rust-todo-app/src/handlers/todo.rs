@@ -1,34 +1,165 @@
-use super::ValidatedJson;
+use super::{
+    etag, history::HistoryState, idempotency::Idempotency, webhook::WebhookState,
+    workspace::CurrentWorkspace, IfMatch, Patch, Patchable, UserId, ValidatedJson,
+};
 
 use axum::{
-    extract::{Path, State},
+    async_trait,
+    extract::{FromRef, FromRequestParts, Path, Query, State},
     response::IntoResponse,
     Json,
 };
-use hyper::StatusCode;
-use std::sync::Arc;
+use hyper::{header, http::request::Parts, StatusCode};
+use serde::Deserialize;
+use std::{convert::Infallible, sync::Arc};
 
-use crate::repositories::todo::{CreateTodo, TodoRepository, UpdateTodo};
+use crate::repositories::{
+    history::{HistoryRepository, NewChange},
+    idempotency::IdempotencyRepository,
+    todo::{AddBlocker, CreateTodo, TodoEntity, TodoRepository, UpdateTodo},
+    webhook::{WebhookEvent, WebhookRepository},
+    RepositoryError, Scope,
+};
 
 #[derive(Clone)]
 pub struct TodoState<T: TodoRepository> {
     pub repository: Arc<T>,
 }
 
-pub async fn create_todo<T: TodoRepository>(
-    State(todo_state): State<TodoState<T>>,
+// 認可レイヤーが選んだワークスペースに絞り込んだリポジトリを渡す
+#[async_trait]
+impl<S, T> FromRequestParts<S> for TodoState<T>
+where
+    T: TodoRepository,
+    TodoState<T>: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let CurrentWorkspace(workspace_id) = parts.extensions.get().copied().unwrap_or_default();
+        let repository = TodoState::<T>::from_ref(state)
+            .repository
+            .scoped(Scope::new(workspace_id));
+        Ok(TodoState {
+            repository: Arc::new(repository),
+        })
+    }
+}
+
+// todo を作成・更新・削除し、操作したユーザーの履歴と webhook をまとめて記録する。
+// REST / 同期 / GraphQL / gRPC のどこから変更しても同じ記録が残るよう、変更はここを通す
+pub struct TodoChanges<'a, T: TodoRepository, H: HistoryRepository, W: WebhookRepository> {
+    pub repository: &'a T,
+    pub history_state: &'a HistoryState<H>,
+    pub webhook_state: &'a WebhookState<W>,
+    pub user_id: &'a str,
+}
+
+impl<T: TodoRepository, H: HistoryRepository, W: WebhookRepository> TodoChanges<'_, T, H, W> {
+    pub async fn create(&self, payload: CreateTodo) -> anyhow::Result<TodoEntity> {
+        let todo = self.repository.create(payload).await?;
+        self.history_state
+            .record(self.user_id, NewChange::create(todo.clone()))
+            .await;
+        self.webhook_state
+            .publish(WebhookEvent::TodoCreated, &todo)
+            .await;
+        Ok(todo)
+    }
+
+    // before は呼び出し側が検証に使った更新前の todo
+    pub async fn update(
+        &self,
+        before: TodoEntity,
+        payload: UpdateTodo,
+        expected_version: Option<i32>,
+    ) -> anyhow::Result<TodoEntity> {
+        let todo = self
+            .repository
+            .update(before.id, payload, expected_version)
+            .await?;
+        self.history_state
+            .record(self.user_id, NewChange::update(before, todo.clone()))
+            .await;
+        self.webhook_state
+            .publish(WebhookEvent::TodoUpdated, &todo)
+            .await;
+        Ok(todo)
+    }
+
+    pub async fn delete(&self, id: i32, expected_version: Option<i32>) -> anyhow::Result<()> {
+        let before = self.repository.find(id).await?;
+        self.repository.delete(id, expected_version).await?;
+        self.webhook_state
+            .publish(WebhookEvent::TodoDeleted, &before)
+            .await;
+        self.history_state
+            .record(self.user_id, NewChange::delete(before))
+            .await;
+        Ok(())
+    }
+
+    // ゴミ箱からの復元は webhook では todo.updated として送る
+    pub async fn restore(&self, id: i32) -> anyhow::Result<TodoEntity> {
+        let todo = self.repository.restore(id).await?;
+        self.history_state
+            .record(self.user_id, NewChange::restore(todo.clone()))
+            .await;
+        self.webhook_state
+            .publish(WebhookEvent::TodoUpdated, &todo)
+            .await;
+        Ok(todo)
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct TodoQuery {
+    label_id: Option<i32>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct UpdateTodoParams {
+    #[serde(default)]
+    force: bool,
+}
+
+pub async fn create_todo<
+    T: TodoRepository,
+    I: IdempotencyRepository,
+    H: HistoryRepository,
+    W: WebhookRepository,
+>(
+    todo_state: TodoState<T>,
+    State(history_state): State<HistoryState<H>>,
+    webhook_state: WebhookState<W>,
+    UserId(user_id): UserId,
+    idempotency: Idempotency<I>,
     ValidatedJson(payload): ValidatedJson<CreateTodo>,
-) -> Result<impl IntoResponse, StatusCode> {
-    let todo = todo_state
-        .repository
-        .create(payload)
+) -> impl IntoResponse {
+    idempotency
+        .run(payload, |payload| async move {
+            let changes = TodoChanges {
+                repository: todo_state.repository.as_ref(),
+                history_state: &history_state,
+                webhook_state: &webhook_state,
+                user_id: &user_id,
+            };
+            let todo = changes
+                .create(payload)
+                .await
+                .or(Err(StatusCode::NOT_FOUND))?;
+            Ok::<_, StatusCode>((
+                StatusCode::CREATED,
+                [(header::ETAG, etag(todo.version))],
+                Json(todo),
+            ))
+        })
         .await
-        .or(Err(StatusCode::NOT_FOUND))?;
-    Ok((StatusCode::CREATED, Json(todo)))
 }
 
 pub async fn find_todo<T: TodoRepository>(
-    State(todo_state): State<TodoState<T>>,
+    todo_state: TodoState<T>,
     Path(id): Path<i32>,
 ) -> Result<impl IntoResponse, StatusCode> {
     let todo = todo_state
@@ -36,37 +167,157 @@ pub async fn find_todo<T: TodoRepository>(
         .find(id)
         .await
         .or(Err(StatusCode::NOT_FOUND))?;
-    Ok((StatusCode::OK, Json(todo)))
+    Ok((
+        StatusCode::OK,
+        [(header::ETAG, etag(todo.version))],
+        Json(todo),
+    ))
 }
 
 pub async fn all_todo<T: TodoRepository>(
-    State(todo_state): State<TodoState<T>>,
+    todo_state: TodoState<T>,
+    Query(query): Query<TodoQuery>,
 ) -> Result<impl IntoResponse, StatusCode> {
-    let todos = todo_state.repository.all().await.unwrap();
+    let todos = match query.label_id {
+        Some(label_id) => todo_state.repository.all_by_label(label_id).await,
+        None => todo_state.repository.all().await,
+    }
+    .or(Err(StatusCode::INTERNAL_SERVER_ERROR))?;
     Ok((StatusCode::OK, Json(todos)))
 }
 
-pub async fn update_todo<T: TodoRepository>(
-    State(todo_state): State<TodoState<T>>,
+impl Patchable for UpdateTodo {
+    const NULLABLE: &'static [&'static str] = &["description"];
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn update_todo<T: TodoRepository, H: HistoryRepository, W: WebhookRepository>(
+    todo_state: TodoState<T>,
+    State(history_state): State<HistoryState<H>>,
+    webhook_state: WebhookState<W>,
+    UserId(user_id): UserId,
+    Path(id): Path<i32>,
+    Query(params): Query<UpdateTodoParams>,
+    if_match: IfMatch,
+    patch: Patch<UpdateTodo>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let before = todo_state
+        .repository
+        .find(id)
+        .await
+        .map_err(|e| (StatusCode::NOT_FOUND, e.to_string()))?;
+    // Merge Patch や JSON Patch の結果は読み込んだ時点の todo に基づくので、
+    // If-Match が無くてもその version から変わっていれば競合にする
+    let version = match patch {
+        Patch::Replace(_) => if_match.version(before.version),
+        Patch::Merge(_) | Patch::Json(_) => {
+            if_match.version(before.version).or(Some(before.version))
+        }
+    };
+    // パッチは現在の todo の編集可能なフィールド (title, description, completed, labels の id) に当てる
+    let payload = patch.apply(UpdateTodo::from(before.clone()))?;
+    // 未完了の blocker が残っている todo は force=true の場合のみ完了にできる
+    let completing = payload.completed == Some(true) && !before.completed;
+    if completing && before.blocked && !params.force {
+        let message = format!("Todo error: [todo {} is blocked]", id);
+        return Err((StatusCode::CONFLICT, message));
+    }
+    let changes = TodoChanges {
+        repository: todo_state.repository.as_ref(),
+        history_state: &history_state,
+        webhook_state: &webhook_state,
+        user_id: &user_id,
+    };
+    let todo = changes
+        .update(before, payload, version)
+        .await
+        .map_err(|e| match e.downcast_ref::<RepositoryError>() {
+            Some(RepositoryError::Conflict(_)) => (StatusCode::PRECONDITION_FAILED, e.to_string()),
+            _ => (StatusCode::NOT_FOUND, e.to_string()),
+        })?;
+    Ok((
+        StatusCode::OK,
+        [(header::ETAG, etag(todo.version))],
+        Json(todo),
+    ))
+}
+
+pub async fn delete_todo<T: TodoRepository, H: HistoryRepository, W: WebhookRepository>(
+    todo_state: TodoState<T>,
+    State(history_state): State<HistoryState<H>>,
+    webhook_state: WebhookState<W>,
+    UserId(user_id): UserId,
+    Path(id): Path<i32>,
+    if_match: IfMatch,
+) -> StatusCode {
+    let version = if if_match.is_empty() {
+        None
+    } else {
+        match todo_state.repository.find(id).await {
+            Ok(todo) => if_match.version(todo.version),
+            Err(_) => return StatusCode::NOT_FOUND,
+        }
+    };
+    let changes = TodoChanges {
+        repository: todo_state.repository.as_ref(),
+        history_state: &history_state,
+        webhook_state: &webhook_state,
+        user_id: &user_id,
+    };
+    match changes.delete(id, version).await {
+        Ok(_) => StatusCode::NO_CONTENT,
+        Err(e) => match e.downcast_ref::<RepositoryError>() {
+            Some(RepositoryError::Conflict(_)) => StatusCode::PRECONDITION_FAILED,
+            _ => StatusCode::NOT_FOUND,
+        },
+    }
+}
+
+pub async fn restore_todo<T: TodoRepository, H: HistoryRepository, W: WebhookRepository>(
+    todo_state: TodoState<T>,
+    State(history_state): State<HistoryState<H>>,
+    webhook_state: WebhookState<W>,
+    UserId(user_id): UserId,
     Path(id): Path<i32>,
-    ValidatedJson(payload): ValidatedJson<UpdateTodo>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let changes = TodoChanges {
+        repository: todo_state.repository.as_ref(),
+        history_state: &history_state,
+        webhook_state: &webhook_state,
+        user_id: &user_id,
+    };
+    let todo = changes.restore(id).await.or(Err(StatusCode::NOT_FOUND))?;
+    Ok((
+        StatusCode::OK,
+        [(header::ETAG, etag(todo.version))],
+        Json(todo),
+    ))
+}
+
+pub async fn add_todo_blocker<T: TodoRepository>(
+    todo_state: TodoState<T>,
+    Path(id): Path<i32>,
+    ValidatedJson(payload): ValidatedJson<AddBlocker>,
 ) -> Result<impl IntoResponse, StatusCode> {
     let todo = todo_state
         .repository
-        .update(id, payload)
+        .add_blocker(id, payload.blocker_id)
         .await
-        .or(Err(StatusCode::NOT_FOUND))?;
+        .map_err(|e| match e.downcast_ref::<RepositoryError>() {
+            Some(RepositoryError::DependencyCycle(_)) => StatusCode::UNPROCESSABLE_ENTITY,
+            _ => StatusCode::NOT_FOUND,
+        })?;
     Ok((StatusCode::OK, Json(todo)))
 }
 
-pub async fn delete_todo<T: TodoRepository>(
-    State(todo_state): State<TodoState<T>>,
-    Path(id): Path<i32>,
-) -> StatusCode {
-    todo_state
+pub async fn remove_todo_blocker<T: TodoRepository>(
+    todo_state: TodoState<T>,
+    Path((id, blocker_id)): Path<(i32, i32)>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let todo = todo_state
         .repository
-        .delete(id)
+        .remove_blocker(id, blocker_id)
         .await
-        .map(|_| StatusCode::NO_CONTENT)
-        .unwrap_or(StatusCode::NOT_FOUND)
+        .or(Err(StatusCode::NOT_FOUND))?;
+    Ok((StatusCode::OK, Json(todo)))
 }
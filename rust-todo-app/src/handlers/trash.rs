@@ -0,0 +1,49 @@
+use axum::{response::IntoResponse, Json};
+use chrono::Utc;
+use hyper::StatusCode;
+use serde::{Deserialize, Serialize};
+
+use super::{label::LabelState, todo::TodoState};
+use crate::repositories::{
+    label::{LabelRepository, TrashedLabel},
+    todo::{TodoRepository, TrashedTodo},
+};
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Trash {
+    pub todos: Vec<TrashedTodo>,
+    pub labels: Vec<TrashedLabel>,
+}
+
+pub async fn all_trash<T: TodoRepository, L: LabelRepository>(
+    todo_state: TodoState<T>,
+    label_state: LabelState<L>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let todos = todo_state
+        .repository
+        .trashed()
+        .await
+        .or(Err(StatusCode::INTERNAL_SERVER_ERROR))?;
+    let labels = label_state
+        .repository
+        .trashed()
+        .await
+        .or(Err(StatusCode::INTERNAL_SERVER_ERROR))?;
+    Ok((StatusCode::OK, Json(Trash { todos, labels })))
+}
+
+pub async fn purge_trash<T: TodoRepository, L: LabelRepository>(
+    todo_state: TodoState<T>,
+    label_state: LabelState<L>,
+) -> StatusCode {
+    let now = Utc::now();
+    if todo_state.repository.purge(now).await.is_err() {
+        return StatusCode::INTERNAL_SERVER_ERROR;
+    }
+    label_state
+        .repository
+        .purge(now)
+        .await
+        .map(|_| StatusCode::NO_CONTENT)
+        .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR)
+}
@@ -0,0 +1,106 @@
+use super::{workspace::CurrentWorkspace, ValidatedJson};
+
+use axum::{
+    async_trait,
+    extract::{FromRef, FromRequestParts, Path},
+    response::IntoResponse,
+    Json,
+};
+use hyper::{http::request::Parts, StatusCode};
+use serde::Serialize;
+use std::{convert::Infallible, sync::Arc};
+
+use crate::repositories::{
+    webhook::{CreateWebhook, WebhookEvent, WebhookRepository},
+    Scope,
+};
+
+#[derive(Clone)]
+pub struct WebhookState<T: WebhookRepository> {
+    pub repository: Arc<T>,
+}
+
+// 登録と配信は認可レイヤーが選んだワークスペースに絞り込む
+#[async_trait]
+impl<S, T> FromRequestParts<S> for WebhookState<T>
+where
+    T: WebhookRepository,
+    WebhookState<T>: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let CurrentWorkspace(workspace_id) = parts.extensions.get().copied().unwrap_or_default();
+        Ok(WebhookState::<T>::from_ref(state).scoped(Scope::new(workspace_id)))
+    }
+}
+
+impl<T: WebhookRepository> WebhookState<T> {
+    pub fn scoped(&self, scope: Scope) -> Self {
+        Self {
+            repository: Arc::new(self.repository.scoped(scope)),
+        }
+    }
+
+    // 配信は非同期に行うため、ここでは配信キューへの登録だけを行う
+    pub async fn publish(&self, event: WebhookEvent, data: &impl Serialize) {
+        let payload = match serde_json::to_value(data) {
+            Ok(payload) => payload,
+            Err(e) => {
+                tracing::error!("failed to serialize webhook payload: {}", e);
+                return;
+            }
+        };
+        if let Err(e) = self.repository.enqueue(event, payload).await {
+            tracing::error!("failed to enqueue webhook {}: {}", event.as_str(), e);
+        }
+    }
+}
+
+pub async fn create_webhook<T: WebhookRepository>(
+    webhook_state: WebhookState<T>,
+    ValidatedJson(payload): ValidatedJson<CreateWebhook>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let subscription = webhook_state
+        .repository
+        .create(payload)
+        .await
+        .or(Err(StatusCode::INTERNAL_SERVER_ERROR))?;
+    Ok((StatusCode::CREATED, Json(subscription)))
+}
+
+pub async fn all_webhook<T: WebhookRepository>(
+    webhook_state: WebhookState<T>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let subscriptions = webhook_state
+        .repository
+        .all()
+        .await
+        .or(Err(StatusCode::INTERNAL_SERVER_ERROR))?;
+    Ok((StatusCode::OK, Json(subscriptions)))
+}
+
+pub async fn delete_webhook<T: WebhookRepository>(
+    webhook_state: WebhookState<T>,
+    Path(id): Path<i32>,
+) -> StatusCode {
+    webhook_state
+        .repository
+        .delete(id)
+        .await
+        .map(|_| StatusCode::NO_CONTENT)
+        .unwrap_or(StatusCode::NOT_FOUND)
+}
+
+pub async fn find_webhook_deliveries<T: WebhookRepository>(
+    webhook_state: WebhookState<T>,
+    Path(id): Path<i32>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let deliveries = webhook_state
+        .repository
+        .deliveries(id)
+        .await
+        .or(Err(StatusCode::INTERNAL_SERVER_ERROR))?;
+    Ok((StatusCode::OK, Json(deliveries)))
+}
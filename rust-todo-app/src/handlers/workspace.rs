@@ -0,0 +1,168 @@
+use super::{api_token::AuthenticatedUser, ValidatedJson};
+
+use axum::{
+    extract::{Path, State},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use hyper::{header::HeaderName, Method, Request, StatusCode};
+use std::sync::Arc;
+
+use crate::repositories::{
+    workspace::{CreateInvitation, CreateWorkspace, Role, WorkspaceRepository},
+    RepositoryError,
+};
+
+pub const WORKSPACE_ID: HeaderName = HeaderName::from_static("x-workspace-id");
+
+#[derive(Clone)]
+pub struct WorkspaceState<T: WorkspaceRepository> {
+    pub repository: Arc<T>,
+}
+
+impl<T: WorkspaceRepository> WorkspaceState<T> {
+    // 所属していないユーザーは 403 にする
+    pub async fn require_role(
+        &self,
+        workspace_id: i32,
+        user_id: &str,
+    ) -> Result<Role, (StatusCode, String)> {
+        self.repository
+            .role(workspace_id, user_id)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+            .ok_or_else(|| {
+                let message = format!("Workspace error: [{} is not a member]", user_id);
+                (StatusCode::FORBIDDEN, message)
+            })
+    }
+}
+
+// 認可レイヤーが選んだワークスペース。None は個人のデータを扱う
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CurrentWorkspace(pub Option<i32>);
+
+// 選ばれたワークスペースでのロール。GraphQL では書き込みの可否を操作ごとに確認するために使う
+#[derive(Debug, Clone, Copy)]
+pub struct WorkspaceRole(pub Role);
+
+// `X-Workspace-Id` ヘッダで指定されたワークスペースのメンバーか確認し、
+// 閲覧者には参照系のリクエストだけを許可する。
+// `X-User-Id` ヘッダは誰でも名乗れるため、メンバーかどうかは認証されたユーザーで判断する
+pub async fn authorize<T: WorkspaceRepository, B>(
+    State(workspace_state): State<WorkspaceState<T>>,
+    mut req: Request<B>,
+    next: Next<B>,
+) -> Result<Response, (StatusCode, String)> {
+    let workspace_id = match req.headers().get(WORKSPACE_ID) {
+        Some(value) => {
+            let workspace_id = value
+                .to_str()
+                .ok()
+                .and_then(|value| value.trim().parse::<i32>().ok())
+                .ok_or_else(|| {
+                    let message = "Workspace error: [X-Workspace-Id must be an integer]";
+                    (StatusCode::BAD_REQUEST, message.to_string())
+                })?;
+            Some(workspace_id)
+        }
+        None => None,
+    };
+
+    if let Some(workspace_id) = workspace_id {
+        let AuthenticatedUser(user_id) = req.extensions().get().cloned().ok_or_else(|| {
+            let message = "Workspace error: [authentication is required]";
+            (StatusCode::UNAUTHORIZED, message.to_string())
+        })?;
+        let role = workspace_state.require_role(workspace_id, &user_id).await?;
+        // GraphQL は 1 つのルートで読み書きの両方を扱うため、mutation かどうかは GraphQL 側で確認する
+        let read_only = matches!(*req.method(), Method::GET | Method::HEAD | Method::OPTIONS)
+            || req.uri().path() == "/graphql";
+        if !read_only && !role.can_edit() {
+            let message = format!(
+                "Workspace error: [{} can not modify workspace data]",
+                user_id
+            );
+            return Err((StatusCode::FORBIDDEN, message));
+        }
+        req.extensions_mut().insert(WorkspaceRole(role));
+    }
+
+    req.extensions_mut().insert(CurrentWorkspace(workspace_id));
+    Ok(next.run(req).await)
+}
+
+pub async fn create_workspace<T: WorkspaceRepository>(
+    State(workspace_state): State<WorkspaceState<T>>,
+    AuthenticatedUser(user_id): AuthenticatedUser,
+    ValidatedJson(payload): ValidatedJson<CreateWorkspace>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let workspace = workspace_state
+        .repository
+        .create(&user_id, payload)
+        .await
+        .or(Err(StatusCode::INTERNAL_SERVER_ERROR))?;
+    Ok((StatusCode::CREATED, Json(workspace)))
+}
+
+pub async fn all_workspace<T: WorkspaceRepository>(
+    State(workspace_state): State<WorkspaceState<T>>,
+    AuthenticatedUser(user_id): AuthenticatedUser,
+) -> Result<impl IntoResponse, StatusCode> {
+    let memberships = workspace_state
+        .repository
+        .all(&user_id)
+        .await
+        .or(Err(StatusCode::INTERNAL_SERVER_ERROR))?;
+    Ok((StatusCode::OK, Json(memberships)))
+}
+
+pub async fn find_workspace_members<T: WorkspaceRepository>(
+    State(workspace_state): State<WorkspaceState<T>>,
+    AuthenticatedUser(user_id): AuthenticatedUser,
+    Path(id): Path<i32>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    workspace_state.require_role(id, &user_id).await?;
+    let members = workspace_state
+        .repository
+        .members(id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok((StatusCode::OK, Json(members)))
+}
+
+pub async fn create_invitation<T: WorkspaceRepository>(
+    State(workspace_state): State<WorkspaceState<T>>,
+    AuthenticatedUser(user_id): AuthenticatedUser,
+    Path(id): Path<i32>,
+    ValidatedJson(payload): ValidatedJson<CreateInvitation>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let role = workspace_state.require_role(id, &user_id).await?;
+    if role != Role::Owner {
+        let message = format!("Workspace error: [{} is not an owner]", user_id);
+        return Err((StatusCode::FORBIDDEN, message));
+    }
+    let invitation = workspace_state
+        .repository
+        .invite(id, &user_id, payload)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok((StatusCode::CREATED, Json(invitation)))
+}
+
+pub async fn accept_invitation<T: WorkspaceRepository>(
+    State(workspace_state): State<WorkspaceState<T>>,
+    AuthenticatedUser(user_id): AuthenticatedUser,
+    Path(token): Path<String>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let member = workspace_state
+        .repository
+        .accept(&token, &user_id)
+        .await
+        .map_err(|e| match e.downcast_ref::<RepositoryError>() {
+            Some(RepositoryError::InvalidToken) => StatusCode::NOT_FOUND,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        })?;
+    Ok((StatusCode::OK, Json(member)))
+}
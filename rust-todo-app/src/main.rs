@@ -1,44 +1,317 @@
+mod grpc;
 mod handlers;
+mod notifiers;
+mod oidc;
 mod repositories;
+mod tasks;
+mod telemetry;
 
-use crate::repositories::{label::LabelRepositoryForDb, todo::TodoRepositoryForDb};
+use crate::repositories::{
+    api_token::ApiTokenRepositoryForDb, history::HistoryRepositoryForDb,
+    idempotency::IdempotencyRepositoryForDb, label::LabelRepositoryForDb,
+    reminder::ReminderRepositoryForDb, todo::TodoRepositoryForDb, user::UserRepositoryForDb,
+    webhook::WebhookRepositoryForDb, workspace::WorkspaceRepositoryForDb,
+};
 use axum::{
-    extract::FromRef,
-    routing::{delete, get, post},
+    body::Body,
+    extract::{DefaultBodyLimit, FromRef},
+    middleware,
+    routing::{delete, get, post, put},
     Router,
 };
 use dotenv::dotenv;
+use grpc::{
+    proto::{label_service_server::LabelServiceServer, todo_service_server::TodoServiceServer},
+    GrpcAuthLayer, LabelGrpcService, TodoGrpcService,
+};
 use handlers::{
-    label::{all_label, create_label, delete_label, LabelState},
-    todo::{all_todo, create_todo, delete_todo, find_todo, update_todo, TodoState},
+    api_token::{all_api_token, create_api_token, revoke_api_token, ApiTokenAuth, ApiTokenState},
+    graphql::{graphiql, graphql, GraphQLState},
+    history::{find_todo_history, redo, undo, HistoryState},
+    idempotency::{IdempotencyState, IDEMPOTENCY_KEY},
+    label::{
+        all_label, create_label, delete_label, find_label, find_label_todos, label_tree,
+        merge_label, move_label, restore_label, LabelState,
+    },
+    oidc::{JwtAuth, OidcState},
+    rate_limit::{limit_failed_authentication, rate_limit, RateLimitConfig, RateLimiter},
+    reminder::{
+        create_reminder, dismiss_reminder, find_todo_reminders, snooze_reminder, ReminderState,
+    },
+    sync::{pull_changes, push_changes},
+    todo::{
+        add_todo_blocker, all_todo, create_todo, delete_todo, find_todo, remove_todo_blocker,
+        restore_todo, update_todo, TodoState,
+    },
+    trash::{all_trash, purge_trash},
+    webhook::{all_webhook, create_webhook, delete_webhook, find_webhook_deliveries, WebhookState},
+    workspace::{
+        accept_invitation, all_workspace, authorize, create_invitation, create_workspace,
+        find_workspace_members, WorkspaceState, WORKSPACE_ID,
+    },
+    DEFAULT_BODY_LIMIT, USER_ID,
+};
+use hyper::header::{AUTHORIZATION, CONTENT_TYPE, ETAG, IF_MATCH, RETRY_AFTER};
+use oidc::{JwtVerifier, OidcConfig};
+#[cfg(feature = "memory")]
+use repositories::{
+    api_token::memory::ApiTokenRepositoryInMemory, history::memory::HistoryRepositoryInMemory,
+    idempotency::memory::IdempotencyRepositoryInMemory, label::memory::LabelRepositoryInMemory,
+    memory::MemoryStore, reminder::memory::ReminderRepositoryInMemory,
+    todo::memory::TodoRepositoryInMemory, user::memory::UserRepositoryInMemory,
+    webhook::memory::WebhookRepositoryInMemory, workspace::memory::WorkspaceRepositoryInMemory,
+};
+use repositories::{
+    api_token::{ApiScope, ApiTokenRepository, CreateApiToken, NewApiToken},
+    history::HistoryRepository,
+    idempotency::IdempotencyRepository,
+    label::LabelRepository,
+    reminder::{ReminderChannel, ReminderRepository},
+    todo::TodoRepository,
+    user::UserRepository,
+    webhook::WebhookRepository,
+    workspace::WorkspaceRepository,
 };
-use hyper::header::CONTENT_TYPE;
-use repositories::{label::LabelRepository, todo::TodoRepository};
 use sqlx::PgPool;
-use std::{env, net::SocketAddr, sync::Arc};
+#[cfg(feature = "memory")]
+use std::path::PathBuf;
+use std::{env, net::SocketAddr, sync::Arc, time::Duration};
+use telemetry::{
+    make_request_span, record_response, LogFormat, OtlpConfig, REQUEST_ID, TRACEPARENT, TRACESTATE,
+};
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+use tower::{
+    layer::util::{Identity, Stack},
+    ServiceBuilder,
+};
 use tower_http::cors::CorsLayer;
 use tower_http::cors::{AllowOrigin, Any};
+use tower_http::{
+    request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer},
+    trace::TraceLayer,
+};
 
 #[derive(Clone)]
-struct AppState<T: TodoRepository, L: LabelRepository> {
+struct AppState<
+    T: TodoRepository,
+    L: LabelRepository,
+    I: IdempotencyRepository,
+    H: HistoryRepository,
+    R: ReminderRepository,
+    W: WebhookRepository,
+    K: WorkspaceRepository,
+    A: ApiTokenRepository,
+    U: UserRepository,
+> {
     todo_state: TodoState<T>,
     label_state: LabelState<L>,
+    idempotency_state: IdempotencyState<I>,
+    history_state: HistoryState<H>,
+    reminder_state: ReminderState<R>,
+    webhook_state: WebhookState<W>,
+    workspace_state: WorkspaceState<K>,
+    api_token_state: ApiTokenState<A>,
+    oidc_state: OidcState<U>,
+    graphql_state: GraphQLState<T, L, H, W>,
+    rate_limiter: Option<RateLimiter>,
+    body_limit: usize,
 }
 
-impl<T: TodoRepository, L: LabelRepository> FromRef<AppState<T, L>> for TodoState<T> {
-    fn from_ref(state: &AppState<T, L>) -> TodoState<T> {
+impl<
+        T: TodoRepository,
+        L: LabelRepository,
+        I: IdempotencyRepository,
+        H: HistoryRepository,
+        R: ReminderRepository,
+        W: WebhookRepository,
+        K: WorkspaceRepository,
+        A: ApiTokenRepository,
+        U: UserRepository,
+    > FromRef<AppState<T, L, I, H, R, W, K, A, U>> for TodoState<T>
+{
+    fn from_ref(state: &AppState<T, L, I, H, R, W, K, A, U>) -> TodoState<T> {
         state.todo_state.clone()
     }
 }
 
-impl<T: TodoRepository, L: LabelRepository> FromRef<AppState<T, L>> for LabelState<L> {
-    fn from_ref(state: &AppState<T, L>) -> LabelState<L> {
+impl<
+        T: TodoRepository,
+        L: LabelRepository,
+        I: IdempotencyRepository,
+        H: HistoryRepository,
+        R: ReminderRepository,
+        W: WebhookRepository,
+        K: WorkspaceRepository,
+        A: ApiTokenRepository,
+        U: UserRepository,
+    > FromRef<AppState<T, L, I, H, R, W, K, A, U>> for LabelState<L>
+{
+    fn from_ref(state: &AppState<T, L, I, H, R, W, K, A, U>) -> LabelState<L> {
         state.label_state.clone()
     }
 }
 
-impl<T: TodoRepository, L: LabelRepository> AppState<T, L> {
-    fn new(todo_repository: T, label_repository: L) -> Self {
+impl<
+        T: TodoRepository,
+        L: LabelRepository,
+        I: IdempotencyRepository,
+        H: HistoryRepository,
+        R: ReminderRepository,
+        W: WebhookRepository,
+        K: WorkspaceRepository,
+        A: ApiTokenRepository,
+        U: UserRepository,
+    > FromRef<AppState<T, L, I, H, R, W, K, A, U>> for IdempotencyState<I>
+{
+    fn from_ref(state: &AppState<T, L, I, H, R, W, K, A, U>) -> IdempotencyState<I> {
+        state.idempotency_state.clone()
+    }
+}
+
+impl<
+        T: TodoRepository,
+        L: LabelRepository,
+        I: IdempotencyRepository,
+        H: HistoryRepository,
+        R: ReminderRepository,
+        W: WebhookRepository,
+        K: WorkspaceRepository,
+        A: ApiTokenRepository,
+        U: UserRepository,
+    > FromRef<AppState<T, L, I, H, R, W, K, A, U>> for HistoryState<H>
+{
+    fn from_ref(state: &AppState<T, L, I, H, R, W, K, A, U>) -> HistoryState<H> {
+        state.history_state.clone()
+    }
+}
+
+impl<
+        T: TodoRepository,
+        L: LabelRepository,
+        I: IdempotencyRepository,
+        H: HistoryRepository,
+        R: ReminderRepository,
+        W: WebhookRepository,
+        K: WorkspaceRepository,
+        A: ApiTokenRepository,
+        U: UserRepository,
+    > FromRef<AppState<T, L, I, H, R, W, K, A, U>> for ReminderState<R>
+{
+    fn from_ref(state: &AppState<T, L, I, H, R, W, K, A, U>) -> ReminderState<R> {
+        state.reminder_state.clone()
+    }
+}
+
+impl<
+        T: TodoRepository,
+        L: LabelRepository,
+        I: IdempotencyRepository,
+        H: HistoryRepository,
+        R: ReminderRepository,
+        W: WebhookRepository,
+        K: WorkspaceRepository,
+        A: ApiTokenRepository,
+        U: UserRepository,
+    > FromRef<AppState<T, L, I, H, R, W, K, A, U>> for WebhookState<W>
+{
+    fn from_ref(state: &AppState<T, L, I, H, R, W, K, A, U>) -> WebhookState<W> {
+        state.webhook_state.clone()
+    }
+}
+
+impl<
+        T: TodoRepository,
+        L: LabelRepository,
+        I: IdempotencyRepository,
+        H: HistoryRepository,
+        R: ReminderRepository,
+        W: WebhookRepository,
+        K: WorkspaceRepository,
+        A: ApiTokenRepository,
+        U: UserRepository,
+    > FromRef<AppState<T, L, I, H, R, W, K, A, U>> for WorkspaceState<K>
+{
+    fn from_ref(state: &AppState<T, L, I, H, R, W, K, A, U>) -> WorkspaceState<K> {
+        state.workspace_state.clone()
+    }
+}
+
+impl<
+        T: TodoRepository,
+        L: LabelRepository,
+        I: IdempotencyRepository,
+        H: HistoryRepository,
+        R: ReminderRepository,
+        W: WebhookRepository,
+        K: WorkspaceRepository,
+        A: ApiTokenRepository,
+        U: UserRepository,
+    > FromRef<AppState<T, L, I, H, R, W, K, A, U>> for ApiTokenState<A>
+{
+    fn from_ref(state: &AppState<T, L, I, H, R, W, K, A, U>) -> ApiTokenState<A> {
+        state.api_token_state.clone()
+    }
+}
+
+impl<
+        T: TodoRepository,
+        L: LabelRepository,
+        I: IdempotencyRepository,
+        H: HistoryRepository,
+        R: ReminderRepository,
+        W: WebhookRepository,
+        K: WorkspaceRepository,
+        A: ApiTokenRepository,
+        U: UserRepository,
+    > FromRef<AppState<T, L, I, H, R, W, K, A, U>> for OidcState<U>
+{
+    fn from_ref(state: &AppState<T, L, I, H, R, W, K, A, U>) -> OidcState<U> {
+        state.oidc_state.clone()
+    }
+}
+
+impl<
+        T: TodoRepository,
+        L: LabelRepository,
+        I: IdempotencyRepository,
+        H: HistoryRepository,
+        R: ReminderRepository,
+        W: WebhookRepository,
+        K: WorkspaceRepository,
+        A: ApiTokenRepository,
+        U: UserRepository,
+    > FromRef<AppState<T, L, I, H, R, W, K, A, U>> for GraphQLState<T, L, H, W>
+{
+    fn from_ref(state: &AppState<T, L, I, H, R, W, K, A, U>) -> GraphQLState<T, L, H, W> {
+        state.graphql_state.clone()
+    }
+}
+
+impl<
+        T: TodoRepository,
+        L: LabelRepository,
+        I: IdempotencyRepository,
+        H: HistoryRepository,
+        R: ReminderRepository,
+        W: WebhookRepository,
+        K: WorkspaceRepository,
+        A: ApiTokenRepository,
+        U: UserRepository,
+    > AppState<T, L, I, H, R, W, K, A, U>
+{
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        todo_repository: T,
+        label_repository: L,
+        idempotency_repository: I,
+        history_repository: H,
+        reminder_repository: R,
+        webhook_repository: W,
+        workspace_repository: K,
+        api_token_repository: A,
+        user_repository: U,
+    ) -> Self {
         Self {
             todo_state: TodoState {
                 repository: Arc::new(todo_repository),
@@ -46,69 +319,585 @@ impl<T: TodoRepository, L: LabelRepository> AppState<T, L> {
             label_state: LabelState {
                 repository: Arc::new(label_repository),
             },
+            idempotency_state: IdempotencyState {
+                repository: Arc::new(idempotency_repository),
+            },
+            history_state: HistoryState {
+                repository: Arc::new(history_repository),
+            },
+            reminder_state: ReminderState {
+                repository: Arc::new(reminder_repository),
+                channels: vec![ReminderChannel::Log],
+            },
+            webhook_state: WebhookState {
+                repository: Arc::new(webhook_repository),
+            },
+            workspace_state: WorkspaceState {
+                repository: Arc::new(workspace_repository),
+            },
+            api_token_state: ApiTokenState {
+                repository: Arc::new(api_token_repository),
+                required: false,
+            },
+            oidc_state: OidcState {
+                repository: Arc::new(user_repository),
+                verifier: None,
+            },
+            graphql_state: GraphQLState::new(),
+            rate_limiter: None,
+            body_limit: DEFAULT_BODY_LIMIT,
         }
     }
+
+    fn with_jwt_verifier(mut self, verifier: JwtVerifier) -> Self {
+        self.oidc_state.verifier = Some(verifier);
+        self.api_token_state.required = true;
+        self
+    }
+
+    fn with_rate_limit(mut self, config: RateLimitConfig) -> Self {
+        self.rate_limiter = Some(RateLimiter::new(config));
+        self
+    }
+
+    fn with_reminder_channels(mut self, channels: Vec<ReminderChannel>) -> Self {
+        self.reminder_state.channels = channels;
+        self
+    }
+
+    fn with_body_limit(mut self, body_limit: usize) -> Self {
+        self.body_limit = body_limit;
+        self
+    }
 }
 
 #[tokio::main]
 async fn main() {
-    let log_level = env::var("RUST_LOG").unwrap_or_else(|_| "info".to_string());
-    env::set_var("RUST_LOG", log_level);
-    tracing_subscriber::fmt::init();
     dotenv().ok();
+    telemetry::init(
+        LogFormat::from_env().expect("failed to configure logging"),
+        OtlpConfig::from_env(),
+    )
+    .expect("failed to configure tracing");
+
+    // `create-token <user_id> [name]` は API トークンを発行して終了する
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("create-token") {
+        create_token_command(&args[1..]).await;
+        telemetry::shutdown().await;
+        return;
+    }
 
+    let shutdown = CancellationToken::new();
+    // STORAGE=memory なら DB を使わずにインメモリのストアで動かす
+    match env::var("STORAGE").as_deref() {
+        #[cfg(feature = "memory")]
+        Ok("memory") => run_in_memory(shutdown).await,
+        #[cfg(not(feature = "memory"))]
+        Ok("memory") => panic!("STORAGE=memory requires the memory feature"),
+        _ => run_with_database(shutdown).await,
+    }
+    tracing::info!("shutdown complete");
+    telemetry::shutdown().await;
+}
+
+async fn connect_database() -> PgPool {
     let database_url = &env::var("DATABASE_URL").expect("DATABASE_URL must be set");
-    tracing::debug!("starting connect database... (url: {})", database_url);
-    let pool = PgPool::connect(database_url)
+    tracing::debug!(
+        "starting connect database... (url: {})",
+        telemetry::redact_url(database_url)
+    );
+    PgPool::connect(database_url)
+        .await
+        .expect("failed connect database")
+}
+
+// トークンやワークスペースを作るには API トークンか JWT で認証されている必要があるため、
+// OIDC を使わない環境では、最初のトークンをこのコマンドでストレージに直接作る。
+// 発行したトークンは全てのスコープを持ち、ワークスペースの作成や招待に使える。
+// STORAGE=memory の場合は MEMORY_SNAPSHOT_PATH のスナップショットに書き込むので、サーバーを起動する前に実行する
+async fn create_token_command(args: &[String]) {
+    let (user_id, name) = match args {
+        [user_id] => (user_id.as_str(), "bootstrap"),
+        [user_id, name] => (user_id.as_str(), name.as_str()),
+        _ => panic!("usage: create-token <user_id> [name]"),
+    };
+    let api_token = match env::var("STORAGE").as_deref() {
+        #[cfg(feature = "memory")]
+        Ok("memory") => {
+            let path = env::var("MEMORY_SNAPSHOT_PATH")
+                .map(PathBuf::from)
+                .expect("MEMORY_SNAPSHOT_PATH must be set to create a token in memory");
+            let store = MemoryStore::load(&path).expect("failed to load snapshot");
+            let repository = ApiTokenRepositoryInMemory::with_store(store.clone());
+            let api_token = issue_token(&repository, user_id, name).await;
+            store.save(&path).expect("failed to save snapshot");
+            api_token
+        }
+        #[cfg(not(feature = "memory"))]
+        Ok("memory") => panic!("STORAGE=memory requires the memory feature"),
+        _ => {
+            let pool = connect_database().await;
+            let api_token =
+                issue_token(&ApiTokenRepositoryForDb::new(pool.clone()), user_id, name).await;
+            pool.close().await;
+            api_token
+        }
+    };
+    // トークンはここでしか表示しない
+    println!("{}", serde_json::to_string_pretty(&api_token).unwrap());
+}
+
+async fn issue_token<A: ApiTokenRepository>(
+    repository: &A,
+    user_id: &str,
+    name: &str,
+) -> NewApiToken {
+    let payload = CreateApiToken {
+        name: name.to_string(),
+        scopes: ApiScope::ALL.to_vec(),
+    };
+    repository
+        .create(user_id, payload)
         .await
-        .expect("failed connect database");
+        .expect("failed to create API token")
+}
+
+async fn run_with_database(shutdown: CancellationToken) {
+    let pool = connect_database().await;
 
-    // NOTE: with_state は create_routes 内に移せない
-    // ref: https://github.com/tokio-rs/axum/issues/1592
-    let app = create_routes().with_state(AppState::new(
+    let idempotency_key_ttl = duration_from_env("IDEMPOTENCY_KEY_TTL_SECS", 24 * 60 * 60);
+    let app_state = AppState::new(
         TodoRepositoryForDb::new(pool.clone()),
         LabelRepositoryForDb::new(pool.clone()),
-    ));
+        IdempotencyRepositoryForDb::new(pool.clone(), idempotency_key_ttl),
+        HistoryRepositoryForDb::new(pool.clone()),
+        ReminderRepositoryForDb::new(pool.clone()),
+        WebhookRepositoryForDb::new(pool.clone()),
+        WorkspaceRepositoryForDb::new(pool.clone()),
+        ApiTokenRepositoryForDb::new(pool.clone()),
+        UserRepositoryForDb::new(pool.clone()),
+    );
+    serve(app_state, vec![], shutdown).await;
+    pool.close().await;
+}
+
+// todo とラベル、ワークスペース、API トークン、ユーザーは一つのストアを共有し、
+// MEMORY_SNAPSHOT_PATH があればそこに定期的に保存して起動時に読み込む。
+// それ以外のリポジトリは保存しないため、再起動すると消える
+#[cfg(feature = "memory")]
+async fn run_in_memory(shutdown: CancellationToken) {
+    let snapshot_path = env::var("MEMORY_SNAPSHOT_PATH").ok().map(PathBuf::from);
+    let store = match &snapshot_path {
+        Some(path) => MemoryStore::load(path).expect("failed to load snapshot"),
+        None => MemoryStore::new(),
+    };
+    tracing::debug!(
+        "starting with in-memory store (snapshot: {:?})",
+        snapshot_path
+    );
+
+    let mut background_tasks = vec![];
+    if let Some(path) = snapshot_path {
+        background_tasks.push(tokio::spawn(tasks::snapshot::save_snapshots(
+            store.clone(),
+            path,
+            duration_from_env("MEMORY_SNAPSHOT_INTERVAL_SECS", 60),
+            shutdown.clone(),
+        )));
+    }
+
+    let idempotency_key_ttl = duration_from_env("IDEMPOTENCY_KEY_TTL_SECS", 24 * 60 * 60);
+    let app_state = AppState::new(
+        TodoRepositoryInMemory::with_store(store.clone()),
+        LabelRepositoryInMemory::with_store(store.clone()),
+        IdempotencyRepositoryInMemory::new(idempotency_key_ttl),
+        HistoryRepositoryInMemory::new(),
+        ReminderRepositoryInMemory::new(),
+        WebhookRepositoryInMemory::new(),
+        WorkspaceRepositoryInMemory::with_store(store.clone()),
+        ApiTokenRepositoryInMemory::with_store(store.clone()),
+        UserRepositoryInMemory::with_store(store),
+    );
+    serve(app_state, background_tasks, shutdown).await;
+}
+
+async fn serve<
+    T: TodoRepository,
+    L: LabelRepository,
+    I: IdempotencyRepository,
+    H: HistoryRepository,
+    R: ReminderRepository,
+    W: WebhookRepository,
+    K: WorkspaceRepository,
+    A: ApiTokenRepository,
+    U: UserRepository,
+>(
+    app_state: AppState<T, L, I, H, R, W, K, A, U>,
+    mut background_tasks: Vec<JoinHandle<()>>,
+    shutdown: CancellationToken,
+) {
+    let trash_retention = duration_from_env("TRASH_RETENTION_SECS", 30 * 24 * 60 * 60);
+    let trash_purge_interval = duration_from_env("TRASH_PURGE_INTERVAL_SECS", 60 * 60);
+    let reminder_poll_interval = duration_from_env("REMINDER_POLL_INTERVAL_SECS", 30);
+    let webhook_poll_interval = duration_from_env("WEBHOOK_POLL_INTERVAL_SECS", 5);
+    let webhook_retry_base = duration_from_env("WEBHOOK_RETRY_BASE_SECS", 30);
+    let notifiers = notifiers::Notifiers::from_env().expect("failed to configure notifiers");
+    let reminder_channels = notifiers.channels();
+    let oidc_config = OidcConfig::from_env().expect("failed to configure OIDC");
+    let jwks_refresh_interval = duration_from_env("OIDC_JWKS_REFRESH_SECS", 60 * 60);
+    let rate_limit_config = RateLimitConfig::from_env().expect("failed to configure rate limit");
+    let rate_limit_sweep_interval = duration_from_env("RATE_LIMIT_SWEEP_INTERVAL_SECS", 60);
+    let body_limit = env::var("BODY_LIMIT_BYTES")
+        .ok()
+        .and_then(|bytes| bytes.parse().ok())
+        .unwrap_or(DEFAULT_BODY_LIMIT);
+    let drain_timeout = duration_from_env("SHUTDOWN_DRAIN_TIMEOUT_SECS", 30);
+    let grpc_port: u16 = env::var("GRPC_PORT")
+        .ok()
+        .and_then(|port| port.parse().ok())
+        .unwrap_or(50051);
+
+    let todo_repository = app_state.todo_state.repository.as_ref().clone();
+    let label_repository = app_state.label_state.repository.as_ref().clone();
+    let reminder_repository = app_state.reminder_state.repository.as_ref().clone();
+    let webhook_repository = app_state.webhook_state.repository.as_ref().clone();
+
+    background_tasks.extend([
+        tokio::spawn(tasks::trash::purge_expired_trash(
+            todo_repository.clone(),
+            label_repository.clone(),
+            trash_retention,
+            trash_purge_interval,
+            shutdown.clone(),
+        )),
+        tokio::spawn(tasks::reminder::fire_due_reminders(
+            reminder_repository.clone(),
+            todo_repository.clone(),
+            notifiers,
+            reminder_poll_interval,
+            shutdown.clone(),
+        )),
+        tokio::spawn(tasks::webhook::deliver_webhooks(
+            webhook_repository.clone(),
+            webhook_poll_interval,
+            webhook_retry_base,
+            shutdown.clone(),
+        )),
+    ]);
+
+    let mut app_state = app_state
+        .with_reminder_channels(reminder_channels)
+        .with_body_limit(body_limit);
+    if let Some(rate_limit_config) = rate_limit_config {
+        app_state = app_state.with_rate_limit(rate_limit_config);
+    }
+    if let Some(rate_limiter) = app_state.rate_limiter.clone() {
+        background_tasks.push(tokio::spawn(tasks::rate_limit::sweep_rate_limit_buckets(
+            rate_limiter,
+            rate_limit_sweep_interval,
+            shutdown.clone(),
+        )));
+    }
+    if let Some(oidc_config) = oidc_config {
+        let verifier = JwtVerifier::load(oidc_config)
+            .await
+            .expect("failed to load JWKS");
+        background_tasks.push(tokio::spawn(tasks::oidc::refresh_jwks(
+            verifier.clone(),
+            jwks_refresh_interval,
+            shutdown.clone(),
+        )));
+        app_state = app_state.with_jwt_verifier(verifier);
+    }
+    // gRPC は別のポートで受け付け、REST と同じリポジトリを使う
+    let grpc_addr = SocketAddr::from(([127, 0, 0, 1], grpc_port));
+    tracing::debug!("grpc listening on {}", grpc_addr);
+    let grpc_server = create_grpc_routes(&app_state).serve_with_shutdown(grpc_addr, {
+        let shutdown = shutdown.clone();
+        async move { shutdown.cancelled().await }
+    });
+    background_tasks.push(tokio::spawn(async move {
+        if let Err(e) = grpc_server.await {
+            tracing::error!("grpc server error: {}", e);
+        }
+    }));
+
+    let app = create_routes(app_state);
 
     let addr = SocketAddr::from(([127, 0, 0, 1], 3000));
     tracing::debug!("listening on {}", addr);
 
-    axum::Server::bind(&addr)
-        .serve(app.into_make_service())
-        .await
-        .unwrap();
+    let server = axum::Server::bind(&addr)
+        // レート制限で接続元の IP を使うため
+        .serve(app.into_make_service_with_connect_info::<SocketAddr>())
+        .with_graceful_shutdown(shutdown_signal(shutdown.clone()));
+
+    // シグナルを受けてから drain_timeout 以内に終わらないリクエストは打ち切る
+    tokio::select! {
+        result = server => result.unwrap(),
+        _ = async {
+            shutdown.cancelled().await;
+            tokio::time::sleep(drain_timeout).await;
+        } => tracing::warn!("drain timeout elapsed, aborting in-flight requests"),
+    }
+
+    let deadline = tokio::time::Instant::now() + drain_timeout;
+    for task in background_tasks {
+        if tokio::time::timeout_at(deadline, task).await.is_err() {
+            tracing::warn!("background task did not stop before the drain timeout");
+        }
+    }
+}
+
+// SIGINT / SIGTERM を受け取ったら、バックグラウンドタスクにも終了を伝える
+async fn shutdown_signal(shutdown: CancellationToken) {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("failed to install Ctrl+C handler");
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("failed to install SIGTERM handler")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+    tracing::info!("shutdown signal received, draining in-flight requests");
+    shutdown.cancel();
+}
+
+fn duration_from_env(key: &str, default_secs: u64) -> Duration {
+    let secs = env::var(key)
+        .ok()
+        .and_then(|secs| secs.parse().ok())
+        .unwrap_or(default_secs);
+    Duration::from_secs(secs)
+}
+
+fn create_grpc_routes<
+    T: TodoRepository,
+    L: LabelRepository,
+    I: IdempotencyRepository,
+    H: HistoryRepository,
+    R: ReminderRepository,
+    W: WebhookRepository,
+    K: WorkspaceRepository,
+    A: ApiTokenRepository,
+    U: UserRepository,
+>(
+    state: &AppState<T, L, I, H, R, W, K, A, U>,
+) -> tonic::transport::server::Router<Stack<GrpcAuthLayer<A, U, K>, Identity>> {
+    tonic::transport::Server::builder()
+        .trace_fn(|req| tracing::info_span!("grpc", path = %req.uri().path()))
+        .layer(GrpcAuthLayer::new(
+            state.api_token_state.clone(),
+            state.oidc_state.clone(),
+            state.workspace_state.clone(),
+        ))
+        .add_service(TodoServiceServer::new(TodoGrpcService::new(
+            state.todo_state.repository.clone(),
+            state.history_state.clone(),
+            state.webhook_state.clone(),
+        )))
+        .add_service(LabelServiceServer::new(LabelGrpcService::new(
+            state.label_state.repository.clone(),
+            state.webhook_state.clone(),
+        )))
 }
 
-fn create_routes<T: TodoRepository, L: LabelRepository>() -> Router<AppState<T, L>> {
-    Router::new()
+fn create_routes<
+    T: TodoRepository,
+    L: LabelRepository,
+    I: IdempotencyRepository,
+    H: HistoryRepository,
+    R: ReminderRepository,
+    W: WebhookRepository,
+    K: WorkspaceRepository,
+    A: ApiTokenRepository,
+    U: UserRepository,
+>(
+    state: AppState<T, L, I, H, R, W, K, A, U>,
+) -> Router {
+    let router = Router::new()
         .route("/", get(|| async { "Hello, World!" }))
-        .route("/todos", post(create_todo::<T>).get(all_todo::<T>))
+        .route("/todos", post(create_todo::<T, I, H, W>).get(all_todo::<T>))
         .route(
             "/todos/:id",
             get(find_todo::<T>)
-                .delete(delete_todo::<T>)
-                .patch(update_todo::<T>),
+                .delete(delete_todo::<T, H, W>)
+                .patch(update_todo::<T, H, W>),
+        )
+        .route("/todos/:id/restore", post(restore_todo::<T, H, W>))
+        .route("/todos/:id/blockers", post(add_todo_blocker::<T>))
+        .route(
+            "/todos/:id/blockers/:blocker_id",
+            delete(remove_todo_blocker::<T>),
+        )
+        .route("/todos/:id/history", get(find_todo_history::<T, H>))
+        .route(
+            "/todos/:id/reminders",
+            post(create_reminder::<T, R>).get(find_todo_reminders::<T, R>),
+        )
+        .route("/reminders/:id/snooze", post(snooze_reminder::<T, R>))
+        .route("/reminders/:id/dismiss", post(dismiss_reminder::<T, R>))
+        .route("/history/undo", post(undo::<T, H, W>))
+        .route("/history/redo", post(redo::<T, H, W>))
+        .route("/labels", post(create_label::<L, I, W>).get(all_label::<L>))
+        .route(
+            "/labels/:id",
+            get(find_label::<L>).delete(delete_label::<L, W>),
+        )
+        .route("/labels/:id/todos", get(find_label_todos::<L, T>))
+        .route("/labels/tree", get(label_tree::<L>))
+        .route("/labels/:id/parent", put(move_label::<L, W>))
+        .route("/labels/:id/merge", post(merge_label::<L, T, W>))
+        .route("/labels/:id/restore", post(restore_label::<L>))
+        .route("/trash", get(all_trash::<T, L>).delete(purge_trash::<T, L>))
+        .route(
+            "/sync",
+            get(pull_changes::<T, L>).post(push_changes::<T, L, H, W>),
+        )
+        .route("/webhooks", post(create_webhook::<W>).get(all_webhook::<W>))
+        .route("/webhooks/:id", delete(delete_webhook::<W>))
+        .route(
+            "/webhooks/:id/deliveries",
+            get(find_webhook_deliveries::<W>),
+        )
+        .route(
+            "/workspaces",
+            post(create_workspace::<K>).get(all_workspace::<K>),
+        )
+        .route("/workspaces/:id/members", get(find_workspace_members::<K>))
+        .route("/workspaces/:id/invitations", post(create_invitation::<K>))
+        .route("/invitations/:token/accept", post(accept_invitation::<K>))
+        .route(
+            "/tokens",
+            post(create_api_token::<A>).get(all_api_token::<A>),
         )
-        .route("/labels", post(create_label::<L>).get(all_label::<L>))
-        .route("/labels/:id", delete(delete_label::<L>))
+        .route("/tokens/:id", delete(revoke_api_token::<A>))
+        .route("/graphql", get(graphiql).post(graphql::<T, L, H, W>))
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            authorize::<K, Body>,
+        ));
+    // 後から追加したレイヤーが先に実行されるため、認証したユーザーごとに数えてからワークスペースを認可する
+    let router = match state.rate_limiter.clone() {
+        Some(rate_limiter) => router.route_layer(middleware::from_fn_with_state(
+            rate_limiter,
+            rate_limit::<Body>,
+        )),
+        None => router,
+    };
+    let router = router
+        .route_layer(middleware::from_extractor_with_state::<ApiTokenAuth<A>, _>(
+            state.clone(),
+        ))
+        .route_layer(middleware::from_extractor_with_state::<JwtAuth<U>, _>(
+            state.clone(),
+        ))
+        .layer(DefaultBodyLimit::max(state.body_limit));
+    // トークンの総当たりも制限できるよう、認証の失敗は認証より前に数える
+    let router = match state.rate_limiter.clone() {
+        Some(rate_limiter) => router.layer(middleware::from_fn_with_state(
+            rate_limiter,
+            limit_failed_authentication::<Body>,
+        )),
+        None => router,
+    };
+    router
         .layer(
             CorsLayer::new()
                 .allow_origin(AllowOrigin::exact("http://localhost:3001".parse().unwrap()))
                 .allow_methods(Any)
-                .allow_headers(vec![CONTENT_TYPE]),
+                .allow_headers(vec![
+                    AUTHORIZATION,
+                    CONTENT_TYPE,
+                    IF_MATCH,
+                    IDEMPOTENCY_KEY,
+                    REQUEST_ID,
+                    TRACEPARENT,
+                    TRACESTATE,
+                    USER_ID,
+                    WORKSPACE_ID,
+                ])
+                .expose_headers(vec![ETAG, RETRY_AFTER, REQUEST_ID]),
         )
+        // リクエスト ID は `X-Request-Id` ヘッダがあればそれを使い、無ければ生成してレスポンスにも付ける
+        .layer(
+            ServiceBuilder::new()
+                .layer(SetRequestIdLayer::new(REQUEST_ID, MakeRequestUuid))
+                .layer(
+                    TraceLayer::new_for_http()
+                        .make_span_with(make_request_span)
+                        .on_response(record_response),
+                )
+                .layer(PropagateRequestIdLayer::new(REQUEST_ID)),
+        )
+        .with_state(state)
 }
 
 #[cfg(test)]
 mod test {
     use axum::response::Response;
     use hyper::{header, Body, Method, Request, StatusCode};
+    use std::time::Duration;
     use tower::ServiceExt;
 
-    use crate::repositories::label::{test_utils::LabelRepositoryInMemory, Label, LabelRepository};
-    use crate::repositories::todo::{test_utils::TodoRepositoryInMemory, CreateTodo, TodoEntity};
+    use crate::oidc::{
+        test_utils::{config, sign, testdata, ISSUER},
+        JwtVerifier,
+    };
+    use crate::repositories::api_token::memory::ApiTokenRepositoryInMemory;
+    use crate::repositories::history::memory::HistoryRepositoryInMemory;
+    use crate::repositories::idempotency::memory::IdempotencyRepositoryInMemory;
+    use crate::repositories::label::{
+        memory::LabelRepositoryInMemory, CreateLabel, Label, LabelRepository,
+    };
+    use crate::repositories::reminder::memory::ReminderRepositoryInMemory;
+    use crate::repositories::todo::{memory::TodoRepositoryInMemory, CreateTodo, TodoEntity};
+    use crate::repositories::user::memory::UserRepositoryInMemory;
+    use crate::repositories::webhook::memory::WebhookRepositoryInMemory;
+    use crate::repositories::workspace::memory::WorkspaceRepositoryInMemory;
     use crate::{create_routes, AppState};
 
+    type TestAppState = AppState<
+        TodoRepositoryInMemory,
+        LabelRepositoryInMemory,
+        IdempotencyRepositoryInMemory,
+        HistoryRepositoryInMemory,
+        ReminderRepositoryInMemory,
+        WebhookRepositoryInMemory,
+        WorkspaceRepositoryInMemory,
+        ApiTokenRepositoryInMemory,
+        UserRepositoryInMemory,
+    >;
+
+    fn build_app_state(
+        todo_repository: TodoRepositoryInMemory,
+        label_repository: LabelRepositoryInMemory,
+    ) -> TestAppState {
+        AppState::new(
+            todo_repository,
+            label_repository,
+            IdempotencyRepositoryInMemory::new(Duration::from_secs(60)),
+            HistoryRepositoryInMemory::new(),
+            ReminderRepositoryInMemory::new(),
+            WebhookRepositoryInMemory::new(),
+            WorkspaceRepositoryInMemory::new(),
+            ApiTokenRepositoryInMemory::new(),
+            UserRepositoryInMemory::new(),
+        )
+    }
+
     fn build_json_req(path: &str, method: Method, json_body: String) -> Request<Body> {
         Request::builder()
             .uri(path)
@@ -126,10 +915,28 @@ mod test {
             .unwrap()
     }
 
+    // testdata/oidc の鍵で JWT による認証を有効にする
+    async fn with_jwt_auth(state: TestAppState) -> TestAppState {
+        let verifier = JwtVerifier::load(config(testdata("jwks.json")))
+            .await
+            .unwrap();
+        state.with_jwt_verifier(verifier)
+    }
+
+    // user_id を subject に持つ JWT で認証する
+    fn with_jwt(mut req: Request<Body>, user_id: &str) -> Request<Body> {
+        let token = sign("rsa-1", ISSUER, user_id, None);
+        req.headers_mut().insert(
+            header::AUTHORIZATION,
+            format!("Bearer {}", token).parse().unwrap(),
+        );
+        req
+    }
+
     #[tokio::test]
     async fn should_return_hello_world() {
         let req = Request::builder().uri("/").body(Body::empty()).unwrap();
-        let app = create_routes().with_state(AppState::new(
+        let app = create_routes(build_app_state(
             TodoRepositoryInMemory::new(vec![]),
             LabelRepositoryInMemory::new(),
         ));
@@ -139,6 +946,32 @@ mod test {
         assert_eq!(body, "Hello, World!");
     }
 
+    #[tokio::test]
+    async fn should_propagate_request_id() {
+        let app = create_routes(build_app_state(
+            TodoRepositoryInMemory::new(vec![]),
+            LabelRepositoryInMemory::new(),
+        ));
+
+        let res = app
+            .clone()
+            .oneshot(build_empty_req("/todos", Method::GET))
+            .await
+            .unwrap();
+        let request_id = res.headers().get("x-request-id").unwrap();
+        assert!(!request_id.is_empty());
+
+        let mut req = build_empty_req("/todos/1", Method::GET);
+        req.headers_mut()
+            .insert("x-request-id", "client-request-id".parse().unwrap());
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        assert_eq!(
+            res.headers().get("x-request-id").unwrap(),
+            "client-request-id"
+        );
+    }
+
     mod test_todo {
         use super::*;
         use crate::repositories::todo::TodoRepository;
@@ -150,23 +983,9 @@ mod test {
                 .unwrap_or_else(|_| panic!("cannot convert Todo instance. body: {}", body))
         }
 
-        async fn res_to_label(res: Response) -> Label {
-            let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
-            let body: String = String::from_utf8(bytes.to_vec()).unwrap();
-            let label: Label = serde_json::from_str(&body)
-                .expect(&format!("cannot convert Label instance. body: {}", body));
-            label
-        }
-
         fn label_fixture() -> (Vec<Label>, Vec<i32>) {
             let id = 999;
-            (
-                vec![Label {
-                    id,
-                    name: String::from("test label"),
-                }],
-                vec![id],
-            )
+            (vec![Label::new(id, String::from("test label"))], vec![id])
         }
 
         #[tokio::test]
@@ -179,7 +998,7 @@ mod test {
                 Method::POST,
                 r#"{"title": "should_return_crated_todo", "labels": [999]}"#.to_string(),
             );
-            let app = create_routes().with_state(AppState::new(
+            let app = create_routes(build_app_state(
                 TodoRepositoryInMemory::new(labels.clone()),
                 LabelRepositoryInMemory::new(),
             ));
@@ -188,6 +1007,69 @@ mod test {
             assert_eq!(todo, expected);
         }
 
+        #[tokio::test]
+        async fn should_replay_create_todo_with_same_idempotency_key() {
+            let (labels, _label_ids) = label_fixture();
+            let todo_repository = TodoRepositoryInMemory::new(labels.clone());
+            let app = create_routes(build_app_state(
+                todo_repository.clone(),
+                LabelRepositoryInMemory::new(),
+            ));
+            let build_req = |json_body: &str| {
+                let mut req = build_json_req("/todos", Method::POST, json_body.to_string());
+                req.headers_mut()
+                    .insert("idempotency-key", "create-todo-1".parse().unwrap());
+                req
+            };
+
+            let first = app
+                .clone()
+                .oneshot(build_req(
+                    r#"{"title": "idempotent todo", "labels": [999]}"#,
+                ))
+                .await
+                .unwrap();
+            assert_eq!(first.status(), StatusCode::CREATED);
+            assert_eq!(first.headers()[header::ETAG], "\"1\"");
+            let first_body = hyper::body::to_bytes(first.into_body()).await.unwrap();
+
+            let retried = app
+                .clone()
+                .oneshot(build_req(
+                    r#"{"title": "idempotent todo", "labels": [999]}"#,
+                ))
+                .await
+                .unwrap();
+            assert_eq!(retried.status(), StatusCode::CREATED);
+            // 保存したレスポンスヘッダもそのまま再生する
+            assert_eq!(retried.headers()[header::ETAG], "\"1\"");
+            assert_eq!(
+                retried.headers()[header::CONTENT_TYPE],
+                mime::APPLICATION_JSON.as_ref()
+            );
+            let retried_body = hyper::body::to_bytes(retried.into_body()).await.unwrap();
+            assert_eq!(retried_body, first_body);
+            assert_eq!(todo_repository.all().await.unwrap().len(), 1);
+
+            let reused = app
+                .clone()
+                .oneshot(build_req(r#"{"title": "other todo", "labels": [999]}"#))
+                .await
+                .unwrap();
+            assert_eq!(reused.status(), StatusCode::UNPROCESSABLE_ENTITY);
+            assert_eq!(todo_repository.all().await.unwrap().len(), 1);
+
+            // 別のユーザーが同じキーを使っても、保存したレスポンスは返さない
+            let mut other_user = build_req(r#"{"title": "idempotent todo", "labels": [999]}"#);
+            other_user
+                .headers_mut()
+                .insert("x-user-id", "bob".parse().unwrap());
+            let res = app.oneshot(other_user).await.unwrap();
+            assert_eq!(res.status(), StatusCode::CREATED);
+            assert_eq!(res_to_todo(res).await.id, 2);
+            assert_eq!(todo_repository.all().await.unwrap().len(), 2);
+        }
+
         #[tokio::test]
         async fn should_find_todo() {
             let (labels, label_ids) = label_fixture();
@@ -199,11 +1081,12 @@ mod test {
                 .await
                 .expect("failed to create todo");
             let req = build_empty_req("/todos/1", Method::GET);
-            let app = create_routes().with_state(AppState::new(
+            let app = create_routes(build_app_state(
                 todo_repository,
                 LabelRepositoryInMemory::new(),
             ));
             let res = app.oneshot(req).await.unwrap();
+            assert_eq!(res.headers()[header::ETAG], "\"1\"");
             let todo = res_to_todo(res).await;
             assert_eq!(todo, expected);
         }
@@ -225,7 +1108,7 @@ mod test {
                 .await
                 .expect("failed to create todo");
             let req = build_empty_req("/todos", Method::GET);
-            let app = create_routes().with_state(AppState::new(
+            let app = create_routes(build_app_state(
                 todo_repository,
                 LabelRepositoryInMemory::new(),
             ));
@@ -242,6 +1125,7 @@ mod test {
             let (labels, label_ids) = label_fixture();
             let mut expected = TodoEntity::new(1, "should_update_todo".to_string(), labels.clone());
             expected.set_completed(true);
+            expected.set_version(2);
             let todo_repository = TodoRepositoryInMemory::new(labels.clone());
             todo_repository
                 .create(CreateTodo::new("before_update_todo".to_string(), label_ids))
@@ -257,93 +1141,2736 @@ mod test {
                 }"#
                 .to_string(),
             );
-            let app = create_routes().with_state(AppState::new(
+            let app = create_routes(build_app_state(
                 todo_repository,
                 LabelRepositoryInMemory::new(),
             ));
             let res = app.oneshot(req).await.unwrap();
+            assert_eq!(res.headers()[header::ETAG], "\"2\"");
             let todo = res_to_todo(res).await;
             assert_eq!(todo, expected);
         }
 
         #[tokio::test]
-        async fn should_delete_todo() {
+        async fn should_reject_update_todo_with_stale_if_match() {
             let (labels, label_ids) = label_fixture();
-            let todo_repository = TodoRepositoryInMemory::new(labels);
+            let todo_repository = TodoRepositoryInMemory::new(labels.clone());
             todo_repository
-                .create(CreateTodo::new("should_delete_todo".to_string(), label_ids))
+                .create(CreateTodo::new("before_update_todo".to_string(), label_ids))
                 .await
                 .expect("failed to create todo");
-            let req = build_empty_req("/todos/1", Method::DELETE);
-            let app = create_routes().with_state(AppState::new(
-                todo_repository,
+            let mut req = build_json_req(
+                "/todos/1",
+                Method::PATCH,
+                r#"{"title": "should_not_update_todo"}"#.to_string(),
+            );
+            req.headers_mut()
+                .insert(header::IF_MATCH, "\"2\"".parse().unwrap());
+            let app = create_routes(build_app_state(
+                todo_repository.clone(),
                 LabelRepositoryInMemory::new(),
             ));
             let res = app.oneshot(req).await.unwrap();
-            assert_eq!(res.status(), StatusCode::NO_CONTENT);
+            assert_eq!(res.status(), StatusCode::PRECONDITION_FAILED);
+            let todo = todo_repository.find(1).await.unwrap();
+            assert_eq!(todo.version, 1);
         }
-    }
 
-    mod test_label {
-        use super::*;
+        #[tokio::test]
+        async fn should_accept_weak_and_listed_if_match() {
+            let todo_repository = TodoRepositoryInMemory::new(vec![]);
+            todo_repository
+                .create(CreateTodo::new("before_update_todo".to_string(), vec![]))
+                .await
+                .expect("failed to create todo");
+            let app = create_routes(build_app_state(
+                todo_repository.clone(),
+                LabelRepositoryInMemory::new(),
+            ));
 
-        async fn res_to_label(res: Response) -> Label {
-            let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
-            let body: String = String::from_utf8(bytes.to_vec()).unwrap();
-            serde_json::from_str(&body)
-                .unwrap_or_else(|_| panic!("cannot convert Label instance. body: {}", body))
+            let mut req = build_json_req(
+                "/todos/1",
+                Method::PATCH,
+                r#"{"title": "after_update_todo"}"#.to_string(),
+            );
+            req.headers_mut()
+                .insert(header::IF_MATCH, "W/\"1\"".parse().unwrap());
+            let res = app.clone().oneshot(req).await.unwrap();
+            assert_eq!(res.status(), StatusCode::OK);
+            assert_eq!(res_to_todo(res).await.version, 2);
+
+            let mut req = build_empty_req("/todos/1", Method::DELETE);
+            req.headers_mut()
+                .insert(header::IF_MATCH, "\"5\", \"1\"".parse().unwrap());
+            let res = app.clone().oneshot(req).await.unwrap();
+            assert_eq!(res.status(), StatusCode::PRECONDITION_FAILED);
+
+            let mut req = build_empty_req("/todos/1", Method::DELETE);
+            req.headers_mut()
+                .insert(header::IF_MATCH, "\"5\", W/\"2\"".parse().unwrap());
+            let res = app.oneshot(req).await.unwrap();
+            assert_eq!(res.status(), StatusCode::NO_CONTENT);
+            assert!(todo_repository.find(1).await.is_err());
         }
 
-        fn build_app_state<T: LabelRepository>(
-            repository: T,
-        ) -> AppState<TodoRepositoryInMemory, T> {
-            AppState::new(TodoRepositoryInMemory::new(vec![]), repository)
+        fn build_patch_req(path: &str, content_type: &str, body: &str) -> Request<Body> {
+            Request::builder()
+                .uri(path)
+                .method(Method::PATCH)
+                .header(header::CONTENT_TYPE, content_type)
+                .body(Body::from(body.to_string()))
+                .unwrap()
         }
 
         #[tokio::test]
-        async fn should_create_label() {
-            let expected = Label::new(1, "should_create_label".to_string());
-            let req = build_json_req(
-                "/labels",
-                Method::POST,
-                r#"{"name": "should_create_label"}"#.to_string(),
+        async fn should_merge_patch_todo() {
+            let (labels, label_ids) = label_fixture();
+            let todo_repository = TodoRepositoryInMemory::new(labels.clone());
+            todo_repository
+                .create(CreateTodo::new("before_merge_patch".to_string(), label_ids))
+                .await
+                .expect("failed to create todo");
+            let app = create_routes(build_app_state(
+                todo_repository.clone(),
+                LabelRepositoryInMemory::new(),
+            ));
+
+            let req = build_patch_req(
+                "/todos/1",
+                "application/merge-patch+json",
+                r#"{"completed": true}"#,
+            );
+            let res = app.clone().oneshot(req).await.unwrap();
+            assert_eq!(res.status(), StatusCode::OK);
+            let todo = res_to_todo(res).await;
+            assert_eq!(todo.title, "before_merge_patch");
+            assert!(todo.completed);
+            assert_eq!(todo.labels, labels);
+
+            let req = build_patch_req(
+                "/todos/1",
+                "application/merge-patch+json",
+                r#"{"labels": null}"#,
+            );
+            let res = app.clone().oneshot(req).await.unwrap();
+            assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+            let req = build_patch_req(
+                "/todos/1",
+                "application/merge-patch+json",
+                r#"{"title": ""}"#,
             );
-            let app = create_routes().with_state(build_app_state(LabelRepositoryInMemory::new()));
             let res = app.oneshot(req).await.unwrap();
-            let label = res_to_label(res).await;
-            assert_eq!(label, expected);
+            assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+            let todo = todo_repository.find(1).await.unwrap();
+            assert_eq!(todo.version, 2);
         }
 
         #[tokio::test]
-        async fn should_get_all_labels() {
-            let label_name = "should_get_all_labels";
-            let expected = vec![Label::new(1, label_name.to_string())];
-            let repository = LabelRepositoryInMemory::new();
-            repository
-                .create(label_name.to_string())
+        async fn should_clear_todo_description_with_null() {
+            let todo_repository = TodoRepositoryInMemory::new(vec![]);
+            todo_repository
+                .create(CreateTodo::new("description".to_string(), vec![]))
                 .await
-                .expect("failed to create label");
-            let req = build_empty_req("/labels", Method::GET);
-            let app = create_routes().with_state(build_app_state(repository));
+                .expect("failed to create todo");
+            let app = create_routes(build_app_state(
+                todo_repository.clone(),
+                LabelRepositoryInMemory::new(),
+            ));
+
+            let req = build_patch_req(
+                "/todos/1",
+                "application/merge-patch+json",
+                r#"{"description": "memo"}"#,
+            );
+            let res = app.clone().oneshot(req).await.unwrap();
+            assert_eq!(res.status(), StatusCode::OK);
+            let todo = res_to_todo(res).await;
+            assert_eq!(todo.description.as_deref(), Some("memo"));
+
+            // 省略したフィールドは変わらない
+            let req = build_json_req(
+                "/todos/1",
+                Method::PATCH,
+                r#"{"completed": true}"#.to_string(),
+            );
+            let res = app.clone().oneshot(req).await.unwrap();
+            let todo = res_to_todo(res).await;
+            assert_eq!(todo.description.as_deref(), Some("memo"));
+
+            let req = build_patch_req(
+                "/todos/1",
+                "application/merge-patch+json",
+                r#"{"description": null}"#,
+            );
+            let res = app.clone().oneshot(req).await.unwrap();
+            assert_eq!(res.status(), StatusCode::OK);
+            let todo = res_to_todo(res).await;
+            assert_eq!(todo.description, None);
+            assert_eq!(todo.title, "description");
+
+            let req = build_json_req(
+                "/todos/1",
+                Method::PATCH,
+                r#"{"description": "memo"}"#.to_string(),
+            );
+            app.clone().oneshot(req).await.unwrap();
+            let req = build_patch_req(
+                "/todos/1",
+                "application/json-patch+json",
+                r#"[{"op": "remove", "path": "/description"}]"#,
+            );
+            let res = app.clone().oneshot(req).await.unwrap();
+            assert_eq!(res.status(), StatusCode::OK);
+            let todo = res_to_todo(res).await;
+            assert_eq!(todo.description, None);
+
+            let req = build_json_req(
+                "/todos/1",
+                Method::PATCH,
+                r#"{"description": "memo"}"#.to_string(),
+            );
+            app.clone().oneshot(req).await.unwrap();
+            let req = build_json_req(
+                "/todos/1",
+                Method::PATCH,
+                r#"{"description": null}"#.to_string(),
+            );
             let res = app.oneshot(req).await.unwrap();
-            let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
-            let body: String = String::from_utf8(bytes.to_vec()).unwrap();
-            let labels: Vec<Label> = serde_json::from_str(&body)
-                .unwrap_or_else(|_| panic!("cannot convert Label instance. body: {}", body));
-            assert_eq!(labels, expected);
+            let todo = res_to_todo(res).await;
+            assert_eq!(todo.description, None);
         }
 
         #[tokio::test]
-        async fn should_delete_label() {
-            let repository = LabelRepositoryInMemory::new();
-            repository
-                .create("should_delete_label".to_string())
+        async fn should_json_patch_todo_labels() {
+            let labels = vec![
+                Label::new(1, String::from("first")),
+                Label::new(2, String::from("second")),
+            ];
+            let todo_repository = TodoRepositoryInMemory::new(labels.clone());
+            todo_repository
+                .create(CreateTodo::new("json_patch".to_string(), vec![1]))
                 .await
-                .expect("failed to create label");
+                .expect("failed to create todo");
+            let app = create_routes(build_app_state(
+                todo_repository.clone(),
+                LabelRepositoryInMemory::new(),
+            ));
+
+            let req = build_patch_req(
+                "/todos/1",
+                "application/json-patch+json",
+                r#"[
+                    {"op": "add", "path": "/labels/-", "value": 2},
+                    {"op": "remove", "path": "/labels/0"}
+                ]"#,
+            );
+            let res = app.clone().oneshot(req).await.unwrap();
+            assert_eq!(res.status(), StatusCode::OK);
+            let todo = res_to_todo(res).await;
+            assert_eq!(todo.labels, vec![labels[1].clone()]);
+
+            let req = build_patch_req(
+                "/todos/1",
+                "application/json-patch+json",
+                r#"[
+                    {"op": "test", "path": "/title", "value": "other"},
+                    {"op": "replace", "path": "/completed", "value": true}
+                ]"#,
+            );
+            let res = app.clone().oneshot(req).await.unwrap();
+            assert_eq!(res.status(), StatusCode::CONFLICT);
+            let todo = todo_repository.find(1).await.unwrap();
+            assert!(!todo.completed);
+
+            let req = build_patch_req("/todos/1", "text/plain", r#"{"completed": true}"#);
+            let res = app.oneshot(req).await.unwrap();
+            assert_eq!(res.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        }
+
+        #[tokio::test]
+        async fn should_reject_delete_todo_with_stale_if_match() {
+            let (labels, label_ids) = label_fixture();
+            let todo_repository = TodoRepositoryInMemory::new(labels);
+            todo_repository
+                .create(CreateTodo::new("should_delete_todo".to_string(), label_ids))
+                .await
+                .expect("failed to create todo");
+            let mut req = build_empty_req("/todos/1", Method::DELETE);
+            req.headers_mut()
+                .insert(header::IF_MATCH, "\"2\"".parse().unwrap());
+            let app = create_routes(build_app_state(
+                todo_repository,
+                LabelRepositoryInMemory::new(),
+            ));
+            let res = app.oneshot(req).await.unwrap();
+            assert_eq!(res.status(), StatusCode::PRECONDITION_FAILED);
+        }
+
+        #[tokio::test]
+        async fn should_refuse_completing_blocked_todo_without_force() {
+            let todo_repository = TodoRepositoryInMemory::new(vec![]);
+            for title in ["blocked", "blocker"] {
+                todo_repository
+                    .create(CreateTodo::new(title.to_string(), vec![]))
+                    .await
+                    .expect("failed to create todo");
+            }
+            let app = create_routes(build_app_state(
+                todo_repository.clone(),
+                LabelRepositoryInMemory::new(),
+            ));
+
+            let req = build_json_req(
+                "/todos/1/blockers",
+                Method::POST,
+                r#"{"blocker_id": 2}"#.to_string(),
+            );
+            let res = app.clone().oneshot(req).await.unwrap();
+            let todo = res_to_todo(res).await;
+            assert!(todo.blocked);
+            assert_eq!(todo.blocked_by, vec![2]);
+
+            let req = build_json_req(
+                "/todos/2/blockers",
+                Method::POST,
+                r#"{"blocker_id": 1}"#.to_string(),
+            );
+            let res = app.clone().oneshot(req).await.unwrap();
+            assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+            let req = build_json_req(
+                "/todos/1",
+                Method::PATCH,
+                r#"{"completed": true}"#.to_string(),
+            );
+            let res = app.clone().oneshot(req).await.unwrap();
+            assert_eq!(res.status(), StatusCode::CONFLICT);
+
+            let req = build_json_req(
+                "/todos/1?force=true",
+                Method::PATCH,
+                r#"{"completed": true}"#.to_string(),
+            );
+            let res = app.clone().oneshot(req).await.unwrap();
+            assert_eq!(res.status(), StatusCode::OK);
+
+            let res = app
+                .oneshot(build_empty_req("/todos/1/blockers/2", Method::DELETE))
+                .await
+                .unwrap();
+            let todo = res_to_todo(res).await;
+            assert!(todo.blocked_by.is_empty());
+        }
+
+        #[tokio::test]
+        async fn should_delete_todo() {
+            let (labels, label_ids) = label_fixture();
+            let todo_repository = TodoRepositoryInMemory::new(labels);
+            todo_repository
+                .create(CreateTodo::new("should_delete_todo".to_string(), label_ids))
+                .await
+                .expect("failed to create todo");
+            let req = build_empty_req("/todos/1", Method::DELETE);
+            let app = create_routes(build_app_state(
+                todo_repository,
+                LabelRepositoryInMemory::new(),
+            ));
+            let res = app.oneshot(req).await.unwrap();
+            assert_eq!(res.status(), StatusCode::NO_CONTENT);
+        }
+    }
+
+    mod test_label {
+        use super::*;
+        use crate::handlers::label::LabelTodos;
+        use crate::repositories::label::{LabelNode, LabelWithCount, TodoCount};
+        use crate::repositories::todo::TodoRepository;
+
+        async fn res_to_label(res: Response) -> Label {
+            let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+            let body: String = String::from_utf8(bytes.to_vec()).unwrap();
+            serde_json::from_str(&body)
+                .unwrap_or_else(|_| panic!("cannot convert Label instance. body: {}", body))
+        }
+
+        #[tokio::test]
+        async fn should_create_label() {
+            let expected = Label::new(1, "should_create_label".to_string());
+            let req = build_json_req(
+                "/labels",
+                Method::POST,
+                r#"{"name": "should_create_label"}"#.to_string(),
+            );
+            let app = create_routes(build_app_state(
+                TodoRepositoryInMemory::new(vec![]),
+                LabelRepositoryInMemory::new(),
+            ));
+            let res = app.oneshot(req).await.unwrap();
+            let label = res_to_label(res).await;
+            assert_eq!(label, expected);
+        }
+
+        #[tokio::test]
+        async fn should_get_all_labels() {
+            let label_name = "should_get_all_labels";
+            let expected = vec![Label::new(1, label_name.to_string())];
+            let repository = LabelRepositoryInMemory::new();
+            repository
+                .create(CreateLabel::new(label_name.to_string()))
+                .await
+                .expect("failed to create label");
+            let req = build_empty_req("/labels", Method::GET);
+            let app = create_routes(build_app_state(
+                TodoRepositoryInMemory::new(vec![]),
+                repository,
+            ));
+            let res = app.oneshot(req).await.unwrap();
+            let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+            let body: String = String::from_utf8(bytes.to_vec()).unwrap();
+            let labels: Vec<Label> = serde_json::from_str(&body)
+                .unwrap_or_else(|_| panic!("cannot convert Label instance. body: {}", body));
+            assert_eq!(labels, expected);
+        }
+
+        #[tokio::test]
+        async fn should_count_todos_by_label() {
+            let label = Label::new(1, "should_count_todos_by_label".to_string());
+            let todo_repository = TodoRepositoryInMemory::new(vec![]);
+            let label_repository = LabelRepositoryInMemory::with_todos(todo_repository.clone());
+            label_repository
+                .create(CreateLabel::new(label.name))
+                .await
+                .expect("failed to create label");
+            todo_repository
+                .create(CreateTodo::new("counted".to_string(), vec![label.id]))
+                .await
+                .expect("failed to create todo");
+            let app = create_routes(build_app_state(todo_repository, label_repository));
+
+            let req = build_json_req(
+                "/labels",
+                Method::POST,
+                r#"{"name": "invalid color", "color": "red"}"#.to_string(),
+            );
+            let res = app.clone().oneshot(req).await.unwrap();
+            assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+            let res = app
+                .oneshot(build_empty_req("/labels", Method::GET))
+                .await
+                .unwrap();
+            let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+            let labels: Vec<LabelWithCount> = serde_json::from_slice(&bytes).unwrap();
+            assert_eq!(
+                labels[0].todo_count,
+                TodoCount {
+                    total: 1,
+                    open: 1,
+                    completed: 0,
+                }
+            );
+        }
+
+        #[tokio::test]
+        async fn should_build_label_tree() {
+            let app = create_routes(build_app_state(
+                TodoRepositoryInMemory::new(vec![]),
+                LabelRepositoryInMemory::new(),
+            ));
+            for body in [
+                r#"{"name": "work"}"#,
+                r#"{"name": "work/backend", "parent_id": 1}"#,
+                r#"{"name": "work/backend/db", "parent_id": 2}"#,
+                r#"{"name": "home"}"#,
+            ] {
+                let req = build_json_req("/labels", Method::POST, body.to_string());
+                let res = app.clone().oneshot(req).await.unwrap();
+                assert_eq!(res.status(), StatusCode::CREATED);
+            }
+
+            let req = build_json_req(
+                "/labels/1/parent",
+                Method::PUT,
+                r#"{"parent_id": 3}"#.to_string(),
+            );
+            let res = app.clone().oneshot(req).await.unwrap();
+            assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+            let req = build_json_req(
+                "/labels/4/parent",
+                Method::PUT,
+                r#"{"parent_id": 1}"#.to_string(),
+            );
+            let res = app.clone().oneshot(req).await.unwrap();
+            assert_eq!(res_to_label(res).await.parent_id, Some(1));
+
+            let res = app
+                .oneshot(build_empty_req("/labels/tree", Method::GET))
+                .await
+                .unwrap();
+            let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+            let tree: Vec<LabelNode> = serde_json::from_slice(&bytes).unwrap();
+            assert_eq!(tree.len(), 1);
+            assert_eq!(tree[0].label.label.name, "work");
+            let children: Vec<&str> = tree[0]
+                .children
+                .iter()
+                .map(|node| node.label.label.name.as_str())
+                .collect();
+            assert_eq!(children, vec!["work/backend", "home"]);
+            assert_eq!(tree[0].children[0].children[0].label.label.id, 3);
+        }
+
+        #[tokio::test]
+        async fn should_find_label_with_todos() {
+            let label = Label::new(1, "should_find_label_with_todos".to_string());
+            let todo_repository = TodoRepositoryInMemory::new(vec![]);
+            let label_repository = LabelRepositoryInMemory::with_todos(todo_repository.clone());
+            label_repository
+                .create(CreateLabel::new(label.name.clone()))
+                .await
+                .expect("failed to create label");
+            for title in ["first", "second", "third"] {
+                todo_repository
+                    .create(CreateTodo::new(title.to_string(), vec![label.id]))
+                    .await
+                    .expect("failed to create todo");
+            }
+            let app = create_routes(build_app_state(todo_repository, label_repository));
+
+            let res = app
+                .clone()
+                .oneshot(build_empty_req("/labels/1", Method::GET))
+                .await
+                .unwrap();
+            assert_eq!(res_to_label(res).await, label);
+            let res = app
+                .clone()
+                .oneshot(build_empty_req("/labels/999", Method::GET))
+                .await
+                .unwrap();
+            assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+            let res = app
+                .clone()
+                .oneshot(build_empty_req(
+                    "/labels/1/todos?page=2&per_page=2",
+                    Method::GET,
+                ))
+                .await
+                .unwrap();
+            assert_eq!(res.status(), StatusCode::OK);
+            let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+            let body: LabelTodos = serde_json::from_slice(&bytes).unwrap();
+            assert_eq!(body.label, label);
+            assert_eq!(body.total, 3);
+            assert_eq!((body.page, body.per_page), (2, 2));
+            let titles: Vec<&str> = body.todos.iter().map(|t| t.title.as_str()).collect();
+            assert_eq!(titles, vec!["first"]);
+
+            let res = app
+                .clone()
+                .oneshot(build_empty_req("/labels/1/todos?per_page=0", Method::GET))
+                .await
+                .unwrap();
+            assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+            let res = app
+                .clone()
+                .oneshot(build_empty_req(
+                    "/labels/1/todos?page=9223372036854775807&per_page=100",
+                    Method::GET,
+                ))
+                .await
+                .unwrap();
+            assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+            let res = app
+                .oneshot(build_empty_req("/labels/999/todos", Method::GET))
+                .await
+                .unwrap();
+            assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        }
+
+        #[tokio::test]
+        async fn should_merge_labels() {
+            let bug = Label::new(1, "bug".to_string());
+            let bugs = Label::new(2, "bugs".to_string());
+            let todo_repository = TodoRepositoryInMemory::new(vec![]);
+            let label_repository = LabelRepositoryInMemory::with_todos(todo_repository.clone());
+            for label in [&bug, &bugs] {
+                label_repository
+                    .create(CreateLabel::new(label.name.clone()))
+                    .await
+                    .expect("failed to create label");
+            }
+            todo_repository
+                .create(CreateTodo::new("both".to_string(), vec![bug.id, bugs.id]))
+                .await
+                .expect("failed to create todo");
+            let app = create_routes(build_app_state(todo_repository.clone(), label_repository));
+
+            let req = build_json_req(
+                "/labels/2/merge",
+                Method::POST,
+                r#"{"target_id": 2}"#.to_string(),
+            );
+            let res = app.clone().oneshot(req).await.unwrap();
+            assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+            let req = build_json_req(
+                "/labels/2/merge",
+                Method::POST,
+                r#"{"target_id": 999}"#.to_string(),
+            );
+            let res = app.clone().oneshot(req).await.unwrap();
+            assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+            let req = build_json_req(
+                "/labels/2/merge",
+                Method::POST,
+                r#"{"target_id": 1}"#.to_string(),
+            );
+            let res = app.clone().oneshot(req).await.unwrap();
+            assert_eq!(res.status(), StatusCode::OK);
+            assert_eq!(res_to_label(res).await, bug);
+
+            let todo = todo_repository.find(1).await.unwrap();
+            assert_eq!(todo.labels, vec![bug]);
+            let req = build_empty_req("/labels/2", Method::DELETE);
+            let res = app.oneshot(req).await.unwrap();
+            assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        }
+
+        #[tokio::test]
+        async fn should_delete_label() {
+            let repository = LabelRepositoryInMemory::new();
+            repository
+                .create(CreateLabel::new("should_delete_label".to_string()))
+                .await
+                .expect("failed to create label");
             let req = build_empty_req("/labels/1", Method::DELETE);
-            let app = create_routes().with_state(build_app_state(repository));
+            let app = create_routes(build_app_state(
+                TodoRepositoryInMemory::new(vec![]),
+                repository,
+            ));
             let res = app.oneshot(req).await.unwrap();
             assert_eq!(res.status(), StatusCode::NO_CONTENT);
         }
     }
+
+    mod test_trash {
+        use super::*;
+        use crate::handlers::trash::Trash;
+        use crate::repositories::todo::TodoRepository;
+
+        async fn res_to_trash(res: Response) -> Trash {
+            let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+            let body: String = String::from_utf8(bytes.to_vec()).unwrap();
+            serde_json::from_str(&body)
+                .unwrap_or_else(|_| panic!("cannot convert Trash instance. body: {}", body))
+        }
+
+        #[tokio::test]
+        async fn should_move_deleted_todo_to_trash_and_restore() {
+            let todo_repository = TodoRepositoryInMemory::new(vec![]);
+            todo_repository
+                .create(CreateTodo::new("trashed_todo".to_string(), vec![]))
+                .await
+                .expect("failed to create todo");
+            let app = create_routes(build_app_state(
+                todo_repository.clone(),
+                LabelRepositoryInMemory::new(),
+            ));
+
+            let res = app
+                .clone()
+                .oneshot(build_empty_req("/todos/1", Method::DELETE))
+                .await
+                .unwrap();
+            assert_eq!(res.status(), StatusCode::NO_CONTENT);
+
+            let res = app
+                .clone()
+                .oneshot(build_empty_req("/trash", Method::GET))
+                .await
+                .unwrap();
+            let trash = res_to_trash(res).await;
+            assert_eq!(trash.todos.len(), 1);
+            assert_eq!(
+                trash.todos[0].todo,
+                TodoEntity::new(1, "trashed_todo".to_string(), vec![])
+            );
+
+            let res = app
+                .clone()
+                .oneshot(build_empty_req("/todos/1/restore", Method::POST))
+                .await
+                .unwrap();
+            assert_eq!(res.status(), StatusCode::OK);
+            assert!(todo_repository.find(1).await.is_ok());
+
+            let res = app
+                .oneshot(build_empty_req("/todos/1/restore", Method::POST))
+                .await
+                .unwrap();
+            assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        }
+
+        #[tokio::test]
+        async fn should_purge_trash() {
+            let label_repository = LabelRepositoryInMemory::new();
+            label_repository
+                .create(CreateLabel::new("trashed_label".to_string()))
+                .await
+                .expect("failed to create label");
+            label_repository
+                .delete(1)
+                .await
+                .expect("failed to delete label");
+            let app = create_routes(build_app_state(
+                TodoRepositoryInMemory::new(vec![]),
+                label_repository.clone(),
+            ));
+
+            let res = app
+                .clone()
+                .oneshot(build_empty_req("/trash", Method::DELETE))
+                .await
+                .unwrap();
+            assert_eq!(res.status(), StatusCode::NO_CONTENT);
+
+            let res = app
+                .oneshot(build_empty_req("/trash", Method::GET))
+                .await
+                .unwrap();
+            let trash = res_to_trash(res).await;
+            assert!(trash.labels.is_empty());
+        }
+    }
+
+    mod test_sync {
+        use super::*;
+        use crate::handlers::sync::{PushResults, SyncChanges, SyncStatus};
+        use crate::repositories::todo::TodoRepository;
+
+        async fn res_to_json<T: serde::de::DeserializeOwned>(res: Response) -> T {
+            let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+            let body: String = String::from_utf8(bytes.to_vec()).unwrap();
+            serde_json::from_str(&body)
+                .unwrap_or_else(|_| panic!("cannot convert response. body: {}", body))
+        }
+
+        #[tokio::test]
+        async fn should_pull_changes_since_cursor() {
+            let todo_repository = TodoRepositoryInMemory::new(vec![]);
+            let label_repository = LabelRepositoryInMemory::new();
+            let todo = todo_repository
+                .create(CreateTodo::new("synced_todo".to_string(), vec![]))
+                .await
+                .expect("failed to create todo");
+            let label = label_repository
+                .create(CreateLabel::new("synced_label".to_string()))
+                .await
+                .expect("failed to create label");
+            let app = create_routes(build_app_state(todo_repository, label_repository));
+
+            let res = app
+                .clone()
+                .oneshot(build_empty_req("/sync", Method::GET))
+                .await
+                .unwrap();
+            assert_eq!(res.status(), StatusCode::OK);
+            let changes: SyncChanges = res_to_json(res).await;
+            assert_eq!(changes.todos.upserts, vec![todo]);
+            assert_eq!(changes.labels.upserts, vec![label]);
+
+            let res = app
+                .clone()
+                .oneshot(build_empty_req("/todos/1", Method::DELETE))
+                .await
+                .unwrap();
+            assert_eq!(res.status(), StatusCode::NO_CONTENT);
+
+            let uri = format!("/sync?since={}", changes.cursor);
+            let res = app
+                .clone()
+                .oneshot(build_empty_req(&uri, Method::GET))
+                .await
+                .unwrap();
+            let changes: SyncChanges = res_to_json(res).await;
+            assert!(changes.todos.upserts.is_empty());
+            assert_eq!(changes.todos.tombstones, vec![1]);
+            assert!(changes.labels.upserts.is_empty());
+            assert!(changes.labels.tombstones.is_empty());
+
+            let res = app
+                .oneshot(build_empty_req("/sync?since=-1", Method::GET))
+                .await
+                .unwrap();
+            assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        }
+
+        #[tokio::test]
+        async fn should_push_client_changes() {
+            let todo_repository = TodoRepositoryInMemory::new(vec![]);
+            let label_repository = LabelRepositoryInMemory::new();
+            todo_repository
+                .create(CreateTodo::new("offline_todo".to_string(), vec![]))
+                .await
+                .expect("failed to create todo");
+            label_repository
+                .create(CreateLabel::new("offline_label".to_string()))
+                .await
+                .expect("failed to create label");
+            let app = create_routes(build_app_state(todo_repository.clone(), label_repository));
+
+            let req = build_json_req(
+                "/sync",
+                Method::POST,
+                r#"{"changes": [
+                    {"op": "create_todo", "client_id": "local-1", "todo": {"title": "created offline", "labels": []}},
+                    {"op": "update_todo", "id": 1, "version": 1, "todo": {"completed": true}},
+                    {"op": "update_todo", "id": 1, "version": 1, "todo": {"title": "stale"}},
+                    {"op": "update_todo", "id": 1, "todo": {"title": "last writer"}},
+                    {"op": "delete_todo", "id": 99},
+                    {"op": "create_label", "client_id": "local-2", "label": {"name": ""}},
+                    {"op": "create_label", "client_id": "local-3", "label": {"name": "offline_label"}}
+                ]}"#
+                .to_string(),
+            );
+            let res = app.oneshot(req).await.unwrap();
+            assert_eq!(res.status(), StatusCode::OK);
+            let PushResults { results } = res_to_json(res).await;
+            let statuses: Vec<SyncStatus> = results.iter().map(|result| result.status).collect();
+            assert_eq!(
+                statuses,
+                vec![
+                    SyncStatus::Applied,
+                    SyncStatus::Applied,
+                    SyncStatus::Conflict,
+                    SyncStatus::Applied,
+                    SyncStatus::Applied,
+                    SyncStatus::Rejected,
+                    SyncStatus::Conflict,
+                ]
+            );
+            assert_eq!(results[0].client_id.as_deref(), Some("local-1"));
+            assert_eq!(results[0].todo.as_ref().unwrap().title, "created offline");
+            // 競合した場合はサーバー側の最新の状態が返る
+            assert_eq!(results[2].todo.as_ref().unwrap().version, 2);
+            assert_eq!(results[6].label.as_ref().unwrap().id, 1);
+
+            let todo = todo_repository.find(1).await.unwrap();
+            assert_eq!(todo.title, "last writer");
+            assert!(todo.completed);
+            assert_eq!(todo.version, 3);
+        }
+    }
+
+    mod test_history {
+        use super::*;
+        use crate::repositories::history::{ChangeAction, ChangeRecord};
+        use crate::repositories::todo::TodoRepository;
+        use crate::repositories::webhook::{CreateWebhook, WebhookEvent, WebhookRepository};
+
+        async fn res_to_change(res: Response) -> ChangeRecord {
+            let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+            let body: String = String::from_utf8(bytes.to_vec()).unwrap();
+            serde_json::from_str(&body)
+                .unwrap_or_else(|_| panic!("cannot convert ChangeRecord instance. body: {}", body))
+        }
+
+        fn with_user(mut req: Request<Body>, user_id: &str) -> Request<Body> {
+            req.headers_mut()
+                .insert("x-user-id", user_id.parse().unwrap());
+            req
+        }
+
+        #[tokio::test]
+        async fn should_undo_and_redo_todo_update() {
+            let todo_repository = TodoRepositoryInMemory::new(vec![]);
+            let app = create_routes(build_app_state(
+                todo_repository.clone(),
+                LabelRepositoryInMemory::new(),
+            ));
+
+            let req = build_json_req(
+                "/todos",
+                Method::POST,
+                r#"{"title": "before", "labels": []}"#.to_string(),
+            );
+            let res = app.clone().oneshot(with_user(req, "alice")).await.unwrap();
+            assert_eq!(res.status(), StatusCode::CREATED);
+            let req = build_json_req(
+                "/todos/1",
+                Method::PATCH,
+                r#"{"title": "after"}"#.to_string(),
+            );
+            let res = app.clone().oneshot(with_user(req, "alice")).await.unwrap();
+            assert_eq!(res.status(), StatusCode::OK);
+
+            // other users have nothing to undo
+            let req = build_empty_req("/history/undo", Method::POST);
+            let res = app.clone().oneshot(with_user(req, "bob")).await.unwrap();
+            assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+            // undo
+            let req = build_empty_req("/history/undo", Method::POST);
+            let res = app.clone().oneshot(with_user(req, "alice")).await.unwrap();
+            let change = res_to_change(res).await;
+            assert_eq!(change.action, ChangeAction::Update);
+            assert!(change.undone);
+            let mut expected = TodoEntity::new(1, "before".to_string(), vec![]);
+            expected.set_version(3);
+            assert_eq!(todo_repository.find(1).await.unwrap(), expected);
+
+            // redo
+            let req = build_empty_req("/history/redo", Method::POST);
+            let res = app.clone().oneshot(with_user(req, "alice")).await.unwrap();
+            let change = res_to_change(res).await;
+            assert!(!change.undone);
+            let mut expected = TodoEntity::new(1, "after".to_string(), vec![]);
+            expected.set_version(4);
+            assert_eq!(todo_repository.find(1).await.unwrap(), expected);
+
+            // history
+            let res = app
+                .oneshot(build_empty_req("/todos/1/history", Method::GET))
+                .await
+                .unwrap();
+            let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+            let records: Vec<ChangeRecord> = serde_json::from_slice(&bytes).unwrap();
+            let actions: Vec<ChangeAction> = records.iter().map(|r| r.action).collect();
+            assert_eq!(actions, vec![ChangeAction::Create, ChangeAction::Update]);
+        }
+
+        #[tokio::test]
+        async fn should_undo_todo_delete() {
+            let todo_repository = TodoRepositoryInMemory::new(vec![]);
+            todo_repository
+                .create(CreateTodo::new("deleted".to_string(), vec![]))
+                .await
+                .expect("failed to create todo");
+            let app = create_routes(build_app_state(
+                todo_repository.clone(),
+                LabelRepositoryInMemory::new(),
+            ));
+
+            let res = app
+                .clone()
+                .oneshot(build_empty_req("/todos/1", Method::DELETE))
+                .await
+                .unwrap();
+            assert_eq!(res.status(), StatusCode::NO_CONTENT);
+            assert!(todo_repository.find(1).await.is_err());
+
+            let res = app
+                .oneshot(build_empty_req("/history/undo", Method::POST))
+                .await
+                .unwrap();
+            assert_eq!(res.status(), StatusCode::OK);
+            assert!(todo_repository.find(1).await.is_ok());
+        }
+
+        #[tokio::test]
+        async fn should_not_find_todo_to_undo_after_it_is_deleted() {
+            let todo_repository = TodoRepositoryInMemory::new(vec![]);
+            let app = create_routes(build_app_state(
+                todo_repository.clone(),
+                LabelRepositoryInMemory::new(),
+            ));
+
+            let req = build_json_req(
+                "/todos",
+                Method::POST,
+                r#"{"title": "before", "labels": []}"#.to_string(),
+            );
+            let res = app.clone().oneshot(with_user(req, "alice")).await.unwrap();
+            assert_eq!(res.status(), StatusCode::CREATED);
+            let req = build_json_req(
+                "/todos/1",
+                Method::PATCH,
+                r#"{"title": "after"}"#.to_string(),
+            );
+            let res = app.clone().oneshot(with_user(req, "alice")).await.unwrap();
+            assert_eq!(res.status(), StatusCode::OK);
+            todo_repository.delete(1, None).await.unwrap();
+
+            let req = build_empty_req("/history/undo", Method::POST);
+            let res = app.oneshot(with_user(req, "alice")).await.unwrap();
+            assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        }
+
+        async fn send(app: &axum::Router, req: Request<Body>, user_id: &str) -> StatusCode {
+            app.clone()
+                .oneshot(with_user(req, user_id))
+                .await
+                .unwrap()
+                .status()
+        }
+
+        #[tokio::test]
+        async fn should_skip_changes_that_conflict_with_later_edits() {
+            let todo_repository = TodoRepositoryInMemory::new(vec![]);
+            let app = create_routes(build_app_state(
+                todo_repository.clone(),
+                LabelRepositoryInMemory::new(),
+            ));
+            for title in ["first", "second"] {
+                let body = format!(r#"{{"title": "{}", "labels": []}}"#, title);
+                let req = build_json_req("/todos", Method::POST, body);
+                assert_eq!(send(&app, req, "alice").await, StatusCode::CREATED);
+            }
+            for user_id in ["alice", "bob"] {
+                let body = format!(r#"{{"title": "{}"}}"#, user_id);
+                let req = build_json_req("/todos/2", Method::PATCH, body);
+                assert_eq!(send(&app, req, user_id).await, StatusCode::OK);
+            }
+
+            // bob の変更を上書きせず、取り消せなかった変更は飛ばして次の変更を取り消す
+            let req = build_empty_req("/history/undo", Method::POST);
+            assert_eq!(send(&app, req, "alice").await, StatusCode::CONFLICT);
+            assert_eq!(todo_repository.find(2).await.unwrap().title, "bob");
+            let req = build_empty_req("/history/undo", Method::POST);
+            assert_eq!(send(&app, req, "alice").await, StatusCode::CONFLICT);
+            assert!(todo_repository.find(2).await.is_ok());
+            let req = build_empty_req("/history/undo", Method::POST);
+            assert_eq!(send(&app, req, "alice").await, StatusCode::OK);
+            assert!(todo_repository.find(1).await.is_err());
+
+            let res = app
+                .oneshot(build_empty_req("/todos/2/history", Method::GET))
+                .await
+                .unwrap();
+            let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+            let records: Vec<ChangeRecord> = serde_json::from_slice(&bytes).unwrap();
+            let skipped: Vec<bool> = records.iter().map(|r| r.skipped).collect();
+            assert_eq!(skipped, vec![true, true, false]);
+        }
+
+        #[tokio::test]
+        async fn should_not_redo_over_later_edits() {
+            let todo_repository = TodoRepositoryInMemory::new(vec![]);
+            let app = create_routes(build_app_state(
+                todo_repository.clone(),
+                LabelRepositoryInMemory::new(),
+            ));
+            let req = build_json_req(
+                "/todos",
+                Method::POST,
+                r#"{"title": "before", "labels": []}"#.to_string(),
+            );
+            assert_eq!(send(&app, req, "alice").await, StatusCode::CREATED);
+            let req = build_json_req(
+                "/todos/1",
+                Method::PATCH,
+                r#"{"title": "after"}"#.to_string(),
+            );
+            assert_eq!(send(&app, req, "alice").await, StatusCode::OK);
+            let req = build_empty_req("/history/undo", Method::POST);
+            assert_eq!(send(&app, req, "alice").await, StatusCode::OK);
+
+            let req = build_json_req("/todos/1", Method::PATCH, r#"{"title": "bob"}"#.to_string());
+            assert_eq!(send(&app, req, "bob").await, StatusCode::OK);
+            let req = build_empty_req("/history/redo", Method::POST);
+            assert_eq!(send(&app, req, "alice").await, StatusCode::CONFLICT);
+            assert_eq!(todo_repository.find(1).await.unwrap().title, "bob");
+            let req = build_empty_req("/history/redo", Method::POST);
+            assert_eq!(send(&app, req, "alice").await, StatusCode::NOT_FOUND);
+        }
+
+        #[tokio::test]
+        async fn should_publish_webhooks_for_undo_and_redo() {
+            let state = build_app_state(
+                TodoRepositoryInMemory::new(vec![]),
+                LabelRepositoryInMemory::new(),
+            );
+            let webhooks = state.webhook_state.repository.clone();
+            let subscription = webhooks
+                .create(CreateWebhook {
+                    url: "http://localhost:8080/hook".to_string(),
+                    secret: "0123456789abcdef".to_string(),
+                    events: vec![],
+                })
+                .await
+                .unwrap();
+            let app = create_routes(state);
+
+            let req = build_json_req(
+                "/todos",
+                Method::POST,
+                r#"{"title": "undone", "labels": []}"#.to_string(),
+            );
+            assert_eq!(send(&app, req, "alice").await, StatusCode::CREATED);
+            let req = build_empty_req("/history/undo", Method::POST);
+            assert_eq!(send(&app, req, "alice").await, StatusCode::OK);
+            let req = build_empty_req("/history/redo", Method::POST);
+            assert_eq!(send(&app, req, "alice").await, StatusCode::OK);
+
+            let events: Vec<WebhookEvent> = webhooks
+                .deliveries(subscription.id)
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|delivery| delivery.event)
+                .collect();
+            assert_eq!(
+                events,
+                vec![
+                    WebhookEvent::TodoUpdated,
+                    WebhookEvent::TodoDeleted,
+                    WebhookEvent::TodoCreated,
+                ]
+            );
+        }
+
+        #[tokio::test]
+        async fn should_record_and_undo_todo_restore() {
+            let todo_repository = TodoRepositoryInMemory::new(vec![]);
+            let state = build_app_state(todo_repository.clone(), LabelRepositoryInMemory::new());
+            let webhooks = state.webhook_state.repository.clone();
+            let subscription = webhooks
+                .create(CreateWebhook {
+                    url: "http://localhost:8080/hook".to_string(),
+                    secret: "0123456789abcdef".to_string(),
+                    events: vec![WebhookEvent::TodoUpdated],
+                })
+                .await
+                .unwrap();
+            let app = create_routes(state);
+
+            let req = build_json_req(
+                "/todos",
+                Method::POST,
+                r#"{"title": "restored", "labels": []}"#.to_string(),
+            );
+            assert_eq!(send(&app, req, "alice").await, StatusCode::CREATED);
+            let req = build_empty_req("/todos/1", Method::DELETE);
+            assert_eq!(send(&app, req, "alice").await, StatusCode::NO_CONTENT);
+            let req = build_empty_req("/todos/1/restore", Method::POST);
+            assert_eq!(send(&app, req, "alice").await, StatusCode::OK);
+
+            let res = app
+                .clone()
+                .oneshot(build_empty_req("/todos/1/history", Method::GET))
+                .await
+                .unwrap();
+            let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+            let records: Vec<ChangeRecord> = serde_json::from_slice(&bytes).unwrap();
+            assert_eq!(records.last().unwrap().action, ChangeAction::Restore);
+            let deliveries = webhooks.deliveries(subscription.id).await.unwrap();
+            assert_eq!(deliveries.len(), 1);
+
+            // 復元を取り消すとゴミ箱へ戻る
+            let req = build_empty_req("/history/undo", Method::POST);
+            assert_eq!(send(&app, req, "alice").await, StatusCode::OK);
+            assert!(todo_repository.find(1).await.is_err());
+            let req = build_empty_req("/history/redo", Method::POST);
+            assert_eq!(send(&app, req, "alice").await, StatusCode::OK);
+            assert!(todo_repository.find(1).await.is_ok());
+        }
+    }
+
+    mod test_reminder {
+        use super::*;
+        use crate::repositories::reminder::{Reminder, ReminderChannel};
+        use crate::repositories::todo::TodoRepository;
+
+        async fn res_to_reminder(res: Response) -> Reminder {
+            let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+            let body: String = String::from_utf8(bytes.to_vec()).unwrap();
+            serde_json::from_str(&body)
+                .unwrap_or_else(|_| panic!("cannot convert Reminder instance. body: {}", body))
+        }
+
+        #[tokio::test]
+        async fn should_reject_reminder_on_unconfigured_channel() {
+            let todo_repository = TodoRepositoryInMemory::new(vec![]);
+            todo_repository
+                .create(CreateTodo::new("reminded".to_string(), vec![]))
+                .await
+                .expect("failed to create todo");
+            let state = build_app_state(todo_repository, LabelRepositoryInMemory::new());
+            let build_req = || {
+                build_json_req(
+                    "/todos/1/reminders",
+                    Method::POST,
+                    r#"{"fire_at": "2023-03-11T09:00:00Z", "channel": "webhook"}"#.to_string(),
+                )
+            };
+
+            let res = create_routes(state.clone())
+                .oneshot(build_req())
+                .await
+                .unwrap();
+            assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+            let app = create_routes(
+                state.with_reminder_channels(vec![ReminderChannel::Log, ReminderChannel::Webhook]),
+            );
+            let res = app.oneshot(build_req()).await.unwrap();
+            assert_eq!(res.status(), StatusCode::CREATED);
+            assert_eq!(res_to_reminder(res).await.channel, ReminderChannel::Webhook);
+        }
+
+        #[tokio::test]
+        async fn should_create_snooze_and_dismiss_reminder() {
+            let todo_repository = TodoRepositoryInMemory::new(vec![]);
+            todo_repository
+                .create(CreateTodo::new("reminded".to_string(), vec![]))
+                .await
+                .expect("failed to create todo");
+            let app = create_routes(build_app_state(
+                todo_repository,
+                LabelRepositoryInMemory::new(),
+            ));
+
+            let req = build_json_req(
+                "/todos/1/reminders",
+                Method::POST,
+                r#"{"fire_at": "2023-03-11T09:00:00Z", "channel": "log"}"#.to_string(),
+            );
+            let res = app.clone().oneshot(req).await.unwrap();
+            assert_eq!(res.status(), StatusCode::CREATED);
+            let reminder = res_to_reminder(res).await;
+            assert_eq!(reminder.channel, ReminderChannel::Log);
+
+            let req = build_json_req(
+                "/todos/2/reminders",
+                Method::POST,
+                r#"{"fire_at": "2023-03-11T09:00:00Z", "channel": "log"}"#.to_string(),
+            );
+            let res = app.clone().oneshot(req).await.unwrap();
+            assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+            let req = build_json_req(
+                "/reminders/1/snooze",
+                Method::POST,
+                r#"{"minutes": 0}"#.to_string(),
+            );
+            let res = app.clone().oneshot(req).await.unwrap();
+            assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+            let req = build_json_req(
+                "/reminders/1/snooze",
+                Method::POST,
+                r#"{"minutes": 10}"#.to_string(),
+            );
+            let res = app.clone().oneshot(req).await.unwrap();
+            let snoozed = res_to_reminder(res).await;
+            assert!(snoozed.fire_at > reminder.fire_at);
+
+            let res = app
+                .clone()
+                .oneshot(build_empty_req("/reminders/1/dismiss", Method::POST))
+                .await
+                .unwrap();
+            let dismissed = res_to_reminder(res).await;
+            assert!(dismissed.dismissed_at.is_some());
+
+            let res = app
+                .oneshot(build_empty_req("/todos/1/reminders", Method::GET))
+                .await
+                .unwrap();
+            let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+            let reminders: Vec<Reminder> = serde_json::from_slice(&bytes).unwrap();
+            assert_eq!(reminders, vec![dismissed]);
+        }
+    }
+
+    mod test_webhook {
+        use super::*;
+        use crate::repositories::todo::TodoRepository;
+        use crate::repositories::webhook::{
+            DeliveryStatus, WebhookDelivery, WebhookEvent, WebhookSubscription,
+        };
+        use serde_json::json;
+
+        #[tokio::test]
+        async fn should_enqueue_deliveries_for_subscribed_events() {
+            let app = create_routes(build_app_state(
+                TodoRepositoryInMemory::new(vec![]),
+                LabelRepositoryInMemory::new(),
+            ));
+
+            let req = build_json_req(
+                "/webhooks",
+                Method::POST,
+                r#"{"url": "not a url", "secret": "0123456789abcdef"}"#.to_string(),
+            );
+            let res = app.clone().oneshot(req).await.unwrap();
+            assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+            let req = build_json_req(
+                "/webhooks",
+                Method::POST,
+                r#"{"url": "http://localhost:8080/hook", "secret": "0123456789abcdef", "events": ["todo.created"]}"#
+                    .to_string(),
+            );
+            let res = app.clone().oneshot(req).await.unwrap();
+            assert_eq!(res.status(), StatusCode::CREATED);
+            let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+            let body: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+            assert!(body.get("secret").is_none());
+            let subscription: WebhookSubscription = serde_json::from_value(body).unwrap();
+            assert_eq!(subscription.events, vec![WebhookEvent::TodoCreated]);
+
+            let req = build_json_req(
+                "/todos",
+                Method::POST,
+                r#"{"title": "should_enqueue", "labels": []}"#.to_string(),
+            );
+            app.clone().oneshot(req).await.unwrap();
+            let req = build_json_req(
+                "/labels",
+                Method::POST,
+                r#"{"name": "should_enqueue"}"#.to_string(),
+            );
+            app.clone().oneshot(req).await.unwrap();
+
+            let res = app
+                .oneshot(build_empty_req(
+                    &format!("/webhooks/{}/deliveries", subscription.id),
+                    Method::GET,
+                ))
+                .await
+                .unwrap();
+            let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+            let deliveries: Vec<WebhookDelivery> = serde_json::from_slice(&bytes).unwrap();
+            assert_eq!(deliveries.len(), 1);
+            assert_eq!(deliveries[0].event, WebhookEvent::TodoCreated);
+            assert_eq!(deliveries[0].status, DeliveryStatus::Pending);
+            assert_eq!(deliveries[0].payload["title"], "should_enqueue");
+        }
+
+        #[tokio::test]
+        async fn should_publish_updates_when_labels_move_and_merge() {
+            let todo_repository = TodoRepositoryInMemory::new(vec![]);
+            let label_repository = LabelRepositoryInMemory::with_todos(todo_repository.clone());
+            for (name, parent_id) in [("bug", None), ("bugs", None), ("bugs/ui", Some(2))] {
+                let mut label = CreateLabel::new(name.to_string());
+                label.parent_id = parent_id;
+                label_repository.create(label).await.unwrap();
+            }
+            todo_repository
+                .create(CreateTodo::new("merged".to_string(), vec![2]))
+                .await
+                .unwrap();
+            todo_repository
+                .create(CreateTodo::new("unchanged".to_string(), vec![1]))
+                .await
+                .unwrap();
+            let app = create_routes(build_app_state(todo_repository, label_repository));
+
+            let req = build_json_req(
+                "/webhooks",
+                Method::POST,
+                r#"{"url": "http://localhost:8080/hook", "secret": "0123456789abcdef", "events": ["label.updated", "todo.updated"]}"#
+                    .to_string(),
+            );
+            let res = app.clone().oneshot(req).await.unwrap();
+            let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+            let subscription: WebhookSubscription = serde_json::from_slice(&bytes).unwrap();
+
+            let req = build_json_req(
+                "/labels/3/parent",
+                Method::PUT,
+                r#"{"parent_id": null}"#.to_string(),
+            );
+            let res = app.clone().oneshot(req).await.unwrap();
+            assert_eq!(res.status(), StatusCode::OK);
+            let req = build_json_req(
+                "/labels/3/parent",
+                Method::PUT,
+                r#"{"parent_id": 2}"#.to_string(),
+            );
+            app.clone().oneshot(req).await.unwrap();
+            let req = build_json_req(
+                "/labels/2/merge",
+                Method::POST,
+                r#"{"target_id": 1}"#.to_string(),
+            );
+            let res = app.clone().oneshot(req).await.unwrap();
+            assert_eq!(res.status(), StatusCode::OK);
+
+            let res = app
+                .oneshot(build_empty_req(
+                    &format!("/webhooks/{}/deliveries", subscription.id),
+                    Method::GET,
+                ))
+                .await
+                .unwrap();
+            let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+            let deliveries: Vec<WebhookDelivery> = serde_json::from_slice(&bytes).unwrap();
+            let mut published: Vec<(WebhookEvent, String, serde_json::Value)> = deliveries
+                .into_iter()
+                .map(|delivery| {
+                    let name = delivery.payload["name"]
+                        .as_str()
+                        .or(delivery.payload["title"].as_str())
+                        .unwrap()
+                        .to_string();
+                    let parent_id = delivery.payload["parent_id"].clone();
+                    (delivery.event, name, parent_id)
+                })
+                .collect();
+            published.sort_by_key(|(event, name, parent_id)| {
+                (event.as_str(), name.clone(), parent_id.to_string())
+            });
+            assert_eq!(
+                published,
+                vec![
+                    // 統合先と、統合で親が変わった子ラベル
+                    (WebhookEvent::LabelUpdated, "bug".to_string(), json!(null)),
+                    (WebhookEvent::LabelUpdated, "bugs/ui".to_string(), json!(1)),
+                    // 2 回の移動
+                    (WebhookEvent::LabelUpdated, "bugs/ui".to_string(), json!(2)),
+                    (
+                        WebhookEvent::LabelUpdated,
+                        "bugs/ui".to_string(),
+                        json!(null)
+                    ),
+                    // 統合でラベルが付け替わった todo だけ
+                    (WebhookEvent::TodoUpdated, "merged".to_string(), json!(null)),
+                ]
+            );
+        }
+    }
+
+    mod test_workspace {
+        use super::*;
+        use crate::repositories::reminder::Reminder;
+        use crate::repositories::webhook::WebhookSubscription;
+        use crate::repositories::workspace::{Invitation, Workspace};
+
+        // ワークスペースのメンバーかどうかは認証されたユーザーで判断するため、JWT を付ける
+        async fn build_app() -> axum::Router {
+            create_routes(
+                with_jwt_auth(build_app_state(
+                    TodoRepositoryInMemory::new(vec![]),
+                    LabelRepositoryInMemory::new(),
+                ))
+                .await,
+            )
+        }
+
+        fn with_headers(
+            req: Request<Body>,
+            user_id: &str,
+            workspace_id: Option<i32>,
+        ) -> Request<Body> {
+            let mut req = with_jwt(req, user_id);
+            if let Some(workspace_id) = workspace_id {
+                req.headers_mut()
+                    .insert("x-workspace-id", workspace_id.into());
+            }
+            req
+        }
+
+        async fn res_to_json<T: serde::de::DeserializeOwned>(res: Response) -> T {
+            let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+            serde_json::from_slice(&bytes).unwrap()
+        }
+
+        #[tokio::test]
+        async fn should_scope_todos_and_enforce_roles() {
+            let app = build_app().await;
+
+            let req = build_json_req(
+                "/workspaces",
+                Method::POST,
+                r#"{"name": "team"}"#.to_string(),
+            );
+            let res = app
+                .clone()
+                .oneshot(with_headers(req, "owner", None))
+                .await
+                .unwrap();
+            assert_eq!(res.status(), StatusCode::CREATED);
+            let workspace: Workspace = res_to_json(res).await;
+
+            let req = build_json_req(
+                &format!("/workspaces/{}/invitations", workspace.id),
+                Method::POST,
+                r#"{"role": "viewer"}"#.to_string(),
+            );
+            let res = app
+                .clone()
+                .oneshot(with_headers(req, "viewer", None))
+                .await
+                .unwrap();
+            assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+            let req = build_json_req(
+                &format!("/workspaces/{}/invitations", workspace.id),
+                Method::POST,
+                r#"{"role": "viewer"}"#.to_string(),
+            );
+            let res = app
+                .clone()
+                .oneshot(with_headers(req, "owner", None))
+                .await
+                .unwrap();
+            assert_eq!(res.status(), StatusCode::CREATED);
+            let invitation: Invitation = res_to_json(res).await;
+
+            let path = format!("/invitations/{}/accept", invitation.token);
+            let req = build_empty_req(&path, Method::POST);
+            let res = app
+                .clone()
+                .oneshot(with_headers(req, "viewer", None))
+                .await
+                .unwrap();
+            assert_eq!(res.status(), StatusCode::OK);
+            let req = build_empty_req(&path, Method::POST);
+            let res = app
+                .clone()
+                .oneshot(with_headers(req, "viewer", None))
+                .await
+                .unwrap();
+            assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+            let req = build_json_req(
+                "/todos",
+                Method::POST,
+                r#"{"title": "shared", "labels": []}"#.to_string(),
+            );
+            let res = app
+                .clone()
+                .oneshot(with_headers(req, "owner", Some(workspace.id)))
+                .await
+                .unwrap();
+            assert_eq!(res.status(), StatusCode::CREATED);
+            let todo: TodoEntity = res_to_json(res).await;
+            assert_eq!(todo.workspace_id, Some(workspace.id));
+
+            let req = build_json_req(
+                "/todos",
+                Method::POST,
+                r#"{"title": "personal", "labels": []}"#.to_string(),
+            );
+            let res = app
+                .clone()
+                .oneshot(with_headers(req, "viewer", None))
+                .await
+                .unwrap();
+            assert_eq!(res.status(), StatusCode::CREATED);
+
+            let req = build_empty_req("/todos", Method::GET);
+            let res = app
+                .clone()
+                .oneshot(with_headers(req, "viewer", Some(workspace.id)))
+                .await
+                .unwrap();
+            assert_eq!(res.status(), StatusCode::OK);
+            let todos: Vec<TodoEntity> = res_to_json(res).await;
+            assert_eq!(todos.len(), 1);
+            assert_eq!(todos[0].title, "shared");
+
+            let req = build_empty_req("/todos", Method::GET);
+            let res = app
+                .clone()
+                .oneshot(with_headers(req, "viewer", None))
+                .await
+                .unwrap();
+            let todos: Vec<TodoEntity> = res_to_json(res).await;
+            assert_eq!(todos.len(), 1);
+            assert_eq!(todos[0].title, "personal");
+
+            let req = build_json_req(
+                &format!("/todos/{}", todo.id),
+                Method::PATCH,
+                r#"{"completed": true}"#.to_string(),
+            );
+            let res = app
+                .clone()
+                .oneshot(with_headers(req, "viewer", Some(workspace.id)))
+                .await
+                .unwrap();
+            assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+            let req = build_empty_req("/todos", Method::GET);
+            let res = app
+                .clone()
+                .oneshot(with_headers(req, "stranger", Some(workspace.id)))
+                .await
+                .unwrap();
+            assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+            // `X-User-Id` で名乗っただけではメンバーとして扱わない
+            let mut req = build_empty_req("/todos", Method::GET);
+            req.headers_mut()
+                .insert("x-user-id", "owner".parse().unwrap());
+            req.headers_mut()
+                .insert("x-workspace-id", workspace.id.into());
+            let res = app.clone().oneshot(req).await.unwrap();
+            assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+            let req = build_empty_req(&format!("/todos/{}", todo.id), Method::GET);
+            let res = app.oneshot(with_headers(req, "owner", None)).await.unwrap();
+            assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        }
+
+        #[tokio::test]
+        async fn should_let_viewers_query_but_not_mutate_with_graphql() {
+            let app = build_app().await;
+
+            let req = build_json_req(
+                "/workspaces",
+                Method::POST,
+                r#"{"name": "team"}"#.to_string(),
+            );
+            let res = app
+                .clone()
+                .oneshot(with_headers(req, "owner", None))
+                .await
+                .unwrap();
+            let workspace: Workspace = res_to_json(res).await;
+            let req = build_json_req(
+                &format!("/workspaces/{}/invitations", workspace.id),
+                Method::POST,
+                r#"{"role": "viewer"}"#.to_string(),
+            );
+            let res = app
+                .clone()
+                .oneshot(with_headers(req, "owner", None))
+                .await
+                .unwrap();
+            let invitation: Invitation = res_to_json(res).await;
+            let path = format!("/invitations/{}/accept", invitation.token);
+            let req = build_empty_req(&path, Method::POST);
+            app.clone()
+                .oneshot(with_headers(req, "viewer", None))
+                .await
+                .unwrap();
+
+            let graphql = |user_id: &str, query: &str| {
+                let body = serde_json::json!({ "query": query });
+                let req = build_json_req("/graphql", Method::POST, body.to_string());
+                with_headers(req, user_id, Some(workspace.id))
+            };
+
+            let mutation = r#"mutation { createTodo(input: { title: "shared" }) { id } }"#;
+            let res = app
+                .clone()
+                .oneshot(graphql("owner", mutation))
+                .await
+                .unwrap();
+            let body: serde_json::Value = res_to_json(res).await;
+            assert_eq!(body["data"]["createTodo"]["id"], 1);
+
+            let res = app
+                .clone()
+                .oneshot(graphql("viewer", "{ todos { title } }"))
+                .await
+                .unwrap();
+            assert_eq!(res.status(), StatusCode::OK);
+            let body: serde_json::Value = res_to_json(res).await;
+            assert_eq!(
+                body["data"]["todos"],
+                serde_json::json!([{ "title": "shared" }])
+            );
+
+            let res = app.oneshot(graphql("viewer", mutation)).await.unwrap();
+            let body: serde_json::Value = res_to_json(res).await;
+            assert_eq!(body["errors"][0]["extensions"]["code"], "FORBIDDEN");
+        }
+
+        #[tokio::test]
+        async fn should_scope_reminders_history_and_webhooks() {
+            let app = build_app().await;
+
+            let req = build_json_req(
+                "/workspaces",
+                Method::POST,
+                r#"{"name": "team"}"#.to_string(),
+            );
+            let res = app
+                .clone()
+                .oneshot(with_headers(req, "owner", None))
+                .await
+                .unwrap();
+            let workspace: Workspace = res_to_json(res).await;
+
+            let req = build_json_req(
+                "/webhooks",
+                Method::POST,
+                r#"{"url": "http://localhost/team", "secret": "0123456789abcdef"}"#.to_string(),
+            );
+            let res = app
+                .clone()
+                .oneshot(with_headers(req, "owner", Some(workspace.id)))
+                .await
+                .unwrap();
+            assert_eq!(res.status(), StatusCode::CREATED);
+            let webhook: WebhookSubscription = res_to_json(res).await;
+            assert_eq!(webhook.workspace_id, Some(workspace.id));
+
+            let req = build_json_req(
+                "/todos",
+                Method::POST,
+                r#"{"title": "shared", "labels": []}"#.to_string(),
+            );
+            let res = app
+                .clone()
+                .oneshot(with_headers(req, "owner", Some(workspace.id)))
+                .await
+                .unwrap();
+            let todo: TodoEntity = res_to_json(res).await;
+
+            let req = build_json_req(
+                &format!("/todos/{}/reminders", todo.id),
+                Method::POST,
+                r#"{"fire_at": "2099-01-01T00:00:00Z", "channel": "log"}"#.to_string(),
+            );
+            let res = app
+                .clone()
+                .oneshot(with_headers(req, "owner", Some(workspace.id)))
+                .await
+                .unwrap();
+            assert_eq!(res.status(), StatusCode::CREATED);
+            let reminder: Reminder = res_to_json(res).await;
+
+            // ワークスペースを選ばないリクエストからは見えない
+            let paths = [
+                (format!("/todos/{}/reminders", todo.id), Method::GET),
+                (format!("/todos/{}/history", todo.id), Method::GET),
+                (format!("/reminders/{}/dismiss", reminder.id), Method::POST),
+                (format!("/webhooks/{}", webhook.id), Method::DELETE),
+            ];
+            for (path, method) in paths {
+                let req = build_empty_req(&path, method);
+                let res = app
+                    .clone()
+                    .oneshot(with_headers(req, "owner", None))
+                    .await
+                    .unwrap();
+                assert_eq!(res.status(), StatusCode::NOT_FOUND, "{}", path);
+            }
+            let req = build_json_req(
+                &format!("/reminders/{}/snooze", reminder.id),
+                Method::POST,
+                r#"{"minutes": 10}"#.to_string(),
+            );
+            let res = app
+                .clone()
+                .oneshot(with_headers(req, "owner", None))
+                .await
+                .unwrap();
+            assert_eq!(res.status(), StatusCode::NOT_FOUND);
+            let req = build_empty_req("/webhooks", Method::GET);
+            let res = app
+                .clone()
+                .oneshot(with_headers(req, "owner", None))
+                .await
+                .unwrap();
+            let webhooks: Vec<WebhookSubscription> = res_to_json(res).await;
+            assert!(webhooks.is_empty());
+
+            let req = build_empty_req(&format!("/todos/{}/history", todo.id), Method::GET);
+            let res = app
+                .clone()
+                .oneshot(with_headers(req, "owner", Some(workspace.id)))
+                .await
+                .unwrap();
+            assert_eq!(res.status(), StatusCode::OK);
+            let req = build_empty_req(&format!("/todos/{}/reminders", todo.id), Method::GET);
+            let res = app
+                .clone()
+                .oneshot(with_headers(req, "owner", Some(workspace.id)))
+                .await
+                .unwrap();
+            let reminders: Vec<Reminder> = res_to_json(res).await;
+            assert_eq!(reminders, vec![reminder]);
+
+            // 個人のデータへの変更はワークスペースの webhook に配信しない
+            let req = build_json_req(
+                "/todos",
+                Method::POST,
+                r#"{"title": "personal", "labels": []}"#.to_string(),
+            );
+            app.clone()
+                .oneshot(with_headers(req, "owner", None))
+                .await
+                .unwrap();
+            let path = format!("/webhooks/{}/deliveries", webhook.id);
+            let req = build_empty_req(&path, Method::GET);
+            let res = app
+                .oneshot(with_headers(req, "owner", Some(workspace.id)))
+                .await
+                .unwrap();
+            let deliveries: Vec<serde_json::Value> = res_to_json(res).await;
+            assert_eq!(deliveries.len(), 1);
+            assert_eq!(deliveries[0]["payload"]["title"], "shared");
+        }
+    }
+
+    mod test_api_token {
+        use super::*;
+        use crate::repositories::api_token::{ApiToken, NewApiToken};
+
+        fn with_token(mut req: Request<Body>, token: &str) -> Request<Body> {
+            req.headers_mut().insert(
+                header::AUTHORIZATION,
+                format!("Bearer {}", token).parse().unwrap(),
+            );
+            req
+        }
+
+        async fn res_to_json<T: serde::de::DeserializeOwned>(res: Response) -> T {
+            let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+            serde_json::from_slice(&bytes).unwrap()
+        }
+
+        #[tokio::test]
+        async fn should_check_token_scopes() {
+            let app = create_routes(
+                with_jwt_auth(build_app_state(
+                    TodoRepositoryInMemory::new(vec![]),
+                    LabelRepositoryInMemory::new(),
+                ))
+                .await,
+            );
+
+            let req = build_json_req(
+                "/tokens",
+                Method::POST,
+                r#"{"name": "ci", "scopes": []}"#.to_string(),
+            );
+            let res = app.clone().oneshot(with_jwt(req, "script")).await.unwrap();
+            assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+            let req = build_json_req(
+                "/tokens",
+                Method::POST,
+                r#"{"name": "ci", "scopes": ["todos:read"]}"#.to_string(),
+            );
+            let res = app.clone().oneshot(with_jwt(req, "script")).await.unwrap();
+            assert_eq!(res.status(), StatusCode::CREATED);
+            let created: NewApiToken = res_to_json(res).await;
+
+            let req = build_json_req(
+                "/todos",
+                Method::POST,
+                r#"{"title": "should_check_token_scopes", "labels": []}"#.to_string(),
+            );
+            let res = app.clone().oneshot(with_jwt(req, "script")).await.unwrap();
+            assert_eq!(res.status(), StatusCode::CREATED);
+
+            let req = build_empty_req("/todos", Method::GET);
+            let res = app
+                .clone()
+                .oneshot(with_token(req, &created.token))
+                .await
+                .unwrap();
+            assert_eq!(res.status(), StatusCode::OK);
+            let todos: Vec<TodoEntity> = res_to_json(res).await;
+            assert_eq!(todos.len(), 1);
+
+            let req = build_json_req(
+                "/todos",
+                Method::POST,
+                r#"{"title": "forbidden", "labels": []}"#.to_string(),
+            );
+            let res = app
+                .clone()
+                .oneshot(with_token(req, &created.token))
+                .await
+                .unwrap();
+            assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+            let req = build_empty_req("/labels", Method::GET);
+            let res = app
+                .clone()
+                .oneshot(with_token(req, &created.token))
+                .await
+                .unwrap();
+            assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+            // トークンで新しいトークンは発行できない
+            let req = build_empty_req("/tokens", Method::GET);
+            let res = app
+                .clone()
+                .oneshot(with_token(req, &created.token))
+                .await
+                .unwrap();
+            assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+            let req = build_empty_req("/todos", Method::GET);
+            let res = app
+                .clone()
+                .oneshot(with_token(req, "tdo_unknown"))
+                .await
+                .unwrap();
+            assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+            let req = build_empty_req("/tokens", Method::GET);
+            let res = app.clone().oneshot(with_jwt(req, "script")).await.unwrap();
+            let tokens: Vec<ApiToken> = res_to_json(res).await;
+            assert_eq!(tokens.len(), 1);
+            assert!(tokens[0].last_used_at.is_some());
+            let body = serde_json::to_string(&tokens).unwrap();
+            assert!(!body.contains(&created.token));
+
+            let req = build_empty_req(&format!("/tokens/{}", created.api_token.id), Method::DELETE);
+            let res = app.clone().oneshot(with_jwt(req, "other")).await.unwrap();
+            assert_eq!(res.status(), StatusCode::NOT_FOUND);
+            let req = build_empty_req(&format!("/tokens/{}", created.api_token.id), Method::DELETE);
+            let res = app.clone().oneshot(with_jwt(req, "script")).await.unwrap();
+            assert_eq!(res.status(), StatusCode::OK);
+
+            let req = build_empty_req("/todos", Method::GET);
+            let res = app.oneshot(with_token(req, &created.token)).await.unwrap();
+            assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        }
+
+        #[tokio::test]
+        async fn should_require_authentication() {
+            // 認証が有効でなくても、`X-User-Id` で名乗っただけではトークンを扱えない
+            let app = create_routes(build_app_state(
+                TodoRepositoryInMemory::new(vec![]),
+                LabelRepositoryInMemory::new(),
+            ));
+            let mut req = build_json_req(
+                "/tokens",
+                Method::POST,
+                r#"{"name": "ci", "scopes": ["todos:read"]}"#.to_string(),
+            );
+            req.headers_mut()
+                .insert("x-user-id", "script".parse().unwrap());
+            let res = app.clone().oneshot(req).await.unwrap();
+            assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+            let req = build_empty_req("/tokens", Method::GET);
+            let res = app.oneshot(req).await.unwrap();
+            assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+            // 認証が有効なら、トークンの無いリクエストは `X-User-Id` があっても拒否する
+            let app = create_routes(
+                with_jwt_auth(build_app_state(
+                    TodoRepositoryInMemory::new(vec![]),
+                    LabelRepositoryInMemory::new(),
+                ))
+                .await,
+            );
+            let mut req = build_empty_req("/todos", Method::GET);
+            req.headers_mut()
+                .insert("x-user-id", "script".parse().unwrap());
+            let res = app.clone().oneshot(req).await.unwrap();
+            assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+            let req = build_empty_req("/todos", Method::GET);
+            let res = app.clone().oneshot(with_jwt(req, "script")).await.unwrap();
+            assert_eq!(res.status(), StatusCode::OK);
+        }
+    }
+
+    mod test_oidc {
+        use super::*;
+        use crate::oidc::{
+            test_utils::{config, sign, testdata, ISSUER},
+            JwtVerifier,
+        };
+        use crate::repositories::{api_token::NewApiToken, workspace::Membership};
+
+        fn with_token(mut req: Request<Body>, token: &str) -> Request<Body> {
+            req.headers_mut().insert(
+                header::AUTHORIZATION,
+                format!("Bearer {}", token).parse().unwrap(),
+            );
+            req
+        }
+
+        async fn res_to_json<T: serde::de::DeserializeOwned>(res: Response) -> T {
+            let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+            serde_json::from_slice(&bytes).unwrap()
+        }
+
+        #[tokio::test]
+        async fn should_create_workspace_with_issued_token() {
+            // OIDC を使わなくても、create-token で発行したトークンでワークスペースを作れる
+            let state = build_app_state(
+                TodoRepositoryInMemory::new(vec![]),
+                LabelRepositoryInMemory::new(),
+            );
+            let api_token = crate::issue_token(
+                state.api_token_state.repository.as_ref(),
+                "admin",
+                "bootstrap",
+            )
+            .await;
+            let app = create_routes(state);
+
+            let req = build_json_req(
+                "/workspaces",
+                Method::POST,
+                r#"{"name": "first"}"#.to_string(),
+            );
+            let res = app
+                .clone()
+                .oneshot(with_token(req, &api_token.token))
+                .await
+                .unwrap();
+            assert_eq!(res.status(), StatusCode::CREATED);
+            let req = build_empty_req("/workspaces", Method::GET);
+            let res = app
+                .oneshot(with_token(req, &api_token.token))
+                .await
+                .unwrap();
+            let memberships: Vec<Membership> = res_to_json(res).await;
+            assert_eq!(memberships[0].workspace.name, "first");
+        }
+
+        #[tokio::test]
+        async fn should_authenticate_with_jwt() {
+            let verifier = JwtVerifier::load(config(testdata("jwks.json")))
+                .await
+                .unwrap();
+            let app = create_routes(
+                build_app_state(
+                    TodoRepositoryInMemory::new(vec![]),
+                    LabelRepositoryInMemory::new(),
+                )
+                .with_jwt_verifier(verifier),
+            );
+
+            // JWT のメールアドレスをユーザーの識別子として扱う
+            let token = sign("ec-1", ISSUER, "sso-subject", Some("sso@example.com"));
+            let req = build_json_req(
+                "/workspaces",
+                Method::POST,
+                r#"{"name": "sso"}"#.to_string(),
+            );
+            let res = app.clone().oneshot(with_token(req, &token)).await.unwrap();
+            assert_eq!(res.status(), StatusCode::CREATED);
+
+            let req = build_empty_req("/workspaces", Method::GET);
+            let res = app.clone().oneshot(with_token(req, &token)).await.unwrap();
+            let memberships: Vec<Membership> = res_to_json(res).await;
+            assert_eq!(memberships.len(), 1);
+            assert_eq!(memberships[0].workspace.name, "sso");
+
+            let req = build_empty_req("/todos", Method::GET);
+            let res = app.clone().oneshot(req).await.unwrap();
+            assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+            // JWT で発行した API トークンでも認証できる
+            let req = build_json_req(
+                "/tokens",
+                Method::POST,
+                r#"{"name": "ci", "scopes": ["todos:read"]}"#.to_string(),
+            );
+            let res = app.clone().oneshot(with_token(req, &token)).await.unwrap();
+            let created: NewApiToken = res_to_json(res).await;
+            let req = build_empty_req("/todos", Method::GET);
+            let res = app
+                .clone()
+                .oneshot(with_token(req, &created.token))
+                .await
+                .unwrap();
+            assert_eq!(res.status(), StatusCode::OK);
+            let req = build_empty_req("/todos", Method::GET);
+            let res = app
+                .clone()
+                .oneshot(with_token(req, "tdo_unknown"))
+                .await
+                .unwrap();
+            assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+            let token = sign("rsa-1", "https://other.example.com", "sso-subject", None);
+            let req = build_empty_req("/todos", Method::GET);
+            let res = app.clone().oneshot(with_token(req, &token)).await.unwrap();
+            assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+            let req = build_empty_req("/todos", Method::GET);
+            let res = app.oneshot(with_token(req, "not.a.jwt")).await.unwrap();
+            assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        }
+
+        #[tokio::test]
+        async fn should_reject_jwt_when_not_configured() {
+            let app = create_routes(build_app_state(
+                TodoRepositoryInMemory::new(vec![]),
+                LabelRepositoryInMemory::new(),
+            ));
+            let token = sign("rsa-1", ISSUER, "sso-subject", None);
+            let req = build_empty_req("/todos", Method::GET);
+            let res = app.oneshot(with_token(req, &token)).await.unwrap();
+            assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        }
+    }
+
+    mod test_limits {
+        use super::*;
+        use crate::handlers::rate_limit::RateLimitConfig;
+
+        #[tokio::test]
+        async fn should_limit_requests_per_client() {
+            let app = create_routes(
+                build_app_state(
+                    TodoRepositoryInMemory::new(vec![]),
+                    LabelRepositoryInMemory::new(),
+                )
+                .with_rate_limit(RateLimitConfig {
+                    burst: 2,
+                    per_second: 0.5,
+                }),
+            );
+
+            for _ in 0..2 {
+                let res = app
+                    .clone()
+                    .oneshot(build_empty_req("/todos", Method::GET))
+                    .await
+                    .unwrap();
+                assert_eq!(res.status(), StatusCode::OK);
+            }
+            let res = app
+                .clone()
+                .oneshot(build_empty_req("/todos", Method::GET))
+                .await
+                .unwrap();
+            assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+            assert_eq!(res.headers().get(header::RETRY_AFTER).unwrap(), "2");
+            assert_eq!(
+                res.headers()[header::CONTENT_TYPE],
+                mime::APPLICATION_JSON.as_ref()
+            );
+            let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+            let body: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+            assert_eq!(body["error"], "Rate limit error: [retry after 2 seconds]");
+        }
+
+        #[tokio::test]
+        async fn should_limit_requests_per_authenticated_user() {
+            let app = create_routes(
+                with_jwt_auth(
+                    build_app_state(
+                        TodoRepositoryInMemory::new(vec![]),
+                        LabelRepositoryInMemory::new(),
+                    )
+                    .with_rate_limit(RateLimitConfig {
+                        burst: 2,
+                        per_second: 0.5,
+                    }),
+                )
+                .await,
+            );
+
+            for _ in 0..2 {
+                let req = build_empty_req("/todos", Method::GET);
+                let res = app.clone().oneshot(with_jwt(req, "alice")).await.unwrap();
+                assert_eq!(res.status(), StatusCode::OK);
+            }
+            let req = build_empty_req("/todos", Method::GET);
+            let res = app.clone().oneshot(with_jwt(req, "alice")).await.unwrap();
+            assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+
+            // 認証されたユーザーごとに別のバケットで数える
+            let req = build_empty_req("/todos", Method::GET);
+            let res = app.clone().oneshot(with_jwt(req, "bob")).await.unwrap();
+            assert_eq!(res.status(), StatusCode::OK);
+
+            // 検証できないトークンは値を変えても同じ接続元として数える
+            for token in ["tdo_first", "tdo_second"] {
+                let mut req = build_empty_req("/todos", Method::GET);
+                req.headers_mut().insert(
+                    header::AUTHORIZATION,
+                    format!("Bearer {}", token).parse().unwrap(),
+                );
+                let res = app.clone().oneshot(req).await.unwrap();
+                assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+            }
+            let mut req = build_empty_req("/todos", Method::GET);
+            req.headers_mut()
+                .insert(header::AUTHORIZATION, "Bearer tdo_third".parse().unwrap());
+            let res = app.oneshot(req).await.unwrap();
+            assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+        }
+
+        #[tokio::test]
+        async fn should_reject_too_large_body() {
+            let app = create_routes(
+                build_app_state(
+                    TodoRepositoryInMemory::new(vec![]),
+                    LabelRepositoryInMemory::new(),
+                )
+                .with_body_limit(64),
+            );
+
+            let req = build_json_req(
+                "/todos",
+                Method::POST,
+                format!(r#"{{"title": "{}", "labels": []}}"#, "a".repeat(64)),
+            );
+            let res = app.clone().oneshot(req).await.unwrap();
+            assert_eq!(res.status(), StatusCode::PAYLOAD_TOO_LARGE);
+            assert_eq!(
+                res.headers().get(header::CONTENT_TYPE).unwrap(),
+                mime::APPLICATION_JSON.as_ref()
+            );
+            let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+            let body: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+            assert!(body["error"].as_str().unwrap().starts_with("Payload error"));
+
+            let req = build_json_req(
+                "/todos",
+                Method::POST,
+                r#"{"title": "small", "labels": []}"#.to_string(),
+            );
+            let res = app.oneshot(req).await.unwrap();
+            assert_eq!(res.status(), StatusCode::CREATED);
+        }
+    }
+
+    mod test_graphql {
+        use super::*;
+        use crate::repositories::api_token::NewApiToken;
+        use crate::repositories::todo::TodoRepository;
+        use serde_json::{json, Value};
+
+        fn build_graphql_req(query: &str, variables: Value) -> Request<Body> {
+            let body = json!({ "query": query, "variables": variables });
+            build_json_req("/graphql", Method::POST, body.to_string())
+        }
+
+        async fn res_to_value(res: Response) -> Value {
+            assert_eq!(res.status(), StatusCode::OK);
+            let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+            serde_json::from_slice(&bytes).unwrap()
+        }
+
+        #[tokio::test]
+        async fn should_query_todos_with_labels() {
+            let parent = Label::new(1, "parent".to_string());
+            let child = Label {
+                parent_id: Some(parent.id),
+                ..Label::new(2, "child".to_string())
+            };
+            let todo_repository = TodoRepositoryInMemory::new(vec![]);
+            let label_repository = LabelRepositoryInMemory::with_todos(todo_repository.clone());
+            label_repository
+                .create(CreateLabel::new(parent.name))
+                .await
+                .expect("failed to create label");
+            label_repository
+                .create(CreateLabel {
+                    parent_id: Some(parent.id),
+                    ..CreateLabel::new(child.name)
+                })
+                .await
+                .expect("failed to create label");
+            todo_repository
+                .create(CreateTodo::new("labeled".to_string(), vec![child.id]))
+                .await
+                .expect("failed to create todo");
+            todo_repository
+                .create(CreateTodo::new("unlabeled".to_string(), vec![]))
+                .await
+                .expect("failed to create todo");
+            let app = create_routes(build_app_state(todo_repository, label_repository));
+
+            let query = r#"
+                query ($labelId: Int) {
+                    todos(filter: { labelId: $labelId, completed: false }) {
+                        title
+                        labels { name todoCount { total } parent { name } }
+                    }
+                    missing: todo(id: 99) { id }
+                    labels { id }
+                }
+            "#;
+            let res = app
+                .clone()
+                .oneshot(build_graphql_req(query, json!({ "labelId": 1 })))
+                .await
+                .unwrap();
+            assert_eq!(
+                res_to_value(res).await,
+                json!({
+                    "data": {
+                        "todos": [{
+                            "title": "labeled",
+                            "labels": [{
+                                "name": "child",
+                                "todoCount": { "total": 1 },
+                                "parent": { "name": "parent" },
+                            }],
+                        }],
+                        "missing": null,
+                        "labels": [{ "id": 1 }, { "id": 2 }],
+                    }
+                })
+            );
+
+            let query = r#"{ todos(filter: { title: "UNLAB" }) { title } }"#;
+            let res = app
+                .oneshot(build_graphql_req(query, Value::Null))
+                .await
+                .unwrap();
+            assert_eq!(
+                res_to_value(res).await,
+                json!({ "data": { "todos": [{ "title": "unlabeled" }] } })
+            );
+        }
+
+        #[tokio::test]
+        async fn should_mutate_todos_and_labels() {
+            let todo_repository = TodoRepositoryInMemory::new(vec![]);
+            let app = create_routes(build_app_state(
+                todo_repository.clone(),
+                LabelRepositoryInMemory::with_todos(todo_repository),
+            ));
+
+            let query =
+                r#"mutation { createLabel(input: { name: "should_mutate" }) { id color } }"#;
+            let res = app
+                .clone()
+                .oneshot(build_graphql_req(query, Value::Null))
+                .await
+                .unwrap();
+            assert_eq!(
+                res_to_value(res).await["data"]["createLabel"],
+                json!({ "id": 1, "color": "#808080" })
+            );
+
+            let query = r#"
+                mutation ($title: String!) {
+                    createTodo(input: { title: $title, labels: [1] }) { id version labels { name } }
+                }
+            "#;
+            let res = app
+                .clone()
+                .oneshot(build_graphql_req(query, json!({ "title": "" })))
+                .await
+                .unwrap();
+            let body = res_to_value(res).await;
+            assert_eq!(body["errors"][0]["extensions"]["code"], "BAD_REQUEST");
+
+            let res = app
+                .clone()
+                .oneshot(build_graphql_req(query, json!({ "title": "created" })))
+                .await
+                .unwrap();
+            assert_eq!(
+                res_to_value(res).await["data"]["createTodo"],
+                json!({ "id": 1, "version": 1, "labels": [{ "name": "should_mutate" }] })
+            );
+
+            let query = r#"
+                mutation ($version: Int) {
+                    updateTodo(id: 1, input: { completed: true }, version: $version) {
+                        completed version
+                    }
+                }
+            "#;
+            let res = app
+                .clone()
+                .oneshot(build_graphql_req(query, json!({ "version": 5 })))
+                .await
+                .unwrap();
+            let body = res_to_value(res).await;
+            assert_eq!(
+                body["errors"][0]["extensions"]["code"],
+                "PRECONDITION_FAILED"
+            );
+
+            let res = app
+                .clone()
+                .oneshot(build_graphql_req(query, json!({ "version": 1 })))
+                .await
+                .unwrap();
+            assert_eq!(
+                res_to_value(res).await["data"]["updateTodo"],
+                json!({ "completed": true, "version": 2 })
+            );
+
+            let query = r#"mutation { deleteTodo(id: 1) deleteLabel(id: 1) }"#;
+            let res = app
+                .clone()
+                .oneshot(build_graphql_req(query, Value::Null))
+                .await
+                .unwrap();
+            assert_eq!(
+                res_to_value(res).await["data"],
+                json!({ "deleteTodo": 1, "deleteLabel": 1 })
+            );
+
+            let query = r#"mutation { deleteTodo(id: 1) }"#;
+            let res = app
+                .oneshot(build_graphql_req(query, Value::Null))
+                .await
+                .unwrap();
+            let body = res_to_value(res).await;
+            assert_eq!(body["errors"][0]["extensions"]["code"], "NOT_FOUND");
+        }
+
+        #[tokio::test]
+        async fn should_check_token_scopes_for_mutations() {
+            let app = create_routes(
+                with_jwt_auth(build_app_state(
+                    TodoRepositoryInMemory::new(vec![]),
+                    LabelRepositoryInMemory::new(),
+                ))
+                .await,
+            );
+
+            let req = build_empty_req("/graphql", Method::GET);
+            let res = app.clone().oneshot(with_jwt(req, "ci")).await.unwrap();
+            assert_eq!(res.status(), StatusCode::OK);
+            let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+            assert!(String::from_utf8_lossy(&bytes).contains("graphiql"));
+
+            let req = build_json_req(
+                "/tokens",
+                Method::POST,
+                r#"{"name": "ci", "scopes": ["todos:read", "labels:read"]}"#.to_string(),
+            );
+            let res = app.clone().oneshot(with_jwt(req, "ci")).await.unwrap();
+            let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+            let created: NewApiToken = serde_json::from_slice(&bytes).unwrap();
+            let with_token = |mut req: Request<Body>| {
+                req.headers_mut().insert(
+                    header::AUTHORIZATION,
+                    format!("Bearer {}", created.token).parse().unwrap(),
+                );
+                req
+            };
+
+            let req = build_graphql_req("{ todos { id } }", Value::Null);
+            let res = app.clone().oneshot(with_token(req)).await.unwrap();
+            assert_eq!(res_to_value(res).await, json!({ "data": { "todos": [] } }));
+
+            let query = r#"mutation { createTodo(input: { title: "forbidden" }) { id } }"#;
+            let req = build_graphql_req(query, Value::Null);
+            let res = app.clone().oneshot(with_token(req)).await.unwrap();
+            let body = res_to_value(res).await;
+            assert_eq!(body["errors"][0]["extensions"]["code"], "FORBIDDEN");
+
+            // JWT で認証したリクエストはスコープに縛られずに書き込める
+            let req = build_graphql_req(query, Value::Null);
+            let res = app.oneshot(with_jwt(req, "ci")).await.unwrap();
+            assert_eq!(
+                res_to_value(res).await["data"]["createTodo"],
+                json!({ "id": 1 })
+            );
+        }
+    }
+
+    mod test_grpc {
+        use super::*;
+        use crate::create_grpc_routes;
+        use crate::grpc::proto::{
+            label_service_client::LabelServiceClient, todo_service_client::TodoServiceClient,
+            CreateLabelRequest, CreateTodoRequest, DeleteTodoRequest, FindTodoRequest,
+            ListLabelsRequest, ListTodosRequest, UpdateTodo, UpdateTodoRequest,
+        };
+        use crate::repositories::{
+            api_token::{ApiScope, ApiTokenRepository, CreateApiToken},
+            history::HistoryRepository,
+            workspace::{CreateInvitation, CreateWorkspace, Role, WorkspaceRepository},
+        };
+        use tokio_stream::{wrappers::TcpListenerStream, StreamExt};
+        use tonic::Code;
+
+        async fn serve(state: &TestAppState) -> String {
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            tokio::spawn(
+                create_grpc_routes(state).serve_with_incoming(TcpListenerStream::new(listener)),
+            );
+            format!("http://{}", addr)
+        }
+
+        fn with_metadata<M>(message: M, entries: &[(&'static str, String)]) -> tonic::Request<M> {
+            let mut req = tonic::Request::new(message);
+            for (key, value) in entries {
+                req.metadata_mut().insert(*key, value.parse().unwrap());
+            }
+            req
+        }
+
+        fn bearer(token: &str) -> (&'static str, String) {
+            ("authorization", format!("Bearer {}", token))
+        }
+
+        fn jwt(user_id: &str) -> (&'static str, String) {
+            bearer(&sign("rsa-1", ISSUER, user_id, None))
+        }
+
+        fn create_request() -> CreateTodoRequest {
+            CreateTodoRequest {
+                title: "grpc".to_string(),
+                labels: vec![],
+            }
+        }
+
+        #[tokio::test]
+        async fn should_authenticate_grpc_requests() {
+            let state = with_jwt_auth(build_app_state(
+                TodoRepositoryInMemory::new(vec![]),
+                LabelRepositoryInMemory::new(),
+            ))
+            .await;
+            let workspaces = state.workspace_state.repository.clone();
+            let workspace = workspaces
+                .create(
+                    "owner",
+                    CreateWorkspace {
+                        name: "grpc".to_string(),
+                    },
+                )
+                .await
+                .unwrap();
+            let invitation = workspaces
+                .invite(
+                    workspace.id,
+                    "owner",
+                    CreateInvitation { role: Role::Viewer },
+                )
+                .await
+                .unwrap();
+            workspaces
+                .accept(&invitation.token, "viewer")
+                .await
+                .unwrap();
+            let read_token = state
+                .api_token_state
+                .repository
+                .create(
+                    "owner",
+                    CreateApiToken {
+                        name: "ci".to_string(),
+                        scopes: vec![ApiScope::TodosRead],
+                    },
+                )
+                .await
+                .unwrap()
+                .token;
+            let mut client = TodoServiceClient::connect(serve(&state).await)
+                .await
+                .unwrap();
+            let workspace_id = ("x-workspace-id", workspace.id.to_string());
+
+            // 認証が有効なら、`x-user-id` で名乗っただけでは呼び出せない
+            let status = client
+                .create_todo(with_metadata(
+                    create_request(),
+                    &[("x-user-id", "owner".to_string())],
+                ))
+                .await
+                .unwrap_err();
+            assert_eq!(status.code(), Code::Unauthenticated);
+
+            // メンバーでないワークスペースは参照もできない
+            let status = client
+                .list_todos(with_metadata(
+                    ListTodosRequest { label_id: None },
+                    &[jwt("stranger"), workspace_id.clone()],
+                ))
+                .await
+                .unwrap_err();
+            assert_eq!(status.code(), Code::PermissionDenied);
+
+            // 閲覧者は参照だけできる
+            let status = client
+                .create_todo(with_metadata(
+                    create_request(),
+                    &[jwt("viewer"), workspace_id.clone()],
+                ))
+                .await
+                .unwrap_err();
+            assert_eq!(status.code(), Code::PermissionDenied);
+            let todo = client
+                .create_todo(with_metadata(
+                    create_request(),
+                    &[jwt("owner"), workspace_id.clone()],
+                ))
+                .await
+                .unwrap()
+                .into_inner();
+            assert_eq!(todo.workspace_id, Some(workspace.id));
+            let found = client
+                .find_todo(with_metadata(
+                    FindTodoRequest { id: todo.id },
+                    &[jwt("viewer"), workspace_id.clone()],
+                ))
+                .await
+                .unwrap()
+                .into_inner();
+            assert_eq!(found, todo);
+
+            // 履歴には `x-user-id` ではなく認証されたユーザーが残る
+            let history = state
+                .history_state
+                .repository
+                .find_by_todo(todo.id)
+                .await
+                .unwrap();
+            assert_eq!(history[0].user_id, "owner");
+
+            // 参照のスコープしか持たないトークンでは書き込めない
+            let status = client
+                .create_todo(with_metadata(create_request(), &[bearer(&read_token)]))
+                .await
+                .unwrap_err();
+            assert_eq!(status.code(), Code::PermissionDenied);
+            let status = client
+                .find_todo(with_metadata(
+                    FindTodoRequest { id: todo.id },
+                    &[bearer(&read_token), workspace_id],
+                ))
+                .await
+                .unwrap()
+                .into_inner();
+            assert_eq!(status, todo);
+        }
+
+        #[tokio::test]
+        async fn should_stream_todos_across_pages() {
+            let state = build_app_state(
+                TodoRepositoryInMemory::new(vec![]),
+                LabelRepositoryInMemory::new(),
+            );
+            let mut client = TodoServiceClient::connect(serve(&state).await)
+                .await
+                .unwrap();
+            for _ in 0..250 {
+                client.create_todo(create_request()).await.unwrap();
+            }
+
+            let mut stream = client
+                .list_todos(ListTodosRequest { label_id: None })
+                .await
+                .unwrap()
+                .into_inner();
+            let mut ids = vec![];
+            while let Some(todo) = stream.next().await {
+                ids.push(todo.unwrap().id);
+            }
+            assert_eq!(ids, (1..=250).rev().collect::<Vec<_>>());
+        }
+
+        #[tokio::test]
+        async fn should_share_repositories_with_rest() {
+            let todo_repository = TodoRepositoryInMemory::new(vec![]);
+            let state = build_app_state(
+                todo_repository.clone(),
+                LabelRepositoryInMemory::with_todos(todo_repository),
+            );
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            tokio::spawn(
+                create_grpc_routes(&state).serve_with_incoming(TcpListenerStream::new(listener)),
+            );
+            let mut todo_client = TodoServiceClient::connect(format!("http://{}", addr))
+                .await
+                .unwrap();
+            let mut label_client = LabelServiceClient::connect(format!("http://{}", addr))
+                .await
+                .unwrap();
+
+            let label = label_client
+                .create_label(CreateLabelRequest {
+                    name: "should_share".to_string(),
+                    ..Default::default()
+                })
+                .await
+                .unwrap()
+                .into_inner();
+            assert_eq!(label.color, "#808080");
+
+            let status = todo_client
+                .create_todo(CreateTodoRequest {
+                    title: "".to_string(),
+                    labels: vec![],
+                })
+                .await
+                .unwrap_err();
+            assert_eq!(status.code(), Code::InvalidArgument);
+
+            for title in ["first", "second"] {
+                todo_client
+                    .create_todo(CreateTodoRequest {
+                        title: title.to_string(),
+                        labels: vec![label.id],
+                    })
+                    .await
+                    .unwrap();
+            }
+            let mut stream = todo_client
+                .list_todos(ListTodosRequest {
+                    label_id: Some(label.id),
+                })
+                .await
+                .unwrap()
+                .into_inner();
+            let mut titles = vec![];
+            while let Some(todo) = stream.next().await {
+                let todo = todo.unwrap();
+                assert_eq!(todo.labels[0].name, "should_share");
+                titles.push(todo.title);
+            }
+            titles.sort();
+            assert_eq!(titles, vec!["first", "second"]);
+
+            let status = todo_client
+                .update_todo(UpdateTodoRequest {
+                    id: 1,
+                    todo: Some(UpdateTodo {
+                        completed: Some(true),
+                        ..Default::default()
+                    }),
+                    expected_version: Some(5),
+                    force: false,
+                })
+                .await
+                .unwrap_err();
+            assert_eq!(status.code(), Code::FailedPrecondition);
+            let todo = todo_client
+                .update_todo(UpdateTodoRequest {
+                    id: 1,
+                    todo: Some(UpdateTodo {
+                        completed: Some(true),
+                        ..Default::default()
+                    }),
+                    expected_version: Some(1),
+                    force: false,
+                })
+                .await
+                .unwrap()
+                .into_inner();
+            assert!(todo.completed);
+            assert_eq!(todo.labels.len(), 1);
+
+            todo_client
+                .delete_todo(DeleteTodoRequest {
+                    id: 2,
+                    expected_version: None,
+                })
+                .await
+                .unwrap();
+            let status = todo_client
+                .find_todo(FindTodoRequest { id: 2 })
+                .await
+                .unwrap_err();
+            assert_eq!(status.code(), Code::NotFound);
+
+            // gRPC で作ったデータは REST からも見える
+            let res = create_routes(state)
+                .oneshot(build_empty_req("/todos", Method::GET))
+                .await
+                .unwrap();
+            let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+            let todos: Vec<TodoEntity> = serde_json::from_slice(&bytes).unwrap();
+            assert_eq!(todos.len(), 1);
+            assert!(todos[0].completed);
+
+            let labels = label_client
+                .list_labels(ListLabelsRequest {})
+                .await
+                .unwrap()
+                .into_inner()
+                .labels;
+            assert_eq!(labels, vec![label]);
+        }
+    }
 }
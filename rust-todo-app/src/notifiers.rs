@@ -0,0 +1,109 @@
+pub mod log;
+pub mod smtp;
+pub mod webhook;
+
+use std::{collections::HashMap, env, sync::Arc};
+
+use anyhow::Context;
+use axum::async_trait;
+use serde::Serialize;
+
+use crate::repositories::{
+    reminder::{Reminder, ReminderChannel},
+    todo::TodoEntity,
+};
+
+#[async_trait]
+pub trait Notifier: std::marker::Send + std::marker::Sync + 'static {
+    async fn notify(&self, notification: &Notification) -> anyhow::Result<()>;
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+pub struct Notification {
+    pub reminder: Reminder,
+    pub todo: TodoEntity,
+}
+
+impl Notification {
+    pub fn subject(&self) -> String {
+        format!("Reminder: {}", self.todo.title)
+    }
+}
+
+// チャネルごとの通知先。設定されていないチャネルへの通知はエラーになる
+#[derive(Clone, Default)]
+pub struct Notifiers {
+    channels: HashMap<ReminderChannel, Arc<dyn Notifier>>,
+}
+
+impl Notifiers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register(mut self, channel: ReminderChannel, notifier: impl Notifier) -> Self {
+        self.channels.insert(channel, Arc::new(notifier));
+        self
+    }
+
+    // log は常に有効。webhook / smtp は環境変数が設定されている場合のみ有効にする
+    pub fn from_env() -> anyhow::Result<Self> {
+        let mut notifiers = Self::new().register(ReminderChannel::Log, log::LogNotifier);
+        if let Ok(url) = env::var("REMINDER_WEBHOOK_URL") {
+            notifiers = notifiers.register(
+                ReminderChannel::Webhook,
+                webhook::WebhookNotifier::new(&url)?,
+            );
+        }
+        if let Ok(url) = env::var("REMINDER_SMTP_URL") {
+            let from = env::var("REMINDER_MAIL_FROM").context("REMINDER_MAIL_FROM must be set")?;
+            let to = env::var("REMINDER_MAIL_TO").context("REMINDER_MAIL_TO must be set")?;
+            notifiers = notifiers.register(
+                ReminderChannel::Smtp,
+                smtp::SmtpNotifier::new(&url, &from, &to)?,
+            );
+        }
+        Ok(notifiers)
+    }
+
+    pub fn channels(&self) -> Vec<ReminderChannel> {
+        self.channels.keys().copied().collect()
+    }
+
+    pub fn is_configured(&self, channel: ReminderChannel) -> bool {
+        self.channels.contains_key(&channel)
+    }
+
+    pub async fn notify(&self, notification: &Notification) -> anyhow::Result<()> {
+        let channel = notification.reminder.channel;
+        let notifier = self
+            .channels
+            .get(&channel)
+            .with_context(|| format!("channel is not configured: {:?}", channel))?;
+        notifier.notify(notification).await
+    }
+}
+
+#[cfg(test)]
+pub mod test_utils {
+    use std::sync::{Arc, RwLock};
+
+    use super::*;
+
+    // 受け取った通知を記録するだけの Notifier
+    #[derive(Debug, Clone, Default)]
+    pub struct RecordingNotifier {
+        pub notifications: Arc<RwLock<Vec<Notification>>>,
+    }
+
+    #[async_trait]
+    impl Notifier for RecordingNotifier {
+        async fn notify(&self, notification: &Notification) -> anyhow::Result<()> {
+            self.notifications
+                .write()
+                .unwrap()
+                .push(notification.clone());
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,19 @@
+use axum::async_trait;
+
+use super::{Notification, Notifier};
+
+#[derive(Debug, Clone)]
+pub struct LogNotifier;
+
+#[async_trait]
+impl Notifier for LogNotifier {
+    async fn notify(&self, notification: &Notification) -> anyhow::Result<()> {
+        tracing::info!(
+            "{} (todo: {}, reminder: {})",
+            notification.subject(),
+            notification.todo.id,
+            notification.reminder.id
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,97 @@
+use std::time::Duration;
+
+use axum::async_trait;
+use reqwest::{Client, Url};
+
+use super::{Notification, Notifier};
+
+// 通知内容を JSON で指定の URL に POST する
+#[derive(Debug, Clone)]
+pub struct WebhookNotifier {
+    client: Client,
+    url: Url,
+}
+
+impl WebhookNotifier {
+    pub fn new(url: &str) -> anyhow::Result<Self> {
+        let client = Client::builder().timeout(Duration::from_secs(10)).build()?;
+        Ok(Self {
+            client,
+            url: url.parse()?,
+        })
+    }
+}
+
+#[async_trait]
+impl Notifier for WebhookNotifier {
+    async fn notify(&self, notification: &Notification) -> anyhow::Result<()> {
+        self.client
+            .post(self.url.clone())
+            .json(notification)
+            .send()
+            .await
+            .and_then(|res| res.error_for_status())
+            // 通知先 URL に含まれる認証情報をログに残さない
+            .map_err(reqwest::Error::without_url)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{net::SocketAddr, sync::Arc};
+
+    use axum::{extract::State, routing::post, Json, Router};
+    use chrono::Utc;
+    use tokio::sync::Mutex;
+
+    use super::*;
+    use crate::repositories::{
+        reminder::{Reminder, ReminderChannel},
+        todo::TodoEntity,
+    };
+
+    #[tokio::test]
+    async fn should_post_notification() {
+        let received = Arc::new(Mutex::new(vec![]));
+        let app = Router::new()
+            .route(
+                "/hook",
+                post(
+                    |State(received): State<Arc<Mutex<Vec<serde_json::Value>>>>,
+                     Json(body): Json<serde_json::Value>| async move {
+                        received.lock().await.push(body);
+                    },
+                ),
+            )
+            .with_state(received.clone());
+        let server = axum::Server::bind(&SocketAddr::from(([127, 0, 0, 1], 0)))
+            .serve(app.into_make_service());
+        let addr = server.local_addr();
+        tokio::spawn(server);
+
+        let notifier = WebhookNotifier::new(&format!("http://{}/hook", addr)).unwrap();
+        let notification = Notification {
+            reminder: Reminder {
+                id: 1,
+                todo_id: 1,
+                fire_at: Utc::now(),
+                channel: ReminderChannel::Webhook,
+                fired_at: None,
+                dismissed_at: None,
+            },
+            todo: TodoEntity::new(1, "should_post_notification".to_string(), vec![]),
+        };
+        notifier.notify(&notification).await.expect("failed notify");
+
+        let received = received.lock().await;
+        assert_eq!(received.len(), 1);
+        assert_eq!(
+            received[0]["todo"]["title"],
+            serde_json::json!("should_post_notification")
+        );
+
+        let notifier = WebhookNotifier::new(&format!("http://{}/missing", addr)).unwrap();
+        assert!(notifier.notify(&notification).await.is_err());
+    }
+}
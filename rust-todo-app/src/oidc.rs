@@ -0,0 +1,323 @@
+use std::{
+    env,
+    path::PathBuf,
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+use jsonwebtoken::{decode, decode_header, jwk::JwkSet, Algorithm, DecodingKey, Validation};
+use serde::Deserialize;
+
+use crate::{repositories::user::Identity, telemetry::redact_url};
+
+// 署名に使えるアルゴリズム。共通鍵の HS256 などは受け付けない
+const ALGORITHMS: [Algorithm; 2] = [Algorithm::RS256, Algorithm::ES256];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JwksSource {
+    Url(String),
+    File(PathBuf),
+}
+
+impl JwksSource {
+    async fn load(&self) -> anyhow::Result<JwkSet> {
+        let jwks = match self {
+            JwksSource::Url(url) => {
+                // reqwest のエラーには URL がそのまま含まれるため取り除く
+                let context = || format!("failed to load JWKS from {}", redact_url(url));
+                reqwest::get(url)
+                    .await
+                    .and_then(|res| res.error_for_status())
+                    .map_err(reqwest::Error::without_url)
+                    .with_context(context)?
+                    .json::<JwkSet>()
+                    .await
+                    .map_err(reqwest::Error::without_url)
+                    .with_context(context)?
+            }
+            JwksSource::File(path) => {
+                let body = tokio::fs::read(path)
+                    .await
+                    .with_context(|| format!("failed to read JWKS file {}", path.display()))?;
+                serde_json::from_slice(&body)
+                    .with_context(|| format!("failed to parse JWKS file {}", path.display()))?
+            }
+        };
+        Ok(jwks)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OidcConfig {
+    pub issuer: String,
+    // 他のクライアント向けに発行された JWT を受け付けないよう、aud の検証は必須にする
+    pub audience: String,
+    pub jwks_source: JwksSource,
+    // 未知の kid が来たときに JWKS を取り直す最短間隔
+    pub refresh_cooldown: Duration,
+}
+
+impl OidcConfig {
+    // OIDC_ISSUER が設定されていない場合は JWT による認証を無効にする
+    pub fn from_env() -> anyhow::Result<Option<Self>> {
+        let issuer = match env::var("OIDC_ISSUER") {
+            Ok(issuer) => issuer,
+            Err(_) => return Ok(None),
+        };
+        let jwks_source = match (env::var("OIDC_JWKS_URL"), env::var("OIDC_JWKS_FILE")) {
+            (Ok(url), _) => JwksSource::Url(url),
+            (Err(_), Ok(path)) => JwksSource::File(path.into()),
+            _ => anyhow::bail!("OIDC_JWKS_URL or OIDC_JWKS_FILE must be set"),
+        };
+        let audience = env::var("OIDC_AUDIENCE")
+            .ok()
+            .filter(|audience| !audience.is_empty())
+            .context("OIDC_AUDIENCE must be set")?;
+        let refresh_cooldown = env::var("OIDC_JWKS_REFRESH_COOLDOWN_SECS")
+            .ok()
+            .and_then(|secs| secs.parse().ok())
+            .unwrap_or(30);
+        Ok(Some(Self {
+            issuer,
+            audience,
+            jwks_source,
+            refresh_cooldown: Duration::from_secs(refresh_cooldown),
+        }))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Claims {
+    iss: String,
+    sub: String,
+    email: Option<String>,
+}
+
+#[derive(Debug)]
+struct CachedJwks {
+    jwks: JwkSet,
+    refreshed_at: Instant,
+}
+
+// 設定された issuer の JWKS で JWT を検証する。鍵のローテーションに備えて
+// 定期的な取り直しと、未知の kid を受け取ったときの取り直しを行う
+#[derive(Debug, Clone)]
+pub struct JwtVerifier {
+    config: OidcConfig,
+    cache: Arc<RwLock<CachedJwks>>,
+}
+
+impl JwtVerifier {
+    pub async fn load(config: OidcConfig) -> anyhow::Result<Self> {
+        let jwks = config.jwks_source.load().await?;
+        Ok(Self {
+            config,
+            cache: Arc::new(RwLock::new(CachedJwks {
+                jwks,
+                refreshed_at: Instant::now(),
+            })),
+        })
+    }
+
+    pub async fn refresh(&self) -> anyhow::Result<()> {
+        let jwks = self.config.jwks_source.load().await?;
+        let mut cache = self.cache.write().unwrap();
+        cache.jwks = jwks;
+        cache.refreshed_at = Instant::now();
+        Ok(())
+    }
+
+    // 未知の kid による取り直し。同時に来たリクエストがそれぞれ取り直さないよう、
+    // 間隔の確認と refreshed_at の更新を同じ書き込みロックの中で行ってから読み込む
+    async fn refresh_for_unknown_kid(&self) -> anyhow::Result<bool> {
+        {
+            let mut cache = self.cache.write().unwrap();
+            if cache.refreshed_at.elapsed() < self.config.refresh_cooldown {
+                return Ok(false);
+            }
+            cache.refreshed_at = Instant::now();
+        }
+        let jwks = self.config.jwks_source.load().await?;
+        self.cache.write().unwrap().jwks = jwks;
+        Ok(true)
+    }
+
+    pub async fn verify(&self, token: &str) -> anyhow::Result<Identity> {
+        let header = decode_header(token)?;
+        anyhow::ensure!(
+            ALGORITHMS.contains(&header.alg),
+            "unsupported algorithm: {:?}",
+            header.alg
+        );
+        let kid = header.kid.context("kid is missing")?;
+
+        let key = match self.find_key(&kid)? {
+            Some(key) => key,
+            None => {
+                if !self.refresh_for_unknown_kid().await? {
+                    anyhow::bail!("unknown kid: {}", kid);
+                }
+                self.find_key(&kid)?
+                    .with_context(|| format!("unknown kid: {}", kid))?
+            }
+        };
+
+        let mut validation = Validation::new(header.alg);
+        validation.set_issuer(&[&self.config.issuer]);
+        validation.set_audience(&[&self.config.audience]);
+        let claims = decode::<Claims>(token, &key, &validation)?.claims;
+        Ok(Identity {
+            issuer: claims.iss,
+            subject: claims.sub,
+            email: claims.email,
+        })
+    }
+
+    fn find_key(&self, kid: &str) -> anyhow::Result<Option<DecodingKey>> {
+        let cache = self.cache.read().unwrap();
+        cache
+            .jwks
+            .find(kid)
+            .map(|jwk| DecodingKey::from_jwk(jwk).map_err(anyhow::Error::from))
+            .transpose()
+    }
+}
+
+#[cfg(test)]
+pub mod test_utils {
+    use std::path::Path;
+
+    use chrono::Utc;
+    use jsonwebtoken::{encode, EncodingKey, Header};
+    use serde::Serialize;
+
+    use super::*;
+
+    pub const ISSUER: &str = "https://idp.example.com";
+    pub const AUDIENCE: &str = "todo-app";
+
+    pub fn testdata(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("testdata/oidc")
+            .join(name)
+    }
+
+    pub fn config(jwks: PathBuf) -> OidcConfig {
+        OidcConfig {
+            issuer: ISSUER.to_string(),
+            audience: AUDIENCE.to_string(),
+            jwks_source: JwksSource::File(jwks),
+            refresh_cooldown: Duration::ZERO,
+        }
+    }
+
+    #[derive(Debug, Serialize)]
+    struct TestClaims<'a> {
+        iss: &'a str,
+        aud: &'a str,
+        sub: &'a str,
+        email: Option<&'a str>,
+        exp: i64,
+    }
+
+    // testdata/oidc にある秘密鍵で署名した JWT を作る
+    pub fn sign(kid: &str, iss: &str, sub: &str, email: Option<&str>) -> String {
+        sign_for(kid, iss, AUDIENCE, sub, email)
+    }
+
+    pub fn sign_for(kid: &str, iss: &str, aud: &str, sub: &str, email: Option<&str>) -> String {
+        let (alg, key) = match kid {
+            "ec-1" => (
+                Algorithm::ES256,
+                EncodingKey::from_ec_pem(&std::fs::read(testdata("ec_1.pem")).unwrap()).unwrap(),
+            ),
+            "rsa-1" | "rsa-2" => {
+                let pem = std::fs::read(testdata(&format!("{}.pem", kid.replace('-', "_"))));
+                (
+                    Algorithm::RS256,
+                    EncodingKey::from_rsa_pem(&pem.unwrap()).unwrap(),
+                )
+            }
+            _ => panic!("unknown kid: {}", kid),
+        };
+        let mut header = Header::new(alg);
+        header.kid = Some(kid.to_string());
+        let claims = TestClaims {
+            iss,
+            aud,
+            sub,
+            email,
+            exp: (Utc::now() + chrono::Duration::minutes(5)).timestamp(),
+        };
+        encode(&header, &claims, &key).unwrap()
+    }
+
+    mod test {
+        use super::*;
+
+        #[tokio::test]
+        async fn should_verify_rs256_and_es256() {
+            let verifier = JwtVerifier::load(config(testdata("jwks.json")))
+                .await
+                .unwrap();
+
+            let token = sign("rsa-1", ISSUER, "rsa-user", Some("rsa@example.com"));
+            let identity = verifier.verify(&token).await.expect("failed verify");
+            assert_eq!(identity.subject, "rsa-user");
+            assert_eq!(identity.email, Some("rsa@example.com".to_string()));
+
+            let token = sign("ec-1", ISSUER, "ec-user", None);
+            let identity = verifier.verify(&token).await.expect("failed verify");
+            assert_eq!(identity.subject, "ec-user");
+            assert_eq!(identity.email, None);
+
+            let token = sign("rsa-1", "https://other.example.com", "rsa-user", None);
+            assert!(verifier.verify(&token).await.is_err());
+            let token = sign_for("rsa-1", ISSUER, "other-client", "rsa-user", None);
+            assert!(verifier.verify(&token).await.is_err());
+            assert!(verifier.verify("not a jwt").await.is_err());
+        }
+
+        #[tokio::test]
+        async fn should_refresh_jwks_on_key_rotation() {
+            let path = env::temp_dir().join(format!("jwks_{}.json", std::process::id()));
+            std::fs::copy(testdata("jwks.json"), &path).unwrap();
+            let verifier = JwtVerifier::load(config(path.clone())).await.unwrap();
+
+            let token = sign("rsa-2", ISSUER, "user", None);
+            assert!(verifier.verify(&token).await.is_err());
+
+            std::fs::copy(testdata("jwks_rotated.json"), &path).unwrap();
+            let identity = verifier.verify(&token).await.expect("failed verify");
+            assert_eq!(identity.subject, "user");
+
+            // ローテーションで消えた鍵の JWT は通らない
+            let token = sign("rsa-1", ISSUER, "user", None);
+            assert!(verifier.verify(&token).await.is_err());
+
+            std::fs::remove_file(path).unwrap();
+        }
+
+        #[tokio::test]
+        async fn should_not_refresh_jwks_within_cooldown() {
+            let path = env::temp_dir().join(format!("jwks_cooldown_{}.json", std::process::id()));
+            std::fs::copy(testdata("jwks.json"), &path).unwrap();
+            let mut config = config(path.clone());
+            config.refresh_cooldown = Duration::from_secs(60);
+            let verifier = JwtVerifier::load(config).await.unwrap();
+            {
+                let mut cache = verifier.cache.write().unwrap();
+                cache.refreshed_at = Instant::now() - Duration::from_secs(120);
+            }
+
+            // 最初の未知の kid だけが取り直し、同じ間隔内の次の取り直しは行わない
+            let token = sign("rsa-2", ISSUER, "user", None);
+            assert!(verifier.verify(&token).await.is_err());
+            std::fs::copy(testdata("jwks_rotated.json"), &path).unwrap();
+            assert!(verifier.verify(&token).await.is_err());
+
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+}
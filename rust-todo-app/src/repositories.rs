@@ -1,14 +1,96 @@
+pub mod api_token;
+#[cfg(test)]
+pub mod conformance;
+pub mod history;
+pub mod idempotency;
 pub mod label;
+#[cfg(any(test, feature = "memory"))]
+pub mod memory;
+pub mod reminder;
+pub mod sync;
+#[cfg(test)]
+#[cfg(feature = "database-test")]
+pub mod test_database;
 pub mod todo;
+pub mod user;
+pub mod webhook;
+pub mod workspace;
 
 use thiserror::Error;
+use tracing::Span;
+
+// sqlx のクエリ 1 回分の span。OpenTelemetry には DB クライアントの span として送る
+pub fn query_span(operation: &'static str, table: &'static str) -> Span {
+    tracing::info_span!(
+        "query",
+        otel.name = %format_args!("{} {}", operation, table),
+        otel.kind = "client",
+        db.system = "postgresql",
+        db.operation = operation,
+        db.sql.table = table,
+    )
+}
 
 #[derive(Debug, Error)]
-enum RepositoryError {
+pub enum RepositoryError {
     #[error("Unexpected Error: [{0}]")]
     Unexpected(String),
     #[error("NotFound, id is {0}")]
     NotFound(i32),
     #[error("Duplicate, id is {0}")]
     Duplicate(i32),
+    #[error("Conflict, id is {0}")]
+    Conflict(i32),
+    #[error("DependencyCycle, id is {0}")]
+    DependencyCycle(i32),
+    #[error("LabelCycle, id is {0}")]
+    LabelCycle(i32),
+    #[error("InvalidToken")]
+    InvalidToken,
+}
+
+// リポジトリが扱うデータの範囲。個人のデータは workspace_id が null になる
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Scope {
+    // バックグラウンドタスクなど、ワークスペースをまたいで扱う場合
+    #[default]
+    All,
+    Personal,
+    Workspace(i32),
+}
+
+impl Scope {
+    pub fn new(workspace_id: Option<i32>) -> Self {
+        match workspace_id {
+            Some(id) => Scope::Workspace(id),
+            None => Scope::Personal,
+        }
+    }
+
+    // 新しく作るデータに設定する workspace_id
+    pub fn workspace_id(&self) -> Option<i32> {
+        match self {
+            Scope::Workspace(id) => Some(*id),
+            _ => None,
+        }
+    }
+
+    // インメモリのリポジトリで絞り込みに使う
+    #[cfg(any(test, feature = "memory"))]
+    pub fn contains(&self, workspace_id: Option<i32>) -> bool {
+        match self {
+            Scope::All => true,
+            _ => self.workspace_id() == workspace_id,
+        }
+    }
+
+    // SQL に渡す値。All は null、個人のデータは 0 として
+    // `($n::integer is null or coalesce(workspace_id, 0) = $n)` の形で絞り込む
+    pub fn bind_value(&self) -> Option<i32> {
+        match self {
+            Scope::All => None,
+            Scope::Personal => Some(0),
+            Scope::Workspace(id) => Some(*id),
+        }
+    }
 }
@@ -0,0 +1,467 @@
+use axum::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::{FromRow, PgPool};
+use validator::Validate;
+
+use super::RepositoryError;
+
+// 発行したトークンの接頭辞。ログなどで API トークンだと判別しやすくする
+const TOKEN_PREFIX: &str = "tdo_";
+
+#[async_trait]
+pub trait ApiTokenRepository: Clone + std::marker::Send + std::marker::Sync + 'static {
+    async fn create(&self, user_id: &str, payload: CreateApiToken) -> anyhow::Result<NewApiToken>;
+    async fn all(&self, user_id: &str) -> anyhow::Result<Vec<ApiToken>>;
+    async fn revoke(&self, user_id: &str, id: i32) -> anyhow::Result<ApiToken>;
+    async fn authenticate(&self, token: &str) -> anyhow::Result<ApiToken>;
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ApiScope {
+    #[serde(rename = "todos:read")]
+    TodosRead,
+    #[serde(rename = "todos:write")]
+    TodosWrite,
+    #[serde(rename = "labels:read")]
+    LabelsRead,
+    #[serde(rename = "labels:write")]
+    LabelsWrite,
+    #[serde(rename = "webhooks:read")]
+    WebhooksRead,
+    #[serde(rename = "webhooks:write")]
+    WebhooksWrite,
+    #[serde(rename = "workspaces:read")]
+    WorkspacesRead,
+    #[serde(rename = "workspaces:write")]
+    WorkspacesWrite,
+}
+
+impl ApiScope {
+    pub const ALL: [ApiScope; 8] = [
+        ApiScope::TodosRead,
+        ApiScope::TodosWrite,
+        ApiScope::LabelsRead,
+        ApiScope::LabelsWrite,
+        ApiScope::WebhooksRead,
+        ApiScope::WebhooksWrite,
+        ApiScope::WorkspacesRead,
+        ApiScope::WorkspacesWrite,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ApiScope::TodosRead => "todos:read",
+            ApiScope::TodosWrite => "todos:write",
+            ApiScope::LabelsRead => "labels:read",
+            ApiScope::LabelsWrite => "labels:write",
+            ApiScope::WebhooksRead => "webhooks:read",
+            ApiScope::WebhooksWrite => "webhooks:write",
+            ApiScope::WorkspacesRead => "workspaces:read",
+            ApiScope::WorkspacesWrite => "workspaces:write",
+        }
+    }
+
+    fn parse(scope: &str) -> anyhow::Result<Self> {
+        match scope {
+            "todos:read" => Ok(ApiScope::TodosRead),
+            "todos:write" => Ok(ApiScope::TodosWrite),
+            "labels:read" => Ok(ApiScope::LabelsRead),
+            "labels:write" => Ok(ApiScope::LabelsWrite),
+            "webhooks:read" => Ok(ApiScope::WebhooksRead),
+            "webhooks:write" => Ok(ApiScope::WebhooksWrite),
+            "workspaces:read" => Ok(ApiScope::WorkspacesRead),
+            "workspaces:write" => Ok(ApiScope::WorkspacesWrite),
+            _ => Err(RepositoryError::Unexpected(format!("unknown scope: {}", scope)).into()),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct ApiToken {
+    pub id: i32,
+    pub user_id: String,
+    pub name: String,
+    pub scopes: Vec<ApiScope>,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+impl ApiToken {
+    pub fn has_scope(&self, scope: ApiScope) -> bool {
+        self.scopes.contains(&scope)
+    }
+}
+
+#[derive(Debug, FromRow)]
+struct ApiTokenFromRow {
+    id: i32,
+    user_id: String,
+    name: String,
+    scopes: Vec<String>,
+    created_at: DateTime<Utc>,
+    last_used_at: Option<DateTime<Utc>>,
+    revoked_at: Option<DateTime<Utc>>,
+}
+
+impl TryFrom<ApiTokenFromRow> for ApiToken {
+    type Error = anyhow::Error;
+
+    fn try_from(row: ApiTokenFromRow) -> anyhow::Result<Self> {
+        Ok(ApiToken {
+            id: row.id,
+            user_id: row.user_id,
+            name: row.name,
+            scopes: row
+                .scopes
+                .iter()
+                .map(|scope| ApiScope::parse(scope))
+                .collect::<anyhow::Result<_>>()?,
+            created_at: row.created_at,
+            last_used_at: row.last_used_at,
+            revoked_at: row.revoked_at,
+        })
+    }
+}
+
+// 発行直後のトークン。平文のトークンはこのレスポンスでしか返さない
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct NewApiToken {
+    #[serde(flatten)]
+    pub api_token: ApiToken,
+    pub token: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Validate)]
+pub struct CreateApiToken {
+    #[validate(length(min = 1, message = "Can not be empty"))]
+    #[validate(length(max = 100, message = "Can not be longer than 100 characters"))]
+    pub name: String,
+    #[validate(length(min = 1, message = "Can not be empty"))]
+    pub scopes: Vec<ApiScope>,
+}
+
+pub fn is_api_token(token: &str) -> bool {
+    token.starts_with(TOKEN_PREFIX)
+}
+
+fn generate_token() -> String {
+    format!(
+        "{}{}",
+        TOKEN_PREFIX,
+        hex::encode(rand::random::<[u8; 32]>())
+    )
+}
+
+// DB にはトークンのハッシュだけを保存する
+fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+fn dedup_scopes(scopes: Vec<ApiScope>) -> Vec<ApiScope> {
+    let mut deduped = Vec::with_capacity(scopes.len());
+    for scope in scopes {
+        if !deduped.contains(&scope) {
+            deduped.push(scope);
+        }
+    }
+    deduped
+}
+
+#[derive(Debug, Clone)]
+pub struct ApiTokenRepositoryForDb {
+    pool: PgPool,
+}
+
+impl ApiTokenRepositoryForDb {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl ApiTokenRepository for ApiTokenRepositoryForDb {
+    #[tracing::instrument(name = "api_token_repository.create", skip_all)]
+    async fn create(&self, user_id: &str, payload: CreateApiToken) -> anyhow::Result<NewApiToken> {
+        let token = generate_token();
+        let scopes: Vec<&str> = dedup_scopes(payload.scopes)
+            .iter()
+            .map(|scope| scope.as_str())
+            .collect();
+        let row = sqlx::query_as::<_, ApiTokenFromRow>(
+            r#"
+            insert into api_tokens (user_id, name, token_hash, scopes)
+            values ($1, $2, $3, $4)
+            returning *
+            "#,
+        )
+        .bind(user_id)
+        .bind(payload.name)
+        .bind(hash_token(&token))
+        .bind(scopes)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(NewApiToken {
+            api_token: row.try_into()?,
+            token,
+        })
+    }
+
+    #[tracing::instrument(name = "api_token_repository.all", skip_all)]
+    async fn all(&self, user_id: &str) -> anyhow::Result<Vec<ApiToken>> {
+        let rows = sqlx::query_as::<_, ApiTokenFromRow>(
+            r#"
+            select * from api_tokens
+            where user_id = $1
+            order by id asc
+            "#,
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter().map(ApiToken::try_from).collect()
+    }
+
+    #[tracing::instrument(name = "api_token_repository.revoke", skip_all)]
+    async fn revoke(&self, user_id: &str, id: i32) -> anyhow::Result<ApiToken> {
+        let row = sqlx::query_as::<_, ApiTokenFromRow>(
+            r#"
+            update api_tokens set revoked_at = now()
+            where id = $1 and user_id = $2 and revoked_at is null
+            returning *
+            "#,
+        )
+        .bind(id)
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or(RepositoryError::NotFound(id))?;
+
+        row.try_into()
+    }
+
+    #[tracing::instrument(name = "api_token_repository.authenticate", skip_all)]
+    async fn authenticate(&self, token: &str) -> anyhow::Result<ApiToken> {
+        let row = sqlx::query_as::<_, ApiTokenFromRow>(
+            r#"
+            update api_tokens set last_used_at = now()
+            where token_hash = $1 and revoked_at is null
+            returning *
+            "#,
+        )
+        .bind(hash_token(token))
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or(RepositoryError::InvalidToken)?;
+
+        row.try_into()
+    }
+}
+
+#[cfg(test)]
+#[cfg(feature = "database-test")]
+mod test {
+    use super::*;
+    use crate::repositories::test_database::TestDatabase;
+
+    #[tokio::test]
+    async fn crud_scenario() {
+        let database = TestDatabase::new().await;
+        let pool = database.pool();
+
+        let repository = ApiTokenRepositoryForDb::new(pool.clone());
+        let user_id = format!("crud_scenario_{}", std::process::id());
+
+        // create
+        let created = repository
+            .create(
+                &user_id,
+                CreateApiToken {
+                    name: "ci".to_string(),
+                    scopes: vec![
+                        ApiScope::TodosRead,
+                        ApiScope::TodosRead,
+                        ApiScope::LabelsWrite,
+                    ],
+                },
+            )
+            .await
+            .expect("failed create");
+        assert!(created.token.starts_with(TOKEN_PREFIX));
+        assert_eq!(
+            created.api_token.scopes,
+            vec![ApiScope::TodosRead, ApiScope::LabelsWrite]
+        );
+        assert_eq!(created.api_token.last_used_at, None);
+
+        // 平文のトークンは保存しない
+        let token_hash = sqlx::query_scalar::<_, String>(
+            r#"
+            select token_hash from api_tokens where id = $1
+            "#,
+        )
+        .bind(created.api_token.id)
+        .fetch_one(&pool)
+        .await
+        .expect("failed select token_hash");
+        assert_ne!(token_hash, created.token);
+
+        // authenticate
+        let authenticated = repository
+            .authenticate(&created.token)
+            .await
+            .expect("failed authenticate");
+        assert_eq!(authenticated.id, created.api_token.id);
+        assert!(authenticated.last_used_at.is_some());
+        assert!(repository.authenticate("tdo_unknown").await.is_err());
+
+        // all
+        let tokens = repository.all(&user_id).await.expect("failed all");
+        assert_eq!(tokens, vec![authenticated.clone()]);
+
+        // revoke
+        let res = repository.revoke("someone", created.api_token.id).await;
+        assert!(res.is_err());
+        let revoked = repository
+            .revoke(&user_id, created.api_token.id)
+            .await
+            .expect("failed revoke");
+        assert!(revoked.revoked_at.is_some());
+        let res = repository.authenticate(&created.token).await;
+        assert!(res.is_err());
+    }
+}
+
+#[cfg(any(test, feature = "memory"))]
+pub mod memory {
+    use super::*;
+    use crate::repositories::memory::{MemoryStore, StoredApiToken};
+
+    #[derive(Debug, Clone)]
+    pub struct ApiTokenRepositoryInMemory {
+        store: MemoryStore,
+    }
+
+    impl ApiTokenRepositoryInMemory {
+        #[cfg(test)]
+        pub fn new() -> Self {
+            Self::with_store(MemoryStore::new())
+        }
+
+        // todo と同じストアを渡せば、スナップショットにトークンも保存される
+        pub fn with_store(store: MemoryStore) -> Self {
+            Self { store }
+        }
+    }
+
+    #[async_trait]
+    impl ApiTokenRepository for ApiTokenRepositoryInMemory {
+        async fn create(
+            &self,
+            user_id: &str,
+            payload: CreateApiToken,
+        ) -> anyhow::Result<NewApiToken> {
+            let mut data = self.store.write();
+            let store = &mut data.api_tokens;
+            let token = generate_token();
+            let api_token = ApiToken {
+                id: store.len() as i32 + 1,
+                user_id: user_id.to_string(),
+                name: payload.name,
+                scopes: dedup_scopes(payload.scopes),
+                created_at: Utc::now(),
+                last_used_at: None,
+                revoked_at: None,
+            };
+            store.push(StoredApiToken {
+                api_token: api_token.clone(),
+                token_hash: hash_token(&token),
+            });
+            Ok(NewApiToken { api_token, token })
+        }
+
+        async fn all(&self, user_id: &str) -> anyhow::Result<Vec<ApiToken>> {
+            let data = self.store.read();
+            let store = &data.api_tokens;
+            let tokens = store
+                .iter()
+                .filter(|stored| stored.api_token.user_id == user_id)
+                .map(|stored| stored.api_token.clone())
+                .collect();
+            Ok(tokens)
+        }
+
+        async fn revoke(&self, user_id: &str, id: i32) -> anyhow::Result<ApiToken> {
+            let mut data = self.store.write();
+            let store = &mut data.api_tokens;
+            let stored = store
+                .iter_mut()
+                .find(|stored| {
+                    stored.api_token.id == id
+                        && stored.api_token.user_id == user_id
+                        && stored.api_token.revoked_at.is_none()
+                })
+                .ok_or(RepositoryError::NotFound(id))?;
+            stored.api_token.revoked_at = Some(Utc::now());
+            Ok(stored.api_token.clone())
+        }
+
+        async fn authenticate(&self, token: &str) -> anyhow::Result<ApiToken> {
+            let mut data = self.store.write();
+            let store = &mut data.api_tokens;
+            let token_hash = hash_token(token);
+            let stored = store
+                .iter_mut()
+                .find(|stored| {
+                    stored.token_hash == token_hash && stored.api_token.revoked_at.is_none()
+                })
+                .ok_or(RepositoryError::InvalidToken)?;
+            stored.api_token.last_used_at = Some(Utc::now());
+            Ok(stored.api_token.clone())
+        }
+    }
+
+    #[cfg(test)]
+    mod test {
+        use super::*;
+
+        #[tokio::test]
+        async fn api_token_scenario() {
+            let repository = ApiTokenRepositoryInMemory::new();
+            let created = repository
+                .create(
+                    "user",
+                    CreateApiToken {
+                        name: "ci".to_string(),
+                        scopes: vec![ApiScope::TodosRead],
+                    },
+                )
+                .await
+                .expect("failed create");
+            assert!(created.api_token.has_scope(ApiScope::TodosRead));
+            assert!(!created.api_token.has_scope(ApiScope::TodosWrite));
+
+            let authenticated = repository
+                .authenticate(&created.token)
+                .await
+                .expect("failed authenticate");
+            assert!(authenticated.last_used_at.is_some());
+            assert_eq!(repository.all("user").await.unwrap(), vec![authenticated]);
+            assert!(repository.all("someone").await.unwrap().is_empty());
+
+            repository
+                .revoke("user", created.api_token.id)
+                .await
+                .expect("failed revoke");
+            assert!(repository.authenticate(&created.token).await.is_err());
+            assert!(repository
+                .revoke("user", created.api_token.id)
+                .await
+                .is_err());
+        }
+    }
+}
@@ -0,0 +1,463 @@
+// TodoRepository / LabelRepository の実装が共通で満たすべき振る舞い。
+// インメモリと DB のテストから同じシナリオを呼び出し、実装ごとの違いを検出する。
+// DB のテストではテストごとにデータベースを作るが、インメモリと同じく件数ではなく自分が作ったデータだけを確かめる
+use chrono::Utc;
+
+use super::{
+    label::{CreateLabel, Label, LabelRepository},
+    todo::{CreateTodo, TodoEntity, TodoRepository, UpdateTodo},
+    RepositoryError, Scope,
+};
+
+fn unique_name(name: &str) -> String {
+    format!("[conformance] {} {}", name, Utc::now().timestamp_micros())
+}
+
+fn assert_error(res: anyhow::Result<impl std::fmt::Debug>, expected: RepositoryError) {
+    let err = res.expect_err("expected an error");
+    let actual = err.downcast_ref::<RepositoryError>();
+    assert_eq!(
+        actual.map(|e| e.to_string()),
+        Some(expected.to_string()),
+        "unexpected error: {:?}",
+        err
+    );
+}
+
+fn sorted_labels(todo: &TodoEntity) -> Vec<Label> {
+    let mut labels = todo.labels.clone();
+    labels.sort_by_key(|label| label.id);
+    labels
+}
+
+fn ids(todos: &[TodoEntity]) -> Vec<i32> {
+    todos.iter().map(|todo| todo.id).collect()
+}
+
+fn update(title: Option<&str>, completed: Option<bool>, labels: Option<Vec<i32>>) -> UpdateTodo {
+    UpdateTodo {
+        title: title.map(str::to_string),
+        description: None,
+        completed,
+        labels,
+    }
+}
+
+pub async fn label_repository_conformance<L: LabelRepository>(repository: L) {
+    // create
+    let mut payload = CreateLabel::new(unique_name("label"));
+    payload.color = "#1a2b3c".to_string();
+    payload.description = Some("description".to_string());
+    let label = repository.create(payload.clone()).await.expect("create");
+    assert_eq!(label.name, payload.name);
+    assert_eq!(label.color, payload.color);
+    assert_eq!(label.description, payload.description);
+    assert_eq!(label.parent_id, None);
+    assert_eq!(label.workspace_id, None);
+    assert_eq!(repository.find(label.id).await.expect("find"), label);
+
+    // 同じ名前のラベルは作れない
+    assert_error(
+        repository.create(payload.clone()).await,
+        RepositoryError::Duplicate(label.id),
+    );
+    // 存在しない親は指定できない
+    let mut orphan = CreateLabel::new(unique_name("orphan"));
+    orphan.parent_id = Some(i32::MAX);
+    assert_error(
+        repository.create(orphan).await,
+        RepositoryError::NotFound(i32::MAX),
+    );
+
+    // all は id の昇順で、todo が無ければ件数は 0
+    let all = repository.all().await.expect("all");
+    let ids: Vec<i32> = all.iter().map(|label| label.label.id).collect();
+    assert!(ids.windows(2).all(|w| w[0] < w[1]), "{:?}", ids);
+    let found = all.iter().find(|l| l.label.id == label.id).expect("all");
+    assert_eq!(found.label, label);
+    assert_eq!(found.todo_count.total, 0);
+
+    // find_many は見つかったラベルだけを返す
+    let found = repository
+        .find_many(&[label.id, i32::MAX, label.id])
+        .await
+        .expect("find_many");
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].label, label);
+
+    // move_to
+    let child = repository
+        .create(CreateLabel::new(unique_name("child")))
+        .await
+        .expect("create");
+    assert!(child.id > label.id);
+    let moved = repository
+        .move_to(child.id, Some(label.id))
+        .await
+        .expect("move_to");
+    assert_eq!(moved.parent_id, Some(label.id));
+    assert_eq!(repository.find(child.id).await.unwrap(), moved);
+    assert_error(
+        repository.move_to(label.id, Some(child.id)).await,
+        RepositoryError::LabelCycle(label.id),
+    );
+    assert_error(
+        repository.move_to(label.id, Some(label.id)).await,
+        RepositoryError::LabelCycle(label.id),
+    );
+    assert_error(
+        repository.move_to(child.id, Some(i32::MAX)).await,
+        RepositoryError::NotFound(i32::MAX),
+    );
+
+    // merge: 統合元の子ラベルは統合先に付け替え、統合元は無くなる
+    let target = repository
+        .create(CreateLabel::new(unique_name("target")))
+        .await
+        .expect("create");
+    assert_error(
+        repository.merge(label.id, child.id).await,
+        RepositoryError::LabelCycle(label.id),
+    );
+    let merged = repository.merge(label.id, target.id).await.expect("merge");
+    assert_eq!(merged.label, target);
+    let children: Vec<i32> = merged.children.iter().map(|label| label.id).collect();
+    assert_eq!(children, vec![child.id]);
+    assert!(merged.todo_ids.is_empty());
+    assert_error(
+        repository.find(label.id).await,
+        RepositoryError::NotFound(label.id),
+    );
+    let child = repository.find(child.id).await.expect("find");
+    assert_eq!(child.parent_id, Some(target.id));
+    assert!(repository.merge(label.id, target.id).await.is_err());
+    let trashed = repository.trashed().await.expect("trashed");
+    assert!(trashed.iter().all(|trashed| trashed.label.id != label.id));
+
+    // delete / trashed / restore
+    repository.delete(child.id).await.expect("delete");
+    assert_error(
+        repository.find(child.id).await,
+        RepositoryError::NotFound(child.id),
+    );
+    assert_error(
+        repository.delete(child.id).await,
+        RepositoryError::NotFound(child.id),
+    );
+    let all = repository.all().await.expect("all");
+    assert!(all.iter().all(|label| label.label.id != child.id));
+    let trashed = repository.trashed().await.expect("trashed");
+    let found = trashed.iter().find(|t| t.label.id == child.id).unwrap();
+    assert_eq!(found.label, child);
+
+    // ゴミ箱に入っている間に同じ名前で作られたラベルがあれば復元できない
+    let duplicated = repository
+        .create(CreateLabel::new(child.name.clone()))
+        .await
+        .expect("create");
+    assert_error(
+        repository.restore(child.id).await,
+        RepositoryError::Duplicate(duplicated.id),
+    );
+    repository.delete(duplicated.id).await.expect("delete");
+    let restored = repository.restore(child.id).await.expect("restore");
+    assert_eq!(restored, child);
+    assert_error(
+        repository.restore(child.id).await,
+        RepositoryError::NotFound(child.id),
+    );
+
+    // 別のワークスペースからは見えない
+    let other = repository.scoped(Scope::Workspace(i32::MAX));
+    assert_error(
+        other.find(target.id).await,
+        RepositoryError::NotFound(target.id),
+    );
+    assert!(other.all().await.unwrap().is_empty());
+    assert!(other.find_many(&[target.id]).await.unwrap().is_empty());
+
+    // purge したラベルはゴミ箱からも消え、id は使い回さない
+    for id in [child.id, target.id] {
+        repository.delete(id).await.expect("delete");
+    }
+    repository.purge(Utc::now()).await.expect("purge");
+    let trashed = repository.trashed().await.expect("trashed");
+    for id in [child.id, target.id, duplicated.id] {
+        assert!(trashed.iter().all(|trashed| trashed.label.id != id));
+    }
+    let recreated = repository
+        .create(CreateLabel::new(unique_name("recreated")))
+        .await
+        .expect("create");
+    assert!(recreated.id > duplicated.id);
+    repository.delete(recreated.id).await.expect("delete");
+    repository.purge(Utc::now()).await.expect("purge");
+}
+
+// labels は todos と同じデータを見るリポジトリを渡す
+pub async fn todo_repository_conformance<T: TodoRepository, L: LabelRepository>(
+    repository: T,
+    labels: L,
+) {
+    let work = labels
+        .create(CreateLabel::new(unique_name("work")))
+        .await
+        .expect("create label");
+    let mut backend = CreateLabel::new(unique_name("work/backend"));
+    backend.parent_id = Some(work.id);
+    let backend = labels.create(backend).await.expect("create label");
+
+    // create
+    let title = unique_name("todo");
+    let todo = repository
+        .create(CreateTodo::new(title.clone(), vec![backend.id, work.id]))
+        .await
+        .expect("create");
+    assert_eq!(todo.title, title);
+    assert!(!todo.completed);
+    assert_eq!(todo.version, 1);
+    assert_eq!(todo.workspace_id, None);
+    assert!(!todo.blocked);
+    assert!(todo.blocked_by.is_empty());
+    assert_eq!(sorted_labels(&todo), vec![work.clone(), backend.clone()]);
+
+    // 存在しないラベルは付けられない
+    assert_error(
+        repository
+            .create(CreateTodo::new(unique_name("unknown"), vec![i32::MAX]))
+            .await,
+        RepositoryError::NotFound(i32::MAX),
+    );
+
+    // find
+    let found = repository.find(todo.id).await.expect("find");
+    assert_eq!(found.title, todo.title);
+    assert_eq!(sorted_labels(&found), sorted_labels(&todo));
+    assert_error(
+        repository.find(i32::MAX).await,
+        RepositoryError::NotFound(i32::MAX),
+    );
+
+    // all は id の降順
+    let other = repository
+        .create(CreateTodo::new(unique_name("other"), vec![backend.id]))
+        .await
+        .expect("create");
+    assert!(other.id > todo.id);
+    let all = repository.all().await.expect("all");
+    let all_ids = ids(&all);
+    assert!(all_ids.windows(2).all(|w| w[0] > w[1]), "{:?}", all_ids);
+    assert!(all_ids.contains(&todo.id) && all_ids.contains(&other.id));
+
+    // update
+    let updated = repository
+        .update(
+            todo.id,
+            update(Some("updated"), Some(true), Some(vec![work.id])),
+            Some(1),
+        )
+        .await
+        .expect("update");
+    assert_eq!(updated.title, "updated");
+    assert!(updated.completed);
+    assert_eq!(updated.version, 2);
+    assert_eq!(updated.labels, vec![work.clone()]);
+    let renamed = repository
+        .update(todo.id, update(Some(&title), None, None), None)
+        .await
+        .expect("update");
+    assert!(renamed.completed);
+    assert_eq!(renamed.labels, vec![work.clone()]);
+    assert_eq!(renamed.version, 3);
+    assert_error(
+        repository
+            .update(todo.id, update(Some("stale"), None, None), Some(2))
+            .await,
+        RepositoryError::Conflict(todo.id),
+    );
+    assert_error(
+        repository
+            .update(todo.id, update(None, None, Some(vec![i32::MAX])), None)
+            .await,
+        RepositoryError::NotFound(i32::MAX),
+    );
+    assert_error(
+        repository
+            .update(i32::MAX, update(Some("missing"), None, None), None)
+            .await,
+        RepositoryError::NotFound(i32::MAX),
+    );
+    let todo = repository.find(todo.id).await.expect("find");
+    assert_eq!(todo, renamed);
+
+    // description は None なら変えず、Some(None) なら消す
+    let described = UpdateTodo {
+        description: Some(Some("memo".to_string())),
+        ..update(None, None, None)
+    };
+    let described = repository
+        .update(other.id, described, None)
+        .await
+        .expect("update");
+    assert_eq!(described.description.as_deref(), Some("memo"));
+    let kept = repository
+        .update(other.id, update(None, Some(false), None), None)
+        .await
+        .expect("update");
+    assert_eq!(kept.description.as_deref(), Some("memo"));
+    let cleared = UpdateTodo {
+        description: Some(None),
+        ..update(None, None, None)
+    };
+    let cleared = repository
+        .update(other.id, cleared, None)
+        .await
+        .expect("update");
+    assert_eq!(cleared.description, None);
+    assert_eq!(cleared.title, other.title);
+
+    // all_by_label は子孫のラベルが付いた todo も含め、id の降順で返す
+    let by_label = repository
+        .all_by_label(work.id)
+        .await
+        .expect("all_by_label");
+    assert_eq!(ids(&by_label), vec![other.id, todo.id]);
+    let by_label = repository
+        .all_by_label(backend.id)
+        .await
+        .expect("all_by_label");
+    assert_eq!(ids(&by_label), vec![other.id]);
+    let page = repository
+        .page_by_label(work.id, 1, 1)
+        .await
+        .expect("page_by_label");
+    assert_eq!(ids(&page.todos), vec![todo.id]);
+    assert_eq!(page.total, 2);
+    // page は all と同じ順で切り出す
+    let all = repository.all().await.expect("all");
+    let page = repository.page(2, 1).await.expect("page");
+    let expected: Vec<i32> = ids(&all).into_iter().skip(1).take(2).collect();
+    assert_eq!(ids(&page.todos), expected);
+    assert_eq!(page.total, all.len() as i64);
+
+    // ゴミ箱に入れたラベルは todo のラベルに含めず、復元すれば戻る
+    labels.delete(backend.id).await.expect("delete label");
+    let hidden = repository.find(other.id).await.expect("find");
+    assert!(hidden.labels.is_empty());
+    assert!(repository
+        .all_by_label(backend.id)
+        .await
+        .unwrap()
+        .is_empty());
+    labels.restore(backend.id).await.expect("restore label");
+    let shown = repository.find(other.id).await.expect("find");
+    assert_eq!(shown.labels, vec![backend.clone()]);
+
+    // blocker
+    let blocked = repository
+        .add_blocker(todo.id, other.id)
+        .await
+        .expect("add_blocker");
+    assert!(blocked.blocked);
+    assert_eq!(blocked.blocked_by, vec![other.id]);
+    assert_error(
+        repository.add_blocker(other.id, todo.id).await,
+        RepositoryError::DependencyCycle(other.id),
+    );
+    assert_error(
+        repository.add_blocker(todo.id, todo.id).await,
+        RepositoryError::DependencyCycle(todo.id),
+    );
+    assert_error(
+        repository.add_blocker(todo.id, i32::MAX).await,
+        RepositoryError::NotFound(i32::MAX),
+    );
+    repository
+        .update(other.id, update(None, Some(true), None), None)
+        .await
+        .expect("update");
+    let unblocked = repository.find(todo.id).await.expect("find");
+    assert!(!unblocked.blocked);
+    assert_eq!(unblocked.blocked_by, vec![other.id]);
+    let removed = repository
+        .remove_blocker(todo.id, other.id)
+        .await
+        .expect("remove_blocker");
+    assert!(removed.blocked_by.is_empty());
+    assert_error(
+        repository.remove_blocker(todo.id, other.id).await,
+        RepositoryError::NotFound(other.id),
+    );
+
+    // delete / trashed / restore
+    assert_error(
+        repository.delete(todo.id, Some(1)).await,
+        RepositoryError::Conflict(todo.id),
+    );
+    repository
+        .delete(todo.id, Some(todo.version))
+        .await
+        .expect("delete");
+    assert_error(
+        repository.find(todo.id).await,
+        RepositoryError::NotFound(todo.id),
+    );
+    assert_error(
+        repository.delete(todo.id, None).await,
+        RepositoryError::NotFound(todo.id),
+    );
+    assert_error(
+        repository.delete(todo.id, Some(todo.version)).await,
+        RepositoryError::NotFound(todo.id),
+    );
+    assert!(!ids(&repository.all().await.unwrap()).contains(&todo.id));
+    let trashed = repository.trashed().await.expect("trashed");
+    let found = trashed.iter().find(|t| t.todo.id == todo.id).unwrap();
+    assert_eq!(found.todo, todo);
+    let restored = repository.restore(todo.id).await.expect("restore");
+    assert_eq!(restored, todo);
+    assert_error(
+        repository.restore(todo.id).await,
+        RepositoryError::NotFound(todo.id),
+    );
+
+    // 別のワークスペースからは見えない
+    let scoped = repository.scoped(Scope::Workspace(i32::MAX));
+    assert_error(
+        scoped.find(todo.id).await,
+        RepositoryError::NotFound(todo.id),
+    );
+    assert!(scoped.all().await.unwrap().is_empty());
+    assert_error(
+        scoped
+            .update(todo.id, update(Some("scoped"), None, None), None)
+            .await,
+        RepositoryError::NotFound(todo.id),
+    );
+
+    // purge した todo は復元できず、id は使い回さない
+    for id in [todo.id, other.id] {
+        repository.delete(id, None).await.expect("delete");
+    }
+    repository.purge(Utc::now()).await.expect("purge");
+    let trashed = repository.trashed().await.expect("trashed");
+    assert!(trashed
+        .iter()
+        .all(|t| t.todo.id != todo.id && t.todo.id != other.id));
+    assert_error(
+        repository.restore(todo.id).await,
+        RepositoryError::NotFound(todo.id),
+    );
+    let recreated = repository
+        .create(CreateTodo::new(unique_name("recreated"), vec![]))
+        .await
+        .expect("create");
+    assert!(recreated.id > other.id);
+
+    repository.delete(recreated.id, None).await.expect("delete");
+    repository.purge(Utc::now()).await.expect("purge");
+    for id in [backend.id, work.id] {
+        labels.delete(id).await.expect("delete label");
+    }
+    labels.purge(Utc::now()).await.expect("purge labels");
+}
@@ -0,0 +1,526 @@
+use axum::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{types::Json, FromRow, PgPool};
+
+use super::{todo::TodoEntity, RepositoryError};
+
+#[async_trait]
+pub trait HistoryRepository: Clone + std::marker::Send + std::marker::Sync + 'static {
+    async fn record(&self, user_id: &str, change: NewChange) -> anyhow::Result<ChangeRecord>;
+    async fn find_by_todo(&self, todo_id: i32) -> anyhow::Result<Vec<ChangeRecord>>;
+    async fn next_undo(&self, user_id: &str) -> anyhow::Result<Option<ChangeRecord>>;
+    async fn next_redo(&self, user_id: &str) -> anyhow::Result<Option<ChangeRecord>>;
+    // version は undo で戻した後の todo の version。redo では None にする
+    async fn set_undone(
+        &self,
+        id: i32,
+        undone: bool,
+        version: Option<i32>,
+    ) -> anyhow::Result<ChangeRecord>;
+    async fn skip(&self, id: i32) -> anyhow::Result<ChangeRecord>;
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeAction {
+    Create,
+    Update,
+    Delete,
+    Restore,
+}
+
+impl ChangeAction {
+    fn as_str(&self) -> &'static str {
+        match self {
+            ChangeAction::Create => "create",
+            ChangeAction::Update => "update",
+            ChangeAction::Delete => "delete",
+            ChangeAction::Restore => "restore",
+        }
+    }
+
+    fn parse(action: &str) -> anyhow::Result<Self> {
+        match action {
+            "create" => Ok(ChangeAction::Create),
+            "update" => Ok(ChangeAction::Update),
+            "delete" => Ok(ChangeAction::Delete),
+            "restore" => Ok(ChangeAction::Restore),
+            _ => Err(RepositoryError::Unexpected(format!("unknown action: {}", action)).into()),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct NewChange {
+    pub todo_id: i32,
+    pub action: ChangeAction,
+    pub before: Option<TodoEntity>,
+    pub after: Option<TodoEntity>,
+}
+
+impl NewChange {
+    pub fn create(after: TodoEntity) -> Self {
+        Self {
+            todo_id: after.id,
+            action: ChangeAction::Create,
+            before: None,
+            after: Some(after),
+        }
+    }
+
+    pub fn update(before: TodoEntity, after: TodoEntity) -> Self {
+        Self {
+            todo_id: after.id,
+            action: ChangeAction::Update,
+            before: Some(before),
+            after: Some(after),
+        }
+    }
+
+    pub fn delete(before: TodoEntity) -> Self {
+        Self {
+            todo_id: before.id,
+            action: ChangeAction::Delete,
+            before: Some(before),
+            after: None,
+        }
+    }
+
+    pub fn restore(after: TodoEntity) -> Self {
+        Self {
+            todo_id: after.id,
+            action: ChangeAction::Restore,
+            before: None,
+            after: Some(after),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct ChangeRecord {
+    pub id: i32,
+    pub user_id: String,
+    pub todo_id: i32,
+    pub action: ChangeAction,
+    pub before: Option<TodoEntity>,
+    pub after: Option<TodoEntity>,
+    pub undone: bool,
+    pub undone_version: Option<i32>,
+    pub skipped: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, FromRow)]
+struct ChangeRecordFromRow {
+    id: i32,
+    user_id: String,
+    todo_id: i32,
+    action: String,
+    before: Option<Json<TodoEntity>>,
+    after: Option<Json<TodoEntity>>,
+    undone: bool,
+    undone_version: Option<i32>,
+    skipped: bool,
+    created_at: DateTime<Utc>,
+}
+
+impl TryFrom<ChangeRecordFromRow> for ChangeRecord {
+    type Error = anyhow::Error;
+
+    fn try_from(row: ChangeRecordFromRow) -> anyhow::Result<Self> {
+        Ok(ChangeRecord {
+            id: row.id,
+            user_id: row.user_id,
+            todo_id: row.todo_id,
+            action: ChangeAction::parse(&row.action)?,
+            before: row.before.map(|Json(todo)| todo),
+            after: row.after.map(|Json(todo)| todo),
+            undone: row.undone,
+            undone_version: row.undone_version,
+            skipped: row.skipped,
+            created_at: row.created_at,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct HistoryRepositoryForDb {
+    pool: PgPool,
+}
+
+impl HistoryRepositoryForDb {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl HistoryRepository for HistoryRepositoryForDb {
+    #[tracing::instrument(name = "history_repository.record", skip_all)]
+    async fn record(&self, user_id: &str, change: NewChange) -> anyhow::Result<ChangeRecord> {
+        let mut tx = self.pool.begin().await?;
+
+        // 新しい変更が入ったら redo できる履歴は破棄する
+        sqlx::query(
+            r#"
+            delete from todo_history where user_id = $1 and undone
+            "#,
+        )
+        .bind(user_id)
+        .execute(&mut tx)
+        .await?;
+
+        let row = sqlx::query_as::<_, ChangeRecordFromRow>(
+            r#"
+            insert into todo_history (user_id, todo_id, action, before, after)
+            values ($1, $2, $3, $4, $5)
+            returning *
+            "#,
+        )
+        .bind(user_id)
+        .bind(change.todo_id)
+        .bind(change.action.as_str())
+        .bind(change.before.map(Json))
+        .bind(change.after.map(Json))
+        .fetch_one(&mut tx)
+        .await?;
+
+        tx.commit().await?;
+
+        row.try_into()
+    }
+
+    #[tracing::instrument(name = "history_repository.find_by_todo", skip_all)]
+    async fn find_by_todo(&self, todo_id: i32) -> anyhow::Result<Vec<ChangeRecord>> {
+        let rows = sqlx::query_as::<_, ChangeRecordFromRow>(
+            r#"
+            select * from todo_history
+            where todo_id = $1
+            order by id asc
+            "#,
+        )
+        .bind(todo_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter().map(ChangeRecord::try_from).collect()
+    }
+
+    #[tracing::instrument(name = "history_repository.next_undo", skip_all)]
+    async fn next_undo(&self, user_id: &str) -> anyhow::Result<Option<ChangeRecord>> {
+        let row = sqlx::query_as::<_, ChangeRecordFromRow>(
+            r#"
+            select * from todo_history
+            where user_id = $1 and not undone and not skipped
+            order by id desc
+            limit 1
+            "#,
+        )
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        row.map(ChangeRecord::try_from).transpose()
+    }
+
+    #[tracing::instrument(name = "history_repository.next_redo", skip_all)]
+    async fn next_redo(&self, user_id: &str) -> anyhow::Result<Option<ChangeRecord>> {
+        let row = sqlx::query_as::<_, ChangeRecordFromRow>(
+            r#"
+            select * from todo_history
+            where user_id = $1 and undone and not skipped
+            order by id asc
+            limit 1
+            "#,
+        )
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        row.map(ChangeRecord::try_from).transpose()
+    }
+
+    #[tracing::instrument(name = "history_repository.set_undone", skip_all)]
+    async fn set_undone(
+        &self,
+        id: i32,
+        undone: bool,
+        version: Option<i32>,
+    ) -> anyhow::Result<ChangeRecord> {
+        let row = sqlx::query_as::<_, ChangeRecordFromRow>(
+            r#"
+            update todo_history set undone = $2, undone_version = $3
+            where id = $1
+            returning *
+            "#,
+        )
+        .bind(id)
+        .bind(undone)
+        .bind(version)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or(RepositoryError::NotFound(id))?;
+
+        row.try_into()
+    }
+
+    #[tracing::instrument(name = "history_repository.skip", skip_all)]
+    async fn skip(&self, id: i32) -> anyhow::Result<ChangeRecord> {
+        let row = sqlx::query_as::<_, ChangeRecordFromRow>(
+            r#"
+            update todo_history set skipped = true
+            where id = $1
+            returning *
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or(RepositoryError::NotFound(id))?;
+
+        row.try_into()
+    }
+}
+
+#[cfg(test)]
+#[cfg(feature = "database-test")]
+mod test {
+    use super::*;
+    use crate::repositories::test_database::TestDatabase;
+
+    #[tokio::test]
+    async fn crud_scenario() {
+        let database = TestDatabase::new().await;
+        let pool = database.pool();
+
+        let repository = HistoryRepositoryForDb::new(pool);
+        let user_id = format!("crud_scenario-{}", std::process::id());
+        let before = TodoEntity::new(-1, "before".to_string(), vec![]);
+        let after = TodoEntity::new(-1, "after".to_string(), vec![]);
+
+        // record
+        let created = repository
+            .record(&user_id, NewChange::create(before.clone()))
+            .await
+            .expect("failed record");
+        assert_eq!(created.action, ChangeAction::Create);
+        assert_eq!(created.after, Some(before.clone()));
+        let updated = repository
+            .record(&user_id, NewChange::update(before, after.clone()))
+            .await
+            .expect("failed record");
+
+        // find_by_todo
+        let records = repository.find_by_todo(-1).await.expect("failed find");
+        assert!(records.contains(&created));
+        assert!(records.contains(&updated));
+
+        // undo
+        let undo = repository
+            .next_undo(&user_id)
+            .await
+            .expect("failed next_undo");
+        assert_eq!(undo, Some(updated.clone()));
+        let undone = repository
+            .set_undone(updated.id, true, Some(3))
+            .await
+            .expect("failed set_undone");
+        assert_eq!(undone.undone_version, Some(3));
+        let undo = repository
+            .next_undo(&user_id)
+            .await
+            .expect("failed next_undo");
+        assert_eq!(undo, Some(created.clone()));
+
+        // redo
+        let redo = repository
+            .next_redo(&user_id)
+            .await
+            .expect("failed next_redo");
+        assert_eq!(redo.map(|r| r.id), Some(updated.id));
+
+        // skipped changes are neither undone nor redone
+        repository.skip(created.id).await.expect("failed skip");
+        let undo = repository
+            .next_undo(&user_id)
+            .await
+            .expect("failed next_undo");
+        assert_eq!(undo, None);
+
+        // new change discards redo
+        repository
+            .record(&user_id, NewChange::delete(after))
+            .await
+            .expect("failed record");
+        let redo = repository
+            .next_redo(&user_id)
+            .await
+            .expect("failed next_redo");
+        assert_eq!(redo, None);
+    }
+}
+
+#[cfg(any(test, feature = "memory"))]
+pub mod memory {
+    use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+    use anyhow::Context;
+
+    use super::*;
+
+    type HistoryDatas = Vec<ChangeRecord>;
+
+    #[derive(Debug, Clone)]
+    pub struct HistoryRepositoryInMemory {
+        store: Arc<RwLock<HistoryDatas>>,
+    }
+
+    impl HistoryRepositoryInMemory {
+        pub fn new() -> Self {
+            Self {
+                store: Arc::default(),
+            }
+        }
+
+        fn write_store_ref(&self) -> RwLockWriteGuard<'_, HistoryDatas> {
+            self.store.write().unwrap()
+        }
+
+        fn read_store_ref(&self) -> RwLockReadGuard<'_, HistoryDatas> {
+            self.store.read().unwrap()
+        }
+    }
+
+    #[async_trait]
+    impl HistoryRepository for HistoryRepositoryInMemory {
+        async fn record(&self, user_id: &str, change: NewChange) -> anyhow::Result<ChangeRecord> {
+            let mut store = self.write_store_ref();
+            let id = store.last().map(|record| record.id).unwrap_or(0) + 1;
+            store.retain(|record| !(record.user_id == user_id && record.undone));
+            let record = ChangeRecord {
+                id,
+                user_id: user_id.to_string(),
+                todo_id: change.todo_id,
+                action: change.action,
+                before: change.before,
+                after: change.after,
+                undone: false,
+                undone_version: None,
+                skipped: false,
+                created_at: Utc::now(),
+            };
+            store.push(record.clone());
+            Ok(record)
+        }
+
+        async fn find_by_todo(&self, todo_id: i32) -> anyhow::Result<Vec<ChangeRecord>> {
+            let store = self.read_store_ref();
+            Ok(store
+                .iter()
+                .filter(|record| record.todo_id == todo_id)
+                .cloned()
+                .collect())
+        }
+
+        async fn next_undo(&self, user_id: &str) -> anyhow::Result<Option<ChangeRecord>> {
+            let store = self.read_store_ref();
+            Ok(store
+                .iter()
+                .rev()
+                .find(|record| record.user_id == user_id && !record.undone && !record.skipped)
+                .cloned())
+        }
+
+        async fn next_redo(&self, user_id: &str) -> anyhow::Result<Option<ChangeRecord>> {
+            let store = self.read_store_ref();
+            Ok(store
+                .iter()
+                .find(|record| record.user_id == user_id && record.undone && !record.skipped)
+                .cloned())
+        }
+
+        async fn set_undone(
+            &self,
+            id: i32,
+            undone: bool,
+            version: Option<i32>,
+        ) -> anyhow::Result<ChangeRecord> {
+            let mut store = self.write_store_ref();
+            let record = store
+                .iter_mut()
+                .find(|record| record.id == id)
+                .context(RepositoryError::NotFound(id))?;
+            record.undone = undone;
+            record.undone_version = version;
+            Ok(record.clone())
+        }
+
+        async fn skip(&self, id: i32) -> anyhow::Result<ChangeRecord> {
+            let mut store = self.write_store_ref();
+            let record = store
+                .iter_mut()
+                .find(|record| record.id == id)
+                .context(RepositoryError::NotFound(id))?;
+            record.skipped = true;
+            Ok(record.clone())
+        }
+    }
+
+    #[cfg(test)]
+    mod test {
+        use super::*;
+
+        #[tokio::test]
+        async fn history_scenario() {
+            let repository = HistoryRepositoryInMemory::new();
+            let before = TodoEntity::new(1, "before".to_string(), vec![]);
+            let after = TodoEntity::new(1, "after".to_string(), vec![]);
+
+            // record
+            let created = repository
+                .record("user", NewChange::create(before.clone()))
+                .await
+                .expect("failed record");
+            let updated = repository
+                .record("user", NewChange::update(before, after.clone()))
+                .await
+                .expect("failed record");
+            repository
+                .record("other", NewChange::delete(after.clone()))
+                .await
+                .expect("failed record");
+
+            // find_by_todo
+            let records = repository.find_by_todo(1).await.expect("failed find");
+            assert_eq!(records.len(), 3);
+
+            // undo
+            let undo = repository.next_undo("user").await.unwrap();
+            assert_eq!(undo, Some(updated.clone()));
+            repository
+                .set_undone(updated.id, true, Some(3))
+                .await
+                .unwrap();
+            let undo = repository.next_undo("user").await.unwrap();
+            assert_eq!(undo, Some(created.clone()));
+
+            // redo
+            let redo = repository.next_redo("user").await.unwrap();
+            assert_eq!(redo.map(|r| r.undone_version), Some(Some(3)));
+
+            // skipped changes are neither undone nor redone
+            repository.skip(created.id).await.unwrap();
+            let undo = repository.next_undo("user").await.unwrap();
+            assert_eq!(undo, None);
+
+            // new change discards redo
+            repository
+                .record("user", NewChange::delete(after))
+                .await
+                .expect("failed record");
+            let redo = repository.next_redo("user").await.unwrap();
+            assert_eq!(redo, None);
+        }
+    }
+}
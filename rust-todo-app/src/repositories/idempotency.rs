@@ -0,0 +1,448 @@
+use std::time::Duration;
+
+use axum::async_trait;
+use sqlx::{types::Json, FromRow, PgPool};
+
+#[async_trait]
+pub trait IdempotencyRepository: Clone + std::marker::Send + std::marker::Sync + 'static {
+    async fn reserve(
+        &self,
+        scope: &str,
+        key: &str,
+        request_body: &[u8],
+    ) -> anyhow::Result<Reservation>;
+    async fn complete(
+        &self,
+        scope: &str,
+        key: &str,
+        response: IdempotentResponse,
+    ) -> anyhow::Result<IdempotentResponse>;
+    async fn release(&self, scope: &str, key: &str) -> anyhow::Result<()>;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IdempotentResponse {
+    pub request_body: Vec<u8>,
+    pub status_code: i16,
+    pub response_headers: Vec<(String, String)>,
+    pub response_body: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Reservation {
+    // キーを予約できたので、呼び出し側がリクエストを処理する
+    Reserved,
+    // 同じキーのリクエストがまだ処理中
+    InProgress { request_body: Vec<u8> },
+    Completed(IdempotentResponse),
+}
+
+#[derive(Debug, FromRow)]
+struct IdempotencyKeyFromRow {
+    request_body: Vec<u8>,
+    status_code: Option<i16>,
+    response_headers: Option<Json<Vec<(String, String)>>>,
+    response_body: Option<Vec<u8>>,
+}
+
+impl From<IdempotencyKeyFromRow> for Reservation {
+    fn from(row: IdempotencyKeyFromRow) -> Self {
+        match (row.status_code, row.response_body) {
+            (Some(status_code), Some(response_body)) => {
+                Reservation::Completed(IdempotentResponse {
+                    request_body: row.request_body,
+                    status_code,
+                    response_headers: row
+                        .response_headers
+                        .map(|headers| headers.0)
+                        .unwrap_or_default(),
+                    response_body,
+                })
+            }
+            _ => Reservation::InProgress {
+                request_body: row.request_body,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct IdempotencyRepositoryForDb {
+    pool: PgPool,
+    ttl: Duration,
+}
+
+impl IdempotencyRepositoryForDb {
+    pub fn new(pool: PgPool, ttl: Duration) -> Self {
+        Self { pool, ttl }
+    }
+
+    async fn find(&self, scope: &str, key: &str) -> anyhow::Result<Option<IdempotencyKeyFromRow>> {
+        let row = sqlx::query_as::<_, IdempotencyKeyFromRow>(
+            r#"
+            select request_body, status_code, response_headers, response_body
+            from idempotency_keys
+            where scope = $1 and key = $2
+            "#,
+        )
+        .bind(scope)
+        .bind(key)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row)
+    }
+}
+
+#[async_trait]
+impl IdempotencyRepository for IdempotencyRepositoryForDb {
+    #[tracing::instrument(name = "idempotency_repository.reserve", skip_all)]
+    async fn reserve(
+        &self,
+        scope: &str,
+        key: &str,
+        request_body: &[u8],
+    ) -> anyhow::Result<Reservation> {
+        // 期限切れのキーはここでまとめて削除する
+        sqlx::query(
+            r#"
+            delete from idempotency_keys
+            where created_at <= now() - make_interval(secs => $1)
+            "#,
+        )
+        .bind(self.ttl.as_secs_f64())
+        .execute(&self.pool)
+        .await?;
+
+        loop {
+            // 主キーの一意制約で、同じキーを予約できるリクエストを一つに絞る
+            let result = sqlx::query(
+                r#"
+                insert into idempotency_keys (scope, key, request_body)
+                values ($1, $2, $3)
+                on conflict (scope, key) do nothing
+                "#,
+            )
+            .bind(scope)
+            .bind(key)
+            .bind(request_body)
+            .execute(&self.pool)
+            .await?;
+            if result.rows_affected() == 1 {
+                return Ok(Reservation::Reserved);
+            }
+            // 読む前に他のリクエストが予約を消していれば、もう一度予約する
+            if let Some(row) = self.find(scope, key).await? {
+                return Ok(row.into());
+            }
+        }
+    }
+
+    #[tracing::instrument(name = "idempotency_repository.complete", skip_all)]
+    async fn complete(
+        &self,
+        scope: &str,
+        key: &str,
+        response: IdempotentResponse,
+    ) -> anyhow::Result<IdempotentResponse> {
+        // 予約が期限切れで消えていれば入れ直す。
+        // 先に保存されたレスポンスがあれば上書きせず、そちらを返す
+        let row = sqlx::query_as::<_, IdempotencyKeyFromRow>(
+            r#"
+            with saved as (
+                insert into idempotency_keys
+                    (scope, key, request_body, status_code, response_headers, response_body)
+                values ($1, $2, $3, $4, $5, $6)
+                on conflict (scope, key) do update
+                set request_body = excluded.request_body,
+                    status_code = excluded.status_code,
+                    response_headers = excluded.response_headers,
+                    response_body = excluded.response_body
+                where idempotency_keys.status_code is null
+                returning request_body, status_code, response_headers, response_body
+            )
+            select request_body, status_code, response_headers, response_body from saved
+            union all
+            select request_body, status_code, response_headers, response_body
+            from idempotency_keys
+            where scope = $1 and key = $2 and not exists (select 1 from saved)
+            "#,
+        )
+        .bind(scope)
+        .bind(key)
+        .bind(&response.request_body)
+        .bind(response.status_code)
+        .bind(Json(&response.response_headers))
+        .bind(&response.response_body)
+        .fetch_one(&self.pool)
+        .await?;
+
+        match Reservation::from(row) {
+            Reservation::Completed(saved) => Ok(saved),
+            _ => Ok(response),
+        }
+    }
+
+    #[tracing::instrument(name = "idempotency_repository.release", skip_all)]
+    async fn release(&self, scope: &str, key: &str) -> anyhow::Result<()> {
+        // 処理中の予約だけを消し、完了したレスポンスは残す
+        sqlx::query(
+            r#"
+            delete from idempotency_keys
+            where scope = $1 and key = $2 and status_code is null
+            "#,
+        )
+        .bind(scope)
+        .bind(key)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+#[cfg(feature = "database-test")]
+mod test {
+    use super::*;
+    use crate::repositories::test_database::TestDatabase;
+
+    #[tokio::test]
+    async fn crud_scenario() {
+        let database = TestDatabase::new().await;
+        let pool = database.pool();
+
+        let repository = IdempotencyRepositoryForDb::new(pool.clone(), Duration::from_secs(60));
+        let scope = "/crud_scenario";
+        let key = "crud_scenario";
+        let request_body = br#"{"name":"crud_scenario"}"#.to_vec();
+        let response = IdempotentResponse {
+            request_body: request_body.clone(),
+            status_code: 201,
+            response_headers: vec![("content-type".to_string(), "application/json".to_string())],
+            response_body: br#"{"id":1,"name":"crud_scenario"}"#.to_vec(),
+        };
+
+        // reserve
+        let reserved = repository
+            .reserve(scope, key, &request_body)
+            .await
+            .expect("failed reserve");
+        assert_eq!(reserved, Reservation::Reserved);
+        let reserved = repository
+            .reserve(scope, key, &request_body)
+            .await
+            .expect("failed reserve");
+        assert_eq!(
+            reserved,
+            Reservation::InProgress {
+                request_body: request_body.clone()
+            }
+        );
+
+        // complete
+        let saved = repository
+            .complete(scope, key, response.clone())
+            .await
+            .expect("failed complete");
+        assert_eq!(saved, response);
+        let reserved = repository
+            .reserve(scope, key, &request_body)
+            .await
+            .expect("failed reserve");
+        assert_eq!(reserved, Reservation::Completed(response.clone()));
+
+        // complete again does not overwrite
+        let saved = repository
+            .complete(
+                scope,
+                key,
+                IdempotentResponse {
+                    status_code: 500,
+                    ..response.clone()
+                },
+            )
+            .await
+            .expect("failed complete");
+        assert_eq!(saved, response);
+
+        // release keeps the completed response
+        repository
+            .release(scope, key)
+            .await
+            .expect("failed release");
+        let reserved = repository
+            .reserve(scope, key, &request_body)
+            .await
+            .expect("failed reserve");
+        assert_eq!(reserved, Reservation::Completed(response));
+
+        // release an in-progress reservation
+        repository
+            .reserve(scope, "released", &request_body)
+            .await
+            .expect("failed reserve");
+        repository
+            .release(scope, "released")
+            .await
+            .expect("failed release");
+        let reserved = repository
+            .reserve(scope, "released", &request_body)
+            .await
+            .expect("failed reserve");
+        assert_eq!(reserved, Reservation::Reserved);
+
+        // expired
+        let repository = IdempotencyRepositoryForDb::new(pool, Duration::ZERO);
+        let reserved = repository
+            .reserve(scope, key, &request_body)
+            .await
+            .expect("failed reserve");
+        assert_eq!(reserved, Reservation::Reserved);
+    }
+}
+
+#[cfg(any(test, feature = "memory"))]
+pub mod memory {
+    use std::{
+        collections::HashMap,
+        sync::{Arc, RwLock, RwLockWriteGuard},
+        time::Instant,
+    };
+
+    use super::*;
+
+    // 予約したリクエストの本文と、完了していればそのレスポンス
+    type IdempotencyDatas =
+        HashMap<(String, String), (Instant, Vec<u8>, Option<IdempotentResponse>)>;
+
+    #[derive(Debug, Clone)]
+    pub struct IdempotencyRepositoryInMemory {
+        store: Arc<RwLock<IdempotencyDatas>>,
+        ttl: Duration,
+    }
+
+    impl IdempotencyRepositoryInMemory {
+        pub fn new(ttl: Duration) -> Self {
+            Self {
+                store: Arc::default(),
+                ttl,
+            }
+        }
+
+        fn write_store_ref(&self) -> RwLockWriteGuard<'_, IdempotencyDatas> {
+            self.store.write().unwrap()
+        }
+    }
+
+    #[async_trait]
+    impl IdempotencyRepository for IdempotencyRepositoryInMemory {
+        async fn reserve(
+            &self,
+            scope: &str,
+            key: &str,
+            request_body: &[u8],
+        ) -> anyhow::Result<Reservation> {
+            let mut store = self.write_store_ref();
+            store.retain(|_, (created_at, _, _)| created_at.elapsed() < self.ttl);
+            let reservation = match store.get(&(scope.to_string(), key.to_string())) {
+                Some((_, _, Some(response))) => Reservation::Completed(response.clone()),
+                Some((_, request_body, None)) => Reservation::InProgress {
+                    request_body: request_body.clone(),
+                },
+                None => {
+                    store.insert(
+                        (scope.to_string(), key.to_string()),
+                        (Instant::now(), request_body.to_vec(), None),
+                    );
+                    Reservation::Reserved
+                }
+            };
+            Ok(reservation)
+        }
+
+        async fn complete(
+            &self,
+            scope: &str,
+            key: &str,
+            response: IdempotentResponse,
+        ) -> anyhow::Result<IdempotentResponse> {
+            let mut store = self.write_store_ref();
+            let (_, request_body, saved) = store
+                .entry((scope.to_string(), key.to_string()))
+                .or_insert_with(|| (Instant::now(), response.request_body.clone(), None));
+            if saved.is_none() {
+                *request_body = response.request_body.clone();
+                *saved = Some(response);
+            }
+            Ok(saved.clone().unwrap())
+        }
+
+        async fn release(&self, scope: &str, key: &str) -> anyhow::Result<()> {
+            let mut store = self.write_store_ref();
+            let key = (scope.to_string(), key.to_string());
+            if matches!(store.get(&key), Some((_, _, None))) {
+                store.remove(&key);
+            }
+            Ok(())
+        }
+    }
+
+    #[cfg(test)]
+    mod test {
+        use super::*;
+
+        #[tokio::test]
+        async fn idempotency_scenario() {
+            let repository = IdempotencyRepositoryInMemory::new(Duration::from_secs(60));
+            let request_body = br#"{"name":"label"}"#.to_vec();
+            let response = IdempotentResponse {
+                request_body: request_body.clone(),
+                status_code: 201,
+                response_headers: vec![],
+                response_body: br#"{"id":1,"name":"label"}"#.to_vec(),
+            };
+
+            // reserve
+            let reserved = repository
+                .reserve("/labels", "key", &request_body)
+                .await
+                .expect("failed reserve");
+            assert_eq!(reserved, Reservation::Reserved);
+            let reserved = repository
+                .reserve("/labels", "key", &request_body)
+                .await
+                .expect("failed reserve");
+            assert!(matches!(reserved, Reservation::InProgress { .. }));
+
+            // complete
+            repository
+                .complete("/labels", "key", response.clone())
+                .await
+                .expect("failed complete");
+            let reserved = repository
+                .reserve("/labels", "key", &request_body)
+                .await
+                .expect("failed reserve");
+            assert_eq!(reserved, Reservation::Completed(response));
+
+            // other scope
+            let reserved = repository
+                .reserve("/todos", "key", &request_body)
+                .await
+                .expect("failed reserve");
+            assert_eq!(reserved, Reservation::Reserved);
+
+            // release
+            repository
+                .release("/todos", "key")
+                .await
+                .expect("failed release");
+            let reserved = repository
+                .reserve("/todos", "key", &request_body)
+                .await
+                .expect("failed reserve");
+            assert_eq!(reserved, Reservation::Reserved);
+        }
+    }
+}
@@ -1,21 +1,159 @@
+use std::collections::HashSet;
+
 use axum::async_trait;
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use sqlx::PgPool;
-use validator::Validate;
+use sqlx::{FromRow, PgPool, Postgres, Transaction};
+use tracing::Instrument;
+use validator::{Validate, ValidationError};
+
+use super::{query_span, sync::Changes, RepositoryError, Scope};
 
-use super::RepositoryError;
+// ラベルの親子関係の変更を直列にするためのロック。
+// 別々のラベルを同時に移動して循環ができないよう、移動する 2 行に加えて木全体をロックする
+const HIERARCHY_LOCK: i64 = 0x746f_646f_6c62;
 
 #[async_trait]
 pub trait LabelRepository: Clone + std::marker::Send + std::marker::Sync + 'static {
-    async fn create(&self, name: String) -> anyhow::Result<Label>;
-    async fn all(&self) -> anyhow::Result<Vec<Label>>;
+    fn scoped(&self, scope: Scope) -> Self;
+    async fn create(&self, payload: CreateLabel) -> anyhow::Result<Label>;
+    async fn find(&self, id: i32) -> anyhow::Result<Label>;
+    async fn all(&self) -> anyhow::Result<Vec<LabelWithCount>>;
+    // 指定した id のラベルだけを件数付きで返す。見つからない id は無視する
+    async fn find_many(&self, ids: &[i32]) -> anyhow::Result<Vec<LabelWithCount>>;
     async fn delete(&self, id: i32) -> anyhow::Result<()>;
+    async fn trashed(&self) -> anyhow::Result<Vec<TrashedLabel>>;
+    async fn restore(&self, id: i32) -> anyhow::Result<Label>;
+    async fn purge(&self, deleted_before: DateTime<Utc>) -> anyhow::Result<u64>;
+    async fn move_to(&self, id: i32, parent_id: Option<i32>) -> anyhow::Result<Label>;
+    async fn merge(&self, id: i32, target_id: i32) -> anyhow::Result<MergedLabel>;
+    async fn changes(&self, since: i64) -> anyhow::Result<Changes<Label>>;
+}
+
+pub const DEFAULT_COLOR: &str = "#808080";
+
+fn default_color() -> String {
+    DEFAULT_COLOR.to_string()
+}
+
+// `#1a2b3c` 形式の 16 進カラーコードのみ受け付ける
+fn validate_color(color: &str) -> Result<(), ValidationError> {
+    let valid = color.len() == 7
+        && color.starts_with('#')
+        && color[1..].chars().all(|c| c.is_ascii_hexdigit());
+    if valid {
+        Ok(())
+    } else {
+        Err(ValidationError::new("color"))
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, sqlx::FromRow)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, FromRow)]
 pub struct Label {
     pub id: i32,
     pub name: String,
+    #[serde(default = "default_color")]
+    pub color: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub parent_id: Option<i32>,
+    #[serde(default)]
+    pub workspace_id: Option<i32>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub struct TodoCount {
+    pub total: i64,
+    pub open: i64,
+    pub completed: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct LabelWithCount {
+    #[serde(flatten)]
+    pub label: Label,
+    pub todo_count: TodoCount,
+}
+
+#[derive(Debug, FromRow)]
+struct LabelWithCountFromRow {
+    #[sqlx(flatten)]
+    label: Label,
+    total: i64,
+    open: i64,
+    completed: i64,
+}
+
+impl From<LabelWithCountFromRow> for LabelWithCount {
+    fn from(row: LabelWithCountFromRow) -> Self {
+        LabelWithCount {
+            label: row.label,
+            todo_count: TodoCount {
+                total: row.total,
+                open: row.open,
+                completed: row.completed,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, FromRow)]
+pub struct TrashedLabel {
+    #[serde(flatten)]
+    #[sqlx(flatten)]
+    pub label: Label,
+    pub deleted_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct LabelNode {
+    #[serde(flatten)]
+    pub label: LabelWithCount,
+    pub children: Vec<LabelNode>,
+}
+
+// 親子関係をたどってラベルを木構造に組み立てる。親がない(ゴミ箱にある)ラベルは根になる
+// 親子関係が循環していても無限に再帰しないよう、一度たどったラベルは再び訪れない。
+// 循環の中にあって根からたどれないラベルも、最後に根として加える
+pub fn build_tree(labels: Vec<LabelWithCount>) -> Vec<LabelNode> {
+    fn node_of(
+        label: &LabelWithCount,
+        labels: &[LabelWithCount],
+        visited: &mut HashSet<i32>,
+    ) -> LabelNode {
+        let mut children = vec![];
+        for child in labels
+            .iter()
+            .filter(|child| child.label.parent_id == Some(label.label.id))
+        {
+            if visited.insert(child.label.id) {
+                children.push(node_of(child, labels, visited));
+            }
+        }
+        LabelNode {
+            label: label.clone(),
+            children,
+        }
+    }
+
+    let ids: HashSet<i32> = labels.iter().map(|label| label.label.id).collect();
+    let mut visited = HashSet::new();
+    let mut roots = vec![];
+    for label in labels
+        .iter()
+        .filter(|label| !label.label.parent_id.is_some_and(|id| ids.contains(&id)))
+    {
+        if visited.insert(label.label.id) {
+            roots.push(node_of(label, &labels, &mut visited));
+        }
+    }
+    for label in &labels {
+        if visited.insert(label.label.id) {
+            roots.push(node_of(label, &labels, &mut visited));
+        }
+    }
+    roots
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Validate)]
@@ -23,185 +161,1090 @@ pub struct CreateLabel {
     #[validate(length(min = 1, message = "Can not be empty"))]
     #[validate(length(max = 100, message = "Can not be longer than 100 characters"))]
     pub name: String,
+    #[serde(default = "default_color")]
+    #[validate(custom(
+        function = "validate_color",
+        message = "Must be a hex color like #1a2b3c"
+    ))]
+    pub color: String,
+    #[validate(length(max = 500, message = "Can not be longer than 500 characters"))]
+    pub description: Option<String>,
+    pub parent_id: Option<i32>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
-pub struct UpdateLabel {
-    id: i32,
-    name: String,
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Validate)]
+pub struct MoveLabel {
+    pub parent_id: Option<i32>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Validate)]
+pub struct MergeLabel {
+    pub target_id: i32,
+}
+
+// 統合先のラベルと、統合で変わった子ラベルと todo
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MergedLabel {
+    pub label: Label,
+    pub children: Vec<Label>,
+    pub todo_ids: Vec<i32>,
 }
 
 #[derive(Debug, Clone)]
 pub struct LabelRepositoryForDb {
     pool: PgPool,
+    scope: Scope,
 }
 
 impl LabelRepositoryForDb {
     pub fn new(pool: PgPool) -> Self {
-        Self { pool }
+        Self {
+            pool,
+            scope: Scope::All,
+        }
+    }
+
+    // トランザクションが終わるまで他の変更からラベルの行をロックする
+    async fn lock(&self, tx: &mut Transaction<'_, Postgres>, id: i32) -> anyhow::Result<()> {
+        sqlx::query(
+            r#"
+            select id from labels
+            where id = $1 and deleted_at is null
+              and ($2::integer is null or coalesce(workspace_id, 0) = $2)
+            for update
+            "#,
+        )
+        .bind(id)
+        .bind(self.scope.bind_value())
+        .fetch_optional(&mut *tx)
+        .instrument(query_span("select", "labels"))
+        .await?
+        .ok_or(RepositoryError::NotFound(id))?;
+
+        Ok(())
+    }
+
+    // ids が None ならすべてのラベルを返す
+    async fn with_counts(&self, ids: Option<&[i32]>) -> anyhow::Result<Vec<LabelWithCount>> {
+        // ゴミ箱に入っている todo は件数に含めない
+        let rows = sqlx::query_as::<_, LabelWithCountFromRow>(
+            r#"
+            select
+                labels.id,
+                labels.name,
+                labels.color,
+                labels.description,
+                labels.parent_id,
+                labels.workspace_id,
+                count(todos.id) as total,
+                count(todos.id) filter (where not todos.completed) as open,
+                count(todos.id) filter (where todos.completed) as completed
+            from labels
+                left join (todo_labels tl
+                            inner join todos on todos.id = tl.todo_id and todos.deleted_at is null)
+                on tl.label_id = labels.id
+            where labels.deleted_at is null
+              and ($1::integer is null or coalesce(labels.workspace_id, 0) = $1)
+              and ($2::integer[] is null or labels.id = any($2))
+            group by labels.id
+            order by labels.id asc;
+            "#,
+        )
+        .bind(self.scope.bind_value())
+        .bind(ids)
+        .fetch_all(&self.pool)
+        .instrument(query_span("select", "labels"))
+        .await?;
+
+        Ok(rows.into_iter().map(LabelWithCount::from).collect())
     }
 }
 
 #[async_trait]
 impl LabelRepository for LabelRepositoryForDb {
-    async fn create(&self, name: String) -> anyhow::Result<Label> {
+    fn scoped(&self, scope: Scope) -> Self {
+        Self {
+            pool: self.pool.clone(),
+            scope,
+        }
+    }
+
+    #[tracing::instrument(name = "label_repository.create", skip_all)]
+    async fn create(&self, payload: CreateLabel) -> anyhow::Result<Label> {
+        // ラベル名はワークスペースごとに重複させない
         let optional_label = sqlx::query_as::<_, Label>(
             r#"
-            select * from labels where name = $1
+            select * from labels
+            where name = $1 and deleted_at is null
+              and coalesce(workspace_id, 0) = coalesce($2, 0)
             "#,
         )
-        .bind(name.clone())
+        .bind(payload.name.clone())
+        .bind(self.scope.workspace_id())
         .fetch_optional(&self.pool)
+        .instrument(query_span("select", "labels"))
         .await?;
 
         if let Some(label) = optional_label {
             return Err(RepositoryError::Duplicate(label.id).into());
         }
+        if let Some(parent_id) = payload.parent_id {
+            self.find(parent_id).await?;
+        }
 
         let label = sqlx::query_as::<_, Label>(
             r#"
-            insert into labels (name) values ($1) returning *
+            insert into labels (name, color, description, parent_id, workspace_id)
+            values ($1, $2, $3, $4, $5)
+            returning *
             "#,
         )
-        .bind(name.clone())
+        .bind(payload.name)
+        .bind(payload.color)
+        .bind(payload.description)
+        .bind(payload.parent_id)
+        .bind(self.scope.workspace_id())
         .fetch_one(&self.pool)
+        .instrument(query_span("insert", "labels"))
         .await?;
 
         Ok(label)
     }
 
-    async fn all(&self) -> anyhow::Result<Vec<Label>> {
-        let labels = sqlx::query_as::<_, Label>(
+    #[tracing::instrument(name = "label_repository.find", skip_all)]
+    async fn find(&self, id: i32) -> anyhow::Result<Label> {
+        // ゴミ箱に入っているラベルは見つからないものとして扱う
+        let label = sqlx::query_as::<_, Label>(
             r#"
             select * from labels
-            order by labels.id asc;
+            where id = $1 and deleted_at is null
+              and ($2::integer is null or coalesce(workspace_id, 0) = $2)
+            "#,
+        )
+        .bind(id)
+        .bind(self.scope.bind_value())
+        .fetch_optional(&self.pool)
+        .instrument(query_span("select", "labels"))
+        .await?
+        .ok_or(RepositoryError::NotFound(id))?;
+
+        Ok(label)
+    }
+
+    #[tracing::instrument(name = "label_repository.all", skip_all)]
+    async fn all(&self) -> anyhow::Result<Vec<LabelWithCount>> {
+        self.with_counts(None).await
+    }
+
+    #[tracing::instrument(name = "label_repository.find_many", skip_all)]
+    async fn find_many(&self, ids: &[i32]) -> anyhow::Result<Vec<LabelWithCount>> {
+        self.with_counts(Some(ids)).await
+    }
+
+    #[tracing::instrument(name = "label_repository.delete", skip_all)]
+    async fn delete(&self, id: i32) -> anyhow::Result<()> {
+        let result = sqlx::query(
+            r#"
+            update labels set deleted_at = now()
+            where id = $1 and deleted_at is null
+              and ($2::integer is null or coalesce(workspace_id, 0) = $2)
             "#,
         )
+        .bind(id)
+        .bind(self.scope.bind_value())
+        .execute(&self.pool)
+        .instrument(query_span("update", "labels"))
+        .await
+        .map_err(|e| RepositoryError::Unexpected(e.to_string()))?;
+        if result.rows_affected() == 0 {
+            return Err(RepositoryError::NotFound(id).into());
+        }
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "label_repository.trashed", skip_all)]
+    async fn trashed(&self) -> anyhow::Result<Vec<TrashedLabel>> {
+        let labels = sqlx::query_as::<_, TrashedLabel>(
+            r#"
+            select * from labels
+            where deleted_at is not null
+              and ($1::integer is null or coalesce(workspace_id, 0) = $1)
+            order by deleted_at desc, id desc
+            "#,
+        )
+        .bind(self.scope.bind_value())
         .fetch_all(&self.pool)
+        .instrument(query_span("select", "labels"))
         .await?;
 
         Ok(labels)
     }
 
-    async fn delete(&self, id: i32) -> anyhow::Result<()> {
+    #[tracing::instrument(name = "label_repository.restore", skip_all)]
+    async fn restore(&self, id: i32) -> anyhow::Result<Label> {
+        let label = sqlx::query_as::<_, Label>(
+            r#"
+            select * from labels
+            where id = $1 and deleted_at is not null
+              and ($2::integer is null or coalesce(workspace_id, 0) = $2)
+            "#,
+        )
+        .bind(id)
+        .bind(self.scope.bind_value())
+        .fetch_optional(&self.pool)
+        .instrument(query_span("select", "labels"))
+        .await?
+        .ok_or(RepositoryError::NotFound(id))?;
+
+        // ゴミ箱に入っている間に同じ名前のラベルが作られていれば復元しない
+        let optional_label = sqlx::query_as::<_, Label>(
+            r#"
+            select * from labels
+            where name = $1 and deleted_at is null
+              and coalesce(workspace_id, 0) = coalesce($2, 0)
+            "#,
+        )
+        .bind(label.name.clone())
+        .bind(label.workspace_id)
+        .fetch_optional(&self.pool)
+        .instrument(query_span("select", "labels"))
+        .await?;
+        if let Some(duplicated) = optional_label {
+            return Err(RepositoryError::Duplicate(duplicated.id).into());
+        }
+
         sqlx::query(
             r#"
-            delete from labels where id = $1
+            update labels set deleted_at = null where id = $1
             "#,
         )
         .bind(id)
         .execute(&self.pool)
-        .await
-        .map_err(|e| match e {
-            sqlx::Error::RowNotFound => RepositoryError::NotFound(id),
-            _ => RepositoryError::Unexpected(e.to_string()),
-        })?;
+        .instrument(query_span("update", "labels"))
+        .await?;
 
-        Ok(())
+        Ok(label)
+    }
+
+    #[tracing::instrument(name = "label_repository.purge", skip_all)]
+    async fn purge(&self, deleted_before: DateTime<Utc>) -> anyhow::Result<u64> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query(
+            r#"
+            delete from todo_labels
+            where label_id in (
+                select id from labels
+                where deleted_at <= $1
+                  and ($2::integer is null or coalesce(workspace_id, 0) = $2)
+            )
+            "#,
+        )
+        .bind(deleted_before)
+        .bind(self.scope.bind_value())
+        .execute(&mut tx)
+        .instrument(query_span("delete", "todo_labels"))
+        .await?;
+
+        // 削除するラベルの子ラベルは根に移す
+        sqlx::query(
+            r#"
+            update labels set parent_id = null
+            where parent_id in (
+                select id from labels
+                where deleted_at <= $1
+                  and ($2::integer is null or coalesce(workspace_id, 0) = $2)
+            )
+            "#,
+        )
+        .bind(deleted_before)
+        .bind(self.scope.bind_value())
+        .execute(&mut tx)
+        .instrument(query_span("update", "labels"))
+        .await?;
+
+        let result = sqlx::query(
+            r#"
+            delete from labels
+            where deleted_at <= $1
+              and ($2::integer is null or coalesce(workspace_id, 0) = $2)
+            "#,
+        )
+        .bind(deleted_before)
+        .bind(self.scope.bind_value())
+        .execute(&mut tx)
+        .instrument(query_span("delete", "labels"))
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(result.rows_affected())
+    }
+
+    #[tracing::instrument(name = "label_repository.move_to", skip_all)]
+    async fn move_to(&self, id: i32, parent_id: Option<i32>) -> anyhow::Result<Label> {
+        // 循環の確認と親の付け替えの間に他の移動が入らないよう、同じトランザクションで行う
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("select pg_advisory_xact_lock($1)")
+            .bind(HIERARCHY_LOCK)
+            .execute(&mut tx)
+            .await?;
+        self.lock(&mut tx, id).await?;
+        if let Some(parent_id) = parent_id {
+            self.lock(&mut tx, parent_id).await?;
+
+            // 移動先の祖先に自分自身が含まれていれば循環になる
+            let cycle = sqlx::query_scalar::<_, bool>(
+                r#"
+                with recursive ancestors(id, parent_id) as (
+                    select id, parent_id from labels where id = $1
+                    union
+                    select labels.id, labels.parent_id from labels
+                    inner join ancestors on labels.id = ancestors.parent_id
+                )
+                select exists (select 1 from ancestors where id = $2)
+                "#,
+            )
+            .bind(parent_id)
+            .bind(id)
+            .fetch_one(&mut tx)
+            .instrument(query_span("select", "labels"))
+            .await?;
+            if cycle {
+                return Err(RepositoryError::LabelCycle(id).into());
+            }
+        }
+
+        let label = sqlx::query_as::<_, Label>(
+            r#"
+            update labels set parent_id = $2 where id = $1 returning *
+            "#,
+        )
+        .bind(id)
+        .bind(parent_id)
+        .fetch_one(&mut tx)
+        .instrument(query_span("update", "labels"))
+        .await?;
+        tx.commit().await?;
+
+        Ok(label)
+    }
+
+    #[tracing::instrument(name = "label_repository.merge", skip_all)]
+    async fn merge(&self, id: i32, target_id: i32) -> anyhow::Result<MergedLabel> {
+        let mut tx = self.pool.begin().await?;
+        // 子ラベルを付け替えるため、移動と同じく木全体をロックする
+        sqlx::query("select pg_advisory_xact_lock($1)")
+            .bind(HIERARCHY_LOCK)
+            .execute(&mut tx)
+            .await?;
+
+        for label_id in [id, target_id] {
+            self.lock(&mut tx, label_id).await?;
+        }
+
+        // 統合先が自分自身か子孫であれば、子ラベルの付け替えで循環になる
+        let cycle = sqlx::query_scalar::<_, bool>(
+            r#"
+            with recursive ancestors(id, parent_id) as (
+                select id, parent_id from labels where id = $1
+                union
+                select labels.id, labels.parent_id from labels
+                inner join ancestors on labels.id = ancestors.parent_id
+            )
+            select exists (select 1 from ancestors where id = $2)
+            "#,
+        )
+        .bind(target_id)
+        .bind(id)
+        .fetch_one(&mut tx)
+        .instrument(query_span("select", "labels"))
+        .await?;
+        if cycle {
+            return Err(RepositoryError::LabelCycle(id).into());
+        }
+
+        // 統合先のラベルが既についている todo には重複して付けない
+        sqlx::query(
+            r#"
+            insert into todo_labels (todo_id, label_id)
+            select distinct todo_id, $2 from todo_labels
+            where label_id = $1
+              and todo_id not in (select todo_id from todo_labels where label_id = $2)
+            "#,
+        )
+        .bind(id)
+        .bind(target_id)
+        .execute(&mut tx)
+        .instrument(query_span("insert", "todo_labels"))
+        .await?;
+
+        let mut todo_ids = sqlx::query_scalar::<_, i32>(
+            r#"
+            delete from todo_labels where label_id = $1
+            returning todo_id
+            "#,
+        )
+        .bind(id)
+        .fetch_all(&mut tx)
+        .instrument(query_span("delete", "todo_labels"))
+        .await?;
+        todo_ids.sort();
+
+        let mut children = sqlx::query_as::<_, Label>(
+            r#"
+            update labels set parent_id = $2 where parent_id = $1
+            returning *
+            "#,
+        )
+        .bind(id)
+        .bind(target_id)
+        .fetch_all(&mut tx)
+        .instrument(query_span("update", "labels"))
+        .await?;
+        children.sort_by_key(|label| label.id);
+
+        sqlx::query(
+            r#"
+            delete from labels where id = $1
+            "#,
+        )
+        .bind(id)
+        .execute(&mut tx)
+        .instrument(query_span("delete", "labels"))
+        .await?;
+
+        let label = sqlx::query_as::<_, Label>(
+            r#"
+            select * from labels where id = $1
+            "#,
+        )
+        .bind(target_id)
+        .fetch_one(&mut tx)
+        .instrument(query_span("select", "labels"))
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(MergedLabel {
+            label,
+            children,
+            todo_ids,
+        })
+    }
+
+    #[tracing::instrument(name = "label_repository.changes", skip_all)]
+    async fn changes(&self, since: i64) -> anyhow::Result<Changes<Label>> {
+        // カーソルと変更されたラベルを同じスナップショットから読む
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("set transaction isolation level repeatable read")
+            .execute(&mut tx)
+            .await?;
+
+        // まだ終わっていない最も古いトランザクションをカーソルにする。
+        // それより前のトランザクションは全て終わっているので、後から見える変更はカーソル以降に入る。
+        // 実行中のトランザクションより後にコミットされた変更は、次回も重ねて返す
+        let cursor = sqlx::query_scalar::<_, i64>(
+            r#"
+            select pg_snapshot_xmin(pg_current_snapshot())::text::bigint
+            "#,
+        )
+        .fetch_one(&mut tx)
+        .instrument(query_span("select", "changes"))
+        .await?;
+
+        let ids = sqlx::query_scalar::<_, i32>(
+            r#"
+            select entity_id from changes
+            where entity = 'label' and xid >= $1::bigint::text::xid8
+              and ($2::integer is null or coalesce(workspace_id, 0) = $2)
+            group by entity_id
+            order by max(seq) asc
+            "#,
+        )
+        .bind(since)
+        .bind(self.scope.bind_value())
+        .fetch_all(&mut tx)
+        .instrument(query_span("select", "changes"))
+        .await?;
+
+        let upserts = sqlx::query_as::<_, Label>(
+            r#"
+            select * from labels
+            where id = any($1) and deleted_at is null
+            order by id asc
+            "#,
+        )
+        .bind(&ids)
+        .fetch_all(&mut tx)
+        .instrument(query_span("select", "labels"))
+        .await?;
+
+        tx.commit().await?;
+
+        let tombstones = ids
+            .into_iter()
+            .filter(|id| !upserts.iter().any(|label| label.id == *id))
+            .collect();
+        Ok(Changes {
+            upserts,
+            tombstones,
+            cursor,
+        })
     }
 }
 
 #[cfg(test)]
 #[cfg(feature = "database-test")]
 mod test {
-    use std::env;
-
     use super::*;
-
-    use dotenv::dotenv;
+    use crate::repositories::{
+        conformance::label_repository_conformance, test_database::TestDatabase,
+    };
 
     #[tokio::test]
     async fn crud_scenario() {
-        dotenv().ok();
-        let database_url = &env::var("DATABASE_URL").expect("DATABASE_URL must be set");
-        let pool = PgPool::connect(database_url)
-            .await
-            .unwrap_or_else(|_| panic!("failed connect database: {}", database_url));
+        let database = TestDatabase::new().await;
+        let pool = database.pool();
 
         let repository = LabelRepositoryForDb::new(pool);
         let label_text = "test_label";
 
         // create
         let label = repository
-            .create(label_text.to_string())
+            .create(CreateLabel {
+                name: label_text.to_string(),
+                color: "#1a2b3c".to_string(),
+                description: Some("description".to_string()),
+                parent_id: None,
+            })
             .await
             .expect("failed create");
         assert_eq!(label.name, label_text);
+        assert_eq!(label.color, "#1a2b3c");
 
         // all
         let labels = repository.all().await.expect("failed all");
-        let label = labels.last().unwrap();
+        let label = &labels.last().unwrap().label;
         assert_eq!(label.name, label_text);
+        assert_eq!(labels.last().unwrap().todo_count, TodoCount::default());
+
+        // find
+        let found = repository.find(label.id).await.expect("failed find");
+        assert_eq!(found, *label);
 
         // delete
         repository.delete(label.id).await.expect("failed delete");
+        let labels = repository.all().await.expect("failed all");
+        assert!(labels.iter().all(|l| l.label.id != label.id));
+        let res = repository.find(label.id).await;
+        assert!(res.is_err());
+
+        // trashed
+        let trashed = repository.trashed().await.expect("failed trashed");
+        assert_eq!(trashed.first().unwrap().label, *label);
+
+        // restore
+        let restored = repository.restore(label.id).await.expect("failed restore");
+        assert_eq!(restored, *label);
+
+        // purge
+        repository.delete(label.id).await.expect("failed delete");
+        repository.purge(Utc::now()).await.expect("failed purge");
+        let trashed = repository.trashed().await.expect("failed trashed");
+        assert!(trashed.iter().all(|trashed| trashed.label.id != label.id));
+
+        // hierarchy
+        let suffix = std::process::id();
+        let parent = repository
+            .create(CreateLabel {
+                name: format!("crud_scenario_parent_{}", suffix),
+                color: DEFAULT_COLOR.to_string(),
+                description: None,
+                parent_id: None,
+            })
+            .await
+            .expect("failed create");
+        let child = repository
+            .create(CreateLabel {
+                name: format!("crud_scenario_child_{}", suffix),
+                color: DEFAULT_COLOR.to_string(),
+                description: None,
+                parent_id: Some(parent.id),
+            })
+            .await
+            .expect("failed create");
+        assert_eq!(child.parent_id, Some(parent.id));
+        let res = repository.move_to(parent.id, Some(child.id)).await;
+        assert!(res.is_err());
+        let res = repository.move_to(parent.id, Some(parent.id)).await;
+        assert!(res.is_err());
+        let moved = repository
+            .move_to(child.id, None)
+            .await
+            .expect("failed move_to");
+        assert_eq!(moved.parent_id, None);
+        repository
+            .move_to(child.id, Some(parent.id))
+            .await
+            .expect("failed move_to");
+        for id in [parent.id, child.id] {
+            repository.delete(id).await.expect("failed delete");
+        }
+        repository.purge(Utc::now()).await.expect("failed purge");
+    }
+
+    #[tokio::test]
+    async fn changes_scenario() {
+        let database = TestDatabase::new().await;
+        let pool = database.pool();
+        let repository = LabelRepositoryForDb::new(pool);
+
+        let cursor = repository
+            .changes(i64::MAX)
+            .await
+            .expect("failed changes")
+            .cursor;
+        let label = repository
+            .create(CreateLabel::new(format!(
+                "changes_scenario_{}",
+                std::process::id()
+            )))
+            .await
+            .expect("failed create");
+        let changes = repository.changes(cursor).await.expect("failed changes");
+        assert!(changes.upserts.contains(&label));
+
+        repository.delete(label.id).await.expect("failed delete");
+        let deleted = repository
+            .changes(changes.cursor)
+            .await
+            .expect("failed changes");
+        assert!(deleted.upserts.iter().all(|upsert| upsert.id != label.id));
+        assert!(deleted.tombstones.contains(&label.id));
+        repository.purge(Utc::now()).await.expect("failed purge");
+    }
+
+    #[tokio::test]
+    async fn should_not_move_labels_concurrently_into_a_cycle() {
+        let database = TestDatabase::new().await;
+        let repository = LabelRepositoryForDb::new(database.pool());
+
+        let mut ids = vec![];
+        for name in ["a", "b", "c", "d"] {
+            let label = repository
+                .create(CreateLabel::new(name.to_string()))
+                .await
+                .expect("failed create");
+            ids.push(label.id);
+        }
+        // a -> b -> c -> d -> a の移動を同時に行っても、どれか一つは循環として拒否される
+        let handles = [(0, 1), (1, 2), (2, 3), (3, 0)].map(|(id, parent_id)| {
+            let repository = repository.clone();
+            let (id, parent_id) = (ids[id], ids[parent_id]);
+            tokio::spawn(async move { repository.move_to(id, Some(parent_id)).await })
+        });
+        let mut moved = 0;
+        for handle in handles {
+            if handle.await.unwrap().is_ok() {
+                moved += 1;
+            }
+        }
+        assert_eq!(moved, 3);
+    }
+
+    #[tokio::test]
+    async fn conforms_to_repository_contract() {
+        let database = TestDatabase::new().await;
+        let pool = database.pool();
+        label_repository_conformance(LabelRepositoryForDb::new(pool)).await;
     }
 }
 
 #[cfg(test)]
 pub mod test_utils {
-    use std::{
-        collections::HashMap,
-        sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard},
-    };
-
-    use anyhow::Context;
-
     use super::*;
 
-    type LabelDatas = HashMap<i32, Label>;
-
     impl Label {
         pub fn new(id: i32, name: String) -> Label {
-            Self { id, name }
+            Self {
+                id,
+                name,
+                color: default_color(),
+                description: None,
+                parent_id: None,
+                workspace_id: None,
+            }
+        }
+
+        pub fn set_parent_id(&mut self, parent_id: Option<i32>) {
+            self.parent_id = parent_id;
         }
     }
 
+    impl CreateLabel {
+        pub fn new(name: String) -> Self {
+            Self {
+                name,
+                color: default_color(),
+                description: None,
+                parent_id: None,
+            }
+        }
+    }
+}
+
+#[cfg(any(test, feature = "memory"))]
+pub mod memory {
+    use std::{
+        cmp::Reverse,
+        collections::{BTreeSet, HashSet},
+    };
+
+    use anyhow::Context;
+
+    use super::*;
+    use crate::repositories::memory::{MemoryData, MemoryStore};
+    #[cfg(test)]
+    use crate::repositories::todo::memory::TodoRepositoryInMemory;
+
     #[derive(Debug, Clone)]
     pub struct LabelRepositoryInMemory {
-        store: Arc<RwLock<LabelDatas>>,
+        store: MemoryStore,
+        scope: Scope,
     }
 
     impl LabelRepositoryInMemory {
-        pub fn new() -> Self {
+        // TodoRepositoryInMemory と同じストアを渡せば、todo の件数やラベルの付け替えが反映される
+        pub fn with_store(store: MemoryStore) -> Self {
             Self {
-                store: Arc::default(),
+                store,
+                scope: Scope::All,
             }
         }
 
-        fn write_store_ref(&self) -> RwLockWriteGuard<LabelDatas> {
-            self.store.write().unwrap()
+        #[cfg(test)]
+        pub fn new() -> Self {
+            Self::with_store(MemoryStore::new())
+        }
+
+        #[cfg(test)]
+        pub fn with_todos(todos: TodoRepositoryInMemory) -> Self {
+            Self::with_store(todos.store())
         }
 
-        fn read_store_ref(&self) -> RwLockReadGuard<LabelDatas> {
-            self.store.read().unwrap()
+        // ゴミ箱のラベルや別のワークスペースのラベルは存在しないものとして扱う
+        fn get_scoped<'a>(&self, data: &'a MemoryData, id: i32) -> anyhow::Result<&'a Label> {
+            let label = data
+                .live_label(id)
+                .filter(|label| self.scope.contains(label.workspace_id))
+                .context(RepositoryError::NotFound(id))?;
+            Ok(label)
+        }
+
+        // ラベル名はワークスペースごとに重複させない
+        fn find_duplicated(
+            data: &MemoryData,
+            name: &str,
+            workspace_id: Option<i32>,
+        ) -> Option<i32> {
+            data.labels
+                .values()
+                .find(|stored| {
+                    stored.deleted_at.is_none()
+                        && stored.label.name == name
+                        && stored.label.workspace_id == workspace_id
+                })
+                .map(|stored| stored.label.id)
+        }
+
+        fn todo_count(&self, data: &MemoryData, label_id: i32) -> TodoCount {
+            let mut todo_count = TodoCount::default();
+            for todo in data.todos.values().filter(|todo| {
+                todo.deleted_at.is_none()
+                    && self.scope.contains(todo.workspace_id)
+                    && todo.label_ids.contains(&label_id)
+            }) {
+                todo_count.total += 1;
+                if todo.completed {
+                    todo_count.completed += 1;
+                } else {
+                    todo_count.open += 1;
+                }
+            }
+            todo_count
+        }
+
+        // ラベルの付け外しは todo の変更として記録する
+        fn record_todo_changes(data: &mut MemoryData, todos: Vec<(i32, Option<i32>)>) {
+            for (id, workspace_id) in todos {
+                data.record_todo_change(id, workspace_id);
+            }
         }
     }
 
     #[async_trait]
     impl LabelRepository for LabelRepositoryInMemory {
-        async fn create(&self, name: String) -> anyhow::Result<Label> {
-            let mut store = self.write_store_ref();
-            let id = store.len() as i32 + 1;
-            let label = Label { id, name };
-            store.insert(id, label.clone());
+        fn scoped(&self, scope: Scope) -> Self {
+            Self {
+                scope,
+                ..self.clone()
+            }
+        }
+
+        async fn create(&self, payload: CreateLabel) -> anyhow::Result<Label> {
+            let mut data = self.store.write();
+            let workspace_id = self.scope.workspace_id();
+            if let Some(id) = Self::find_duplicated(&data, &payload.name, workspace_id) {
+                return Err(RepositoryError::Duplicate(id).into());
+            }
+            if let Some(parent_id) = payload.parent_id {
+                self.get_scoped(&data, parent_id)?;
+            }
+            let id = data.next_label_id();
+            let label = Label {
+                id,
+                name: payload.name,
+                color: payload.color,
+                description: payload.description,
+                parent_id: payload.parent_id,
+                workspace_id,
+            };
+            data.insert_label(label.clone());
+            Ok(label)
+        }
+
+        async fn find(&self, id: i32) -> anyhow::Result<Label> {
+            let data = self.store.read();
+            let label = self.get_scoped(&data, id)?.clone();
             Ok(label)
         }
 
-        async fn all(&self) -> anyhow::Result<Vec<Label>> {
-            let store = self.read_store_ref();
-            Ok(store.values().cloned().collect())
+        async fn all(&self) -> anyhow::Result<Vec<LabelWithCount>> {
+            let data = self.store.read();
+            let labels = data
+                .labels
+                .values()
+                .filter(|stored| {
+                    stored.deleted_at.is_none() && self.scope.contains(stored.label.workspace_id)
+                })
+                .map(|stored| LabelWithCount {
+                    label: stored.label.clone(),
+                    todo_count: self.todo_count(&data, stored.label.id),
+                })
+                .collect();
+            Ok(labels)
+        }
+
+        async fn find_many(&self, ids: &[i32]) -> anyhow::Result<Vec<LabelWithCount>> {
+            let data = self.store.read();
+            let ids: BTreeSet<i32> = ids.iter().copied().collect();
+            let labels = ids
+                .into_iter()
+                .filter_map(|id| self.get_scoped(&data, id).ok())
+                .map(|label| LabelWithCount {
+                    label: label.clone(),
+                    todo_count: self.todo_count(&data, label.id),
+                })
+                .collect();
+            Ok(labels)
         }
 
         async fn delete(&self, id: i32) -> anyhow::Result<()> {
-            let mut store = self.write_store_ref();
-            store.remove(&id).context(RepositoryError::NotFound(id))?;
+            let mut data = self.store.write();
+            let label = self.get_scoped(&data, id)?.clone();
+            data.labels.get_mut(&id).unwrap().deleted_at = Some(Utc::now());
+            data.record_label_change(id, label.workspace_id);
             Ok(())
         }
+
+        async fn trashed(&self) -> anyhow::Result<Vec<TrashedLabel>> {
+            let data = self.store.read();
+            let mut labels: Vec<TrashedLabel> = data
+                .labels
+                .values()
+                .filter(|stored| self.scope.contains(stored.label.workspace_id))
+                .filter_map(|stored| {
+                    Some(TrashedLabel {
+                        label: stored.label.clone(),
+                        deleted_at: stored.deleted_at?,
+                    })
+                })
+                .collect();
+            labels.sort_by_key(|trashed| Reverse(trashed.deleted_at));
+            Ok(labels)
+        }
+
+        async fn restore(&self, id: i32) -> anyhow::Result<Label> {
+            let mut data = self.store.write();
+            let label = data
+                .labels
+                .get(&id)
+                .filter(|stored| {
+                    stored.deleted_at.is_some() && self.scope.contains(stored.label.workspace_id)
+                })
+                .map(|stored| stored.label.clone())
+                .context(RepositoryError::NotFound(id))?;
+            if let Some(duplicated) = Self::find_duplicated(&data, &label.name, label.workspace_id)
+            {
+                return Err(RepositoryError::Duplicate(duplicated).into());
+            }
+            data.labels.get_mut(&id).unwrap().deleted_at = None;
+            data.record_label_change(id, label.workspace_id);
+            Ok(label)
+        }
+
+        async fn purge(&self, deleted_before: DateTime<Utc>) -> anyhow::Result<u64> {
+            let mut data = self.store.write();
+            let purged: HashSet<i32> = data
+                .labels
+                .values()
+                .filter(|stored| {
+                    stored
+                        .deleted_at
+                        .is_some_and(|deleted_at| deleted_at <= deleted_before)
+                        && self.scope.contains(stored.label.workspace_id)
+                })
+                .map(|stored| stored.label.id)
+                .collect();
+            let mut changed_labels = vec![];
+            data.labels.retain(|id, stored| {
+                if purged.contains(id) {
+                    changed_labels.push((stored.label.id, stored.label.workspace_id));
+                    return false;
+                }
+                true
+            });
+
+            // 削除したラベルの子ラベルは根に移す
+            for stored in data.labels.values_mut() {
+                if stored
+                    .label
+                    .parent_id
+                    .is_some_and(|parent_id| purged.contains(&parent_id))
+                {
+                    stored.label.parent_id = None;
+                    changed_labels.push((stored.label.id, stored.label.workspace_id));
+                }
+            }
+            for (id, workspace_id) in changed_labels {
+                data.record_label_change(id, workspace_id);
+            }
+
+            // todo_labels と同じく、削除したラベルは todo からも外す
+            let mut changed_todos = vec![];
+            for todo in data.todos.values_mut() {
+                if todo.label_ids.iter().any(|id| purged.contains(id)) {
+                    todo.label_ids.retain(|id| !purged.contains(id));
+                    changed_todos.push((todo.id, todo.workspace_id));
+                }
+            }
+            Self::record_todo_changes(&mut data, changed_todos);
+            Ok(purged.len() as u64)
+        }
+
+        async fn move_to(&self, id: i32, parent_id: Option<i32>) -> anyhow::Result<Label> {
+            let mut data = self.store.write();
+            self.get_scoped(&data, id)?;
+            // 移動先から親をたどって自分自身に行き着けば循環になる
+            let mut ancestor = parent_id;
+            while let Some(ancestor_id) = ancestor {
+                if ancestor_id == id {
+                    return Err(RepositoryError::LabelCycle(id).into());
+                }
+                let label = self.get_scoped(&data, ancestor_id)?;
+                ancestor = label.parent_id;
+            }
+            let label = &mut data.labels.get_mut(&id).unwrap().label;
+            label.parent_id = parent_id;
+            let label = label.clone();
+            data.record_label_change(id, label.workspace_id);
+            Ok(label)
+        }
+
+        async fn merge(&self, id: i32, target_id: i32) -> anyhow::Result<MergedLabel> {
+            let mut data = self.store.write();
+            let source = self.get_scoped(&data, id)?.clone();
+            let target = self.get_scoped(&data, target_id)?.clone();
+            // 統合先から親をたどって自分自身に行き着けば循環になる
+            let mut ancestor = Some(target_id);
+            while let Some(ancestor_id) = ancestor {
+                if ancestor_id == id {
+                    return Err(RepositoryError::LabelCycle(id).into());
+                }
+                ancestor = data
+                    .labels
+                    .get(&ancestor_id)
+                    .and_then(|stored| stored.label.parent_id);
+            }
+
+            // 子ラベルは統合先に付け替える
+            let mut children = vec![];
+            for stored in data.labels.values_mut() {
+                if stored.label.parent_id == Some(id) {
+                    stored.label.parent_id = Some(target_id);
+                    children.push(stored.label.clone());
+                }
+            }
+            for child in &children {
+                data.record_label_change(child.id, child.workspace_id);
+            }
+
+            // 統合元が付いていた todo は統合先に付け替える。ゴミ箱の todo も含める
+            let mut changed_todos = vec![];
+            for todo in data.todos.values_mut() {
+                if !todo.label_ids.contains(&id) {
+                    continue;
+                }
+                todo.label_ids.retain(|label_id| *label_id != id);
+                if !todo.label_ids.contains(&target_id) {
+                    todo.label_ids.push(target_id);
+                    todo.label_ids.sort();
+                }
+                changed_todos.push((todo.id, todo.workspace_id));
+            }
+            let todo_ids = changed_todos.iter().map(|(id, _)| *id).collect();
+            Self::record_todo_changes(&mut data, changed_todos);
+
+            data.labels.remove(&id);
+            data.record_label_change(id, source.workspace_id);
+            Ok(MergedLabel {
+                label: target,
+                children,
+                todo_ids,
+            })
+        }
+
+        async fn changes(&self, since: i64) -> anyhow::Result<Changes<Label>> {
+            let data = self.store.read();
+            let cursor = data.cursor();
+            let ids = data.label_changes.since(since, cursor, self.scope);
+            let mut upserts: Vec<Label> = ids
+                .iter()
+                .filter_map(|id| data.live_label(*id))
+                .cloned()
+                .collect();
+            upserts.sort_by_key(|label| label.id);
+            let tombstones = ids
+                .into_iter()
+                .filter(|id| data.live_label(*id).is_none())
+                .collect();
+            Ok(Changes {
+                upserts,
+                tombstones,
+                cursor,
+            })
+        }
     }
 
+    #[cfg(test)]
     mod test {
         use super::*;
+        use crate::repositories::{
+            conformance::label_repository_conformance,
+            todo::{CreateTodo, TodoRepository, UpdateTodo},
+        };
 
         #[tokio::test]
         async fn label_crud_scenario() {
@@ -211,18 +1254,167 @@ pub mod test_utils {
 
             // create
             let label = repository
-                .create(label_text.to_string())
+                .create(CreateLabel::new(label_text.to_string()))
                 .await
                 .expect("failed create");
             assert_eq!(label.name, label_text);
+            assert_eq!(label.color, DEFAULT_COLOR);
 
             // all
             let labels = repository.all().await.expect("failed all");
-            let label = labels.last().unwrap();
+            let label = &labels.last().unwrap().label;
             assert_eq!(label.name, label_text);
 
+            // find
+            let found = repository.find(label.id).await.expect("failed find");
+            assert_eq!(found, *label);
+
             // delete
             repository.delete(label.id).await.expect("failed delete");
+            assert!(repository.all().await.unwrap().is_empty());
+            let res = repository.find(label.id).await;
+            assert!(res.is_err());
+
+            // trashed
+            let trashed = repository.trashed().await.expect("failed trashed");
+            assert_eq!(trashed[0].label, *label);
+
+            // restore
+            let restored = repository.restore(label.id).await.expect("failed restore");
+            assert_eq!(restored, *label);
+
+            // purge
+            repository.delete(label.id).await.expect("failed delete");
+            let purged = repository.purge(Utc::now()).await.expect("failed purge");
+            assert_eq!(purged, 1);
+        }
+
+        #[tokio::test]
+        async fn should_count_todos_by_label() {
+            let label = Label::new(1, "counted".to_string());
+            let todos = TodoRepositoryInMemory::new(vec![]);
+            let repository = LabelRepositoryInMemory::with_todos(todos.clone());
+            repository
+                .create(CreateLabel::new(label.name.clone()))
+                .await
+                .expect("failed create");
+            for title in ["open", "completed", "deleted"] {
+                todos
+                    .create(CreateTodo::new(title.to_string(), vec![label.id]))
+                    .await
+                    .expect("failed create todo");
+            }
+            todos
+                .create(CreateTodo::new("unlabeled".to_string(), vec![]))
+                .await
+                .expect("failed create todo");
+            let mut completed = UpdateTodo::from(todos.find(2).await.unwrap());
+            completed.completed = Some(true);
+            todos.update(2, completed, None).await.unwrap();
+            todos.delete(3, None).await.unwrap();
+
+            let labels = repository.all().await.expect("failed all");
+            assert_eq!(
+                labels[0].todo_count,
+                TodoCount {
+                    total: 2,
+                    open: 1,
+                    completed: 1,
+                }
+            );
+        }
+
+        #[tokio::test]
+        async fn should_merge_labels() {
+            let bug = Label::new(1, "bug".to_string());
+            let bugs = Label::new(2, "bugs".to_string());
+            let todos = TodoRepositoryInMemory::new(vec![]);
+            let repository = LabelRepositoryInMemory::with_todos(todos.clone());
+            for label in [&bug, &bugs] {
+                repository
+                    .create(CreateLabel::new(label.name.clone()))
+                    .await
+                    .expect("failed create");
+            }
+            let mut child = CreateLabel::new("bugs/ui".to_string());
+            child.parent_id = Some(bugs.id);
+            let child = repository.create(child).await.expect("failed create");
+            todos
+                .create(CreateTodo::new("both".to_string(), vec![bug.id, bugs.id]))
+                .await
+                .expect("failed create todo");
+            todos
+                .create(CreateTodo::new("source only".to_string(), vec![bugs.id]))
+                .await
+                .expect("failed create todo");
+
+            let res = repository.merge(bugs.id, bugs.id).await;
+            assert!(res.is_err());
+            let res = repository.merge(bugs.id, child.id).await;
+            assert!(res.is_err());
+            let res = repository.merge(bugs.id, 999).await;
+            assert!(res.is_err());
+
+            let cursor = repository.changes(0).await.unwrap().cursor;
+            let merged = repository
+                .merge(bugs.id, bug.id)
+                .await
+                .expect("failed merge");
+            assert_eq!(merged.label, bug);
+            assert_eq!(merged.todo_ids, vec![1, 2]);
+            for todo in todos.all().await.unwrap() {
+                assert_eq!(todo.labels, vec![bug.clone()]);
+            }
+
+            // 統合元は tombstone、付け替えた子ラベルと todo は upsert として返る
+            let changes = repository.changes(cursor).await.expect("failed changes");
+            let mut moved = child.clone();
+            moved.set_parent_id(Some(bug.id));
+            assert_eq!(changes.upserts, vec![moved]);
+            assert_eq!(changes.tombstones, vec![bugs.id]);
+            let changes = todos.changes(cursor).await.expect("failed changes");
+            assert_eq!(changes.upserts.len(), 2);
+            let labels = repository.all().await.expect("failed all");
+            assert_eq!(labels.len(), 2);
+            assert_eq!(labels[0].todo_count.total, 2);
+            assert_eq!(labels[1].label.parent_id, Some(bug.id));
+            assert!(repository.trashed().await.unwrap().is_empty());
+        }
+
+        #[tokio::test]
+        async fn conforms_to_repository_contract() {
+            label_repository_conformance(LabelRepositoryInMemory::new()).await;
+        }
+
+        #[test]
+        fn should_build_tree_without_revisiting_labels_in_a_cycle() {
+            let labels: Vec<LabelWithCount> = [(1, None), (2, Some(1)), (3, Some(4)), (4, Some(3))]
+                .into_iter()
+                .map(|(id, parent_id)| {
+                    let mut label = Label::new(id, format!("label_{}", id));
+                    label.set_parent_id(parent_id);
+                    LabelWithCount {
+                        label,
+                        todo_count: TodoCount::default(),
+                    }
+                })
+                .collect();
+
+            let tree = build_tree(labels);
+            fn ids(nodes: &[LabelNode]) -> Vec<(i32, Vec<i32>)> {
+                nodes
+                    .iter()
+                    .map(|node| {
+                        (
+                            node.label.label.id,
+                            ids(&node.children).iter().map(|(id, _)| *id).collect(),
+                        )
+                    })
+                    .collect()
+            }
+            // 循環している 3 と 4 は、根からたどれないため片方を根にしてもう片方を子にする
+            assert_eq!(ids(&tree), vec![(1, vec![2]), (3, vec![4])]);
+            assert!(tree[1].children[0].children.is_empty());
         }
     }
 }
@@ -0,0 +1,357 @@
+// DB を使わずに動かすための、todo とラベルが共有するインメモリのストア。
+// DB と同じく id は使い回さず、todo のラベルは id で持ってラベルのテーブルから引き直す。
+// ワークスペースや API トークン、ユーザーも同じストアに置き、
+// 中身はそのまま JSON のスナップショットとして保存・復元できる
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::Write,
+    path::Path,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, RwLock, RwLockReadGuard, RwLockWriteGuard,
+    },
+};
+
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    api_token::ApiToken,
+    label::Label,
+    user::User,
+    workspace::{Invitation, Member, Workspace},
+    Scope,
+};
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct StoredTodo {
+    pub id: i32,
+    pub title: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    pub completed: bool,
+    pub version: i32,
+    // todo_labels と同じく id だけを持つ
+    pub label_ids: Vec<i32>,
+    pub workspace_id: Option<i32>,
+    pub deleted_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct StoredLabel {
+    #[serde(flatten)]
+    pub label: Label,
+    pub deleted_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct StoredWorkspaces {
+    pub workspaces: Vec<Workspace>,
+    pub members: Vec<Member>,
+    pub invitations: Vec<Invitation>,
+}
+
+// トークンそのものは保存せず、ハッシュだけを持つ
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct StoredApiToken {
+    pub api_token: ApiToken,
+    pub token_hash: String,
+}
+
+// changes テーブルの代わりに、id ごとに最後に変更された連番とワークスペースを覚えておく
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ChangeLog {
+    changes: BTreeMap<i32, (i64, Option<i32>)>,
+}
+
+impl ChangeLog {
+    pub fn record(&mut self, seq: i64, id: i32, workspace_id: Option<i32>) {
+        self.changes.insert(id, (seq, workspace_id));
+    }
+
+    // since より後、until までに変更された id を変更順に返す
+    pub fn since(&self, since: i64, until: i64, scope: Scope) -> Vec<i32> {
+        let mut ids: Vec<(i64, i32)> = self
+            .changes
+            .iter()
+            .filter(|(_, (seq, workspace_id))| {
+                *seq > since && *seq <= until && scope.contains(*workspace_id)
+            })
+            .map(|(id, (seq, _))| (*seq, *id))
+            .collect();
+        ids.sort();
+        ids.into_iter().map(|(_, id)| id).collect()
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct MemoryData {
+    pub todos: BTreeMap<i32, StoredTodo>,
+    pub labels: BTreeMap<i32, StoredLabel>,
+    // (todo_id, blocker_id)
+    pub dependencies: Vec<(i32, i32)>,
+    pub todo_changes: ChangeLog,
+    pub label_changes: ChangeLog,
+    last_todo_id: i32,
+    last_label_id: i32,
+    // DB のシーケンスと同じく、todo とラベルで一つの連番を共有する
+    sequence: i64,
+    // 再起動しても同じ id のワークスペースが作られて以前の todo が見えないよう、一緒に保存する。
+    // これらを持たない以前のスナップショットも読めるようにする
+    #[serde(default)]
+    pub workspaces: StoredWorkspaces,
+    #[serde(default)]
+    pub api_tokens: Vec<StoredApiToken>,
+    #[serde(default)]
+    pub users: Vec<User>,
+}
+
+impl MemoryData {
+    pub fn next_todo_id(&mut self) -> i32 {
+        self.last_todo_id += 1;
+        self.last_todo_id
+    }
+
+    pub fn next_label_id(&mut self) -> i32 {
+        self.last_label_id += 1;
+        self.last_label_id
+    }
+
+    // 既にある id でラベルを入れる。以降の id はそれより大きくする
+    pub fn insert_label(&mut self, label: Label) {
+        self.last_label_id = self.last_label_id.max(label.id);
+        self.record_label_change(label.id, label.workspace_id);
+        self.labels.insert(
+            label.id,
+            StoredLabel {
+                label,
+                deleted_at: None,
+            },
+        );
+    }
+
+    pub fn cursor(&self) -> i64 {
+        self.sequence
+    }
+
+    pub fn record_todo_change(&mut self, id: i32, workspace_id: Option<i32>) {
+        self.sequence += 1;
+        self.todo_changes.record(self.sequence, id, workspace_id);
+    }
+
+    pub fn record_label_change(&mut self, id: i32, workspace_id: Option<i32>) {
+        self.sequence += 1;
+        self.label_changes.record(self.sequence, id, workspace_id);
+    }
+
+    // ゴミ箱に入っていないラベル
+    pub fn live_label(&self, id: i32) -> Option<&Label> {
+        self.labels
+            .get(&id)
+            .filter(|stored| stored.deleted_at.is_none())
+            .map(|stored| &stored.label)
+    }
+
+    // ゴミ箱に入っていない todo
+    pub fn live_todo(&self, id: i32) -> Option<&StoredTodo> {
+        self.todos.get(&id).filter(|todo| todo.deleted_at.is_none())
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MemoryStore {
+    data: Arc<RwLock<MemoryData>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // スナップショットがあれば読み込む。初回の起動では空のストアになる
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        if !path.exists() {
+            return Ok(Self::new());
+        }
+        let json = fs::read(path)
+            .with_context(|| format!("failed to read snapshot {}", path.display()))?;
+        let data: MemoryData = serde_json::from_slice(&json)
+            .with_context(|| format!("failed to parse snapshot {}", path.display()))?;
+        Ok(Self {
+            data: Arc::new(RwLock::new(data)),
+        })
+    }
+
+    // 書き込み途中で止まっても前回のスナップショットが壊れないよう、一時ファイルから置き換える。
+    // 一時ファイルは他のスナップショットや保存中の別の呼び出しと重ならない名前にし、
+    // 置き換える前にディスクへ書き出す
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        static SEQUENCE: AtomicU64 = AtomicU64::new(0);
+
+        let json = serde_json::to_vec(&*self.read())?;
+        let file_name = path
+            .file_name()
+            .with_context(|| format!("invalid snapshot path {}", path.display()))?;
+        let tmp = path.with_file_name(format!(
+            ".{}.{}.{}.tmp",
+            file_name.to_string_lossy(),
+            std::process::id(),
+            SEQUENCE.fetch_add(1, Ordering::Relaxed)
+        ));
+        let write = || -> std::io::Result<()> {
+            let mut file = File::create(&tmp)?;
+            file.write_all(&json)?;
+            file.sync_all()
+        };
+        if let Err(e) = write() {
+            let _ = fs::remove_file(&tmp);
+            return Err(e).with_context(|| format!("failed to write snapshot {}", tmp.display()));
+        }
+        if let Err(e) = fs::rename(&tmp, path) {
+            let _ = fs::remove_file(&tmp);
+            return Err(e)
+                .with_context(|| format!("failed to replace snapshot {}", path.display()));
+        }
+        Ok(())
+    }
+
+    pub fn read(&self) -> RwLockReadGuard<'_, MemoryData> {
+        self.data.read().unwrap()
+    }
+
+    pub fn write(&self) -> RwLockWriteGuard<'_, MemoryData> {
+        self.data.write().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn should_return_changes_since_cursor() {
+        let mut log = ChangeLog::default();
+        log.record(1, 1, None);
+        log.record(2, 2, Some(1));
+        log.record(3, 3, None);
+        log.record(4, 1, None);
+
+        assert_eq!(log.since(1, 4, Scope::All), vec![2, 3, 1]);
+        assert_eq!(log.since(1, 4, Scope::Personal), vec![3, 1]);
+        assert_eq!(log.since(1, 3, Scope::All), vec![2, 3]);
+        assert_eq!(log.since(4, 4, Scope::All), Vec::<i32>::new());
+    }
+
+    #[test]
+    fn should_save_and_load_snapshot() {
+        let path = std::env::temp_dir().join(format!("todos-snapshot-{}.json", std::process::id()));
+        let store = MemoryStore::load(&path).expect("failed load");
+        {
+            let mut data = store.write();
+            let id = data.next_label_id();
+            data.insert_label(Label::new(id, "label".to_string()));
+            let id = data.next_todo_id();
+            data.todos.insert(
+                id,
+                StoredTodo {
+                    id,
+                    title: "todo".to_string(),
+                    description: None,
+                    completed: false,
+                    version: 1,
+                    label_ids: vec![1],
+                    workspace_id: None,
+                    deleted_at: None,
+                },
+            );
+        }
+        store.save(&path).expect("failed save");
+
+        let loaded = MemoryStore::load(&path).expect("failed load");
+        assert_eq!(*loaded.read(), *store.read());
+        // 読み込んだ後も id は使い回さない
+        assert_eq!(loaded.write().next_todo_id(), 2);
+        assert_eq!(loaded.write().next_label_id(), 2);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn should_save_snapshot_without_leaving_temporary_files() {
+        let dir = std::env::temp_dir().join(format!("todos-snapshot-dir-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        // 拡張子が tmp のスナップショットでも一時ファイルと重ならない
+        let path = dir.join("snapshot.tmp");
+        let store = MemoryStore::new();
+        store.write().next_todo_id();
+        store.save(&path).expect("failed save");
+        store.save(&path).expect("failed save");
+
+        let loaded = MemoryStore::load(&path).expect("failed load");
+        assert_eq!(*loaded.read(), *store.read());
+        let files: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(files, vec!["snapshot.tmp"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn should_not_reuse_workspace_ids_after_loading_snapshot() {
+        use crate::repositories::workspace::{
+            memory::WorkspaceRepositoryInMemory, CreateWorkspace, WorkspaceRepository,
+        };
+
+        let path =
+            std::env::temp_dir().join(format!("todos-workspaces-{}.json", std::process::id()));
+        let store = MemoryStore::new();
+        let workspace = WorkspaceRepositoryInMemory::with_store(store.clone())
+            .create(
+                "owner",
+                CreateWorkspace {
+                    name: "team".to_string(),
+                },
+            )
+            .await
+            .unwrap();
+        store.save(&path).expect("failed save");
+
+        let repository = WorkspaceRepositoryInMemory::with_store(MemoryStore::load(&path).unwrap());
+        let memberships = repository.all("owner").await.unwrap();
+        assert_eq!(memberships[0].workspace, workspace);
+        let other = repository
+            .create(
+                "someone",
+                CreateWorkspace {
+                    name: "other".to_string(),
+                },
+            )
+            .await
+            .unwrap();
+        assert!(other.id > workspace.id);
+        assert_eq!(
+            repository.role(workspace.id, "someone").await.unwrap(),
+            None
+        );
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn should_load_snapshot_without_workspaces() {
+        let data: MemoryData = serde_json::from_value(serde_json::json!({
+            "todos": {},
+            "labels": {},
+            "dependencies": [],
+            "todo_changes": { "changes": {} },
+            "label_changes": { "changes": {} },
+            "last_todo_id": 3,
+            "last_label_id": 1,
+            "sequence": 5
+        }))
+        .expect("failed parse");
+        assert_eq!(data.workspaces, StoredWorkspaces::default());
+        assert!(data.api_tokens.is_empty() && data.users.is_empty());
+    }
+}
@@ -0,0 +1,542 @@
+use axum::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgPool};
+use validator::Validate;
+
+use super::RepositoryError;
+
+// 通知中のリマインダーが終わるまで他のワーカーに再取得されないようにする猶予。
+// 通知に失敗したリマインダーは猶予が切れた後に再び取得される
+const CLAIM_LEASE_SECS: i64 = 60;
+
+#[async_trait]
+pub trait ReminderRepository: Clone + std::marker::Send + std::marker::Sync + 'static {
+    async fn create(&self, todo_id: i32, payload: CreateReminder) -> anyhow::Result<Reminder>;
+    async fn find(&self, id: i32) -> anyhow::Result<Reminder>;
+    async fn find_by_todo(&self, todo_id: i32) -> anyhow::Result<Vec<Reminder>>;
+    async fn snooze(&self, id: i32, fire_at: DateTime<Utc>) -> anyhow::Result<Reminder>;
+    async fn dismiss(&self, id: i32) -> anyhow::Result<Reminder>;
+    async fn claim_due(&self, limit: i64) -> anyhow::Result<Vec<Reminder>>;
+    async fn mark_fired(&self, id: i32) -> anyhow::Result<Reminder>;
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum ReminderChannel {
+    Webhook,
+    Smtp,
+    Log,
+}
+
+impl ReminderChannel {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReminderChannel::Webhook => "webhook",
+            ReminderChannel::Smtp => "smtp",
+            ReminderChannel::Log => "log",
+        }
+    }
+
+    fn parse(channel: &str) -> anyhow::Result<Self> {
+        match channel {
+            "webhook" => Ok(ReminderChannel::Webhook),
+            "smtp" => Ok(ReminderChannel::Smtp),
+            "log" => Ok(ReminderChannel::Log),
+            _ => Err(RepositoryError::Unexpected(format!("unknown channel: {}", channel)).into()),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Reminder {
+    pub id: i32,
+    pub todo_id: i32,
+    pub fire_at: DateTime<Utc>,
+    pub channel: ReminderChannel,
+    pub fired_at: Option<DateTime<Utc>>,
+    pub dismissed_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, FromRow)]
+struct ReminderFromRow {
+    id: i32,
+    todo_id: i32,
+    fire_at: DateTime<Utc>,
+    channel: String,
+    fired_at: Option<DateTime<Utc>>,
+    dismissed_at: Option<DateTime<Utc>>,
+}
+
+impl TryFrom<ReminderFromRow> for Reminder {
+    type Error = anyhow::Error;
+
+    fn try_from(row: ReminderFromRow) -> anyhow::Result<Self> {
+        Ok(Reminder {
+            id: row.id,
+            todo_id: row.todo_id,
+            fire_at: row.fire_at,
+            channel: ReminderChannel::parse(&row.channel)?,
+            fired_at: row.fired_at,
+            dismissed_at: row.dismissed_at,
+        })
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Validate)]
+pub struct CreateReminder {
+    pub fire_at: DateTime<Utc>,
+    pub channel: ReminderChannel,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Validate)]
+pub struct SnoozeReminder {
+    #[validate(range(min = 1, max = 10080, message = "must be 1 to 10080 minutes"))]
+    pub minutes: i64,
+}
+
+#[derive(Debug, Clone)]
+pub struct ReminderRepositoryForDb {
+    pool: PgPool,
+}
+
+impl ReminderRepositoryForDb {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl ReminderRepository for ReminderRepositoryForDb {
+    #[tracing::instrument(name = "reminder_repository.create", skip_all)]
+    async fn create(&self, todo_id: i32, payload: CreateReminder) -> anyhow::Result<Reminder> {
+        let row = sqlx::query_as::<_, ReminderFromRow>(
+            r#"
+            insert into reminders (todo_id, fire_at, channel)
+            values ($1, $2, $3)
+            returning *
+            "#,
+        )
+        .bind(todo_id)
+        .bind(payload.fire_at)
+        .bind(payload.channel.as_str())
+        .fetch_one(&self.pool)
+        .await?;
+
+        row.try_into()
+    }
+
+    #[tracing::instrument(name = "reminder_repository.find", skip_all)]
+    async fn find(&self, id: i32) -> anyhow::Result<Reminder> {
+        let row = sqlx::query_as::<_, ReminderFromRow>(
+            r#"
+            select * from reminders where id = $1
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or(RepositoryError::NotFound(id))?;
+
+        row.try_into()
+    }
+
+    #[tracing::instrument(name = "reminder_repository.find_by_todo", skip_all)]
+    async fn find_by_todo(&self, todo_id: i32) -> anyhow::Result<Vec<Reminder>> {
+        let rows = sqlx::query_as::<_, ReminderFromRow>(
+            r#"
+            select * from reminders
+            where todo_id = $1
+            order by fire_at asc, id asc
+            "#,
+        )
+        .bind(todo_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter().map(Reminder::try_from).collect()
+    }
+
+    #[tracing::instrument(name = "reminder_repository.snooze", skip_all)]
+    async fn snooze(&self, id: i32, fire_at: DateTime<Utc>) -> anyhow::Result<Reminder> {
+        // 通知済みのリマインダーも再通知の対象に戻す
+        let row = sqlx::query_as::<_, ReminderFromRow>(
+            r#"
+            update reminders set fire_at = $2, fired_at = null, claimed_until = null
+            where id = $1 and dismissed_at is null
+            returning *
+            "#,
+        )
+        .bind(id)
+        .bind(fire_at)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or(RepositoryError::NotFound(id))?;
+
+        row.try_into()
+    }
+
+    #[tracing::instrument(name = "reminder_repository.dismiss", skip_all)]
+    async fn dismiss(&self, id: i32) -> anyhow::Result<Reminder> {
+        let row = sqlx::query_as::<_, ReminderFromRow>(
+            r#"
+            update reminders set dismissed_at = coalesce(dismissed_at, now())
+            where id = $1
+            returning *
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or(RepositoryError::NotFound(id))?;
+
+        row.try_into()
+    }
+
+    #[tracing::instrument(name = "reminder_repository.claim_due", skip_all)]
+    async fn claim_due(&self, limit: i64) -> anyhow::Result<Vec<Reminder>> {
+        // 複数インスタンスで同じリマインダーを二重に通知しないよう、
+        // 他のトランザクションがロック中の行は読み飛ばし、通知が終わるまで確保しておく。
+        // fired_at は通知に成功してから mark_fired で立てる
+        let rows = sqlx::query_as::<_, ReminderFromRow>(
+            r#"
+            update reminders set claimed_until = now() + make_interval(secs => $2)
+            where id in (
+                select id from reminders
+                where fired_at is null
+                  and dismissed_at is null
+                  and fire_at <= now()
+                  and (claimed_until is null or claimed_until <= now())
+                order by fire_at asc
+                limit $1
+                for update skip locked
+            )
+            returning *
+            "#,
+        )
+        .bind(limit)
+        .bind(CLAIM_LEASE_SECS as f64)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter().map(Reminder::try_from).collect()
+    }
+
+    #[tracing::instrument(name = "reminder_repository.mark_fired", skip_all)]
+    async fn mark_fired(&self, id: i32) -> anyhow::Result<Reminder> {
+        let row = sqlx::query_as::<_, ReminderFromRow>(
+            r#"
+            update reminders set fired_at = now(), claimed_until = null
+            where id = $1
+            returning *
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or(RepositoryError::NotFound(id))?;
+
+        row.try_into()
+    }
+}
+
+#[cfg(test)]
+#[cfg(feature = "database-test")]
+mod test {
+    use chrono::Duration;
+
+    use super::*;
+    use crate::repositories::test_database::TestDatabase;
+
+    #[tokio::test]
+    async fn crud_scenario() {
+        let database = TestDatabase::new().await;
+        let pool = database.pool();
+
+        let repository = ReminderRepositoryForDb::new(pool);
+        // 実在しない todo id を使い、他のテストのデータと混ざらないようにする
+        let todo_id = -(std::process::id() as i32);
+
+        // create
+        let due = repository
+            .create(
+                todo_id,
+                CreateReminder {
+                    fire_at: Utc::now() - Duration::minutes(1),
+                    channel: ReminderChannel::Log,
+                },
+            )
+            .await
+            .expect("failed create");
+        assert_eq!(due.channel, ReminderChannel::Log);
+        assert_eq!(due.fired_at, None);
+        let later = repository
+            .create(
+                todo_id,
+                CreateReminder {
+                    fire_at: Utc::now() + Duration::hours(1),
+                    channel: ReminderChannel::Webhook,
+                },
+            )
+            .await
+            .expect("failed create");
+
+        // find
+        let found = repository.find(due.id).await.expect("failed find");
+        assert_eq!(found, due);
+
+        // find_by_todo
+        let reminders = repository.find_by_todo(todo_id).await.expect("failed find");
+        assert_eq!(reminders, vec![due.clone(), later.clone()]);
+
+        // claim_due
+        let claimed = repository.claim_due(100).await.expect("failed claim");
+        assert!(claimed.iter().any(|reminder| reminder.id == due.id));
+        assert!(!claimed.iter().any(|reminder| reminder.id == later.id));
+        // 確保している間は他のワーカーに渡さない
+        let claimed = repository.claim_due(100).await.expect("failed claim");
+        assert!(!claimed.iter().any(|reminder| reminder.id == due.id));
+        // 猶予が切れたら通知に失敗したものとして再び取得する
+        sqlx::query("update reminders set claimed_until = now() where id = $1")
+            .bind(due.id)
+            .execute(&repository.pool)
+            .await
+            .expect("failed expire claim");
+        let claimed = repository.claim_due(100).await.expect("failed claim");
+        assert!(claimed.iter().any(|reminder| reminder.id == due.id));
+
+        // mark_fired
+        let fired = repository
+            .mark_fired(due.id)
+            .await
+            .expect("failed mark_fired");
+        assert!(fired.fired_at.is_some());
+        sqlx::query("update reminders set claimed_until = now() where id = $1")
+            .bind(due.id)
+            .execute(&repository.pool)
+            .await
+            .expect("failed expire claim");
+        let claimed = repository.claim_due(100).await.expect("failed claim");
+        assert!(!claimed.iter().any(|reminder| reminder.id == due.id));
+
+        // snooze
+        let snoozed = repository
+            .snooze(due.id, Utc::now() - Duration::seconds(1))
+            .await
+            .expect("failed snooze");
+        assert_eq!(snoozed.fired_at, None);
+        let claimed = repository.claim_due(100).await.expect("failed claim");
+        assert!(claimed.iter().any(|reminder| reminder.id == due.id));
+
+        // dismiss
+        let dismissed = repository.dismiss(later.id).await.expect("failed dismiss");
+        assert!(dismissed.dismissed_at.is_some());
+        let res = repository.snooze(later.id, Utc::now()).await;
+        assert!(res.is_err());
+        let res = repository.dismiss(i32::MAX).await;
+        assert!(res.is_err());
+    }
+}
+
+#[cfg(any(test, feature = "memory"))]
+pub mod memory {
+    use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+    use anyhow::Context;
+
+    use super::*;
+
+    // リマインダーと、通知中であれば確保している期限
+    type ReminderDatas = Vec<(Reminder, Option<DateTime<Utc>>)>;
+
+    #[derive(Debug, Clone)]
+    pub struct ReminderRepositoryInMemory {
+        store: Arc<RwLock<ReminderDatas>>,
+    }
+
+    impl ReminderRepositoryInMemory {
+        pub fn new() -> Self {
+            Self {
+                store: Arc::default(),
+            }
+        }
+
+        fn write_store_ref(&self) -> RwLockWriteGuard<'_, ReminderDatas> {
+            self.store.write().unwrap()
+        }
+
+        fn read_store_ref(&self) -> RwLockReadGuard<'_, ReminderDatas> {
+            self.store.read().unwrap()
+        }
+    }
+
+    #[async_trait]
+    impl ReminderRepository for ReminderRepositoryInMemory {
+        async fn create(&self, todo_id: i32, payload: CreateReminder) -> anyhow::Result<Reminder> {
+            let mut store = self.write_store_ref();
+            let reminder = Reminder {
+                id: (store.len() + 1) as i32,
+                todo_id,
+                fire_at: payload.fire_at,
+                channel: payload.channel,
+                fired_at: None,
+                dismissed_at: None,
+            };
+            store.push((reminder.clone(), None));
+            Ok(reminder)
+        }
+
+        async fn find(&self, id: i32) -> anyhow::Result<Reminder> {
+            let store = self.read_store_ref();
+            store
+                .iter()
+                .map(|(reminder, _)| reminder)
+                .find(|reminder| reminder.id == id)
+                .cloned()
+                .context(RepositoryError::NotFound(id))
+        }
+
+        async fn find_by_todo(&self, todo_id: i32) -> anyhow::Result<Vec<Reminder>> {
+            let store = self.read_store_ref();
+            let mut reminders = store
+                .iter()
+                .map(|(reminder, _)| reminder)
+                .filter(|reminder| reminder.todo_id == todo_id)
+                .cloned()
+                .collect::<Vec<_>>();
+            reminders.sort_by_key(|reminder| (reminder.fire_at, reminder.id));
+            Ok(reminders)
+        }
+
+        async fn snooze(&self, id: i32, fire_at: DateTime<Utc>) -> anyhow::Result<Reminder> {
+            let mut store = self.write_store_ref();
+            let (reminder, claimed_until) = store
+                .iter_mut()
+                .find(|(reminder, _)| reminder.id == id && reminder.dismissed_at.is_none())
+                .context(RepositoryError::NotFound(id))?;
+            reminder.fire_at = fire_at;
+            reminder.fired_at = None;
+            *claimed_until = None;
+            Ok(reminder.clone())
+        }
+
+        async fn dismiss(&self, id: i32) -> anyhow::Result<Reminder> {
+            let mut store = self.write_store_ref();
+            let (reminder, _) = store
+                .iter_mut()
+                .find(|(reminder, _)| reminder.id == id)
+                .context(RepositoryError::NotFound(id))?;
+            reminder.dismissed_at.get_or_insert_with(Utc::now);
+            Ok(reminder.clone())
+        }
+
+        async fn claim_due(&self, limit: i64) -> anyhow::Result<Vec<Reminder>> {
+            let mut store = self.write_store_ref();
+            let now = Utc::now();
+            let mut due = store
+                .iter_mut()
+                .filter(|(reminder, claimed_until)| {
+                    reminder.fired_at.is_none()
+                        && reminder.dismissed_at.is_none()
+                        && reminder.fire_at <= now
+                        && claimed_until.is_none_or(|claimed_until| claimed_until <= now)
+                })
+                .collect::<Vec<_>>();
+            due.sort_by_key(|(reminder, _)| reminder.fire_at);
+            let lease = chrono::Duration::seconds(CLAIM_LEASE_SECS);
+            Ok(due
+                .into_iter()
+                .take(limit as usize)
+                .map(|(reminder, claimed_until)| {
+                    *claimed_until = Some(now + lease);
+                    reminder.clone()
+                })
+                .collect())
+        }
+
+        async fn mark_fired(&self, id: i32) -> anyhow::Result<Reminder> {
+            let mut store = self.write_store_ref();
+            let (reminder, claimed_until) = store
+                .iter_mut()
+                .find(|(reminder, _)| reminder.id == id)
+                .context(RepositoryError::NotFound(id))?;
+            reminder.fired_at = Some(Utc::now());
+            *claimed_until = None;
+            Ok(reminder.clone())
+        }
+    }
+
+    #[cfg(test)]
+    mod test {
+        use chrono::Duration;
+
+        use super::*;
+
+        #[tokio::test]
+        async fn reminder_scenario() {
+            let repository = ReminderRepositoryInMemory::new();
+            let due = repository
+                .create(
+                    1,
+                    CreateReminder {
+                        fire_at: Utc::now() - Duration::minutes(1),
+                        channel: ReminderChannel::Log,
+                    },
+                )
+                .await
+                .expect("failed create");
+            let later = repository
+                .create(
+                    1,
+                    CreateReminder {
+                        fire_at: Utc::now() + Duration::hours(1),
+                        channel: ReminderChannel::Smtp,
+                    },
+                )
+                .await
+                .expect("failed create");
+
+            // claim_due
+            let claimed = repository.claim_due(10).await.expect("failed claim");
+            assert_eq!(claimed.len(), 1);
+            assert_eq!(claimed[0].id, due.id);
+            let claimed = repository.claim_due(10).await.expect("failed claim");
+            assert!(claimed.is_empty());
+            let fired = repository
+                .mark_fired(due.id)
+                .await
+                .expect("failed mark_fired");
+            assert!(fired.fired_at.is_some());
+
+            // snooze
+            repository
+                .snooze(later.id, Utc::now() - Duration::seconds(1))
+                .await
+                .expect("failed snooze");
+            let claimed = repository.claim_due(10).await.expect("failed claim");
+            assert_eq!(claimed.len(), 1);
+            assert_eq!(claimed[0].id, later.id);
+
+            // dismiss
+            repository
+                .snooze(due.id, Utc::now() - Duration::seconds(1))
+                .await
+                .expect("failed snooze");
+            repository.dismiss(due.id).await.expect("failed dismiss");
+            let claimed = repository.claim_due(10).await.expect("failed claim");
+            assert!(claimed.is_empty());
+            let res = repository.snooze(due.id, Utc::now()).await;
+            assert!(res.is_err());
+
+            // find
+            let found = repository.find(later.id).await.expect("failed find");
+            assert_eq!(found.todo_id, 1);
+            assert!(repository.find(later.id + 1).await.is_err());
+
+            // find_by_todo
+            let reminders = repository.find_by_todo(1).await.expect("failed find");
+            assert_eq!(reminders.len(), 2);
+            assert!(repository.find_by_todo(2).await.unwrap().is_empty());
+        }
+    }
+}
@@ -0,0 +1,12 @@
+use serde::{Deserialize, Serialize};
+
+// cursor より後に変更されたデータ。削除されたものは id だけを tombstone として返す
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Changes<T> {
+    pub upserts: Vec<T>,
+    pub tombstones: Vec<i32>,
+    // 次回に since に渡す値。DB ではまだ終わっていない最も古いトランザクション、
+    // インメモリでは変更の連番で、どちらもこれより後の変更だけを返す
+    #[serde(skip)]
+    pub cursor: i64,
+}
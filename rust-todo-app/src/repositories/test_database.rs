@@ -0,0 +1,153 @@
+// DB テストごとに作り捨てるデータベース。
+// マイグレーションを適用したテンプレートを一度だけ作り、テストごとにそこから複製するので、
+// テストを並列に実行しても互いのデータが見えず、終わればデータベースごと削除する
+use std::{
+    env,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+use dotenv::dotenv;
+use sha2::{Digest, Sha256};
+use sqlx::{migrate::Migrator, Connection, PgConnection, PgPool};
+use tokio::sync::OnceCell;
+use url::Url;
+
+static MIGRATOR: Migrator = sqlx::migrate!("./db/migrations");
+
+// テンプレートを作る処理をプロセスをまたいで直列にするためのロック
+const TEMPLATE_LOCK: i64 = 0x746f_646f_7374;
+
+static TEMPLATE: OnceCell<String> = OnceCell::const_new();
+static COUNTER: AtomicU32 = AtomicU32::new(0);
+
+#[derive(Debug)]
+pub struct TestDatabase {
+    pool: PgPool,
+    name: String,
+}
+
+impl TestDatabase {
+    pub async fn new() -> Self {
+        let template = TEMPLATE.get_or_init(create_template).await;
+        let name = format!(
+            "todos_test_{}_{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::SeqCst)
+        );
+        let mut conn = connect_admin().await;
+        execute(&mut conn, &format!(r#"DROP DATABASE IF EXISTS "{}""#, name)).await;
+        execute(
+            &mut conn,
+            &format!(r#"CREATE DATABASE "{}" TEMPLATE "{}""#, name, template),
+        )
+        .await;
+        let pool = PgPool::connect(database_url(&name).as_str())
+            .await
+            .unwrap_or_else(|e| panic!("failed connect database {}: {}", name, e));
+        Self { pool, name }
+    }
+
+    pub fn pool(&self) -> PgPool {
+        self.pool.clone()
+    }
+}
+
+impl Drop for TestDatabase {
+    // Drop では await できないため、別スレッドのランタイムで削除する。
+    // テストが panic しても、残った接続ごと削除される
+    fn drop(&mut self) {
+        let name = self.name.clone();
+        let dropped = std::thread::spawn(move || {
+            tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .expect("failed build runtime")
+                .block_on(async {
+                    let mut conn = connect_admin().await;
+                    let sql = format!(r#"DROP DATABASE IF EXISTS "{}" WITH (FORCE)"#, name);
+                    execute(&mut conn, &sql).await;
+                })
+        })
+        .join();
+        if dropped.is_err() {
+            eprintln!("failed drop database {}", self.name);
+        }
+    }
+}
+
+// DATABASE_URL のデータベース名だけを差し替える
+fn database_url(name: &str) -> Url {
+    dotenv().ok();
+    let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+    let mut url = Url::parse(&database_url)
+        .unwrap_or_else(|_| panic!("invalid DATABASE_URL: {}", database_url));
+    url.set_path(name);
+    url
+}
+
+async fn connect_admin() -> PgConnection {
+    let url = database_url("postgres");
+    PgConnection::connect(url.as_str())
+        .await
+        .unwrap_or_else(|e| panic!("failed connect database {}: {}", url, e))
+}
+
+async fn execute(conn: &mut PgConnection, sql: &str) {
+    sqlx::query(sql)
+        .execute(conn)
+        .await
+        .unwrap_or_else(|e| panic!("failed execute `{}`: {}", sql, e));
+}
+
+// マイグレーションの内容からテンプレート名を決め、無ければ作る。
+// マイグレーションを追加すれば別のテンプレートになるので、古いものを気にせず使える
+async fn create_template() -> String {
+    let mut hasher = Sha256::new();
+    for migration in MIGRATOR.iter() {
+        hasher.update(migration.version.to_be_bytes());
+        hasher.update(&migration.checksum);
+    }
+    let name = format!("todos_template_{}", &hex::encode(hasher.finalize())[..16]);
+
+    let mut conn = connect_admin().await;
+    sqlx::query("SELECT pg_advisory_lock($1)")
+        .bind(TEMPLATE_LOCK)
+        .execute(&mut conn)
+        .await
+        .expect("failed lock template");
+    let exists: bool =
+        sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM pg_database WHERE datname = $1)")
+            .bind(&name)
+            .fetch_one(&mut conn)
+            .await
+            .expect("failed find template");
+    if !exists {
+        // 途中で失敗したテンプレートを残さないよう、別名で作ってから名前を変える
+        let building = format!("{}_building", name);
+        execute(
+            &mut conn,
+            &format!(r#"DROP DATABASE IF EXISTS "{}""#, building),
+        )
+        .await;
+        execute(&mut conn, &format!(r#"CREATE DATABASE "{}""#, building)).await;
+        let pool = PgPool::connect(database_url(&building).as_str())
+            .await
+            .unwrap_or_else(|e| panic!("failed connect database {}: {}", building, e));
+        MIGRATOR
+            .run(&pool)
+            .await
+            .unwrap_or_else(|e| panic!("failed migrate {}: {}", building, e));
+        pool.close().await;
+        execute(
+            &mut conn,
+            &format!(r#"ALTER DATABASE "{}" RENAME TO "{}""#, building, name),
+        )
+        .await;
+    }
+    sqlx::query("SELECT pg_advisory_unlock($1)")
+        .bind(TEMPLATE_LOCK)
+        .execute(&mut conn)
+        .await
+        .expect("failed unlock template");
+    name
+}
@@ -1,17 +1,45 @@
+use std::collections::HashMap;
+
 use axum::async_trait;
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use sqlx::{FromRow, PgPool};
+use sqlx::{Executor, FromRow, PgConnection, PgPool, Postgres};
+use tracing::Instrument;
 use validator::Validate;
 
-use super::{label::Label, RepositoryError};
+use super::{label::Label, query_span, sync::Changes, RepositoryError, Scope};
+
+// 依存関係の追加を直列にするためのロック。
+// 別々の辺を同時に追加して循環ができないよう、2 行ではなくグラフ全体をロックする
+const DEPENDENCY_LOCK: i64 = 0x746f_646f_6470;
 
 #[async_trait]
 pub trait TodoRepository: Clone + std::marker::Send + std::marker::Sync + 'static {
+    fn scoped(&self, scope: Scope) -> Self;
     async fn create(&self, payload: CreateTodo) -> anyhow::Result<TodoEntity>;
     async fn find(&self, id: i32) -> anyhow::Result<TodoEntity>;
     async fn all(&self) -> anyhow::Result<Vec<TodoEntity>>;
-    async fn update(&self, id: i32, payload: UpdateTodo) -> anyhow::Result<TodoEntity>;
-    async fn delete(&self, id: i32) -> anyhow::Result<()>;
+    async fn all_by_label(&self, label_id: i32) -> anyhow::Result<Vec<TodoEntity>>;
+    async fn page(&self, limit: i64, offset: i64) -> anyhow::Result<TodoPage>;
+    async fn page_by_label(
+        &self,
+        label_id: i32,
+        limit: i64,
+        offset: i64,
+    ) -> anyhow::Result<TodoPage>;
+    async fn update(
+        &self,
+        id: i32,
+        payload: UpdateTodo,
+        expected_version: Option<i32>,
+    ) -> anyhow::Result<TodoEntity>;
+    async fn delete(&self, id: i32, expected_version: Option<i32>) -> anyhow::Result<()>;
+    async fn trashed(&self) -> anyhow::Result<Vec<TrashedTodo>>;
+    async fn restore(&self, id: i32) -> anyhow::Result<TodoEntity>;
+    async fn purge(&self, deleted_before: DateTime<Utc>) -> anyhow::Result<u64>;
+    async fn add_blocker(&self, id: i32, blocker_id: i32) -> anyhow::Result<TodoEntity>;
+    async fn remove_blocker(&self, id: i32, blocker_id: i32) -> anyhow::Result<TodoEntity>;
+    async fn changes(&self, since: i64) -> anyhow::Result<Changes<TodoEntity>>;
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, FromRow)]
@@ -19,55 +47,95 @@ pub struct TodoFromRow {
     id: i32,
     title: String,
     completed: bool,
+    version: i32,
 }
 
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, FromRow)]
 pub struct TodoWithLabelFromRow {
     id: i32,
     title: String,
+    description: Option<String>,
     completed: bool,
+    version: i32,
+    deleted_at: Option<DateTime<Utc>>,
+    workspace_id: Option<i32>,
     label_id: Option<i32>,
     label_name: Option<String>,
+    label_color: Option<String>,
+    label_description: Option<String>,
+    label_parent_id: Option<i32>,
+    label_workspace_id: Option<i32>,
+}
+
+impl TodoWithLabelFromRow {
+    fn label(&self) -> Option<Label> {
+        Some(Label {
+            id: self.label_id?,
+            name: self.label_name.clone()?,
+            color: self.label_color.clone()?,
+            description: self.label_description.clone(),
+            parent_id: self.label_parent_id,
+            workspace_id: self.label_workspace_id,
+        })
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
 pub struct TodoEntity {
-    id: i32,
-    title: String,
-    completed: bool,
+    pub id: i32,
+    pub title: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    pub completed: bool,
+    pub version: i32,
     pub labels: Vec<Label>,
+    #[serde(default)]
+    pub workspace_id: Option<i32>,
+    // 未完了の blocker が残っている間は true
+    #[serde(default)]
+    pub blocked: bool,
+    #[serde(default)]
+    pub blocked_by: Vec<i32>,
+}
+
+// ページ分割した todo と、ページ分割前の件数
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct TodoPage {
+    pub todos: Vec<TodoEntity>,
+    pub total: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct TrashedTodo {
+    #[serde(flatten)]
+    pub todo: TodoEntity,
+    pub deleted_at: DateTime<Utc>,
 }
 
 fn fold_entities(rows: Vec<TodoWithLabelFromRow>) -> Vec<TodoEntity> {
-    let mut rows = rows.iter();
     let mut accum: Vec<TodoEntity> = vec![];
-    'outer: while let Some(row) = rows.next() {
+    'outer: for row in rows.iter() {
         let todos = accum.iter_mut();
         for todo in todos {
             // idが一致=Todoに紐づくラベルが複数存在
             if todo.id == row.id {
-                todo.labels.push(Label {
-                    id: row.label_id.unwrap(),
-                    name: row.label_name.clone().unwrap(),
-                });
+                todo.labels.push(row.label().unwrap());
                 continue 'outer;
             }
         }
 
         // Todoのidに一致がなかったのみ到達、TodoEntityを作成
-        let labels = if row.label_id.is_some() {
-            vec![Label {
-                id: row.label_id.unwrap(),
-                name: row.label_name.clone().unwrap(),
-            }]
-        } else {
-            vec![]
-        };
+        let labels = row.label().into_iter().collect();
         accum.push(TodoEntity {
             id: row.id,
             title: row.title.clone(),
+            description: row.description.clone(),
             completed: row.completed,
+            version: row.version,
             labels,
+            workspace_id: row.workspace_id,
+            blocked: false,
+            blocked_by: vec![],
         });
     }
     accum
@@ -78,119 +146,450 @@ pub struct CreateTodo {
     #[validate(length(min = 1, message = "Can not be empty"))]
     #[validate(length(max = 100, message = "Can not be longer than 100 characters"))]
     title: String,
+    #[serde(default)]
+    #[validate(length(max = 1000, message = "Can not be longer than 1000 characters"))]
+    description: Option<String>,
     labels: Vec<i32>,
 }
 
+impl CreateTodo {
+    pub fn new(title: String, label_ids: Vec<i32>) -> Self {
+        Self {
+            title,
+            description: None,
+            labels: label_ids,
+        }
+    }
+}
+
+// description は省略すると変更せず、null を指定すると消す
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Validate)]
 pub struct UpdateTodo {
     #[validate(length(min = 1, message = "Can not be empty"))]
     #[validate(length(max = 100, message = "Can not be longer than 100 characters"))]
-    title: Option<String>,
-    completed: Option<bool>,
-    labels: Option<Vec<i32>>,
+    pub title: Option<String>,
+    #[serde(
+        default,
+        deserialize_with = "nullable",
+        skip_serializing_if = "Option::is_none"
+    )]
+    #[validate(length(max = 1000, message = "Can not be longer than 1000 characters"))]
+    pub description: Option<Option<String>>,
+    pub completed: Option<bool>,
+    pub labels: Option<Vec<i32>>,
+}
+
+// フィールドがあれば null でも Some にして、省略された場合と区別する
+fn nullable<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Validate)]
+pub struct AddBlocker {
+    pub blocker_id: i32,
+}
+
+impl From<TodoEntity> for UpdateTodo {
+    fn from(todo: TodoEntity) -> Self {
+        Self {
+            title: Some(todo.title),
+            description: Some(todo.description),
+            completed: Some(todo.completed),
+            labels: Some(todo.labels.iter().map(|label| label.id).collect()),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct TodoRepositoryForDb {
     pool: PgPool,
+    scope: Scope,
 }
 
 impl TodoRepositoryForDb {
     pub fn new(pool: PgPool) -> Self {
-        Self { pool }
+        Self {
+            pool,
+            scope: Scope::All,
+        }
+    }
+
+    async fn attach_dependencies<'e, E: Executor<'e, Database = Postgres>>(
+        &self,
+        executor: E,
+        mut todos: Vec<TodoEntity>,
+    ) -> anyhow::Result<Vec<TodoEntity>> {
+        let ids: Vec<i32> = todos.iter().map(|todo| todo.id).collect();
+        let rows = sqlx::query_as::<_, (i32, i32, bool)>(
+            r#"
+            select d.todo_id, d.blocker_id, blockers.completed
+            from todo_dependencies d
+            inner join todos blockers on blockers.id = d.blocker_id
+            where d.todo_id = any($1) and blockers.deleted_at is null
+            order by d.blocker_id asc
+            "#,
+        )
+        .bind(ids)
+        .fetch_all(executor)
+        .instrument(query_span("select", "todo_dependencies"))
+        .await?;
+
+        for todo in todos.iter_mut() {
+            for (_, blocker_id, completed) in rows.iter().filter(|row| row.0 == todo.id) {
+                todo.blocked_by.push(*blocker_id);
+                todo.blocked |= !completed;
+            }
+        }
+        Ok(todos)
+    }
+
+    // 書き込みと同じトランザクションの中から読み直せるよう、接続を受け取る
+    async fn find_in(&self, conn: &mut PgConnection, id: i32) -> anyhow::Result<TodoEntity> {
+        let items = sqlx::query_as::<_, TodoWithLabelFromRow>(
+            r#"
+            select todos.*, labels.id as label_id, labels.name as label_name,
+                labels.color as label_color, labels.description as label_description,
+                labels.parent_id as label_parent_id, labels.workspace_id as label_workspace_id
+            from todos
+                        left outer join (
+                            todo_labels tl
+                            inner join labels on labels.id = tl.label_id and labels.deleted_at is null
+                        ) on todos.id = tl.todo_id
+            where todos.id=$1 and todos.deleted_at is null
+              and ($2::integer is null or coalesce(todos.workspace_id, 0) = $2);
+            "#,
+        )
+        .bind(id)
+        .bind(self.scope.bind_value())
+        .fetch_all(&mut *conn)
+        .instrument(query_span("select", "todos"))
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::RowNotFound => RepositoryError::NotFound(id),
+            _ => RepositoryError::Unexpected(e.to_string()),
+        })?;
+
+        let todos = self.attach_dependencies(conn, fold_entities(items)).await?;
+        let todo = todos.first().ok_or(RepositoryError::NotFound(id))?;
+        Ok(todo.clone())
+    }
+
+    // 存在しないラベルや別のワークスペースのラベルは NotFound にする
+    async fn check_labels(&self, label_ids: &[i32]) -> anyhow::Result<()> {
+        let found = sqlx::query_scalar::<_, i32>(
+            r#"
+            select id from labels
+            where id = any($1) and deleted_at is null
+              and ($2::integer is null or coalesce(workspace_id, 0) = $2)
+            "#,
+        )
+        .bind(label_ids)
+        .bind(self.scope.bind_value())
+        .fetch_all(&self.pool)
+        .instrument(query_span("select", "labels"))
+        .await?;
+        if let Some(missing) = label_ids.iter().find(|id| !found.contains(id)) {
+            return Err(RepositoryError::NotFound(*missing).into());
+        }
+        Ok(())
     }
 }
 
 #[async_trait]
 impl TodoRepository for TodoRepositoryForDb {
+    fn scoped(&self, scope: Scope) -> Self {
+        Self {
+            pool: self.pool.clone(),
+            scope,
+        }
+    }
+
+    #[tracing::instrument(name = "todo_repository.create", skip_all)]
     async fn create(&self, payload: CreateTodo) -> anyhow::Result<TodoEntity> {
-        let tx = self.pool.begin().await?;
+        self.check_labels(&payload.labels).await?;
+        let mut tx = self.pool.begin().await?;
         let row = sqlx::query_as::<_, TodoFromRow>(
             r#"
-            insert into todos (title, completed)
-            values ($1, false)
+            insert into todos (title, description, completed, workspace_id)
+            values ($1, $2, false, $3)
             returning *;
             "#,
         )
         .bind(payload.title.clone())
-        .fetch_one(&self.pool)
+        .bind(payload.description.clone())
+        .bind(self.scope.workspace_id())
+        .fetch_one(&mut tx)
+        .instrument(query_span("insert", "todos"))
         .await?;
 
+        // 別のワークスペースのラベルは付けない
         sqlx::query(
             r#"
             insert into todo_labels (todo_id, label_id)
             select $1, id
-            from unnest($2) as t(id);
+            from labels
+            where id = any($2)
+              and ($3::integer is null or coalesce(workspace_id, 0) = $3);
             "#,
         )
         .bind(row.id)
         .bind(payload.labels)
-        .execute(&self.pool)
+        .bind(self.scope.bind_value())
+        .execute(&mut tx)
+        .instrument(query_span("insert", "todo_labels"))
         .await?;
 
+        let todo = self.find_in(&mut tx, row.id).await?;
         tx.commit().await?;
 
-        let todo = self.find(row.id).await?;
-
         Ok(todo)
     }
 
+    #[tracing::instrument(name = "todo_repository.find", skip_all)]
     async fn find(&self, id: i32) -> anyhow::Result<TodoEntity> {
+        let mut conn = self.pool.acquire().await?;
+        self.find_in(&mut conn, id).await
+    }
+
+    #[tracing::instrument(name = "todo_repository.all", skip_all)]
+    async fn all(&self) -> anyhow::Result<Vec<TodoEntity>> {
         let items = sqlx::query_as::<_, TodoWithLabelFromRow>(
             r#"
-            select todos.*, labels.id as label_id, labels.name as label_name
-            from todos
-                        left outer join todo_labels tl on todos.id = tl.todo_id
-                        left outer join labels on labels.id = tl.label_id
-            where todos.id=$1;
+            select todos.*, labels.id as label_id, labels.name as label_name,
+                labels.color as label_color, labels.description as label_description,
+                labels.parent_id as label_parent_id, labels.workspace_id as label_workspace_id from todos
+            left join (
+                todo_labels tl
+                inner join labels on labels.id = tl.label_id and labels.deleted_at is null
+            ) on tl.todo_id = todos.id
+            where todos.deleted_at is null
+              and ($1::integer is null or coalesce(todos.workspace_id, 0) = $1)
+            order by todos.id desc
             "#,
         )
-        .bind(id)
+        .bind(self.scope.bind_value())
         .fetch_all(&self.pool)
-        .await
-        .map_err(|e| match e {
-            sqlx::Error::RowNotFound => RepositoryError::NotFound(id),
-            _ => RepositoryError::Unexpected(e.to_string()),
-        })?;
+        .instrument(query_span("select", "todos"))
+        .await?;
 
-        let todos = fold_entities(items);
-        let todo = todos.first().ok_or(RepositoryError::NotFound(id))?;
-        Ok(todo.clone())
+        let todos = self
+            .attach_dependencies(&self.pool, fold_entities(items))
+            .await?;
+
+        Ok(todos)
     }
 
-    async fn all(&self) -> anyhow::Result<Vec<TodoEntity>> {
+    #[tracing::instrument(name = "todo_repository.all_by_label", skip_all)]
+    async fn all_by_label(&self, label_id: i32) -> anyhow::Result<Vec<TodoEntity>> {
+        // 指定したラベルか、その子孫のラベルが付いた todo を返す
+        let items = sqlx::query_as::<_, TodoWithLabelFromRow>(
+            r#"
+            with recursive descendants(id) as (
+                select id from labels where id = $1 and deleted_at is null
+                union
+                select labels.id from labels
+                inner join descendants on labels.parent_id = descendants.id
+                where labels.deleted_at is null
+            )
+            select todos.*, labels.id as label_id, labels.name as label_name,
+                labels.color as label_color, labels.description as label_description,
+                labels.parent_id as label_parent_id, labels.workspace_id as label_workspace_id from todos
+            left join (
+                todo_labels tl
+                inner join labels on labels.id = tl.label_id and labels.deleted_at is null
+            ) on tl.todo_id = todos.id
+            where todos.deleted_at is null
+              and ($2::integer is null or coalesce(todos.workspace_id, 0) = $2)
+              and todos.id in (
+                select todo_id from todo_labels
+                where label_id in (select id from descendants)
+              )
+            order by todos.id desc
+            "#,
+        )
+        .bind(label_id)
+        .bind(self.scope.bind_value())
+        .fetch_all(&self.pool)
+        .instrument(query_span("select", "todos"))
+        .await?;
+
+        let todos = self
+            .attach_dependencies(&self.pool, fold_entities(items))
+            .await?;
+
+        Ok(todos)
+    }
+
+    #[tracing::instrument(name = "todo_repository.page", skip_all)]
+    async fn page(&self, limit: i64, offset: i64) -> anyhow::Result<TodoPage> {
+        // ラベルとの結合で行が増えるので、先に todo の id でページを切り出す
         let items = sqlx::query_as::<_, TodoWithLabelFromRow>(
             r#"
-            select todos.*, labels.id as label_id, labels.name as label_name from todos
-            left join todo_labels tl on tl.todo_id = todos.id
-            left join labels on labels.id = tl.label_id
+            with page as (
+                select id from todos
+                where deleted_at is null
+                  and ($3::integer is null or coalesce(workspace_id, 0) = $3)
+                order by id desc
+                limit $1 offset $2
+            )
+            select todos.*, labels.id as label_id, labels.name as label_name,
+                labels.color as label_color, labels.description as label_description,
+                labels.parent_id as label_parent_id, labels.workspace_id as label_workspace_id from todos
+            left join (
+                todo_labels tl
+                inner join labels on labels.id = tl.label_id and labels.deleted_at is null
+            ) on tl.todo_id = todos.id
+            where todos.id in (select id from page)
             order by todos.id desc
             "#,
         )
+        .bind(limit)
+        .bind(offset)
+        .bind(self.scope.bind_value())
         .fetch_all(&self.pool)
+        .instrument(query_span("select", "todos"))
+        .await?;
+
+        let total = sqlx::query_scalar::<_, i64>(
+            r#"
+            select count(*) from todos
+            where deleted_at is null
+              and ($1::integer is null or coalesce(workspace_id, 0) = $1)
+            "#,
+        )
+        .bind(self.scope.bind_value())
+        .fetch_one(&self.pool)
+        .instrument(query_span("select", "todos"))
+        .await?;
+
+        let todos = self
+            .attach_dependencies(&self.pool, fold_entities(items))
+            .await?;
+
+        Ok(TodoPage { todos, total })
+    }
+
+    #[tracing::instrument(name = "todo_repository.page_by_label", skip_all)]
+    async fn page_by_label(
+        &self,
+        label_id: i32,
+        limit: i64,
+        offset: i64,
+    ) -> anyhow::Result<TodoPage> {
+        // ラベルとの結合で行が増えるので、先に todo の id でページを切り出す
+        let items = sqlx::query_as::<_, TodoWithLabelFromRow>(
+            r#"
+            with recursive descendants(id) as (
+                select id from labels where id = $1 and deleted_at is null
+                union
+                select labels.id from labels
+                inner join descendants on labels.parent_id = descendants.id
+                where labels.deleted_at is null
+            ),
+            page as (
+                select id from todos
+                where deleted_at is null
+                  and ($4::integer is null or coalesce(workspace_id, 0) = $4)
+                  and id in (
+                    select todo_id from todo_labels
+                    where label_id in (select id from descendants)
+                  )
+                order by id desc
+                limit $2 offset $3
+            )
+            select todos.*, labels.id as label_id, labels.name as label_name,
+                labels.color as label_color, labels.description as label_description,
+                labels.parent_id as label_parent_id, labels.workspace_id as label_workspace_id from todos
+            left join (
+                todo_labels tl
+                inner join labels on labels.id = tl.label_id and labels.deleted_at is null
+            ) on tl.todo_id = todos.id
+            where todos.id in (select id from page)
+            order by todos.id desc
+            "#,
+        )
+        .bind(label_id)
+        .bind(limit)
+        .bind(offset)
+        .bind(self.scope.bind_value())
+        .fetch_all(&self.pool)
+        .instrument(query_span("select", "todos"))
+        .await?;
+
+        let total = sqlx::query_scalar::<_, i64>(
+            r#"
+            with recursive descendants(id) as (
+                select id from labels where id = $1 and deleted_at is null
+                union
+                select labels.id from labels
+                inner join descendants on labels.parent_id = descendants.id
+                where labels.deleted_at is null
+            )
+            select count(*) from todos
+            where deleted_at is null
+              and ($2::integer is null or coalesce(workspace_id, 0) = $2)
+              and id in (
+                select todo_id from todo_labels
+                where label_id in (select id from descendants)
+              )
+            "#,
+        )
+        .bind(label_id)
+        .bind(self.scope.bind_value())
+        .fetch_one(&self.pool)
+        .instrument(query_span("select", "todos"))
         .await?;
 
-        Ok(fold_entities(items))
+        let todos = self
+            .attach_dependencies(&self.pool, fold_entities(items))
+            .await?;
+
+        Ok(TodoPage { todos, total })
     }
 
-    async fn update(&self, id: i32, payload: UpdateTodo) -> anyhow::Result<TodoEntity> {
-        let tx = self.pool.begin().await?;
+    #[tracing::instrument(name = "todo_repository.update", skip_all)]
+    async fn update(
+        &self,
+        id: i32,
+        payload: UpdateTodo,
+        expected_version: Option<i32>,
+    ) -> anyhow::Result<TodoEntity> {
+        if let Some(labels) = &payload.labels {
+            self.check_labels(labels).await?;
+        }
+        let mut tx = self.pool.begin().await?;
 
         // todo update
-        let old_todo = self.find(id).await?;
-        sqlx::query(
+        let old_todo = self.find_in(&mut tx, id).await?;
+        if expected_version.is_some_and(|version| version != old_todo.version) {
+            return Err(RepositoryError::Conflict(id).into());
+        }
+        // version 条件で他のリクエストによる同時更新を検出する
+        let result = sqlx::query(
             r#"
             update todos
             set title = coalesce($1, title),
-                completed = coalesce($2, completed)
-            where id = $3
+                description = $2,
+                completed = coalesce($3, completed),
+                version = version + 1
+            where id = $4 and version = $5
             "#,
         )
         .bind(payload.title.unwrap_or(old_todo.title))
+        .bind(payload.description.unwrap_or(old_todo.description))
         .bind(payload.completed.unwrap_or(old_todo.completed))
         .bind(id)
-        .execute(&self.pool)
+        .bind(old_todo.version)
+        .execute(&mut tx)
+        .instrument(query_span("update", "todos"))
         .await?;
+        if result.rows_affected() == 0 {
+            return Err(RepositoryError::Conflict(id).into());
+        }
 
         if let Some(labels) = payload.labels {
             // delete old labels
@@ -200,7 +599,8 @@ impl TodoRepository for TodoRepositoryForDb {
                 "#,
             )
             .bind(id)
-            .execute(&self.pool)
+            .execute(&mut tx)
+            .instrument(query_span("delete", "todo_labels"))
             .await?;
 
             // insert new labels
@@ -208,55 +608,334 @@ impl TodoRepository for TodoRepositoryForDb {
                 r#"
                 insert into
                     todo_labels (todo_id, label_id)
-                select $1, id from unnest($2) as t(id);
+                select $1, id from labels
+                where id = any($2)
+                  and ($3::integer is null or coalesce(workspace_id, 0) = $3);
                 "#,
             )
             .bind(id)
             .bind(labels)
-            .execute(&self.pool)
+            .bind(self.scope.bind_value())
+            .execute(&mut tx)
+            .instrument(query_span("insert", "todo_labels"))
             .await?;
         }
 
+        let todo = self.find_in(&mut tx, id).await?;
         tx.commit().await?;
+
+        Ok(todo)
+    }
+
+    #[tracing::instrument(name = "todo_repository.delete", skip_all)]
+    async fn delete(&self, id: i32, expected_version: Option<i32>) -> anyhow::Result<()> {
+        // ゴミ箱に移すだけで、ラベルの紐付けは復元用に残しておく。
+        // version 条件も同じ文に含め、他のリクエストによる同時更新を検出する
+        let result = sqlx::query(
+            r#"
+            update todos set deleted_at = now()
+            where id = $1 and deleted_at is null
+              and ($2::integer is null or coalesce(workspace_id, 0) = $2)
+              and ($3::integer is null or version = $3)
+            "#,
+        )
+        .bind(id)
+        .bind(self.scope.bind_value())
+        .bind(expected_version)
+        .execute(&self.pool)
+        .instrument(query_span("update", "todos"))
+        .await
+        .map_err(|e| RepositoryError::Unexpected(e.to_string()))?;
+        if result.rows_affected() == 0 {
+            // todo が残っていれば version の不一致
+            self.find(id).await?;
+            return Err(RepositoryError::Conflict(id).into());
+        }
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "todo_repository.trashed", skip_all)]
+    async fn trashed(&self) -> anyhow::Result<Vec<TrashedTodo>> {
+        let items = sqlx::query_as::<_, TodoWithLabelFromRow>(
+            r#"
+            select todos.*, labels.id as label_id, labels.name as label_name,
+                labels.color as label_color, labels.description as label_description,
+                labels.parent_id as label_parent_id, labels.workspace_id as label_workspace_id from todos
+            left join (
+                todo_labels tl
+                inner join labels on labels.id = tl.label_id and labels.deleted_at is null
+            ) on tl.todo_id = todos.id
+            where todos.deleted_at is not null
+              and ($1::integer is null or coalesce(todos.workspace_id, 0) = $1)
+            order by todos.deleted_at desc, todos.id desc
+            "#,
+        )
+        .bind(self.scope.bind_value())
+        .fetch_all(&self.pool)
+        .instrument(query_span("select", "todos"))
+        .await?;
+
+        let deleted_at: HashMap<i32, DateTime<Utc>> = items
+            .iter()
+            .filter_map(|row| row.deleted_at.map(|deleted_at| (row.id, deleted_at)))
+            .collect();
+        let todos = self
+            .attach_dependencies(&self.pool, fold_entities(items))
+            .await?
+            .into_iter()
+            .map(|todo| TrashedTodo {
+                deleted_at: deleted_at[&todo.id],
+                todo,
+            })
+            .collect();
+
+        Ok(todos)
+    }
+
+    #[tracing::instrument(name = "todo_repository.restore", skip_all)]
+    async fn restore(&self, id: i32) -> anyhow::Result<TodoEntity> {
+        let result = sqlx::query(
+            r#"
+            update todos set deleted_at = null
+            where id = $1 and deleted_at is not null
+              and ($2::integer is null or coalesce(workspace_id, 0) = $2)
+            "#,
+        )
+        .bind(id)
+        .bind(self.scope.bind_value())
+        .execute(&self.pool)
+        .instrument(query_span("update", "todos"))
+        .await?;
+        if result.rows_affected() == 0 {
+            return Err(RepositoryError::NotFound(id).into());
+        }
+
         let todo = self.find(id).await?;
 
         Ok(todo)
     }
 
-    async fn delete(&self, id: i32) -> anyhow::Result<()> {
-        let tx = self.pool.begin().await?;
+    #[tracing::instrument(name = "todo_repository.purge", skip_all)]
+    async fn purge(&self, deleted_before: DateTime<Utc>) -> anyhow::Result<u64> {
+        let mut tx = self.pool.begin().await?;
 
-        // todo's label delete
         sqlx::query(
             r#"
-            delete from todo_labels where todo_id = $1
+            delete from todo_labels
+            where todo_id in (
+                select id from todos
+                where deleted_at <= $1
+                  and ($2::integer is null or coalesce(workspace_id, 0) = $2)
+            )
+            "#,
+        )
+        .bind(deleted_before)
+        .bind(self.scope.bind_value())
+        .execute(&mut tx)
+        .instrument(query_span("delete", "todo_labels"))
+        .await?;
+
+        sqlx::query(
+            r#"
+            delete from todo_dependencies
+            where todo_id in (
+                select id from todos
+                where deleted_at <= $1
+                  and ($2::integer is null or coalesce(workspace_id, 0) = $2)
+            )
+               or blocker_id in (
+                   select id from todos
+                   where deleted_at <= $1
+                     and ($2::integer is null or coalesce(workspace_id, 0) = $2)
+               )
+            "#,
+        )
+        .bind(deleted_before)
+        .bind(self.scope.bind_value())
+        .execute(&mut tx)
+        .instrument(query_span("delete", "todo_dependencies"))
+        .await?;
+
+        sqlx::query(
+            r#"
+            delete from reminders
+            where todo_id in (
+                select id from todos
+                where deleted_at <= $1
+                  and ($2::integer is null or coalesce(workspace_id, 0) = $2)
+            )
+            "#,
+        )
+        .bind(deleted_before)
+        .bind(self.scope.bind_value())
+        .execute(&mut tx)
+        .instrument(query_span("delete", "reminders"))
+        .await?;
+
+        let result = sqlx::query(
+            r#"
+            delete from todos
+            where deleted_at <= $1
+              and ($2::integer is null or coalesce(workspace_id, 0) = $2)
+            "#,
+        )
+        .bind(deleted_before)
+        .bind(self.scope.bind_value())
+        .execute(&mut tx)
+        .instrument(query_span("delete", "todos"))
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(result.rows_affected())
+    }
+
+    #[tracing::instrument(name = "todo_repository.add_blocker", skip_all)]
+    async fn add_blocker(&self, id: i32, blocker_id: i32) -> anyhow::Result<TodoEntity> {
+        if id == blocker_id {
+            return Err(RepositoryError::DependencyCycle(id).into());
+        }
+        self.find(id).await?;
+        self.find(blocker_id).await?;
+
+        // 循環の確認と追加の間に他の依存関係が追加されないよう、同じトランザクションで行う
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("select pg_advisory_xact_lock($1)")
+            .bind(DEPENDENCY_LOCK)
+            .execute(&mut tx)
+            .await?;
+
+        // blocker が既に id の完了を待っている場合は循環になる
+        let cycle = sqlx::query_scalar::<_, bool>(
+            r#"
+            with recursive blockers(id) as (
+                select blocker_id from todo_dependencies where todo_id = $1
+                union
+                select d.blocker_id
+                from todo_dependencies d
+                inner join blockers on blockers.id = d.todo_id
+            )
+            select exists(select 1 from blockers where id = $2)
             "#,
         )
+        .bind(blocker_id)
         .bind(id)
-        .execute(&self.pool)
-        .await
-        .map_err(|e| match e {
-            sqlx::Error::RowNotFound => RepositoryError::NotFound(id),
-            _ => RepositoryError::Unexpected(e.to_string()),
-        })?;
+        .fetch_one(&mut tx)
+        .instrument(query_span("select", "todo_dependencies"))
+        .await?;
+        if cycle {
+            return Err(RepositoryError::DependencyCycle(id).into());
+        }
 
-        // todo delete
         sqlx::query(
             r#"
-            delete from todos where id = $1
+            insert into todo_dependencies (todo_id, blocker_id)
+            values ($1, $2)
+            on conflict do nothing
+            "#,
+        )
+        .bind(id)
+        .bind(blocker_id)
+        .execute(&mut tx)
+        .instrument(query_span("insert", "todo_dependencies"))
+        .await?;
+        tx.commit().await?;
+
+        let todo = self.find(id).await?;
+
+        Ok(todo)
+    }
+
+    #[tracing::instrument(name = "todo_repository.remove_blocker", skip_all)]
+    async fn remove_blocker(&self, id: i32, blocker_id: i32) -> anyhow::Result<TodoEntity> {
+        self.find(id).await?;
+        let result = sqlx::query(
+            r#"
+            delete from todo_dependencies where todo_id = $1 and blocker_id = $2
             "#,
         )
         .bind(id)
+        .bind(blocker_id)
         .execute(&self.pool)
-        .await
-        .map_err(|e| match e {
-            sqlx::Error::RowNotFound => RepositoryError::NotFound(id),
-            _ => RepositoryError::Unexpected(e.to_string()),
-        })?;
+        .instrument(query_span("delete", "todo_dependencies"))
+        .await?;
+        if result.rows_affected() == 0 {
+            return Err(RepositoryError::NotFound(blocker_id).into());
+        }
+
+        let todo = self.find(id).await?;
+
+        Ok(todo)
+    }
+
+    #[tracing::instrument(name = "todo_repository.changes", skip_all)]
+    async fn changes(&self, since: i64) -> anyhow::Result<Changes<TodoEntity>> {
+        // カーソルと変更された todo を同じスナップショットから読む
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("set transaction isolation level repeatable read")
+            .execute(&mut tx)
+            .await?;
+
+        // まだ終わっていない最も古いトランザクションをカーソルにする。
+        // それより前のトランザクションは全て終わっているので、後から見える変更はカーソル以降に入る。
+        // 実行中のトランザクションより後にコミットされた変更は、次回も重ねて返す
+        let cursor = sqlx::query_scalar::<_, i64>(
+            r#"
+            select pg_snapshot_xmin(pg_current_snapshot())::text::bigint
+            "#,
+        )
+        .fetch_one(&mut tx)
+        .instrument(query_span("select", "changes"))
+        .await?;
+
+        let ids = sqlx::query_scalar::<_, i32>(
+            r#"
+            select entity_id from changes
+            where entity = 'todo' and xid >= $1::bigint::text::xid8
+              and ($2::integer is null or coalesce(workspace_id, 0) = $2)
+            group by entity_id
+            order by max(seq) asc
+            "#,
+        )
+        .bind(since)
+        .bind(self.scope.bind_value())
+        .fetch_all(&mut tx)
+        .instrument(query_span("select", "changes"))
+        .await?;
+
+        let items = sqlx::query_as::<_, TodoWithLabelFromRow>(
+            r#"
+            select todos.*, labels.id as label_id, labels.name as label_name,
+                labels.color as label_color, labels.description as label_description,
+                labels.parent_id as label_parent_id, labels.workspace_id as label_workspace_id from todos
+            left join (
+                todo_labels tl
+                inner join labels on labels.id = tl.label_id and labels.deleted_at is null
+            ) on tl.todo_id = todos.id
+            where todos.id = any($1) and todos.deleted_at is null
+            order by todos.id asc
+            "#,
+        )
+        .bind(&ids)
+        .fetch_all(&mut tx)
+        .instrument(query_span("select", "todos"))
+        .await?;
 
         tx.commit().await?;
 
-        Ok(())
+        let upserts = self
+            .attach_dependencies(&self.pool, fold_entities(items))
+            .await?;
+        let tombstones = ids
+            .into_iter()
+            .filter(|id| !upserts.iter().any(|todo| todo.id == *id))
+            .collect();
+        Ok(Changes {
+            upserts,
+            tombstones,
+            cursor,
+        })
     }
 }
 
@@ -264,43 +943,76 @@ impl TodoRepository for TodoRepositoryForDb {
 #[cfg(feature = "database-test")]
 mod test {
     // scenario testing for TodoRepositoryForDb
-    use dotenv::dotenv;
-    use sqlx::PgPool;
-    use std::env;
-
     use super::*;
+    use crate::repositories::{
+        conformance::todo_repository_conformance,
+        label::{CreateLabel, LabelRepository, LabelRepositoryForDb},
+        test_database::TestDatabase,
+    };
 
     #[test]
     fn fold_entities_test() {
         let label_1 = Label {
             id: 1,
             name: "label_1".to_string(),
+            color: "#ff0000".to_string(),
+            description: None,
+            parent_id: None,
+            workspace_id: None,
         };
         let label_2 = Label {
             id: 2,
             name: "label_2".to_string(),
+            color: "#00ff00".to_string(),
+            description: Some("label_2 description".to_string()),
+            parent_id: Some(1),
+            workspace_id: None,
         };
         let rows = vec![
             TodoWithLabelFromRow {
                 id: 1,
                 title: "todo_1".to_string(),
+                description: None,
                 completed: false,
+                version: 1,
+                deleted_at: None,
+                workspace_id: None,
                 label_id: Some(label_1.id),
                 label_name: Some(label_1.name.clone()),
+                label_color: Some(label_1.color.clone()),
+                label_description: label_1.description.clone(),
+                label_parent_id: label_1.parent_id,
+                label_workspace_id: label_1.workspace_id,
             },
             TodoWithLabelFromRow {
                 id: 1,
                 title: "todo_1".to_string(),
+                description: None,
                 completed: false,
+                version: 1,
+                deleted_at: None,
+                workspace_id: None,
                 label_id: Some(label_2.id),
                 label_name: Some(label_2.name.clone()),
+                label_color: Some(label_2.color.clone()),
+                label_description: label_2.description.clone(),
+                label_parent_id: label_2.parent_id,
+                label_workspace_id: label_2.workspace_id,
             },
             TodoWithLabelFromRow {
                 id: 2,
                 title: "todo_2".to_string(),
+                description: None,
                 completed: false,
+                version: 1,
+                deleted_at: None,
+                workspace_id: None,
                 label_id: Some(label_1.id),
                 label_name: Some(label_1.name.clone()),
+                label_color: Some(label_1.color.clone()),
+                label_description: label_1.description.clone(),
+                label_parent_id: label_1.parent_id,
+                label_workspace_id: label_1.workspace_id,
             },
         ];
 
@@ -312,14 +1024,24 @@ mod test {
                 TodoEntity {
                     id: 1,
                     title: "todo_1".to_string(),
+                    description: None,
                     completed: false,
+                    version: 1,
                     labels: vec![label_1.clone(), label_2],
+                    workspace_id: None,
+                    blocked: false,
+                    blocked_by: vec![],
                 },
                 TodoEntity {
                     id: 2,
                     title: "todo_2".to_string(),
+                    description: None,
                     completed: false,
+                    version: 1,
                     labels: vec![label_1],
+                    workspace_id: None,
+                    blocked: false,
+                    blocked_by: vec![],
                 }
             ]
         )
@@ -327,11 +1049,8 @@ mod test {
 
     #[tokio::test]
     async fn crud_scenario() {
-        dotenv().ok();
-        let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
-        let pool = PgPool::connect(&database_url)
-            .await
-            .unwrap_or_else(|_| panic!("Error connecting to {}", database_url));
+        let database = TestDatabase::new().await;
+        let pool = database.pool();
 
         // label data prepare
         let label_name = "test label".to_string();
@@ -386,21 +1105,66 @@ mod test {
                 todo.id,
                 UpdateTodo {
                     title: Some(updated_title.to_string()),
+                    description: None,
                     completed: Some(true),
                     labels: Some(vec![]),
                 },
+                Some(todo.version),
             )
             .await
             .expect("update failed");
         assert_eq!(updated.id, todo.id);
         assert_eq!(updated.title, updated_title);
         assert!(updated.completed);
+        assert_eq!(updated.version, todo.version + 1);
+
+        // update with stale version
+        let result = repository
+            .update(
+                todo.id,
+                UpdateTodo {
+                    title: Some("[crud_scenario] stale todo".to_string()),
+                    description: None,
+                    completed: None,
+                    labels: None,
+                },
+                Some(todo.version),
+            )
+            .await;
+        assert!(matches!(
+            result.unwrap_err().downcast_ref::<RepositoryError>(),
+            Some(RepositoryError::Conflict(_))
+        ));
+
+        // delete with stale version
+        let result = repository.delete(todo.id, Some(todo.version)).await;
+        assert!(result.is_err());
 
         // delete
-        repository.delete(todo.id).await.expect("delete failed");
+        repository
+            .delete(todo.id, Some(updated.version))
+            .await
+            .expect("delete failed");
         let result = repository.find(todo.id).await;
         assert!(result.is_err());
 
+        // trashed
+        let trashed = repository.trashed().await.expect("trashed failed");
+        assert_eq!(trashed.first().unwrap().todo, updated);
+
+        // restore
+        let restored = repository.restore(todo.id).await.expect("restore failed");
+        assert_eq!(restored, updated);
+
+        // purge
+        repository
+            .delete(todo.id, None)
+            .await
+            .expect("delete failed");
+        repository.purge(Utc::now()).await.expect("purge failed");
+        let trashed = repository.trashed().await.expect("trashed failed");
+        assert!(trashed.iter().all(|trashed| trashed.todo.id != todo.id));
+
         let todo_rows = sqlx::query(
             r#"
             select * from todos where id = $1
@@ -422,125 +1186,730 @@ mod test {
 
         assert!(todo_rows.is_empty());
         assert!(todo_labels_rows.is_empty());
+
+        // dependencies
+        let todo = repository
+            .create(CreateTodo::new(
+                "[crud_scenario] blocked".to_string(),
+                vec![],
+            ))
+            .await
+            .expect("create failed");
+        let blocker = repository
+            .create(CreateTodo::new(
+                "[crud_scenario] blocker".to_string(),
+                vec![],
+            ))
+            .await
+            .expect("create failed");
+
+        // add blocker
+        let blocked = repository
+            .add_blocker(todo.id, blocker.id)
+            .await
+            .expect("add_blocker failed");
+        assert!(blocked.blocked);
+        assert_eq!(blocked.blocked_by, vec![blocker.id]);
+
+        // cycle
+        let result = repository.add_blocker(blocker.id, todo.id).await;
+        assert!(matches!(
+            result.unwrap_err().downcast_ref::<RepositoryError>(),
+            Some(RepositoryError::DependencyCycle(_))
+        ));
+
+        // complete blocker
+        repository
+            .update(
+                blocker.id,
+                UpdateTodo {
+                    title: None,
+                    description: None,
+                    completed: Some(true),
+                    labels: None,
+                },
+                None,
+            )
+            .await
+            .expect("update failed");
+        let unblocked = repository.find(todo.id).await.expect("find failed");
+        assert!(!unblocked.blocked);
+
+        // remove blocker
+        let removed = repository
+            .remove_blocker(todo.id, blocker.id)
+            .await
+            .expect("remove_blocker failed");
+        assert!(removed.blocked_by.is_empty());
+
+        repository
+            .delete(todo.id, None)
+            .await
+            .expect("delete failed");
+        repository
+            .delete(blocker.id, None)
+            .await
+            .expect("delete failed");
+
+        // descendant labels
+        let child_label = sqlx::query_as::<_, Label>(
+            r#"
+            insert into labels (name, parent_id) values ($1, $2) returning *
+            "#,
+        )
+        .bind(format!(
+            "[crud_scenario] child label {}",
+            std::process::id()
+        ))
+        .bind(label_1.id)
+        .fetch_one(&repository.pool)
+        .await
+        .expect("Failed to insert label");
+        let todo = repository
+            .create(CreateTodo::new(
+                "[crud_scenario] child labeled".to_string(),
+                vec![child_label.id],
+            ))
+            .await
+            .expect("create failed");
+        let todos = repository
+            .all_by_label(label_1.id)
+            .await
+            .expect("all_by_label failed");
+        assert!(todos.contains(&todo));
+        let todos = repository
+            .all_by_label(child_label.id)
+            .await
+            .expect("all_by_label failed");
+        assert_eq!(todos, vec![todo.clone()]);
+        let page = repository
+            .page_by_label(label_1.id, 1, 0)
+            .await
+            .expect("page_by_label failed");
+        assert_eq!(page.todos, vec![todo.clone()]);
+        assert!(page.total >= 1);
+        let page = repository
+            .page_by_label(child_label.id, 1, 1)
+            .await
+            .expect("page_by_label failed");
+        assert_eq!(page.todos, vec![]);
+        assert_eq!(page.total, 1);
+
+        repository
+            .delete(todo.id, None)
+            .await
+            .expect("delete failed");
+        sqlx::query(
+            r#"
+            update labels set deleted_at = now() where id = $1
+            "#,
+        )
+        .bind(child_label.id)
+        .execute(&repository.pool)
+        .await
+        .expect("Failed to delete label");
+
+        // merge labels
+        let label_repository = LabelRepositoryForDb::new(repository.pool.clone());
+        let source = label_repository
+            .create(CreateLabel::new(format!(
+                "[crud_scenario] merged label {}",
+                std::process::id()
+            )))
+            .await
+            .expect("create label failed");
+        let both = repository
+            .create(CreateTodo::new(
+                "[crud_scenario] both labels".to_string(),
+                vec![label_1.id, source.id],
+            ))
+            .await
+            .expect("create failed");
+        let source_only = repository
+            .create(CreateTodo::new(
+                "[crud_scenario] source label only".to_string(),
+                vec![source.id],
+            ))
+            .await
+            .expect("create failed");
+        let merged = label_repository
+            .merge(source.id, label_1.id)
+            .await
+            .expect("merge failed");
+        assert_eq!(merged.label.id, label_1.id);
+        assert_eq!(merged.todo_ids, vec![both.id, source_only.id]);
+        for id in [both.id, source_only.id] {
+            let todo = repository.find(id).await.expect("find failed");
+            assert_eq!(todo.labels, vec![label_1.clone()]);
+            repository.delete(id, None).await.expect("delete failed");
+        }
+        let res = label_repository.merge(source.id, label_1.id).await;
+        assert!(res.is_err());
+    }
+
+    #[tokio::test]
+    async fn changes_scenario() {
+        let database = TestDatabase::new().await;
+        let pool = database.pool();
+        let repository = TodoRepositoryForDb::new(pool);
+
+        let cursor = repository
+            .changes(i64::MAX)
+            .await
+            .expect("changes failed")
+            .cursor;
+        let todo = repository
+            .create(CreateTodo::new(
+                "[changes_scenario] todo".to_string(),
+                vec![],
+            ))
+            .await
+            .expect("create failed");
+
+        // 作成した todo が upsert として返る
+        let changes = repository.changes(cursor).await.expect("changes failed");
+        assert!(changes.cursor >= cursor);
+        assert!(changes.upserts.contains(&todo));
+        assert!(!changes.tombstones.contains(&todo.id));
+
+        // 削除した todo は tombstone になる
+        repository
+            .delete(todo.id, None)
+            .await
+            .expect("delete failed");
+        let deleted = repository
+            .changes(changes.cursor)
+            .await
+            .expect("changes failed");
+        assert!(deleted.upserts.iter().all(|upsert| upsert.id != todo.id));
+        assert!(deleted.tombstones.contains(&todo.id));
+
+        // 別のワークスペースの変更は返さない
+        let scoped = repository
+            .scoped(Scope::Workspace(i32::MAX))
+            .changes(cursor)
+            .await
+            .expect("changes failed");
+        assert!(!scoped.tombstones.contains(&todo.id));
+    }
+
+    #[tokio::test]
+    async fn changes_should_not_skip_later_committed_changes() {
+        let database = TestDatabase::new().await;
+        let pool = database.pool();
+        let repository = TodoRepositoryForDb::new(pool.clone());
+
+        let first = repository
+            .create(CreateTodo::new("first".to_string(), vec![]))
+            .await
+            .expect("create failed");
+        let cursor = repository.changes(0).await.expect("changes failed").cursor;
+
+        // 先に始めたトランザクションがコミットされる前に、別の変更をコミットする
+        let mut tx = pool.begin().await.expect("begin failed");
+        sqlx::query("update todos set title = 'first updated' where id = $1")
+            .bind(first.id)
+            .execute(&mut tx)
+            .await
+            .expect("update failed");
+        let xid = sqlx::query_scalar::<_, i64>("select pg_current_xact_id()::text::bigint")
+            .fetch_one(&mut tx)
+            .await
+            .expect("xid failed");
+        let second = repository
+            .create(CreateTodo::new("second".to_string(), vec![]))
+            .await
+            .expect("create failed");
+
+        // 後からコミットされた変更は見えても、カーソルは実行中のトランザクションを越えない
+        let changes = repository.changes(cursor).await.expect("changes failed");
+        assert!(changes.cursor <= xid);
+        let ids: Vec<i32> = changes.upserts.iter().map(|todo| todo.id).collect();
+        assert!(ids.contains(&second.id));
+
+        tx.commit().await.expect("commit failed");
+        let changes = repository
+            .changes(changes.cursor)
+            .await
+            .expect("changes failed");
+        let updated = changes
+            .upserts
+            .iter()
+            .find(|todo| todo.id == first.id)
+            .expect("first is skipped");
+        assert_eq!(updated.title, "first updated");
+    }
+
+    #[tokio::test]
+    async fn should_not_add_blockers_concurrently_into_a_cycle() {
+        let database = TestDatabase::new().await;
+        let repository = TodoRepositoryForDb::new(database.pool());
+
+        let mut ids = vec![];
+        for title in ["a", "b", "c"] {
+            let todo = repository
+                .create(CreateTodo::new(title.to_string(), vec![]))
+                .await
+                .expect("create failed");
+            ids.push(todo.id);
+        }
+        // a -> b -> c -> a の辺を同時に追加しても、どれか一つは循環として拒否される
+        let edges = [(ids[0], ids[1]), (ids[1], ids[2]), (ids[2], ids[0])];
+        let handles = edges.map(|(id, blocker_id)| {
+            let repository = repository.clone();
+            tokio::spawn(async move { repository.add_blocker(id, blocker_id).await })
+        });
+        let mut added = 0;
+        for handle in handles {
+            if handle.await.unwrap().is_ok() {
+                added += 1;
+            }
+        }
+        assert_eq!(added, 2);
+    }
+
+    #[tokio::test]
+    async fn conforms_to_repository_contract() {
+        let database = TestDatabase::new().await;
+        let pool = database.pool();
+        todo_repository_conformance(
+            TodoRepositoryForDb::new(pool.clone()),
+            LabelRepositoryForDb::new(pool),
+        )
+        .await;
     }
 }
 
 #[cfg(test)]
 pub mod test_utils {
-    use anyhow::Context;
-    use std::{
-        collections::HashMap,
-        sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard},
-    };
-
     use super::*;
 
-    type TodoDatas = HashMap<i32, TodoEntity>;
-
     impl TodoEntity {
         pub fn new(id: i32, title: String, labels: Vec<Label>) -> Self {
             Self {
                 id,
                 title,
+                description: None,
                 completed: false,
+                version: 1,
                 labels,
+                workspace_id: None,
+                blocked: false,
+                blocked_by: vec![],
             }
         }
 
         pub fn set_completed(&mut self, completed: bool) {
             self.completed = completed;
         }
-    }
 
-    impl CreateTodo {
-        pub fn new(title: String, label_ids: Vec<i32>) -> Self {
-            Self {
-                title,
-                labels: label_ids,
-            }
+        pub fn set_version(&mut self, version: i32) {
+            self.version = version;
         }
     }
+}
+
+#[cfg(any(test, feature = "memory"))]
+pub mod memory {
+    use anyhow::Context;
+    use std::cmp::Reverse;
+
+    use super::*;
+    use crate::repositories::memory::{MemoryData, MemoryStore, StoredTodo};
 
     #[derive(Debug, Clone)]
     pub struct TodoRepositoryInMemory {
-        store: Arc<RwLock<TodoDatas>>,
-        labels: Vec<Label>,
+        store: MemoryStore,
+        scope: Scope,
     }
 
     impl TodoRepositoryInMemory {
-        pub fn new(labels: Vec<Label>) -> Self {
+        // LabelRepositoryInMemory と同じストアを渡せば、ラベルの変更がそのまま todo に反映される
+        pub fn with_store(store: MemoryStore) -> Self {
             Self {
-                store: Arc::default(),
-                labels,
+                store,
+                scope: Scope::All,
             }
         }
 
-        fn write_store_ref(&self) -> RwLockWriteGuard<TodoDatas> {
-            self.store.write().unwrap()
+        // labels を入れた新しいストアで作る
+        #[cfg(test)]
+        pub fn new(labels: Vec<Label>) -> Self {
+            let store = MemoryStore::new();
+            for label in labels {
+                store.write().insert_label(label);
+            }
+            Self::with_store(store)
         }
 
-        fn read_store_ref(&self) -> RwLockReadGuard<TodoDatas> {
-            self.store.read().unwrap()
+        #[cfg(test)]
+        pub fn store(&self) -> MemoryStore {
+            self.store.clone()
         }
 
-        fn resolve_labels(&self, label_ids: Vec<i32>) -> Vec<Label> {
-            self.labels
+        // ラベルはゴミ箱に入っていないものだけを id の順に、blocker はゴミ箱に入っていないものだけを返す
+        fn entity(&self, data: &MemoryData, todo: &StoredTodo) -> TodoEntity {
+            let mut labels: Vec<Label> = todo
+                .label_ids
                 .iter()
-                .filter(|label| label_ids.contains(&label.id))
+                .filter_map(|id| data.live_label(*id))
                 .cloned()
+                .collect();
+            labels.sort_by_key(|label| label.id);
+            let mut blocked_by: Vec<i32> = data
+                .dependencies
+                .iter()
+                .filter(|(todo_id, blocker_id)| {
+                    *todo_id == todo.id && data.live_todo(*blocker_id).is_some()
+                })
+                .map(|(_, blocker_id)| *blocker_id)
+                .collect();
+            blocked_by.sort();
+            let blocked = blocked_by
+                .iter()
+                .any(|blocker_id| !data.todos[blocker_id].completed);
+            TodoEntity {
+                id: todo.id,
+                title: todo.title.clone(),
+                description: todo.description.clone(),
+                completed: todo.completed,
+                version: todo.version,
+                labels,
+                workspace_id: todo.workspace_id,
+                blocked,
+                blocked_by,
+            }
+        }
+
+        // blocker の完了や削除で blocked が変わる todo も変更として記録する
+        fn record_change(data: &mut MemoryData, id: i32) {
+            let mut changed = vec![id];
+            changed.extend(
+                data.dependencies
+                    .iter()
+                    .filter(|(_, blocker_id)| *blocker_id == id)
+                    .map(|(todo_id, _)| *todo_id),
+            );
+            for id in changed {
+                if let Some(workspace_id) = data.todos.get(&id).map(|todo| todo.workspace_id) {
+                    data.record_todo_change(id, workspace_id);
+                }
+            }
+        }
+
+        // label_id 自身とその子孫のラベルの id
+        fn descendant_label_ids(&self, data: &MemoryData, label_id: i32) -> Vec<i32> {
+            if data.live_label(label_id).is_none() {
+                return vec![];
+            }
+            let mut ids = vec![label_id];
+            let mut index = 0;
+            while index < ids.len() {
+                let parent_id = ids[index];
+                ids.extend(
+                    data.labels
+                        .values()
+                        .filter(|stored| stored.deleted_at.is_none())
+                        .filter(|stored| stored.label.parent_id == Some(parent_id))
+                        .map(|stored| stored.label.id),
+                );
+                index += 1;
+            }
+            ids
+        }
+
+        // 存在しないラベルや別のワークスペースのラベルは NotFound にする
+        fn resolve_labels(
+            &self,
+            data: &MemoryData,
+            label_ids: Vec<i32>,
+        ) -> anyhow::Result<Vec<i32>> {
+            let mut resolved = vec![];
+            for label_id in label_ids {
+                data.live_label(label_id)
+                    .filter(|label| self.scope.contains(label.workspace_id))
+                    .context(RepositoryError::NotFound(label_id))?;
+                if !resolved.contains(&label_id) {
+                    resolved.push(label_id);
+                }
+            }
+            resolved.sort();
+            Ok(resolved)
+        }
+
+        // ゴミ箱の todo や別のワークスペースの todo は存在しないものとして扱う
+        fn get_scoped<'a>(&self, data: &'a MemoryData, id: i32) -> anyhow::Result<&'a StoredTodo> {
+            let todo = data
+                .live_todo(id)
+                .filter(|todo| self.scope.contains(todo.workspace_id))
+                .context(RepositoryError::NotFound(id))?;
+            Ok(todo)
+        }
+
+        // id の降順
+        fn live_todos(&self, data: &MemoryData) -> Vec<TodoEntity> {
+            data.todos
+                .values()
+                .rev()
+                .filter(|todo| todo.deleted_at.is_none() && self.scope.contains(todo.workspace_id))
+                .map(|todo| self.entity(data, todo))
                 .collect()
         }
     }
 
     #[async_trait]
     impl TodoRepository for TodoRepositoryInMemory {
+        fn scoped(&self, scope: Scope) -> Self {
+            Self {
+                scope,
+                ..self.clone()
+            }
+        }
+
         async fn create(&self, payload: CreateTodo) -> anyhow::Result<TodoEntity> {
-            let mut store = self.write_store_ref();
-            let id = (store.len() + 1) as i32;
-            let labels = self.resolve_labels(payload.labels);
-            let todo = TodoEntity::new(id, payload.title, labels);
-            store.insert(id, todo.clone());
-            Ok(todo)
+            let mut data = self.store.write();
+            let label_ids = self.resolve_labels(&data, payload.labels)?;
+            let id = data.next_todo_id();
+            let todo = StoredTodo {
+                id,
+                title: payload.title,
+                description: payload.description,
+                completed: false,
+                version: 1,
+                label_ids,
+                workspace_id: self.scope.workspace_id(),
+                deleted_at: None,
+            };
+            data.record_todo_change(id, todo.workspace_id);
+            data.todos.insert(id, todo.clone());
+            Ok(self.entity(&data, &todo))
         }
 
         async fn find(&self, id: i32) -> anyhow::Result<TodoEntity> {
-            let store = self.read_store_ref();
-            let todo = store
-                .get(&id)
-                .cloned()
-                .ok_or(RepositoryError::NotFound(id))?;
-            Ok(todo)
+            let data = self.store.read();
+            let todo = self.get_scoped(&data, id)?;
+            Ok(self.entity(&data, todo))
         }
 
         async fn all(&self) -> anyhow::Result<Vec<TodoEntity>> {
-            let store = self.read_store_ref();
-            Ok(Vec::from_iter(store.values().cloned()))
-        }
-
-        async fn update(&self, id: i32, payload: UpdateTodo) -> anyhow::Result<TodoEntity> {
-            let mut store = self.write_store_ref();
-            let todo = store.get(&id).context(RepositoryError::NotFound(id))?;
-            let title = payload.title.unwrap_or_else(|| todo.title.clone());
-            let completed = payload.completed.unwrap_or(todo.completed);
-            let labels = match payload.labels {
-                Some(label_ids) => self.resolve_labels(label_ids),
-                None => todo.labels.clone(),
+            let data = self.store.read();
+            Ok(self.live_todos(&data))
+        }
+
+        async fn all_by_label(&self, label_id: i32) -> anyhow::Result<Vec<TodoEntity>> {
+            let data = self.store.read();
+            let label_ids = self.descendant_label_ids(&data, label_id);
+            Ok(self
+                .live_todos(&data)
+                .into_iter()
+                .filter(|todo| {
+                    todo.labels
+                        .iter()
+                        .any(|label| label_ids.contains(&label.id))
+                })
+                .collect())
+        }
+
+        async fn page(&self, limit: i64, offset: i64) -> anyhow::Result<TodoPage> {
+            let todos = self.all().await?;
+            Ok(TodoPage {
+                total: todos.len() as i64,
+                todos: todos
+                    .into_iter()
+                    .skip(offset as usize)
+                    .take(limit as usize)
+                    .collect(),
+            })
+        }
+
+        async fn page_by_label(
+            &self,
+            label_id: i32,
+            limit: i64,
+            offset: i64,
+        ) -> anyhow::Result<TodoPage> {
+            let todos = self.all_by_label(label_id).await?;
+            Ok(TodoPage {
+                total: todos.len() as i64,
+                todos: todos
+                    .into_iter()
+                    .skip(offset as usize)
+                    .take(limit as usize)
+                    .collect(),
+            })
+        }
+
+        async fn update(
+            &self,
+            id: i32,
+            payload: UpdateTodo,
+            expected_version: Option<i32>,
+        ) -> anyhow::Result<TodoEntity> {
+            let mut data = self.store.write();
+            let todo = self.get_scoped(&data, id)?;
+            if expected_version.is_some_and(|version| version != todo.version) {
+                return Err(RepositoryError::Conflict(id).into());
+            }
+            let label_ids = match payload.labels {
+                Some(label_ids) => self.resolve_labels(&data, label_ids)?,
+                // ゴミ箱のラベルも、復元したときのために残しておく
+                None => todo.label_ids.clone(),
             };
-            let mut todo = TodoEntity::new(id, title, labels);
-            todo.set_completed(completed);
-            store.insert(id, todo.clone());
-            Ok(todo)
+            let todo = StoredTodo {
+                title: payload.title.unwrap_or_else(|| todo.title.clone()),
+                description: payload
+                    .description
+                    .unwrap_or_else(|| todo.description.clone()),
+                completed: payload.completed.unwrap_or(todo.completed),
+                version: todo.version + 1,
+                label_ids,
+                ..todo.clone()
+            };
+            data.todos.insert(id, todo.clone());
+            Self::record_change(&mut data, id);
+            Ok(self.entity(&data, &todo))
         }
 
-        async fn delete(&self, id: i32) -> anyhow::Result<()> {
-            let mut store = self.write_store_ref();
-            store.remove(&id).context(RepositoryError::NotFound(id))?;
+        async fn delete(&self, id: i32, expected_version: Option<i32>) -> anyhow::Result<()> {
+            let mut data = self.store.write();
+            let todo = self.get_scoped(&data, id)?;
+            if expected_version.is_some_and(|version| version != todo.version) {
+                return Err(RepositoryError::Conflict(id).into());
+            }
+            data.todos.get_mut(&id).unwrap().deleted_at = Some(Utc::now());
+            Self::record_change(&mut data, id);
             Ok(())
         }
+
+        async fn trashed(&self) -> anyhow::Result<Vec<TrashedTodo>> {
+            let data = self.store.read();
+            let mut todos: Vec<TrashedTodo> = data
+                .todos
+                .values()
+                .filter(|todo| self.scope.contains(todo.workspace_id))
+                .filter_map(|todo| {
+                    Some(TrashedTodo {
+                        deleted_at: todo.deleted_at?,
+                        todo: self.entity(&data, todo),
+                    })
+                })
+                .collect();
+            todos.sort_by_key(|trashed| Reverse(trashed.deleted_at));
+            Ok(todos)
+        }
+
+        async fn restore(&self, id: i32) -> anyhow::Result<TodoEntity> {
+            let mut data = self.store.write();
+            let todo = data
+                .todos
+                .get_mut(&id)
+                .filter(|todo| todo.deleted_at.is_some() && self.scope.contains(todo.workspace_id))
+                .context(RepositoryError::NotFound(id))?;
+            todo.deleted_at = None;
+            let todo = todo.clone();
+            Self::record_change(&mut data, id);
+            Ok(self.entity(&data, &todo))
+        }
+
+        async fn purge(&self, deleted_before: DateTime<Utc>) -> anyhow::Result<u64> {
+            let mut data = self.store.write();
+            let purged: Vec<(i32, Option<i32>)> = data
+                .todos
+                .values()
+                .filter(|todo| {
+                    todo.deleted_at
+                        .is_some_and(|deleted_at| deleted_at <= deleted_before)
+                        && self.scope.contains(todo.workspace_id)
+                })
+                .map(|todo| (todo.id, todo.workspace_id))
+                .collect();
+            for (id, workspace_id) in purged.iter() {
+                data.todos.remove(id);
+                data.record_todo_change(*id, *workspace_id);
+            }
+            let purged: Vec<i32> = purged.into_iter().map(|(id, _)| id).collect();
+            // 完全削除した todo を blocker にしていた todo も変更として記録する
+            let (removed, dependencies): (Vec<_>, Vec<_>) =
+                data.dependencies.iter().partition(|(todo_id, blocker_id)| {
+                    purged.contains(todo_id) || purged.contains(blocker_id)
+                });
+            data.dependencies = dependencies;
+            for (todo_id, _) in removed {
+                if let Some(workspace_id) = data.todos.get(&todo_id).map(|t| t.workspace_id) {
+                    data.record_todo_change(todo_id, workspace_id);
+                }
+            }
+            Ok(purged.len() as u64)
+        }
+
+        async fn add_blocker(&self, id: i32, blocker_id: i32) -> anyhow::Result<TodoEntity> {
+            let mut data = self.store.write();
+            let todo = self.get_scoped(&data, id)?.clone();
+            self.get_scoped(&data, blocker_id)?;
+
+            // blocker から辿れる blocker に id が含まれていれば循環になる
+            let mut stack = vec![blocker_id];
+            while let Some(current) = stack.pop() {
+                if current == id {
+                    return Err(RepositoryError::DependencyCycle(id).into());
+                }
+                stack.extend(
+                    data.dependencies
+                        .iter()
+                        .filter(|(todo_id, _)| *todo_id == current)
+                        .map(|(_, blocker_id)| *blocker_id),
+                );
+            }
+            if !data.dependencies.contains(&(id, blocker_id)) {
+                data.dependencies.push((id, blocker_id));
+            }
+            data.record_todo_change(id, todo.workspace_id);
+
+            Ok(self.entity(&data, &todo))
+        }
+
+        async fn remove_blocker(&self, id: i32, blocker_id: i32) -> anyhow::Result<TodoEntity> {
+            let mut data = self.store.write();
+            let todo = self.get_scoped(&data, id)?.clone();
+            let index = data
+                .dependencies
+                .iter()
+                .position(|dependency| *dependency == (id, blocker_id))
+                .context(RepositoryError::NotFound(blocker_id))?;
+            data.dependencies.remove(index);
+            data.record_todo_change(id, todo.workspace_id);
+
+            Ok(self.entity(&data, &todo))
+        }
+
+        async fn changes(&self, since: i64) -> anyhow::Result<Changes<TodoEntity>> {
+            let data = self.store.read();
+            let cursor = data.cursor();
+            let ids = data.todo_changes.since(since, cursor, self.scope);
+            let mut upserts: Vec<TodoEntity> = ids
+                .iter()
+                .filter_map(|id| data.live_todo(*id))
+                .map(|todo| self.entity(&data, todo))
+                .collect();
+            upserts.sort_by_key(|todo| todo.id);
+            let tombstones = ids
+                .into_iter()
+                .filter(|id| data.live_todo(*id).is_none())
+                .collect();
+            Ok(Changes {
+                upserts,
+                tombstones,
+                cursor,
+            })
+        }
     }
 
+    #[cfg(test)]
     mod test {
         use super::*;
+        use crate::repositories::{
+            conformance::todo_repository_conformance, label::memory::LabelRepositoryInMemory,
+        };
 
         #[tokio::test]
         async fn todo_crud_scenario() {
@@ -573,21 +1942,225 @@ pub mod test_utils {
                     id,
                     UpdateTodo {
                         title: Some(title.clone()),
+                        description: None,
                         completed: Some(true),
                         labels: Some(vec![]),
                     },
+                    Some(1),
                 )
                 .await
                 .expect("failed update todo.");
 
             let mut expected = TodoEntity::new(id, title, vec![]);
             expected.set_completed(true);
+            expected.set_version(2);
 
             assert_eq!(todo, expected);
 
+            // update with stale version
+            let res = repository
+                .update(
+                    id,
+                    UpdateTodo {
+                        title: None,
+                        description: None,
+                        completed: Some(false),
+                        labels: None,
+                    },
+                    Some(1),
+                )
+                .await;
+            assert!(matches!(
+                res.unwrap_err().downcast_ref::<RepositoryError>(),
+                Some(RepositoryError::Conflict(_))
+            ));
+
+            // delete with stale version
+            let res = repository.delete(id, Some(1)).await;
+            assert!(res.is_err());
+
             // delete
-            let res = repository.delete(id).await;
+            let res = repository.delete(id, Some(2)).await;
             assert!(res.is_ok());
+            let res = repository.find(id).await;
+            assert!(res.is_err());
+
+            // trashed
+            let trashed = repository.trashed().await.expect("failed get trashed");
+            assert_eq!(trashed.len(), 1);
+            assert_eq!(trashed[0].todo, expected);
+
+            // restore
+            let todo = repository.restore(id).await.expect("failed restore todo");
+            assert_eq!(todo, expected);
+            assert!(repository.trashed().await.unwrap().is_empty());
+
+            // purge
+            repository
+                .delete(id, None)
+                .await
+                .expect("failed delete todo");
+            let purged = repository
+                .purge(Utc::now())
+                .await
+                .expect("failed purge todos");
+            assert_eq!(purged, 1);
+            assert!(repository.trashed().await.unwrap().is_empty());
+        }
+
+        #[tokio::test]
+        async fn dependency_scenario() {
+            let repository = TodoRepositoryInMemory::new(vec![]);
+            let todo = repository
+                .create(CreateTodo::new("blocked".to_string(), vec![]))
+                .await
+                .expect("failed create todo");
+            let blocker = repository
+                .create(CreateTodo::new("blocker".to_string(), vec![]))
+                .await
+                .expect("failed create todo");
+
+            // add blocker
+            let blocked = repository
+                .add_blocker(todo.id, blocker.id)
+                .await
+                .expect("failed add blocker");
+            assert!(blocked.blocked);
+            assert_eq!(blocked.blocked_by, vec![blocker.id]);
+
+            // cycle
+            let res = repository.add_blocker(blocker.id, todo.id).await;
+            assert!(matches!(
+                res.unwrap_err().downcast_ref::<RepositoryError>(),
+                Some(RepositoryError::DependencyCycle(_))
+            ));
+            let res = repository.add_blocker(todo.id, todo.id).await;
+            assert!(res.is_err());
+
+            // complete blocker
+            repository
+                .update(
+                    blocker.id,
+                    UpdateTodo {
+                        title: None,
+                        description: None,
+                        completed: Some(true),
+                        labels: None,
+                    },
+                    None,
+                )
+                .await
+                .expect("failed update todo");
+            let unblocked = repository.find(todo.id).await.unwrap();
+            assert!(!unblocked.blocked);
+            assert_eq!(unblocked.blocked_by, vec![blocker.id]);
+
+            // remove blocker
+            let removed = repository
+                .remove_blocker(todo.id, blocker.id)
+                .await
+                .expect("failed remove blocker");
+            assert!(removed.blocked_by.is_empty());
+            let res = repository.remove_blocker(todo.id, blocker.id).await;
+            assert!(res.is_err());
+        }
+
+        #[tokio::test]
+        async fn changes_scenario() {
+            let repository = TodoRepositoryInMemory::new(vec![]);
+            let todo = repository
+                .create(CreateTodo::new("blocked".to_string(), vec![]))
+                .await
+                .expect("failed create todo");
+            let blocker = repository
+                .create(CreateTodo::new("blocker".to_string(), vec![]))
+                .await
+                .expect("failed create todo");
+            let changes = repository.changes(0).await.expect("failed changes");
+            assert_eq!(changes.upserts, vec![todo.clone(), blocker.clone()]);
+            assert!(changes.tombstones.is_empty());
+
+            // blocker を完了すると、待っていた todo も変更として返る
+            repository
+                .add_blocker(todo.id, blocker.id)
+                .await
+                .expect("failed add blocker");
+            let cursor = repository.changes(0).await.unwrap().cursor;
+            repository
+                .update(
+                    blocker.id,
+                    UpdateTodo {
+                        title: None,
+                        description: None,
+                        completed: Some(true),
+                        labels: None,
+                    },
+                    None,
+                )
+                .await
+                .expect("failed update todo");
+            let changes = repository.changes(cursor).await.expect("failed changes");
+            let ids: Vec<i32> = changes.upserts.iter().map(|todo| todo.id).collect();
+            assert_eq!(ids, vec![todo.id, blocker.id]);
+            assert!(changes.cursor > cursor);
+
+            // 削除した todo は tombstone になる
+            repository
+                .delete(todo.id, None)
+                .await
+                .expect("failed delete todo");
+            let deleted = repository
+                .changes(changes.cursor)
+                .await
+                .expect("failed changes");
+            assert!(deleted.upserts.is_empty());
+            assert_eq!(deleted.tombstones, vec![todo.id]);
+
+            // 別のワークスペースの変更は返さない
+            let scoped = repository
+                .scoped(Scope::Workspace(1))
+                .changes(0)
+                .await
+                .expect("failed changes");
+            assert!(scoped.upserts.is_empty());
+            assert!(scoped.tombstones.is_empty());
+        }
+
+        #[tokio::test]
+        async fn all_by_label_includes_descendants() {
+            let parent = Label::new(1, "work".to_string());
+            let mut child = Label::new(2, "work/backend".to_string());
+            child.set_parent_id(Some(parent.id));
+            let mut grandchild = Label::new(3, "work/backend/db".to_string());
+            grandchild.set_parent_id(Some(child.id));
+            let other = Label::new(4, "home".to_string());
+            let repository =
+                TodoRepositoryInMemory::new(vec![parent, child, grandchild, other.clone()]);
+            for (title, label_id) in [("backend", 2), ("db", 3), ("home", 4)] {
+                repository
+                    .create(CreateTodo::new(title.to_string(), vec![label_id]))
+                    .await
+                    .expect("failed create todo");
+            }
+
+            let ids = |todos: Vec<TodoEntity>| todos.iter().map(|t| t.id).collect::<Vec<_>>();
+            let todos = repository.all_by_label(1).await.unwrap();
+            assert_eq!(ids(todos), vec![2, 1]);
+            let todos = repository.all_by_label(3).await.unwrap();
+            assert_eq!(ids(todos), vec![2]);
+            let todos = repository.all_by_label(other.id).await.unwrap();
+            assert_eq!(ids(todos), vec![3]);
+
+            let page = repository.page_by_label(1, 1, 1).await.unwrap();
+            assert_eq!(ids(page.todos), vec![1]);
+            assert_eq!(page.total, 2);
+        }
+
+        #[tokio::test]
+        async fn conforms_to_repository_contract() {
+            let todos = TodoRepositoryInMemory::new(vec![]);
+            let labels = LabelRepositoryInMemory::with_todos(todos.clone());
+            todo_repository_conformance(todos, labels).await;
         }
     }
 }
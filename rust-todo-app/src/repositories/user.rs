@@ -0,0 +1,197 @@
+use axum::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgPool};
+
+#[async_trait]
+pub trait UserRepository: Clone + std::marker::Send + std::marker::Sync + 'static {
+    async fn upsert(&self, identity: Identity) -> anyhow::Result<User>;
+}
+
+// IdP から受け取ったクレームのうち、ローカルのユーザーとの対応付けに使うもの
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Identity {
+    pub issuer: String,
+    pub subject: String,
+    pub email: Option<String>,
+}
+
+impl Identity {
+    // 初めて見たユーザーのローカルでの識別子。メールアドレスがあればそれを使い、
+    // `X-User-Id` で作られた既存のデータとつながるようにする
+    fn user_id(&self) -> String {
+        self.email.clone().unwrap_or_else(|| self.subject.clone())
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, FromRow)]
+pub struct User {
+    pub id: i32,
+    pub user_id: String,
+    pub issuer: String,
+    pub subject: String,
+    pub email: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub last_seen_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone)]
+pub struct UserRepositoryForDb {
+    pool: PgPool,
+}
+
+impl UserRepositoryForDb {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl UserRepository for UserRepositoryForDb {
+    #[tracing::instrument(name = "user_repository.upsert", skip_all)]
+    async fn upsert(&self, identity: Identity) -> anyhow::Result<User> {
+        // user_id は最初に作ったときのまま変えない
+        let user = sqlx::query_as::<_, User>(
+            r#"
+            insert into users (user_id, issuer, subject, email)
+            values ($1, $2, $3, $4)
+            on conflict (issuer, subject)
+            do update set email = excluded.email, last_seen_at = now()
+            returning *
+            "#,
+        )
+        .bind(identity.user_id())
+        .bind(identity.issuer)
+        .bind(identity.subject)
+        .bind(identity.email)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(user)
+    }
+}
+
+#[cfg(test)]
+#[cfg(feature = "database-test")]
+mod test {
+    use super::*;
+    use crate::repositories::test_database::TestDatabase;
+
+    #[tokio::test]
+    async fn crud_scenario() {
+        let database = TestDatabase::new().await;
+        let pool = database.pool();
+
+        let repository = UserRepositoryForDb::new(pool);
+        let subject = format!("crud_scenario_{}", std::process::id());
+        let identity = Identity {
+            issuer: "https://idp.example.com".to_string(),
+            subject: subject.clone(),
+            email: Some(format!("{}@example.com", subject)),
+        };
+
+        // 初めて見たユーザーは作成する
+        let created = repository
+            .upsert(identity.clone())
+            .await
+            .expect("failed upsert");
+        assert_eq!(created.user_id, format!("{}@example.com", subject));
+        assert_eq!(created.subject, subject);
+
+        // メールアドレスが変わってもローカルの識別子は変わらない
+        let updated = repository
+            .upsert(Identity {
+                email: Some("changed@example.com".to_string()),
+                ..identity
+            })
+            .await
+            .expect("failed upsert");
+        assert_eq!(updated.id, created.id);
+        assert_eq!(updated.user_id, created.user_id);
+        assert_eq!(updated.email, Some("changed@example.com".to_string()));
+        assert!(updated.last_seen_at >= created.last_seen_at);
+    }
+}
+
+#[cfg(any(test, feature = "memory"))]
+pub mod memory {
+    use super::*;
+    use crate::repositories::memory::MemoryStore;
+
+    #[derive(Debug, Clone)]
+    pub struct UserRepositoryInMemory {
+        store: MemoryStore,
+    }
+
+    impl UserRepositoryInMemory {
+        #[cfg(test)]
+        pub fn new() -> Self {
+            Self::with_store(MemoryStore::new())
+        }
+
+        // todo と同じストアを渡せば、スナップショットにユーザーも保存される
+        pub fn with_store(store: MemoryStore) -> Self {
+            Self { store }
+        }
+    }
+
+    #[async_trait]
+    impl UserRepository for UserRepositoryInMemory {
+        async fn upsert(&self, identity: Identity) -> anyhow::Result<User> {
+            let mut data = self.store.write();
+            let store = &mut data.users;
+            let now = Utc::now();
+            if let Some(user) = store
+                .iter_mut()
+                .find(|user| user.issuer == identity.issuer && user.subject == identity.subject)
+            {
+                user.email = identity.email;
+                user.last_seen_at = now;
+                return Ok(user.clone());
+            }
+
+            let user = User {
+                id: store.len() as i32 + 1,
+                user_id: identity.user_id(),
+                issuer: identity.issuer,
+                subject: identity.subject,
+                email: identity.email,
+                created_at: now,
+                last_seen_at: now,
+            };
+            store.push(user.clone());
+            Ok(user)
+        }
+    }
+
+    #[cfg(test)]
+    mod test {
+        use super::*;
+
+        #[tokio::test]
+        async fn user_scenario() {
+            let repository = UserRepositoryInMemory::new();
+            let identity = Identity {
+                issuer: "https://idp.example.com".to_string(),
+                subject: "subject".to_string(),
+                email: None,
+            };
+            let created = repository
+                .upsert(identity.clone())
+                .await
+                .expect("failed upsert");
+            assert_eq!(created.user_id, "subject");
+
+            let updated = repository
+                .upsert(Identity {
+                    email: Some("user@example.com".to_string()),
+                    ..identity
+                })
+                .await
+                .expect("failed upsert");
+            assert_eq!(updated.id, created.id);
+            assert_eq!(updated.user_id, "subject");
+            assert_eq!(updated.email, Some("user@example.com".to_string()));
+        }
+    }
+}
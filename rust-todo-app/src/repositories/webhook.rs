@@ -0,0 +1,879 @@
+use axum::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{types::Json, FromRow, PgPool};
+use validator::Validate;
+
+use super::{RepositoryError, Scope};
+
+// 配信中のリクエストが終わるまで他のワーカーに再取得されないようにする猶予
+const CLAIM_LEASE_SECS: i64 = 60;
+
+#[async_trait]
+pub trait WebhookRepository: Clone + std::marker::Send + std::marker::Sync + 'static {
+    // 配信の取得や結果の記録はワークスペースをまたいで行うため、絞り込まない
+    fn scoped(&self, scope: Scope) -> Self;
+    async fn create(&self, payload: CreateWebhook) -> anyhow::Result<WebhookSubscription>;
+    async fn all(&self) -> anyhow::Result<Vec<WebhookSubscription>>;
+    async fn delete(&self, id: i32) -> anyhow::Result<()>;
+    async fn enqueue(
+        &self,
+        event: WebhookEvent,
+        payload: serde_json::Value,
+    ) -> anyhow::Result<Vec<WebhookDelivery>>;
+    async fn deliveries(&self, subscription_id: i32) -> anyhow::Result<Vec<WebhookDelivery>>;
+    async fn claim_due(&self, limit: i64) -> anyhow::Result<Vec<PendingDelivery>>;
+    async fn record_success(&self, id: i32, status_code: i32) -> anyhow::Result<WebhookDelivery>;
+    async fn record_failure(
+        &self,
+        id: i32,
+        status_code: Option<i32>,
+        error: &str,
+        retry_at: Option<DateTime<Utc>>,
+    ) -> anyhow::Result<WebhookDelivery>;
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WebhookEvent {
+    #[serde(rename = "todo.created")]
+    TodoCreated,
+    #[serde(rename = "todo.updated")]
+    TodoUpdated,
+    #[serde(rename = "todo.deleted")]
+    TodoDeleted,
+    #[serde(rename = "label.created")]
+    LabelCreated,
+    #[serde(rename = "label.updated")]
+    LabelUpdated,
+    #[serde(rename = "label.deleted")]
+    LabelDeleted,
+}
+
+impl WebhookEvent {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WebhookEvent::TodoCreated => "todo.created",
+            WebhookEvent::TodoUpdated => "todo.updated",
+            WebhookEvent::TodoDeleted => "todo.deleted",
+            WebhookEvent::LabelCreated => "label.created",
+            WebhookEvent::LabelUpdated => "label.updated",
+            WebhookEvent::LabelDeleted => "label.deleted",
+        }
+    }
+
+    fn parse(event: &str) -> anyhow::Result<Self> {
+        match event {
+            "todo.created" => Ok(WebhookEvent::TodoCreated),
+            "todo.updated" => Ok(WebhookEvent::TodoUpdated),
+            "todo.deleted" => Ok(WebhookEvent::TodoDeleted),
+            "label.created" => Ok(WebhookEvent::LabelCreated),
+            "label.updated" => Ok(WebhookEvent::LabelUpdated),
+            "label.deleted" => Ok(WebhookEvent::LabelDeleted),
+            _ => Err(RepositoryError::Unexpected(format!("unknown event: {}", event)).into()),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DeliveryStatus {
+    Pending,
+    Succeeded,
+    Failed,
+}
+
+impl DeliveryStatus {
+    fn as_str(&self) -> &'static str {
+        match self {
+            DeliveryStatus::Pending => "pending",
+            DeliveryStatus::Succeeded => "succeeded",
+            DeliveryStatus::Failed => "failed",
+        }
+    }
+
+    fn parse(status: &str) -> anyhow::Result<Self> {
+        match status {
+            "pending" => Ok(DeliveryStatus::Pending),
+            "succeeded" => Ok(DeliveryStatus::Succeeded),
+            "failed" => Ok(DeliveryStatus::Failed),
+            _ => Err(RepositoryError::Unexpected(format!("unknown status: {}", status)).into()),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct WebhookSubscription {
+    pub id: i32,
+    pub url: String,
+    // 署名用の secret はレスポンスに含めない
+    #[serde(skip_serializing, default)]
+    pub secret: String,
+    // 空の場合はすべてのイベントを配信する
+    pub events: Vec<WebhookEvent>,
+    pub workspace_id: Option<i32>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, FromRow)]
+struct WebhookSubscriptionFromRow {
+    id: i32,
+    url: String,
+    secret: String,
+    events: Vec<String>,
+    workspace_id: Option<i32>,
+    created_at: DateTime<Utc>,
+}
+
+impl TryFrom<WebhookSubscriptionFromRow> for WebhookSubscription {
+    type Error = anyhow::Error;
+
+    fn try_from(row: WebhookSubscriptionFromRow) -> anyhow::Result<Self> {
+        Ok(WebhookSubscription {
+            id: row.id,
+            url: row.url,
+            secret: row.secret,
+            events: row
+                .events
+                .iter()
+                .map(|event| WebhookEvent::parse(event))
+                .collect::<anyhow::Result<_>>()?,
+            workspace_id: row.workspace_id,
+            created_at: row.created_at,
+        })
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct WebhookDelivery {
+    pub id: i32,
+    pub subscription_id: i32,
+    pub event: WebhookEvent,
+    pub payload: serde_json::Value,
+    pub status: DeliveryStatus,
+    pub attempts: i32,
+    pub next_attempt_at: DateTime<Utc>,
+    pub last_status_code: Option<i32>,
+    pub last_error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub delivered_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, FromRow)]
+struct WebhookDeliveryFromRow {
+    id: i32,
+    subscription_id: i32,
+    event: String,
+    payload: Json<serde_json::Value>,
+    status: String,
+    attempts: i32,
+    next_attempt_at: DateTime<Utc>,
+    last_status_code: Option<i32>,
+    last_error: Option<String>,
+    created_at: DateTime<Utc>,
+    delivered_at: Option<DateTime<Utc>>,
+}
+
+impl TryFrom<WebhookDeliveryFromRow> for WebhookDelivery {
+    type Error = anyhow::Error;
+
+    fn try_from(row: WebhookDeliveryFromRow) -> anyhow::Result<Self> {
+        Ok(WebhookDelivery {
+            id: row.id,
+            subscription_id: row.subscription_id,
+            event: WebhookEvent::parse(&row.event)?,
+            payload: row.payload.0,
+            status: DeliveryStatus::parse(&row.status)?,
+            attempts: row.attempts,
+            next_attempt_at: row.next_attempt_at,
+            last_status_code: row.last_status_code,
+            last_error: row.last_error,
+            created_at: row.created_at,
+            delivered_at: row.delivered_at,
+        })
+    }
+}
+
+// 配信ワーカーが取得した配信と、その送信先
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PendingDelivery {
+    pub delivery: WebhookDelivery,
+    pub url: String,
+    pub secret: String,
+}
+
+#[derive(Debug, FromRow)]
+struct PendingDeliveryFromRow {
+    #[sqlx(flatten)]
+    delivery: WebhookDeliveryFromRow,
+    url: String,
+    secret: String,
+}
+
+impl TryFrom<PendingDeliveryFromRow> for PendingDelivery {
+    type Error = anyhow::Error;
+
+    fn try_from(row: PendingDeliveryFromRow) -> anyhow::Result<Self> {
+        Ok(PendingDelivery {
+            delivery: row.delivery.try_into()?,
+            url: row.url,
+            secret: row.secret,
+        })
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Validate)]
+pub struct CreateWebhook {
+    #[validate(url(message = "Must be a valid URL"))]
+    pub url: String,
+    #[validate(length(min = 16, message = "Must be at least 16 characters"))]
+    #[validate(length(max = 255, message = "Can not be longer than 255 characters"))]
+    pub secret: String,
+    #[serde(default)]
+    pub events: Vec<WebhookEvent>,
+}
+
+#[derive(Debug, Clone)]
+pub struct WebhookRepositoryForDb {
+    pool: PgPool,
+    scope: Scope,
+}
+
+impl WebhookRepositoryForDb {
+    pub fn new(pool: PgPool) -> Self {
+        Self {
+            pool,
+            scope: Scope::All,
+        }
+    }
+}
+
+#[async_trait]
+impl WebhookRepository for WebhookRepositoryForDb {
+    fn scoped(&self, scope: Scope) -> Self {
+        Self {
+            pool: self.pool.clone(),
+            scope,
+        }
+    }
+
+    #[tracing::instrument(name = "webhook_repository.create", skip_all)]
+    async fn create(&self, payload: CreateWebhook) -> anyhow::Result<WebhookSubscription> {
+        let events: Vec<&str> = payload.events.iter().map(|event| event.as_str()).collect();
+        let row = sqlx::query_as::<_, WebhookSubscriptionFromRow>(
+            r#"
+            insert into webhook_subscriptions (url, secret, events, workspace_id)
+            values ($1, $2, $3, $4)
+            returning *
+            "#,
+        )
+        .bind(payload.url)
+        .bind(payload.secret)
+        .bind(events)
+        .bind(self.scope.workspace_id())
+        .fetch_one(&self.pool)
+        .await?;
+
+        row.try_into()
+    }
+
+    #[tracing::instrument(name = "webhook_repository.all", skip_all)]
+    async fn all(&self) -> anyhow::Result<Vec<WebhookSubscription>> {
+        let rows = sqlx::query_as::<_, WebhookSubscriptionFromRow>(
+            r#"
+            select * from webhook_subscriptions
+            where ($1::integer is null or coalesce(workspace_id, 0) = $1)
+            order by id asc
+            "#,
+        )
+        .bind(self.scope.bind_value())
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter()
+            .map(WebhookSubscription::try_from)
+            .collect()
+    }
+
+    #[tracing::instrument(name = "webhook_repository.delete", skip_all)]
+    async fn delete(&self, id: i32) -> anyhow::Result<()> {
+        let result = sqlx::query(
+            r#"
+            delete from webhook_subscriptions
+            where id = $1 and ($2::integer is null or coalesce(workspace_id, 0) = $2)
+            "#,
+        )
+        .bind(id)
+        .bind(self.scope.bind_value())
+        .execute(&self.pool)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(RepositoryError::NotFound(id).into());
+        }
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "webhook_repository.enqueue", skip_all)]
+    async fn enqueue(
+        &self,
+        event: WebhookEvent,
+        payload: serde_json::Value,
+    ) -> anyhow::Result<Vec<WebhookDelivery>> {
+        let rows = sqlx::query_as::<_, WebhookDeliveryFromRow>(
+            r#"
+            insert into webhook_deliveries (subscription_id, event, payload)
+            select id, $1, $2 from webhook_subscriptions
+            where (cardinality(events) = 0 or $1 = any(events))
+              and ($3::integer is null or coalesce(workspace_id, 0) = $3)
+            returning *
+            "#,
+        )
+        .bind(event.as_str())
+        .bind(Json(payload))
+        .bind(self.scope.bind_value())
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter().map(WebhookDelivery::try_from).collect()
+    }
+
+    #[tracing::instrument(name = "webhook_repository.deliveries", skip_all)]
+    async fn deliveries(&self, subscription_id: i32) -> anyhow::Result<Vec<WebhookDelivery>> {
+        let rows = sqlx::query_as::<_, WebhookDeliveryFromRow>(
+            r#"
+            select d.* from webhook_deliveries d
+            join webhook_subscriptions s on s.id = d.subscription_id
+            where d.subscription_id = $1
+              and ($2::integer is null or coalesce(s.workspace_id, 0) = $2)
+            order by d.id desc
+            "#,
+        )
+        .bind(subscription_id)
+        .bind(self.scope.bind_value())
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter().map(WebhookDelivery::try_from).collect()
+    }
+
+    #[tracing::instrument(name = "webhook_repository.claim_due", skip_all)]
+    async fn claim_due(&self, limit: i64) -> anyhow::Result<Vec<PendingDelivery>> {
+        // 複数インスタンスで同じ配信を二重に送らないよう、ロック中の行は読み飛ばす
+        let rows = sqlx::query_as::<_, PendingDeliveryFromRow>(
+            r#"
+            update webhook_deliveries d
+            set attempts = d.attempts + 1,
+                next_attempt_at = now() + make_interval(secs => $2)
+            from webhook_subscriptions s
+            where s.id = d.subscription_id
+              and d.id in (
+                select id from webhook_deliveries
+                where status = 'pending'
+                  and next_attempt_at <= now()
+                order by next_attempt_at asc
+                limit $1
+                for update skip locked
+              )
+            returning d.*, s.url, s.secret
+            "#,
+        )
+        .bind(limit)
+        .bind(CLAIM_LEASE_SECS as f64)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter().map(PendingDelivery::try_from).collect()
+    }
+
+    #[tracing::instrument(name = "webhook_repository.record_success", skip_all)]
+    async fn record_success(&self, id: i32, status_code: i32) -> anyhow::Result<WebhookDelivery> {
+        let row = sqlx::query_as::<_, WebhookDeliveryFromRow>(
+            r#"
+            update webhook_deliveries
+            set status = $2, last_status_code = $3, last_error = null, delivered_at = now()
+            where id = $1
+            returning *
+            "#,
+        )
+        .bind(id)
+        .bind(DeliveryStatus::Succeeded.as_str())
+        .bind(status_code)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or(RepositoryError::NotFound(id))?;
+
+        row.try_into()
+    }
+
+    #[tracing::instrument(name = "webhook_repository.record_failure", skip_all)]
+    async fn record_failure(
+        &self,
+        id: i32,
+        status_code: Option<i32>,
+        error: &str,
+        retry_at: Option<DateTime<Utc>>,
+    ) -> anyhow::Result<WebhookDelivery> {
+        // retry_at がなければリトライを打ち切る
+        let status = match retry_at {
+            Some(_) => DeliveryStatus::Pending,
+            None => DeliveryStatus::Failed,
+        };
+        let row = sqlx::query_as::<_, WebhookDeliveryFromRow>(
+            r#"
+            update webhook_deliveries
+            set status = $2,
+                last_status_code = $3,
+                last_error = $4,
+                next_attempt_at = coalesce($5, next_attempt_at)
+            where id = $1
+            returning *
+            "#,
+        )
+        .bind(id)
+        .bind(status.as_str())
+        .bind(status_code)
+        .bind(error)
+        .bind(retry_at)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or(RepositoryError::NotFound(id))?;
+
+        row.try_into()
+    }
+}
+
+#[cfg(test)]
+#[cfg(feature = "database-test")]
+mod test {
+    use super::*;
+    use crate::repositories::test_database::TestDatabase;
+
+    #[tokio::test]
+    async fn crud_scenario() {
+        let database = TestDatabase::new().await;
+        let pool = database.pool();
+
+        let repository = WebhookRepositoryForDb::new(pool);
+
+        // create
+        let all_events = repository
+            .create(CreateWebhook {
+                url: "http://localhost/all".to_string(),
+                secret: "crud_scenario_secret".to_string(),
+                events: vec![],
+            })
+            .await
+            .expect("failed create");
+        let labels_only = repository
+            .create(CreateWebhook {
+                url: "http://localhost/labels".to_string(),
+                secret: "crud_scenario_secret".to_string(),
+                events: vec![WebhookEvent::LabelCreated],
+            })
+            .await
+            .expect("failed create");
+        assert_eq!(labels_only.events, vec![WebhookEvent::LabelCreated]);
+
+        // all
+        let subscriptions = repository.all().await.expect("failed all");
+        assert!(subscriptions.contains(&all_events));
+        assert!(subscriptions.contains(&labels_only));
+
+        // scoped
+        let other = repository.scoped(Scope::Workspace(i32::MAX));
+        assert!(!other.all().await.unwrap().contains(&all_events));
+        let enqueued = other
+            .enqueue(
+                WebhookEvent::TodoCreated,
+                serde_json::json!({"title": "crud_scenario"}),
+            )
+            .await
+            .expect("failed enqueue");
+        assert!(enqueued.is_empty());
+        assert!(other.delete(all_events.id).await.is_err());
+
+        // enqueue
+        let enqueued = repository
+            .enqueue(
+                WebhookEvent::TodoCreated,
+                serde_json::json!({"title": "crud_scenario"}),
+            )
+            .await
+            .expect("failed enqueue");
+        let delivery = enqueued
+            .iter()
+            .find(|delivery| delivery.subscription_id == all_events.id)
+            .expect("not enqueued")
+            .clone();
+        assert!(!enqueued
+            .iter()
+            .any(|delivery| delivery.subscription_id == labels_only.id));
+        assert_eq!(delivery.status, DeliveryStatus::Pending);
+
+        // claim_due
+        let claimed = repository.claim_due(100).await.expect("failed claim");
+        let pending = claimed
+            .iter()
+            .find(|pending| pending.delivery.id == delivery.id)
+            .expect("not claimed");
+        assert_eq!(pending.url, all_events.url);
+        assert_eq!(pending.secret, all_events.secret);
+        assert_eq!(pending.delivery.attempts, 1);
+        let claimed = repository.claim_due(100).await.expect("failed claim");
+        assert!(!claimed
+            .iter()
+            .any(|pending| pending.delivery.id == delivery.id));
+
+        // record_failure
+        let retried = repository
+            .record_failure(delivery.id, Some(500), "server error", Some(Utc::now()))
+            .await
+            .expect("failed record_failure");
+        assert_eq!(retried.status, DeliveryStatus::Pending);
+        assert_eq!(retried.last_status_code, Some(500));
+        let claimed = repository.claim_due(100).await.expect("failed claim");
+        assert!(claimed
+            .iter()
+            .any(|pending| pending.delivery.id == delivery.id));
+
+        // record_success
+        let succeeded = repository
+            .record_success(delivery.id, 200)
+            .await
+            .expect("failed record_success");
+        assert_eq!(succeeded.status, DeliveryStatus::Succeeded);
+        assert_eq!(succeeded.attempts, 2);
+        assert!(succeeded.delivered_at.is_some());
+
+        // deliveries
+        let deliveries = repository
+            .deliveries(all_events.id)
+            .await
+            .expect("failed deliveries");
+        assert_eq!(deliveries, vec![succeeded]);
+
+        // delete
+        repository
+            .delete(all_events.id)
+            .await
+            .expect("failed delete");
+        repository
+            .delete(labels_only.id)
+            .await
+            .expect("failed delete");
+        let res = repository.delete(all_events.id).await;
+        assert!(res.is_err());
+        let deliveries = repository
+            .deliveries(all_events.id)
+            .await
+            .expect("failed deliveries");
+        assert!(deliveries.is_empty());
+    }
+}
+
+#[cfg(any(test, feature = "memory"))]
+pub mod memory {
+    use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+    use anyhow::Context;
+
+    use super::*;
+
+    impl WebhookSubscription {
+        fn accepts(&self, event: WebhookEvent) -> bool {
+            self.events.is_empty() || self.events.contains(&event)
+        }
+    }
+
+    #[derive(Debug, Default)]
+    struct WebhookDatas {
+        subscriptions: Vec<WebhookSubscription>,
+        deliveries: Vec<WebhookDelivery>,
+    }
+
+    #[derive(Debug, Clone)]
+    pub struct WebhookRepositoryInMemory {
+        store: Arc<RwLock<WebhookDatas>>,
+        scope: Scope,
+    }
+
+    impl WebhookRepositoryInMemory {
+        pub fn new() -> Self {
+            Self {
+                store: Arc::default(),
+                scope: Scope::All,
+            }
+        }
+
+        fn in_scope(&self, subscription: &WebhookSubscription) -> bool {
+            self.scope.contains(subscription.workspace_id)
+        }
+
+        fn write_store_ref(&self) -> RwLockWriteGuard<'_, WebhookDatas> {
+            self.store.write().unwrap()
+        }
+
+        fn read_store_ref(&self) -> RwLockReadGuard<'_, WebhookDatas> {
+            self.store.read().unwrap()
+        }
+    }
+
+    #[async_trait]
+    impl WebhookRepository for WebhookRepositoryInMemory {
+        fn scoped(&self, scope: Scope) -> Self {
+            Self {
+                store: self.store.clone(),
+                scope,
+            }
+        }
+
+        async fn create(&self, payload: CreateWebhook) -> anyhow::Result<WebhookSubscription> {
+            let mut store = self.write_store_ref();
+            let id = store.subscriptions.last().map(|s| s.id).unwrap_or(0) + 1;
+            let subscription = WebhookSubscription {
+                id,
+                url: payload.url,
+                secret: payload.secret,
+                events: payload.events,
+                workspace_id: self.scope.workspace_id(),
+                created_at: Utc::now(),
+            };
+            store.subscriptions.push(subscription.clone());
+            Ok(subscription)
+        }
+
+        async fn all(&self) -> anyhow::Result<Vec<WebhookSubscription>> {
+            let store = self.read_store_ref();
+            Ok(store
+                .subscriptions
+                .iter()
+                .filter(|subscription| self.in_scope(subscription))
+                .cloned()
+                .collect())
+        }
+
+        async fn delete(&self, id: i32) -> anyhow::Result<()> {
+            let mut store = self.write_store_ref();
+            let index = store
+                .subscriptions
+                .iter()
+                .position(|subscription| subscription.id == id && self.in_scope(subscription))
+                .context(RepositoryError::NotFound(id))?;
+            store.subscriptions.remove(index);
+            store
+                .deliveries
+                .retain(|delivery| delivery.subscription_id != id);
+            Ok(())
+        }
+
+        async fn enqueue(
+            &self,
+            event: WebhookEvent,
+            payload: serde_json::Value,
+        ) -> anyhow::Result<Vec<WebhookDelivery>> {
+            let mut store = self.write_store_ref();
+            let now = Utc::now();
+            let mut id = store.deliveries.last().map(|d| d.id).unwrap_or(0);
+            let deliveries: Vec<WebhookDelivery> = store
+                .subscriptions
+                .iter()
+                .filter(|subscription| subscription.accepts(event) && self.in_scope(subscription))
+                .map(|subscription| {
+                    id += 1;
+                    WebhookDelivery {
+                        id,
+                        subscription_id: subscription.id,
+                        event,
+                        payload: payload.clone(),
+                        status: DeliveryStatus::Pending,
+                        attempts: 0,
+                        next_attempt_at: now,
+                        last_status_code: None,
+                        last_error: None,
+                        created_at: now,
+                        delivered_at: None,
+                    }
+                })
+                .collect();
+            store.deliveries.extend(deliveries.clone());
+            Ok(deliveries)
+        }
+
+        async fn deliveries(&self, subscription_id: i32) -> anyhow::Result<Vec<WebhookDelivery>> {
+            let store = self.read_store_ref();
+            let in_scope = store.subscriptions.iter().any(|subscription| {
+                subscription.id == subscription_id && self.in_scope(subscription)
+            });
+            if !in_scope {
+                return Ok(vec![]);
+            }
+            Ok(store
+                .deliveries
+                .iter()
+                .rev()
+                .filter(|delivery| delivery.subscription_id == subscription_id)
+                .cloned()
+                .collect())
+        }
+
+        async fn claim_due(&self, limit: i64) -> anyhow::Result<Vec<PendingDelivery>> {
+            let mut store = self.write_store_ref();
+            let now = Utc::now();
+            let WebhookDatas {
+                subscriptions,
+                deliveries,
+            } = &mut *store;
+            Ok(deliveries
+                .iter_mut()
+                .filter(|delivery| {
+                    delivery.status == DeliveryStatus::Pending && delivery.next_attempt_at <= now
+                })
+                .take(limit as usize)
+                .filter_map(|delivery| {
+                    let subscription = subscriptions
+                        .iter()
+                        .find(|subscription| subscription.id == delivery.subscription_id)?;
+                    delivery.attempts += 1;
+                    delivery.next_attempt_at = now + chrono::Duration::seconds(CLAIM_LEASE_SECS);
+                    Some(PendingDelivery {
+                        delivery: delivery.clone(),
+                        url: subscription.url.clone(),
+                        secret: subscription.secret.clone(),
+                    })
+                })
+                .collect())
+        }
+
+        async fn record_success(
+            &self,
+            id: i32,
+            status_code: i32,
+        ) -> anyhow::Result<WebhookDelivery> {
+            let mut store = self.write_store_ref();
+            let delivery = store
+                .deliveries
+                .iter_mut()
+                .find(|delivery| delivery.id == id)
+                .context(RepositoryError::NotFound(id))?;
+            delivery.status = DeliveryStatus::Succeeded;
+            delivery.last_status_code = Some(status_code);
+            delivery.last_error = None;
+            delivery.delivered_at = Some(Utc::now());
+            Ok(delivery.clone())
+        }
+
+        async fn record_failure(
+            &self,
+            id: i32,
+            status_code: Option<i32>,
+            error: &str,
+            retry_at: Option<DateTime<Utc>>,
+        ) -> anyhow::Result<WebhookDelivery> {
+            let mut store = self.write_store_ref();
+            let delivery = store
+                .deliveries
+                .iter_mut()
+                .find(|delivery| delivery.id == id)
+                .context(RepositoryError::NotFound(id))?;
+            delivery.last_status_code = status_code;
+            delivery.last_error = Some(error.to_string());
+            match retry_at {
+                Some(retry_at) => delivery.next_attempt_at = retry_at,
+                None => delivery.status = DeliveryStatus::Failed,
+            }
+            Ok(delivery.clone())
+        }
+    }
+
+    #[cfg(test)]
+    mod test {
+        use super::*;
+
+        #[tokio::test]
+        async fn webhook_scenario() {
+            let repository = WebhookRepositoryInMemory::new();
+            let all_events = repository
+                .create(CreateWebhook {
+                    url: "http://localhost/all".to_string(),
+                    secret: "webhook_scenario".to_string(),
+                    events: vec![],
+                })
+                .await
+                .expect("failed create");
+            let todos_only = repository
+                .create(CreateWebhook {
+                    url: "http://localhost/todos".to_string(),
+                    secret: "webhook_scenario".to_string(),
+                    events: vec![WebhookEvent::TodoCreated],
+                })
+                .await
+                .expect("failed create");
+
+            // enqueue
+            let enqueued = repository
+                .enqueue(WebhookEvent::LabelCreated, serde_json::json!({"id": 1}))
+                .await
+                .expect("failed enqueue");
+            assert_eq!(enqueued.len(), 1);
+            assert_eq!(enqueued[0].subscription_id, all_events.id);
+
+            // claim_due
+            let claimed = repository.claim_due(10).await.expect("failed claim");
+            assert_eq!(claimed.len(), 1);
+            assert_eq!(claimed[0].url, all_events.url);
+            assert_eq!(claimed[0].delivery.attempts, 1);
+            assert!(repository.claim_due(10).await.unwrap().is_empty());
+
+            // record_failure
+            let failed = repository
+                .record_failure(enqueued[0].id, None, "connection refused", None)
+                .await
+                .expect("failed record_failure");
+            assert_eq!(failed.status, DeliveryStatus::Failed);
+            assert!(repository.claim_due(10).await.unwrap().is_empty());
+
+            // deliveries
+            let deliveries = repository
+                .deliveries(all_events.id)
+                .await
+                .expect("failed deliveries");
+            assert_eq!(deliveries, vec![failed]);
+            assert!(repository
+                .deliveries(todos_only.id)
+                .await
+                .unwrap()
+                .is_empty());
+
+            // delete
+            repository
+                .delete(all_events.id)
+                .await
+                .expect("failed delete");
+            assert_eq!(repository.all().await.unwrap(), vec![todos_only.clone()]);
+
+            // scoped
+            let personal = repository.scoped(Scope::Personal);
+            let workspace = repository.scoped(Scope::Workspace(1));
+            let team = workspace
+                .create(CreateWebhook {
+                    url: "http://localhost/team".to_string(),
+                    secret: "webhook_scenario".to_string(),
+                    events: vec![],
+                })
+                .await
+                .expect("failed create");
+            assert_eq!(team.workspace_id, Some(1));
+            assert_eq!(workspace.all().await.unwrap(), vec![team.clone()]);
+            assert_eq!(personal.all().await.unwrap(), vec![todos_only]);
+            let enqueued = workspace
+                .enqueue(WebhookEvent::TodoCreated, serde_json::json!({"id": 2}))
+                .await
+                .expect("failed enqueue");
+            assert_eq!(enqueued.len(), 1);
+            assert_eq!(enqueued[0].subscription_id, team.id);
+            assert!(personal.deliveries(team.id).await.unwrap().is_empty());
+            assert!(personal.delete(team.id).await.is_err());
+        }
+    }
+}
@@ -0,0 +1,639 @@
+use axum::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgPool};
+use validator::Validate;
+
+use super::RepositoryError;
+
+// 招待トークンの有効期間
+pub const INVITATION_TTL_DAYS: i64 = 7;
+
+#[async_trait]
+pub trait WorkspaceRepository: Clone + std::marker::Send + std::marker::Sync + 'static {
+    async fn create(&self, user_id: &str, payload: CreateWorkspace) -> anyhow::Result<Workspace>;
+    async fn all(&self, user_id: &str) -> anyhow::Result<Vec<Membership>>;
+    async fn role(&self, workspace_id: i32, user_id: &str) -> anyhow::Result<Option<Role>>;
+    async fn members(&self, workspace_id: i32) -> anyhow::Result<Vec<Member>>;
+    async fn invite(
+        &self,
+        workspace_id: i32,
+        invited_by: &str,
+        payload: CreateInvitation,
+    ) -> anyhow::Result<Invitation>;
+    async fn accept(&self, token: &str, user_id: &str) -> anyhow::Result<Member>;
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Owner,
+    Editor,
+    Viewer,
+}
+
+impl Role {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Role::Owner => "owner",
+            Role::Editor => "editor",
+            Role::Viewer => "viewer",
+        }
+    }
+
+    fn parse(role: &str) -> anyhow::Result<Self> {
+        match role {
+            "owner" => Ok(Role::Owner),
+            "editor" => Ok(Role::Editor),
+            "viewer" => Ok(Role::Viewer),
+            _ => Err(RepositoryError::Unexpected(format!("unknown role: {}", role)).into()),
+        }
+    }
+
+    // todo やラベルを変更できるかどうか
+    pub fn can_edit(&self) -> bool {
+        matches!(self, Role::Owner | Role::Editor)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, FromRow)]
+pub struct Workspace {
+    pub id: i32,
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+}
+
+// ユーザーが所属しているワークスペースと、そこでのロール
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Membership {
+    #[serde(flatten)]
+    pub workspace: Workspace,
+    pub role: Role,
+}
+
+#[derive(Debug, FromRow)]
+struct MembershipFromRow {
+    #[sqlx(flatten)]
+    workspace: Workspace,
+    role: String,
+}
+
+impl TryFrom<MembershipFromRow> for Membership {
+    type Error = anyhow::Error;
+
+    fn try_from(row: MembershipFromRow) -> anyhow::Result<Self> {
+        Ok(Membership {
+            workspace: row.workspace,
+            role: Role::parse(&row.role)?,
+        })
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Member {
+    pub workspace_id: i32,
+    pub user_id: String,
+    pub role: Role,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, FromRow)]
+struct MemberFromRow {
+    workspace_id: i32,
+    user_id: String,
+    role: String,
+    created_at: DateTime<Utc>,
+}
+
+impl TryFrom<MemberFromRow> for Member {
+    type Error = anyhow::Error;
+
+    fn try_from(row: MemberFromRow) -> anyhow::Result<Self> {
+        Ok(Member {
+            workspace_id: row.workspace_id,
+            user_id: row.user_id,
+            role: Role::parse(&row.role)?,
+            created_at: row.created_at,
+        })
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Invitation {
+    pub id: i32,
+    pub workspace_id: i32,
+    pub token: String,
+    pub role: Role,
+    pub invited_by: String,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub accepted_by: Option<String>,
+    pub accepted_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, FromRow)]
+struct InvitationFromRow {
+    id: i32,
+    workspace_id: i32,
+    token: String,
+    role: String,
+    invited_by: String,
+    created_at: DateTime<Utc>,
+    expires_at: DateTime<Utc>,
+    accepted_by: Option<String>,
+    accepted_at: Option<DateTime<Utc>>,
+}
+
+impl TryFrom<InvitationFromRow> for Invitation {
+    type Error = anyhow::Error;
+
+    fn try_from(row: InvitationFromRow) -> anyhow::Result<Self> {
+        Ok(Invitation {
+            id: row.id,
+            workspace_id: row.workspace_id,
+            token: row.token,
+            role: Role::parse(&row.role)?,
+            invited_by: row.invited_by,
+            created_at: row.created_at,
+            expires_at: row.expires_at,
+            accepted_by: row.accepted_by,
+            accepted_at: row.accepted_at,
+        })
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Validate)]
+pub struct CreateWorkspace {
+    #[validate(length(min = 1, message = "Can not be empty"))]
+    #[validate(length(max = 100, message = "Can not be longer than 100 characters"))]
+    pub name: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Validate)]
+pub struct CreateInvitation {
+    pub role: Role,
+}
+
+fn generate_token() -> String {
+    hex::encode(rand::random::<[u8; 24]>())
+}
+
+#[derive(Debug, Clone)]
+pub struct WorkspaceRepositoryForDb {
+    pool: PgPool,
+}
+
+impl WorkspaceRepositoryForDb {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl WorkspaceRepository for WorkspaceRepositoryForDb {
+    #[tracing::instrument(name = "workspace_repository.create", skip_all)]
+    async fn create(&self, user_id: &str, payload: CreateWorkspace) -> anyhow::Result<Workspace> {
+        let mut tx = self.pool.begin().await?;
+
+        let workspace = sqlx::query_as::<_, Workspace>(
+            r#"
+            insert into workspaces (name) values ($1)
+            returning *
+            "#,
+        )
+        .bind(payload.name)
+        .fetch_one(&mut tx)
+        .await?;
+
+        // 作成したユーザーがオーナーになる
+        sqlx::query(
+            r#"
+            insert into workspace_members (workspace_id, user_id, role)
+            values ($1, $2, $3)
+            "#,
+        )
+        .bind(workspace.id)
+        .bind(user_id)
+        .bind(Role::Owner.as_str())
+        .execute(&mut tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(workspace)
+    }
+
+    #[tracing::instrument(name = "workspace_repository.all", skip_all)]
+    async fn all(&self, user_id: &str) -> anyhow::Result<Vec<Membership>> {
+        let rows = sqlx::query_as::<_, MembershipFromRow>(
+            r#"
+            select workspaces.*, workspace_members.role
+            from workspaces
+            inner join workspace_members on workspace_members.workspace_id = workspaces.id
+            where workspace_members.user_id = $1
+            order by workspaces.id asc
+            "#,
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter().map(Membership::try_from).collect()
+    }
+
+    #[tracing::instrument(name = "workspace_repository.role", skip_all)]
+    async fn role(&self, workspace_id: i32, user_id: &str) -> anyhow::Result<Option<Role>> {
+        let role = sqlx::query_scalar::<_, String>(
+            r#"
+            select role from workspace_members
+            where workspace_id = $1 and user_id = $2
+            "#,
+        )
+        .bind(workspace_id)
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        role.map(|role| Role::parse(&role)).transpose()
+    }
+
+    #[tracing::instrument(name = "workspace_repository.members", skip_all)]
+    async fn members(&self, workspace_id: i32) -> anyhow::Result<Vec<Member>> {
+        let rows = sqlx::query_as::<_, MemberFromRow>(
+            r#"
+            select * from workspace_members
+            where workspace_id = $1
+            order by created_at asc, user_id asc
+            "#,
+        )
+        .bind(workspace_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter().map(Member::try_from).collect()
+    }
+
+    #[tracing::instrument(name = "workspace_repository.invite", skip_all)]
+    async fn invite(
+        &self,
+        workspace_id: i32,
+        invited_by: &str,
+        payload: CreateInvitation,
+    ) -> anyhow::Result<Invitation> {
+        let row = sqlx::query_as::<_, InvitationFromRow>(
+            r#"
+            insert into workspace_invitations (workspace_id, token, role, invited_by, expires_at)
+            values ($1, $2, $3, $4, now() + make_interval(days => $5))
+            returning *
+            "#,
+        )
+        .bind(workspace_id)
+        .bind(generate_token())
+        .bind(payload.role.as_str())
+        .bind(invited_by)
+        .bind(INVITATION_TTL_DAYS as i32)
+        .fetch_one(&self.pool)
+        .await?;
+
+        row.try_into()
+    }
+
+    #[tracing::instrument(name = "workspace_repository.accept", skip_all)]
+    async fn accept(&self, token: &str, user_id: &str) -> anyhow::Result<Member> {
+        let mut tx = self.pool.begin().await?;
+
+        // 招待は一度しか使えない
+        let invitation: Invitation = sqlx::query_as::<_, InvitationFromRow>(
+            r#"
+            update workspace_invitations
+            set accepted_by = $2, accepted_at = now()
+            where token = $1 and accepted_at is null and expires_at > now()
+            returning *
+            "#,
+        )
+        .bind(token)
+        .bind(user_id)
+        .fetch_optional(&mut tx)
+        .await?
+        .ok_or(RepositoryError::InvalidToken)?
+        .try_into()?;
+
+        // 既に所属しているユーザーのロールは変えない
+        sqlx::query(
+            r#"
+            insert into workspace_members (workspace_id, user_id, role)
+            values ($1, $2, $3)
+            on conflict (workspace_id, user_id) do nothing
+            "#,
+        )
+        .bind(invitation.workspace_id)
+        .bind(user_id)
+        .bind(invitation.role.as_str())
+        .execute(&mut tx)
+        .await?;
+
+        let row = sqlx::query_as::<_, MemberFromRow>(
+            r#"
+            select * from workspace_members
+            where workspace_id = $1 and user_id = $2
+            "#,
+        )
+        .bind(invitation.workspace_id)
+        .bind(user_id)
+        .fetch_one(&mut tx)
+        .await?;
+
+        tx.commit().await?;
+
+        row.try_into()
+    }
+}
+
+#[cfg(test)]
+#[cfg(feature = "database-test")]
+mod test {
+    use super::*;
+    use crate::repositories::test_database::TestDatabase;
+
+    #[tokio::test]
+    async fn crud_scenario() {
+        let database = TestDatabase::new().await;
+        let pool = database.pool();
+
+        let repository = WorkspaceRepositoryForDb::new(pool);
+        let owner = format!("crud_scenario_owner_{}", std::process::id());
+        let viewer = format!("crud_scenario_viewer_{}", std::process::id());
+
+        // create
+        let workspace = repository
+            .create(
+                &owner,
+                CreateWorkspace {
+                    name: "crud_scenario".to_string(),
+                },
+            )
+            .await
+            .expect("failed create");
+        assert_eq!(workspace.name, "crud_scenario");
+
+        // all
+        let memberships = repository.all(&owner).await.expect("failed all");
+        assert_eq!(
+            memberships,
+            vec![Membership {
+                workspace: workspace.clone(),
+                role: Role::Owner,
+            }]
+        );
+
+        // role
+        let role = repository
+            .role(workspace.id, &owner)
+            .await
+            .expect("failed role");
+        assert_eq!(role, Some(Role::Owner));
+        let role = repository
+            .role(workspace.id, &viewer)
+            .await
+            .expect("failed role");
+        assert_eq!(role, None);
+
+        // invite and accept
+        let invitation = repository
+            .invite(
+                workspace.id,
+                &owner,
+                CreateInvitation { role: Role::Viewer },
+            )
+            .await
+            .expect("failed invite");
+        assert_eq!(invitation.accepted_at, None);
+        let member = repository
+            .accept(&invitation.token, &viewer)
+            .await
+            .expect("failed accept");
+        assert_eq!(member.role, Role::Viewer);
+        let res = repository.accept(&invitation.token, &viewer).await;
+        assert!(res.is_err());
+
+        // members
+        let members = repository
+            .members(workspace.id)
+            .await
+            .expect("failed members");
+        let users: Vec<&str> = members.iter().map(|m| m.user_id.as_str()).collect();
+        assert_eq!(users, vec![owner.as_str(), viewer.as_str()]);
+    }
+}
+
+#[cfg(any(test, feature = "memory"))]
+pub mod memory {
+    use super::*;
+    use crate::repositories::memory::MemoryStore;
+
+    #[derive(Debug, Clone)]
+    pub struct WorkspaceRepositoryInMemory {
+        store: MemoryStore,
+    }
+
+    impl WorkspaceRepositoryInMemory {
+        #[cfg(test)]
+        pub fn new() -> Self {
+            Self::with_store(MemoryStore::new())
+        }
+
+        // todo と同じストアを渡せば、スナップショットにワークスペースも保存される
+        pub fn with_store(store: MemoryStore) -> Self {
+            Self { store }
+        }
+    }
+
+    #[async_trait]
+    impl WorkspaceRepository for WorkspaceRepositoryInMemory {
+        async fn create(
+            &self,
+            user_id: &str,
+            payload: CreateWorkspace,
+        ) -> anyhow::Result<Workspace> {
+            let mut data = self.store.write();
+            let store = &mut data.workspaces;
+            let workspace = Workspace {
+                id: store.workspaces.len() as i32 + 1,
+                name: payload.name,
+                created_at: Utc::now(),
+            };
+            store.workspaces.push(workspace.clone());
+            store.members.push(Member {
+                workspace_id: workspace.id,
+                user_id: user_id.to_string(),
+                role: Role::Owner,
+                created_at: workspace.created_at,
+            });
+            Ok(workspace)
+        }
+
+        async fn all(&self, user_id: &str) -> anyhow::Result<Vec<Membership>> {
+            let data = self.store.read();
+            let store = &data.workspaces;
+            let memberships = store
+                .members
+                .iter()
+                .filter(|member| member.user_id == user_id)
+                .filter_map(|member| {
+                    let workspace = store
+                        .workspaces
+                        .iter()
+                        .find(|workspace| workspace.id == member.workspace_id)?;
+                    Some(Membership {
+                        workspace: workspace.clone(),
+                        role: member.role,
+                    })
+                })
+                .collect();
+            Ok(memberships)
+        }
+
+        async fn role(&self, workspace_id: i32, user_id: &str) -> anyhow::Result<Option<Role>> {
+            let data = self.store.read();
+            let store = &data.workspaces;
+            let role = store
+                .members
+                .iter()
+                .find(|member| member.workspace_id == workspace_id && member.user_id == user_id)
+                .map(|member| member.role);
+            Ok(role)
+        }
+
+        async fn members(&self, workspace_id: i32) -> anyhow::Result<Vec<Member>> {
+            let data = self.store.read();
+            let store = &data.workspaces;
+            let members = store
+                .members
+                .iter()
+                .filter(|member| member.workspace_id == workspace_id)
+                .cloned()
+                .collect();
+            Ok(members)
+        }
+
+        async fn invite(
+            &self,
+            workspace_id: i32,
+            invited_by: &str,
+            payload: CreateInvitation,
+        ) -> anyhow::Result<Invitation> {
+            let mut data = self.store.write();
+            let store = &mut data.workspaces;
+            let created_at = Utc::now();
+            let invitation = Invitation {
+                id: store.invitations.len() as i32 + 1,
+                workspace_id,
+                token: generate_token(),
+                role: payload.role,
+                invited_by: invited_by.to_string(),
+                created_at,
+                expires_at: created_at + chrono::Duration::days(INVITATION_TTL_DAYS),
+                accepted_by: None,
+                accepted_at: None,
+            };
+            store.invitations.push(invitation.clone());
+            Ok(invitation)
+        }
+
+        async fn accept(&self, token: &str, user_id: &str) -> anyhow::Result<Member> {
+            let mut data = self.store.write();
+            let store = &mut data.workspaces;
+            let now = Utc::now();
+            let invitation = store
+                .invitations
+                .iter_mut()
+                .find(|invitation| {
+                    invitation.token == token
+                        && invitation.accepted_at.is_none()
+                        && invitation.expires_at > now
+                })
+                .ok_or(RepositoryError::InvalidToken)?;
+            invitation.accepted_by = Some(user_id.to_string());
+            invitation.accepted_at = Some(now);
+            let (workspace_id, role) = (invitation.workspace_id, invitation.role);
+
+            let existing = store
+                .members
+                .iter()
+                .find(|member| member.workspace_id == workspace_id && member.user_id == user_id)
+                .cloned();
+            let member = existing.unwrap_or_else(|| {
+                let member = Member {
+                    workspace_id,
+                    user_id: user_id.to_string(),
+                    role,
+                    created_at: now,
+                };
+                store.members.push(member.clone());
+                member
+            });
+            Ok(member)
+        }
+    }
+
+    #[cfg(test)]
+    mod test {
+        use super::*;
+
+        #[tokio::test]
+        async fn workspace_scenario() {
+            let repository = WorkspaceRepositoryInMemory::new();
+            let workspace = repository
+                .create(
+                    "owner",
+                    CreateWorkspace {
+                        name: "team".to_string(),
+                    },
+                )
+                .await
+                .expect("failed create");
+            assert_eq!(
+                repository.role(workspace.id, "owner").await.unwrap(),
+                Some(Role::Owner)
+            );
+
+            let invitation = repository
+                .invite(
+                    workspace.id,
+                    "owner",
+                    CreateInvitation { role: Role::Editor },
+                )
+                .await
+                .expect("failed invite");
+            let member = repository
+                .accept(&invitation.token, "editor")
+                .await
+                .expect("failed accept");
+            assert_eq!(member.role, Role::Editor);
+            assert!(repository
+                .accept(&invitation.token, "someone")
+                .await
+                .is_err());
+
+            // 既にオーナーのユーザーは招待を受けてもロールが変わらない
+            let invitation = repository
+                .invite(
+                    workspace.id,
+                    "owner",
+                    CreateInvitation { role: Role::Viewer },
+                )
+                .await
+                .expect("failed invite");
+            let member = repository
+                .accept(&invitation.token, "owner")
+                .await
+                .expect("failed accept");
+            assert_eq!(member.role, Role::Owner);
+
+            let memberships = repository.all("editor").await.expect("failed all");
+            assert_eq!(memberships[0].workspace, workspace);
+            assert_eq!(memberships[0].role, Role::Editor);
+            assert_eq!(repository.members(workspace.id).await.unwrap().len(), 2);
+        }
+    }
+}
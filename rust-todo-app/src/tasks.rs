@@ -0,0 +1,7 @@
+pub mod oidc;
+pub mod rate_limit;
+pub mod reminder;
+#[cfg(any(test, feature = "memory"))]
+pub mod snapshot;
+pub mod trash;
+pub mod webhook;
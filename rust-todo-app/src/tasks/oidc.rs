@@ -0,0 +1,23 @@
+use std::time::Duration;
+
+use tokio_util::sync::CancellationToken;
+
+use crate::oidc::JwtVerifier;
+
+// IdP の鍵のローテーションに追従するため、JWKS を定期的に取り直す
+pub async fn refresh_jwks(verifier: JwtVerifier, interval: Duration, shutdown: CancellationToken) {
+    let mut interval = tokio::time::interval(interval);
+    // 起動時に読み込んでいるため、最初の tick は読み飛ばす
+    interval.tick().await;
+    loop {
+        tokio::select! {
+            _ = shutdown.cancelled() => break,
+            _ = interval.tick() => {}
+        }
+        match verifier.refresh().await {
+            Ok(()) => tracing::debug!("refreshed JWKS"),
+            Err(e) => tracing::error!("failed to refresh JWKS: {}", e),
+        }
+    }
+    tracing::debug!("stopped refreshing JWKS");
+}
@@ -0,0 +1,57 @@
+use std::time::Duration;
+
+use tokio_util::sync::CancellationToken;
+
+use crate::handlers::rate_limit::RateLimiter;
+
+// リクエストのたびに全クライアントを走査しないよう、満タンに戻ったバケットは定期的に捨てる
+pub async fn sweep_rate_limit_buckets(
+    rate_limiter: RateLimiter,
+    interval: Duration,
+    shutdown: CancellationToken,
+) {
+    let mut interval = tokio::time::interval(interval);
+    loop {
+        tokio::select! {
+            _ = shutdown.cancelled() => break,
+            _ = interval.tick() => {}
+        }
+        let count = rate_limiter.sweep();
+        tracing::debug!("swept {} rate limit buckets", count);
+    }
+    tracing::debug!("stopped sweeping rate limit buckets");
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::handlers::rate_limit::RateLimitConfig;
+
+    #[tokio::test]
+    async fn should_sweep_only_full_buckets() {
+        let rate_limiter = RateLimiter::new(RateLimitConfig {
+            burst: 2,
+            per_second: 0.001,
+        });
+        rate_limiter.acquire("alice").unwrap();
+        rate_limiter.acquire("bob").unwrap();
+        assert_eq!(rate_limiter.sweep(), 0);
+
+        let rate_limiter = RateLimiter::new(RateLimitConfig {
+            burst: 2,
+            per_second: 1000.0,
+        });
+        rate_limiter.acquire("idle").unwrap();
+        let shutdown = CancellationToken::new();
+        let task = tokio::spawn(sweep_rate_limit_buckets(
+            rate_limiter.clone(),
+            Duration::from_millis(10),
+            shutdown.clone(),
+        ));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        shutdown.cancel();
+        task.await.unwrap();
+        // 回復して満タンになったバケットは既に捨てられている
+        assert_eq!(rate_limiter.sweep(), 0);
+    }
+}
@@ -0,0 +1,165 @@
+use std::time::Duration;
+
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    notifiers::{Notification, Notifiers},
+    repositories::{reminder::ReminderRepository, todo::TodoRepository, RepositoryError},
+};
+
+// 1 回のポーリングで通知するリマインダーの上限
+const BATCH_SIZE: i64 = 100;
+
+// 期限の来たリマインダーを定期的に取得して通知する。shutdown がキャンセルされたら終了する
+pub async fn fire_due_reminders<R: ReminderRepository, T: TodoRepository>(
+    reminder_repository: R,
+    todo_repository: T,
+    notifiers: Notifiers,
+    interval: Duration,
+    shutdown: CancellationToken,
+) {
+    let mut interval = tokio::time::interval(interval);
+    loop {
+        tokio::select! {
+            _ = shutdown.cancelled() => break,
+            _ = interval.tick() => {}
+        }
+        fire(&reminder_repository, &todo_repository, &notifiers).await;
+    }
+    tracing::debug!("stopped firing reminders");
+}
+
+async fn fire<R: ReminderRepository, T: TodoRepository>(
+    reminder_repository: &R,
+    todo_repository: &T,
+    notifiers: &Notifiers,
+) {
+    let reminders = match reminder_repository.claim_due(BATCH_SIZE).await {
+        Ok(reminders) => reminders,
+        Err(e) => {
+            tracing::error!("failed to claim due reminders: {}", e);
+            return;
+        }
+    };
+    for reminder in reminders {
+        let id = reminder.id;
+        // 設定から外されたチャネルは再び通知しても送れないため、通知済みとして扱う
+        if !notifiers.is_configured(reminder.channel) {
+            tracing::warn!(
+                "skipped reminder {}: channel {} is not configured",
+                id,
+                reminder.channel.as_str()
+            );
+        } else {
+            // 削除済み・完了済みの todo については通知せず、通知済みとして扱う。
+            // todo を読めなかった場合は通知済みにせず、確保の猶予が切れた後にやり直す
+            match todo_repository.find(reminder.todo_id).await {
+                Ok(todo) if !todo.completed => {
+                    let notification = Notification { reminder, todo };
+                    // 失敗した場合は通知済みにせず、確保の猶予が切れた後に再び通知する
+                    if let Err(e) = notifiers.notify(&notification).await {
+                        tracing::error!("failed to notify reminder {}: {}", id, e);
+                        continue;
+                    }
+                }
+                Ok(_) => tracing::debug!("skipped reminder {}", id),
+                Err(e) => match e.downcast_ref::<RepositoryError>() {
+                    Some(RepositoryError::NotFound(_)) => {
+                        tracing::debug!("skipped reminder {}", id)
+                    }
+                    _ => {
+                        tracing::error!("failed to find todo for reminder {}: {}", id, e);
+                        continue;
+                    }
+                },
+            }
+        }
+        if let Err(e) = reminder_repository.mark_fired(id).await {
+            tracing::error!("failed to mark reminder {} as fired: {}", id, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::Utc;
+
+    use super::*;
+    use crate::{
+        notifiers::test_utils::RecordingNotifier,
+        repositories::{
+            reminder::{memory::ReminderRepositoryInMemory, CreateReminder, ReminderChannel},
+            todo::{memory::TodoRepositoryInMemory, CreateTodo, UpdateTodo},
+        },
+    };
+
+    #[tokio::test]
+    async fn should_notify_due_reminders_once() {
+        let todo_repository = TodoRepositoryInMemory::new(vec![]);
+        let reminder_repository = ReminderRepositoryInMemory::new();
+        for title in ["open", "completed"] {
+            todo_repository
+                .create(CreateTodo::new(title.to_string(), vec![]))
+                .await
+                .unwrap();
+        }
+        todo_repository
+            .update(
+                2,
+                UpdateTodo {
+                    title: None,
+                    description: None,
+                    completed: Some(true),
+                    labels: None,
+                },
+                None,
+            )
+            .await
+            .unwrap();
+        for (todo_id, channel) in [
+            (1, ReminderChannel::Log),
+            (1, ReminderChannel::Webhook),
+            (2, ReminderChannel::Log),
+        ] {
+            reminder_repository
+                .create(
+                    todo_id,
+                    CreateReminder {
+                        fire_at: Utc::now(),
+                        channel,
+                    },
+                )
+                .await
+                .unwrap();
+        }
+        let notifier = RecordingNotifier::default();
+        let notifiers = Notifiers::new().register(ReminderChannel::Log, notifier.clone());
+
+        let shutdown = CancellationToken::new();
+        let task = tokio::spawn(fire_due_reminders(
+            reminder_repository.clone(),
+            todo_repository,
+            notifiers,
+            Duration::from_millis(10),
+            shutdown.clone(),
+        ));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        shutdown.cancel();
+        task.await.unwrap();
+
+        assert!(reminder_repository.claim_due(10).await.unwrap().is_empty());
+        // webhook は未設定、todo 2 は完了済みのため通知されない
+        {
+            let notifications = notifier.notifications.read().unwrap();
+            assert_eq!(notifications.len(), 1);
+            assert_eq!(notifications[0].reminder.id, 1);
+            assert_eq!(notifications[0].todo.title, "open");
+        }
+        // 未設定のチャネルのリマインダーは再通知の対象に残さない
+        let reminders = reminder_repository.find_by_todo(1).await.unwrap();
+        let fired: Vec<bool> = reminders.iter().map(|r| r.fired_at.is_some()).collect();
+        assert_eq!(fired, vec![true, true]);
+        let skipped = reminder_repository.find_by_todo(2).await.unwrap();
+        assert!(skipped[0].fired_at.is_some());
+    }
+}
@@ -0,0 +1,75 @@
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use tokio_util::sync::CancellationToken;
+
+use crate::repositories::memory::MemoryStore;
+
+// インメモリのストアを定期的に JSON のスナップショットとして保存する。
+// shutdown がキャンセルされたら、最後にもう一度保存して終了する
+pub async fn save_snapshots(
+    store: MemoryStore,
+    path: PathBuf,
+    interval: Duration,
+    shutdown: CancellationToken,
+) {
+    let mut interval = tokio::time::interval(interval);
+    // 起動直後の tick は読み込んだ内容をそのまま書き戻すだけなので飛ばす
+    interval.tick().await;
+    loop {
+        tokio::select! {
+            _ = shutdown.cancelled() => break,
+            _ = interval.tick() => {}
+        }
+        save(&store, &path).await;
+    }
+    save(&store, &path).await;
+    tracing::debug!("stopped saving snapshots");
+}
+
+// シリアライズとファイルの書き込みでランタイムのスレッドを止めないよう、ブロッキング用のスレッドで保存する
+async fn save(store: &MemoryStore, path: &Path) {
+    let saved = tokio::task::spawn_blocking({
+        let (store, path) = (store.clone(), path.to_path_buf());
+        move || store.save(&path)
+    })
+    .await;
+    match saved {
+        Ok(Ok(_)) => tracing::debug!("saved snapshot to {}", path.display()),
+        Ok(Err(e)) => tracing::error!("failed to save snapshot: {:#}", e),
+        Err(e) => tracing::error!("failed to save snapshot: {}", e),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::repositories::todo::{memory::TodoRepositoryInMemory, CreateTodo, TodoRepository};
+
+    #[tokio::test]
+    async fn should_save_snapshot_on_shutdown() {
+        let path = std::env::temp_dir().join(format!("todos-task-{}.json", std::process::id()));
+        let store = MemoryStore::new();
+        let todo_repository = TodoRepositoryInMemory::with_store(store.clone());
+
+        let shutdown = CancellationToken::new();
+        let task = tokio::spawn(save_snapshots(
+            store,
+            path.clone(),
+            Duration::from_secs(60),
+            shutdown.clone(),
+        ));
+        let todo = todo_repository
+            .create(CreateTodo::new("saved todo".to_string(), vec![]))
+            .await
+            .unwrap();
+        shutdown.cancel();
+        task.await.unwrap();
+
+        let loaded = TodoRepositoryInMemory::with_store(MemoryStore::load(&path).unwrap());
+        assert_eq!(loaded.find(todo.id).await.unwrap(), todo);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
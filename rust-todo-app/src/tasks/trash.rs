@@ -0,0 +1,80 @@
+use std::time::Duration;
+
+use chrono::Utc;
+use tokio_util::sync::CancellationToken;
+
+use crate::repositories::{label::LabelRepository, todo::TodoRepository};
+
+// 保存期間を過ぎたゴミ箱の todo / label を定期的に完全削除する。shutdown がキャンセルされたら終了する
+pub async fn purge_expired_trash<T: TodoRepository, L: LabelRepository>(
+    todo_repository: T,
+    label_repository: L,
+    retention: Duration,
+    interval: Duration,
+    shutdown: CancellationToken,
+) {
+    let retention = chrono::Duration::from_std(retention).expect("retention is too long");
+    let mut interval = tokio::time::interval(interval);
+    loop {
+        tokio::select! {
+            _ = shutdown.cancelled() => break,
+            _ = interval.tick() => {}
+        }
+        let deleted_before = Utc::now() - retention;
+        match todo_repository.purge(deleted_before).await {
+            Ok(count) => tracing::debug!("purged {} todos from trash", count),
+            Err(e) => tracing::error!("failed to purge todos from trash: {}", e),
+        }
+        match label_repository.purge(deleted_before).await {
+            Ok(count) => tracing::debug!("purged {} labels from trash", count),
+            Err(e) => tracing::error!("failed to purge labels from trash: {}", e),
+        }
+    }
+    tracing::debug!("stopped purging trash");
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::repositories::{
+        label::memory::LabelRepositoryInMemory,
+        todo::{memory::TodoRepositoryInMemory, CreateTodo},
+    };
+
+    #[tokio::test]
+    async fn should_purge_only_expired_trash() {
+        let todo_repository = TodoRepositoryInMemory::new(vec![]);
+        let label_repository = LabelRepositoryInMemory::new();
+        todo_repository
+            .create(CreateTodo::new("trashed todo".to_string(), vec![]))
+            .await
+            .unwrap();
+        todo_repository.delete(1, None).await.unwrap();
+
+        let shutdown = CancellationToken::new();
+        let task = tokio::spawn(purge_expired_trash(
+            todo_repository.clone(),
+            label_repository.clone(),
+            Duration::from_secs(60),
+            Duration::from_millis(10),
+            shutdown.clone(),
+        ));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(todo_repository.trashed().await.unwrap().len(), 1);
+        shutdown.cancel();
+        task.await.unwrap();
+
+        let shutdown = CancellationToken::new();
+        let task = tokio::spawn(purge_expired_trash(
+            todo_repository.clone(),
+            label_repository,
+            Duration::ZERO,
+            Duration::from_millis(10),
+            shutdown.clone(),
+        ));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(todo_repository.trashed().await.unwrap().is_empty());
+        shutdown.cancel();
+        task.await.unwrap();
+    }
+}
@@ -0,0 +1,222 @@
+use std::time::Duration;
+
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use reqwest::Client;
+use sha2::Sha256;
+use tokio_util::sync::CancellationToken;
+
+use crate::repositories::webhook::{PendingDelivery, WebhookRepository};
+
+pub const EVENT_HEADER: &str = "x-webhook-event";
+pub const DELIVERY_HEADER: &str = "x-webhook-delivery";
+pub const SIGNATURE_HEADER: &str = "x-webhook-signature";
+
+// 1 回のポーリングで配信する上限
+const BATCH_SIZE: i64 = 100;
+// この回数失敗したら配信を打ち切る
+const MAX_ATTEMPTS: i32 = 8;
+
+// リクエストボディの HMAC-SHA256 を `sha256=<hex>` 形式で返す
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC can take key of any size");
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+// 失敗するたびに retry_base * 2^(attempts - 1) だけ間隔を空けて再送する
+fn backoff(retry_base: Duration, attempts: i32) -> Duration {
+    let exponent = (attempts - 1).clamp(0, 16) as u32;
+    retry_base.saturating_mul(2u32.pow(exponent))
+}
+
+// 配信待ちの webhook を定期的に取得して送信する。shutdown がキャンセルされたら、
+// 送信中のバッチを終えてから終了する
+pub async fn deliver_webhooks<W: WebhookRepository>(
+    repository: W,
+    interval: Duration,
+    retry_base: Duration,
+    shutdown: CancellationToken,
+) {
+    let client = Client::builder()
+        .timeout(Duration::from_secs(10))
+        .build()
+        .expect("failed to build http client");
+    let mut interval = tokio::time::interval(interval);
+    loop {
+        tokio::select! {
+            _ = shutdown.cancelled() => break,
+            _ = interval.tick() => {}
+        }
+        deliver(&repository, &client, retry_base).await;
+    }
+    tracing::debug!("stopped delivering webhooks");
+}
+
+async fn deliver<W: WebhookRepository>(repository: &W, client: &Client, retry_base: Duration) {
+    let pendings = match repository.claim_due(BATCH_SIZE).await {
+        Ok(pendings) => pendings,
+        Err(e) => {
+            tracing::error!("failed to claim webhook deliveries: {}", e);
+            return;
+        }
+    };
+    for pending in pendings {
+        let delivery = &pending.delivery;
+        let result = match send(client, &pending).await {
+            Ok(status_code) => repository.record_success(delivery.id, status_code).await,
+            Err((status_code, error)) => {
+                tracing::warn!("failed to deliver webhook {}: {}", delivery.id, error);
+                let retry_at = (delivery.attempts < MAX_ATTEMPTS).then(|| {
+                    let delay = backoff(retry_base, delivery.attempts);
+                    Utc::now() + chrono::Duration::from_std(delay).unwrap_or(chrono::Duration::MAX)
+                });
+                repository
+                    .record_failure(delivery.id, status_code, &error, retry_at)
+                    .await
+            }
+        };
+        if let Err(e) = result {
+            tracing::error!("failed to record webhook delivery {}: {}", delivery.id, e);
+        }
+    }
+}
+
+async fn send(client: &Client, pending: &PendingDelivery) -> Result<i32, (Option<i32>, String)> {
+    let delivery = &pending.delivery;
+    let body = serde_json::to_vec(&serde_json::json!({
+        "id": delivery.id,
+        "event": delivery.event,
+        "created_at": delivery.created_at,
+        "data": delivery.payload,
+    }))
+    .map_err(|e| (None, e.to_string()))?;
+    let res = client
+        .post(&pending.url)
+        .header(
+            reqwest::header::CONTENT_TYPE,
+            mime::APPLICATION_JSON.as_ref(),
+        )
+        .header(EVENT_HEADER, delivery.event.as_str())
+        .header(DELIVERY_HEADER, delivery.id)
+        .header(SIGNATURE_HEADER, sign(&pending.secret, &body))
+        .body(body)
+        .send()
+        .await
+        // 送信先 URL に含まれる認証情報を配信履歴やログに残さない
+        .map_err(|e| (None, e.without_url().to_string()))?;
+    let status = res.status();
+    if status.is_success() {
+        Ok(status.as_u16() as i32)
+    } else {
+        Err((
+            Some(status.as_u16() as i32),
+            format!("unexpected status: {}", status),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        net::SocketAddr,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+    };
+
+    use axum::{body::Bytes, extract::State, routing::post, Router};
+    use hyper::{HeaderMap, StatusCode};
+    use tokio::sync::Mutex;
+
+    use super::*;
+    use crate::repositories::webhook::{
+        memory::WebhookRepositoryInMemory, CreateWebhook, DeliveryStatus, WebhookEvent,
+    };
+
+    #[derive(Clone, Default)]
+    struct Receiver {
+        calls: Arc<AtomicUsize>,
+        received: Arc<Mutex<Vec<(HeaderMap, Bytes)>>>,
+    }
+
+    // 1 回目は 500 を返し、2 回目以降は受け付ける
+    async fn receive(
+        State(receiver): State<Receiver>,
+        headers: HeaderMap,
+        body: Bytes,
+    ) -> StatusCode {
+        if receiver.calls.fetch_add(1, Ordering::SeqCst) == 0 {
+            return StatusCode::INTERNAL_SERVER_ERROR;
+        }
+        receiver.received.lock().await.push((headers, body));
+        StatusCode::NO_CONTENT
+    }
+
+    #[test]
+    fn should_backoff_exponentially() {
+        let base = Duration::from_secs(30);
+        assert_eq!(backoff(base, 1), Duration::from_secs(30));
+        assert_eq!(backoff(base, 2), Duration::from_secs(60));
+        assert_eq!(backoff(base, 4), Duration::from_secs(240));
+    }
+
+    #[tokio::test]
+    async fn should_deliver_signed_webhook_with_retry() {
+        let receiver = Receiver::default();
+        let app = Router::new()
+            .route("/hook", post(receive))
+            .with_state(receiver.clone());
+        let server = axum::Server::bind(&SocketAddr::from(([127, 0, 0, 1], 0)))
+            .serve(app.into_make_service());
+        let addr = server.local_addr();
+        tokio::spawn(server);
+
+        let repository = WebhookRepositoryInMemory::new();
+        let subscription = repository
+            .create(CreateWebhook {
+                url: format!("http://{}/hook", addr),
+                secret: "should_deliver_signed".to_string(),
+                events: vec![WebhookEvent::TodoCreated],
+            })
+            .await
+            .unwrap();
+        repository
+            .enqueue(
+                WebhookEvent::TodoCreated,
+                serde_json::json!({"id": 1, "title": "webhook"}),
+            )
+            .await
+            .unwrap();
+
+        let shutdown = CancellationToken::new();
+        let task = tokio::spawn(deliver_webhooks(
+            repository.clone(),
+            Duration::from_millis(10),
+            Duration::ZERO,
+            shutdown.clone(),
+        ));
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        shutdown.cancel();
+        task.await.unwrap();
+
+        let deliveries = repository.deliveries(subscription.id).await.unwrap();
+        assert_eq!(deliveries.len(), 1);
+        assert_eq!(deliveries[0].status, DeliveryStatus::Succeeded);
+        assert_eq!(deliveries[0].attempts, 2);
+        assert_eq!(deliveries[0].last_status_code, Some(204));
+
+        let received = receiver.received.lock().await;
+        assert_eq!(received.len(), 1);
+        let (headers, body) = &received[0];
+        assert_eq!(headers[EVENT_HEADER], "todo.created");
+        assert_eq!(
+            headers[SIGNATURE_HEADER].to_str().unwrap(),
+            sign(&subscription.secret, body)
+        );
+        let body: serde_json::Value = serde_json::from_slice(body).unwrap();
+        assert_eq!(body["data"]["title"], "webhook");
+    }
+}